@@ -0,0 +1,65 @@
+// compares the copy methods on many small files and on one large file. Run with
+// `cargo bench --bench copy` (add `--features io-uring` to include io_uring); under `cargo test`
+// it only runs a handful of copies to check that everything still works.
+use std::env;
+use std::fs;
+use std::time::{Duration, Instant};
+use rodeo::copy::{Copier, CopyMethod, DEFAULT_BUFFER_SIZE};
+
+const METHODS: &[(&str, CopyMethod)] = &[
+    ("std", CopyMethod::Std),
+    ("buffered", CopyMethod::Buffered),
+    ("io_uring", CopyMethod::IoUring),
+];
+
+fn main() {
+    let full = env::args().any(|a| a == "--bench");
+    let (small_files, large_size) = if full { (5000, 256 * 1024 * 1024) } else { (10, 1024 * 1024) };
+
+    let dir = env::temp_dir().join(format!("rodeo-bench-{}", std::process::id()));
+    let source_dir = dir.join("source");
+    let dest_dir = dir.join("dest");
+    fs::create_dir_all(&source_dir).expect("could not create benchmark directory");
+    fs::create_dir_all(&dest_dir).expect("could not create benchmark directory");
+
+    // small files the size of a typical dotfile, and one large file
+    let small: Vec<(String, String)> = (0..small_files)
+        .map(|i| {
+            let from = source_dir.join(format!("small-{}", i));
+            fs::write(&from, vec![b'x'; 4096]).expect("could not write benchmark file");
+            (path(&from), path(&dest_dir.join(format!("small-{}", i))))
+        })
+        .collect();
+    let large_from = source_dir.join("large");
+    fs::write(&large_from, vec![b'x'; large_size]).expect("could not write benchmark file");
+    let large = vec![(path(&large_from), path(&dest_dir.join("large")))];
+
+    for buffer_size in [16 * 1024, DEFAULT_BUFFER_SIZE, 1024 * 1024] {
+        for (name, method) in METHODS {
+            let copier = Copier { method: *method, buffer_size, rate_limit: None, umask: None, mode: None, progress: None };
+            println!(
+                "{:>8} buffer {:>7}: {} small files {:>10.2?}, {} MiB file {:>10.2?}",
+                name,
+                buffer_size,
+                small.len(),
+                time(&copier, &small),
+                large_size / 1024 / 1024,
+                time(&copier, &large),
+            );
+        }
+    }
+
+    fs::remove_dir_all(&dir).expect("could not remove benchmark directory");
+}
+
+fn time(copier: &Copier, files: &[(String, String)]) -> Duration {
+    let started = Instant::now();
+    for (from, to) in files {
+        copier.copy(from, to).expect("copy failed");
+    }
+    started.elapsed()
+}
+
+fn path(path: &std::path::Path) -> String {
+    path.to_string_lossy().into_owned()
+}
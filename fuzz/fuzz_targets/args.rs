@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rodeo::config::Config;
+
+// any command line must either parse or give an error, never panic. Arguments are separated by
+// NUL bytes, which can't appear in real ones.
+fuzz_target!(|data: &[u8]| {
+    // the hostname command would otherwise be run for every input
+    std::env::set_var("HOSTNAME", "fuzz");
+    std::env::set_var("HOME", "/nonexistent/home");
+    let args = String::from_utf8_lossy(data);
+    let _ = Config::from_args(args.split('\0').map(|a| a.to_owned()));
+});
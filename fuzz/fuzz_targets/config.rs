@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rodeo::format::ConfigFormat;
+use rodeo::Settings;
+
+// config files in any of the formats rodeo reads must either load or give an error, never panic.
+// The config is placed somewhere that doesn't exist, so there is no conf.d next to it.
+fuzz_target!(|data: &[u8]| {
+    let contents = match std::str::from_utf8(data) {
+        Ok(val) => val,
+        Err(_) => return,
+    };
+    for format in [ConfigFormat::Toml, ConfigFormat::Yaml, ConfigFormat::Json] {
+        let _ = Settings::new_from_str(contents.to_owned(), "/nonexistent/home".to_owned(), "/nonexistent/rodeo.toml".to_owned(), format);
+    }
+});
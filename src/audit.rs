@@ -0,0 +1,164 @@
+use std::fs::{self, OpenOptions};
+use std::io::prelude::*;
+use std::process::{Command, Output};
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use crate::defaults::DEFAULT_SHELL;
+use crate::state;
+
+// how much of a command's output is kept in the audit log
+const MAX_OUTPUT_LEN: usize = 2000;
+
+// one external command rodeo ran
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditRecord {
+    // seconds since the unix epoch at which the command was started
+    pub timestamp: u64,
+    // "hook", "vcs", "registry" or "escalate"
+    pub kind: String,
+    pub command: String,
+    pub duration_ms: u128,
+    // None if the command could not be started or was killed by a signal
+    pub exit_status: Option<i32>,
+    // stdout followed by stderr, truncated to MAX_OUTPUT_LEN bytes
+    pub output: String,
+}
+
+impl AuditRecord {
+    pub fn to_text(&self) -> String {
+        let status = match self.exit_status {
+            Some(code) => format!("exit {}", code),
+            None => "did not exit normally".to_owned(),
+        };
+        let mut text = format!(
+            "{} [{}] {} ({}, {}ms)",
+            state::format_timestamp(self.timestamp),
+            self.kind,
+            self.command,
+            status,
+            self.duration_ms
+        );
+        for line in self.output.lines() {
+            text.push_str(&format!("\n    {}", line));
+        }
+        text
+    }
+}
+
+// append-only record of every hook, vcs and registry command rodeo has run, kept as one JSON object per line
+// in <state_dir>/audit.log
+#[derive(Clone, Debug)]
+pub struct AuditLog {
+    pub path: String,
+    // variables set for hooks on top of rodeo's own environment, from the config's [env] table
+    pub env: Vec<(String, String)>,
+    // what hooks are run with; everything else goes through bash
+    pub shell: String,
+}
+
+impl AuditLog {
+    pub fn new(state_dir: &str) -> AuditLog {
+        AuditLog { path: format!("{}/audit.log", state_dir), env: Vec::new(), shell: DEFAULT_SHELL.to_owned() }
+    }
+
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> AuditLog {
+        self.env = env;
+        self
+    }
+
+    pub fn with_shell(mut self, shell: &str) -> AuditLog {
+        self.shell = shell.to_owned();
+        self
+    }
+
+    // runs shell_command through bash (or shell, for hooks), logging it before handing back its
+    // output. Failing to write the log never stops the command from running; it's only reported on
+    // stderr.
+    pub fn run(&self, kind: &str, shell_command: &str) -> std::io::Result<Output> {
+        let mut command = Command::new(if kind == "hook" { &self.shell[..] } else { DEFAULT_SHELL });
+        command.arg("-c").arg(shell_command);
+        if kind == "hook" {
+            command.envs(self.env.iter().map(|(name, value)| (name, value)));
+        }
+        self.run_command(kind, shell_command, &mut command)
+    }
+
+    // same as run, but for a command that doesn't go through a shell (e.g. on Windows, where
+    // there may not be one). description is what gets written to the log as the command.
+    pub fn run_command(&self, kind: &str, description: &str, command: &mut Command) -> std::io::Result<Output> {
+        let timestamp = state::now();
+        let started = Instant::now();
+        let result = command.output();
+
+        let (exit_status, output) = match &result {
+            Ok(val) => (
+                val.status.code(),
+                format!("{}{}", String::from_utf8_lossy(&val.stdout), String::from_utf8_lossy(&val.stderr)),
+            ),
+            Err(e) => (None, format!("could not run command: {}", e)),
+        };
+
+        let record = AuditRecord {
+            timestamp,
+            kind: kind.to_owned(),
+            command: description.to_owned(),
+            duration_ms: started.elapsed().as_millis(),
+            exit_status,
+            output: truncate(output),
+        };
+        if let Err(e) = self.append(&record) {
+            eprintln!("could not write to audit log \"{}\": {}", self.path, e);
+        }
+
+        result
+    }
+
+    pub fn append(&self, record: &AuditRecord) -> std::io::Result<()> {
+        let line = serde_json::to_string(record)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+        if let Some(parent) = std::path::Path::new(&self.path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", line)
+    }
+
+    // every record in the log, oldest first. Lines that can't be parsed are skipped.
+    pub fn read(&self) -> std::io::Result<Vec<AuditRecord>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(val) => val,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        Ok(contents.lines().filter_map(|l| serde_json::from_str(l).ok()).collect())
+    }
+}
+
+// short description of why a command failed, e.g. "exit status 1: fatal: not a git repository"
+pub fn describe_failure(output: &Output) -> String {
+    let status = match output.status.code() {
+        Some(code) => format!("exit status {}", code),
+        None => "killed by a signal".to_owned(),
+    };
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.trim().is_empty() {
+        status
+    } else {
+        format!("{}: {}", status, stderr.trim())
+    }
+}
+
+// cuts text down to MAX_OUTPUT_LEN bytes without splitting a character
+fn truncate(mut text: String) -> String {
+    if text.len() > MAX_OUTPUT_LEN {
+        let mut end = MAX_OUTPUT_LEN;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("... (truncated)");
+    }
+    text
+}
@@ -0,0 +1,247 @@
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+use crate::event::{Event, EventKind};
+use crate::copy::{self, Copier};
+use crate::state;
+
+// name of the one-time backup set taken before rodeo first deploys on a machine
+pub const PRE_RODEO: &str = "pre-rodeo";
+
+// one file covered by a backup set. backup is None when there was nothing at destination when the
+// backup was taken, meaning that restoring should remove whatever rodeo put there.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BackupEntry {
+    pub destination: String,
+    pub backup: Option<String>,
+}
+
+// the list of everything in a backup set, stored as manifest.json inside the set's directory
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Manifest {
+    // seconds since the unix epoch
+    pub created: u64,
+    pub entries: Vec<BackupEntry>,
+}
+
+impl Manifest {
+    pub fn load(set_dir: &str) -> std::io::Result<Manifest> {
+        let contents = fs::read_to_string(format!("{}/manifest.json", set_dir))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, set_dir: &str) -> std::io::Result<()> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        fs::create_dir_all(set_dir)?;
+        fs::write(format!("{}/manifest.json", set_dir), contents)
+    }
+}
+
+// where the backups deploy takes of the files it overwrites go, one set per deploy
+pub fn backups_dir(state_dir: &str) -> String {
+    format!("{}/backups", state_dir)
+}
+
+// a backup set found in the state directory, named after its directory
+#[derive(Clone, Debug)]
+pub struct BackupSet {
+    pub name: String,
+    pub manifest: Manifest,
+}
+
+// every backup set there is, the pre-rodeo one included, newest first. Sets whose manifest can't
+// be read are left out.
+pub fn sets(state_dir: &str) -> Vec<BackupSet> {
+    let mut names: Vec<String> = fs::read_dir(backups_dir(state_dir))
+        .map(|entries| entries.flatten().map(|e| e.file_name().to_string_lossy().into_owned()).collect())
+        .unwrap_or_default();
+    names.sort();
+    let mut sets: Vec<BackupSet> = names.into_iter()
+        .filter_map(|name| {
+            let manifest = Manifest::load(&format!("{}/{}", backups_dir(state_dir), name)).ok()?;
+            Some(BackupSet { name, manifest })
+        })
+        .collect();
+    if let Ok(manifest) = Manifest::load(&pre_rodeo_dir(state_dir)) {
+        sets.insert(0, BackupSet { name: PRE_RODEO.to_owned(), manifest });
+    }
+    sets.reverse();
+    sets
+}
+
+// one backed up version of a file
+#[derive(Clone, Debug, Serialize)]
+pub struct Version {
+    pub set: String,
+    // seconds since the unix epoch
+    pub created: u64,
+    pub backup: String,
+}
+
+// every backed up version of the file at destination in sets, in their order
+pub fn versions(sets: &[BackupSet], destination: &str) -> Vec<Version> {
+    sets.iter()
+        .flat_map(|set| set.manifest.entries.iter()
+            .filter(|e| e.destination == destination)
+            .filter_map(move |e| Some(Version { set: set.name.clone(), created: set.manifest.created, backup: e.backup.clone()? })))
+        .collect()
+}
+
+// the backed up versions of one managed file, newest first, as `rodeo restore` lists them
+#[derive(Clone, Debug, Serialize)]
+pub struct FileBackups {
+    pub program: String,
+    pub path: String,
+    pub deployed_file: String,
+    pub versions: Vec<Version>,
+}
+
+impl FileBackups {
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}: {}", self.program, self.path);
+        for version in self.versions.iter() {
+            text.push_str(&format!("\n\t{} ({})", version.set, state::format_timestamp(version.created)));
+        }
+        text
+    }
+}
+
+pub fn pre_rodeo_dir(state_dir: &str) -> String {
+    format!("{}/{}", state_dir, PRE_RODEO)
+}
+
+// the pre-rodeo snapshot only ever happens once: as soon as its manifest exists, it's done
+pub fn needs_pre_rodeo(state_dir: &str) -> bool {
+    !Path::new(&format!("{}/manifest.json", pre_rodeo_dir(state_dir))).exists()
+}
+
+// archives whatever currently lives at each of the given destinations into the pre-rodeo backup
+// set. Files are stored under the set's directory at their absolute path, e.g. ~/.bashrc ends up
+// at <state_dir>/pre-rodeo/files/home/<user>/.bashrc.
+pub fn snapshot_pre_rodeo(state_dir: &str, destinations: &[String]) -> Vec<Event> {
+    let set_dir = pre_rodeo_dir(state_dir);
+    let mut events = Vec::new();
+    let mut manifest = Manifest {
+        created: state::now(),
+        entries: Vec::new(),
+    };
+
+    for destination in destinations {
+        if !Path::new(destination).is_file() {
+            manifest.entries.push(BackupEntry { destination: destination.clone(), backup: None });
+            continue;
+        }
+
+        let (event, entry) = archive(&set_dir, destination);
+        manifest.entries.extend(entry);
+        events.push(event);
+    }
+
+    // only write the manifest (and so mark the snapshot as done) if every file made it
+    let manifest_event = Event::new(EventKind::File, "backup", "", &set_dir[..]);
+    if events.iter().any(|e| e.error.is_some()) {
+        events.push(manifest_event.failed("pre-rodeo backup incomplete, it will be retried on the next deploy"));
+    } else if let Err(e) = manifest.save(&set_dir) {
+        events.push(manifest_event.failed(format!("could not write pre-rodeo manifest: {}", e)));
+    }
+
+    events
+}
+
+// archives the files deploy is about to overwrite into a new backup set in backups_dir, named
+// after the time it is taken, e.g. <state_dir>/backups/2024-05-01T18-30-00, laid out like the
+// pre-rodeo one. Nothing is written if destinations is empty.
+pub fn snapshot(state_dir: &str, destinations: &[String]) -> Vec<Event> {
+    if destinations.is_empty() {
+        return Vec::new();
+    }
+    let created = state::now();
+    let name = state::format_timestamp(created).replace(' ', "T").replace(':', "-");
+    // two deploys within the same second get a set each
+    let mut set_dir = format!("{}/{}", backups_dir(state_dir), name);
+    let mut n = 1;
+    while Path::new(&set_dir).exists() {
+        n += 1;
+        set_dir = format!("{}/{}-{}", backups_dir(state_dir), name, n);
+    }
+
+    let mut manifest = Manifest { created, entries: Vec::new() };
+    let mut events = Vec::new();
+    for destination in destinations {
+        let (event, entry) = archive(&set_dir, destination);
+        manifest.entries.extend(entry);
+        events.push(event);
+    }
+    if let Err(e) = manifest.save(&set_dir) {
+        events.push(Event::new(EventKind::File, "backup", "", &set_dir[..]).failed(format!("could not write backup manifest: {}", e)));
+    }
+    events
+}
+
+// copies destination into set_dir at its absolute path, e.g. ~/.bashrc to
+// <set_dir>/files/home/<user>/.bashrc, returning what happened and, if it worked, its manifest entry
+fn archive(set_dir: &str, destination: &str) -> (Event, Option<BackupEntry>) {
+    let backup = format!("{}/files/{}", set_dir, destination.trim_start_matches('/'));
+    let event = Event::new(EventKind::File, "backup", destination, &backup[..]);
+
+    // make sure the directory structure exists, then copy the original over
+    if let Some(parent) = Path::new(&backup).parent() {
+        fs::create_dir_all(parent).unwrap_or_default();
+    }
+    match Copier::default().copy(destination, &backup) {
+        Ok(_) => (event, Some(BackupEntry { destination: destination.to_owned(), backup: Some(backup) })),
+        Err(e) => (event.failed(format!("could not back up \"{}\": {}", destination, e)), None),
+    }
+}
+
+// puts everything in the pre-rodeo backup set back where it came from. Destinations that didn't
+// exist before rodeo's first deploy are removed.
+pub fn restore_pre_rodeo(state_dir: &str) -> Vec<Event> {
+    let set_dir = pre_rodeo_dir(state_dir);
+    let manifest = match Manifest::load(&set_dir) {
+        Ok(val) => val,
+        Err(e) => {
+            return vec![Event::new(EventKind::File, "restore", &set_dir[..], "")
+                .failed(format!("could not read pre-rodeo backup manifest: {}", e))]
+        }
+    };
+
+    let mut events = Vec::new();
+    for entry in manifest.entries {
+        match entry.backup {
+            Some(backup) => events.push(put_back(&backup, &entry.destination)),
+            None => {
+                let event = Event::new(EventKind::File, "remove", &entry.destination[..], "");
+                events.push(match fs::remove_file(&entry.destination) {
+                    Ok(_) => event,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        event.skipped(format!("\"{}\" did not exist before rodeo and is already gone", entry.destination))
+                    }
+                    Err(e) => event.failed(format!("could not remove \"{}\": {}", entry.destination, e)),
+                });
+            }
+        }
+    }
+
+    events
+}
+
+// copies the backed up file at backup to destination
+pub fn put_back(backup: &str, destination: &str) -> Event {
+    let event = Event::new(EventKind::File, "restore", backup, destination);
+    if let Some(parent) = Path::new(destination).parent() {
+        if let Err(e) = fs::create_dir_all(parent) {
+            return event.failed(format!("could not restore \"{}\": could not create \"{}\": {}", destination, parent.display(), e));
+        }
+    }
+    // renamed into place rather than copied onto destination, which would write through a link to
+    // the repository copy it is linked to. The file counts as changed now, so that sync keeps it
+    // rather than the older repository copy.
+    match Copier::default().replace(backup, destination).and_then(|_| copy::set_modified(destination, SystemTime::now())) {
+        Ok(_) => event,
+        Err(e) => event.failed(format!("could not restore \"{}\": {}", destination, e)),
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::link;
+use crate::mapping::{FileState, Target};
+use crate::watch;
+
+// how recently a file may have been modified for its state to still be cached. A file changed
+// again within the same timestamp tick, without changing size, would otherwise look untouched.
+const RACY_NANOS: u128 = 2_000_000_000;
+
+// the state of every managed file as last worked out, kept in the state directory between runs so
+// that listing states (status, tree) only has to compare the files that changed since. Whether a
+// file changed goes by the size and modification time of both of its copies, unless rodeo watch is
+// running: it brings the cache up to date as files change, so it is taken at its word then.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct StatusCache {
+    #[serde(skip)]
+    path: String,
+    #[serde(skip)]
+    changed: bool,
+    // whether rodeo watch keeps the entries up to date, so they needn't be checked
+    #[serde(skip)]
+    trusted: bool,
+    // files whose state was worked out too soon after they changed to be kept, by deployed file
+    #[serde(skip)]
+    unsettled: BTreeSet<String>,
+    // by deployed file
+    entries: BTreeMap<String, Entry>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Entry {
+    repo_file: String,
+    repo: Option<Stamp>,
+    deployed: Option<Stamp>,
+    state: FileState,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+struct Stamp {
+    len: u64,
+    // nanoseconds since the unix epoch
+    modified: u128,
+}
+
+impl Stamp {
+    fn of(path: &str) -> Option<Stamp> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+        Some(Stamp { len: metadata.len(), modified })
+    }
+}
+
+impl StatusCache {
+    // the cache kept in state_dir. A missing or unreadable cache is an empty one.
+    pub fn load(state_dir: &str) -> StatusCache {
+        let path = format!("{}/status-cache.json", state_dir);
+        let mut cache: StatusCache = fs::read_to_string(&path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+        cache.path = path;
+        cache.trusted = watch::running(state_dir).is_some();
+        cache
+    }
+
+    // the same cache, checking every entry against the files however up to date it is kept, for
+    // rodeo watch itself
+    pub fn checking(mut self) -> StatusCache {
+        self.trusted = false;
+        self
+    }
+
+    // target's state, from the cache if neither copy changed since it was worked out
+    pub fn state(&mut self, target: &Target) -> FileState {
+        if self.trusted {
+            if let Some(entry) = self.entries.get(&target.deployed_file).filter(|e| e.repo_file == target.repo_file) {
+                return entry.state;
+            }
+        }
+        // a link's stamp is that of the file it points to, which says nothing about the link, and
+        // two hard links to one file always look the same. What a template renders to changes with
+        // the variables as well.
+        if target.template.is_some() || link::is_link(&target.deployed_file) || link::same_file(&target.deployed_file, &target.repo_file) {
+            self.forget(&target.deployed_file);
+            return target.state();
+        }
+        let repo = Stamp::of(&target.repo_file);
+        let deployed = Stamp::of(&target.deployed_file);
+        if let Some(entry) = self.entries.get(&target.deployed_file) {
+            if entry.repo_file == target.repo_file && entry.repo == repo && entry.deployed == deployed {
+                return entry.state;
+            }
+        }
+
+        let state = target.state();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or_default();
+        let settled = [repo, deployed].iter().flatten().all(|s| s.modified + RACY_NANOS < now);
+        if settled {
+            let entry = Entry { repo_file: target.repo_file.clone(), repo, deployed, state };
+            self.entries.insert(target.deployed_file.clone(), entry);
+            self.unsettled.remove(&target.deployed_file);
+        } else {
+            self.entries.remove(&target.deployed_file);
+            self.unsettled.insert(target.deployed_file.clone());
+        }
+        self.changed = true;
+        state
+    }
+
+    // drops what is cached for the file deployed at deployed_file, so that its state is worked out
+    // again
+    pub fn forget(&mut self, deployed_file: &str) {
+        if self.entries.remove(deployed_file).is_some() {
+            self.changed = true;
+        }
+    }
+
+    // drops the entries of files that aren't in deployed_files, which are no longer managed
+    pub fn keep_only(&mut self, deployed_files: &BTreeSet<String>) {
+        let before = self.entries.len();
+        self.entries.retain(|file, _| deployed_files.contains(file));
+        self.changed |= self.entries.len() != before;
+    }
+
+    // the files that changed too recently to be cached, as state left them out, clearing the list
+    pub fn take_unsettled(&mut self) -> BTreeSet<String> {
+        std::mem::take(&mut self.unsettled)
+    }
+
+    // writes the cache back if anything in it changed. It only saves work, so failing to write it
+    // isn't worth reporting. While rodeo watch keeps it, the cache is its to write. It is written
+    // next to where it goes and renamed over it, so that it is never read half written.
+    pub fn save(&mut self) {
+        if !self.changed || self.trusted {
+            return;
+        }
+        self.changed = false;
+        if let Some(dir) = std::path::Path::new(&self.path).parent() {
+            fs::create_dir_all(dir).unwrap_or_default();
+        }
+        if let Ok(contents) = serde_json::to_string(self) {
+            let partial = format!("{}.partial", self.path);
+            fs::write(&partial, contents).and_then(|_| fs::rename(&partial, &self.path)).unwrap_or_default();
+        }
+    }
+}
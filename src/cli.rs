@@ -0,0 +1,948 @@
+use crate::summary::{EXIT_CONFIG_ERROR, EXIT_OK, EXIT_SYNC_ERROR, EXIT_VCS_ERROR};
+
+// declarative description of rodeo's command line and config file. The help text, the man page and
+// command dispatch in main are all generated from these tables, so adding a command or a config key
+// here is the only place it needs documenting.
+
+pub struct CommandSpec {
+    // canonical name, which is what main matches on
+    pub name: &'static str,
+    // every other spelling accepted for the command
+    pub aliases: &'static [&'static str],
+    // anything that goes after the command name in usage lines, e.g. "--pre-rodeo"
+    pub usage: &'static str,
+    pub description: &'static str,
+}
+
+pub struct FlagSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+pub struct KeySpec {
+    // "" for top level keys, otherwise the name of the table (or array of tables) the key lives in,
+    // e.g. "program" for [[program]], "defaults" for [defaults] or "group.<name>" for [group.<name>]
+    pub table: &'static str,
+    pub key: &'static str,
+    pub kind: &'static str,
+    pub required: bool,
+    pub description: &'static str,
+}
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "deploy",
+        aliases: &["d"],
+        usage: "",
+        description: "deploys all dotfiles referenced in your config in your local dotfiles repo to your system, overwriting existing files",
+    },
+    CommandSpec {
+        name: "collect",
+        aliases: &["c"],
+        usage: "",
+        description: "collects all dotfiles referenced in your config that are active in your system to your local dotfiles repo, overwriting existing files",
+    },
+    CommandSpec {
+        name: "sync",
+        aliases: &[
+            "sync-full", "sync_full", "full_sync", "full-sync", "fsync",
+            "sync-remote", "sync_remote", "remote_sync", "remote-sync", "rsync",
+        ],
+        usage: "[--local-only | --no-pull | --no-push]",
+        description: "pulls from the remote, replaces every dotfile referenced in your config, both deployed and collected, with whichever respective file was last modified, then commits and pushes the result. Uses the backend set by \"vcs\" in your config (git by default). With git, the incoming revision is checked out in a temporary worktree first and the changes it would make to deployed files are shown; the sync only goes ahead once confirmed (or with --yes).",
+    },
+    CommandSpec {
+        name: "sync-local",
+        aliases: &["sync_local", "local_sync", "local-sync", "lsync"],
+        usage: "",
+        description: "same as sync --local-only",
+    },
+    CommandSpec {
+        name: "unlink",
+        aliases: &[],
+        usage: "",
+        description: "replaces every deployed file that is a symlink or hard link to its copy in your local dotfiles repo (see deploy_mode) with a copy of that file, e.g. before switching back to deploy_mode = \"copy\" or uninstalling rodeo",
+    },
+    CommandSpec {
+        name: "prune",
+        aliases: &[],
+        usage: "[--yes]",
+        description: "lists the files rodeo deployed on this machine that are no longer in your config (see ~/.local/share/rodeo/state.json), then after asking removes those that haven't changed since, backing them up first. Deploy offers the same at its end",
+    },
+    CommandSpec {
+        name: "import",
+        aliases: &[],
+        usage: "--scan [--yes] | (dotbot|yadm) <file> [--yes]",
+        description: "with --scan, looks through your home directory for well-known dotfiles (bash, git, vim, nvim, tmux, alacritty, ...) that aren't in your config yet. With dotbot or yadm, translates that manager's setup instead: file is a dotbot install.conf.yaml, or yadm's repository or the output of `yadm list -a`, and anything that can't be translated is listed. Either way it lists the program entries it would add, then after asking adds them to your config and collects their files into your local dotfiles repo",
+    },
+    CommandSpec {
+        name: "enable",
+        aliases: &[],
+        usage: "<program>",
+        description: "sets enabled = true on program in your config file, so that it is deployed, collected and synced again",
+    },
+    CommandSpec {
+        name: "disable",
+        aliases: &[],
+        usage: "<program>",
+        description: "sets enabled = false on program in your config file, so that every command leaves it alone while keeping its configuration",
+    },
+    CommandSpec {
+        name: "resume",
+        aliases: &[],
+        usage: "",
+        description: "finishes a deploy, collect or sync that was interrupted part way through by running it again",
+    },
+    CommandSpec {
+        name: "abort",
+        aliases: &[],
+        usage: "",
+        description: "undoes everything an interrupted deploy, collect or sync had written, putting files back the way they were before it started",
+    },
+    CommandSpec {
+        name: "plan",
+        aliases: &[],
+        usage: "<deploy | collect | sync | sync-local>",
+        description: "shows what the given command would do to every managed file (create, overwrite, unchanged or skip) without doing it; same as giving that command --dry-run. With --json, prints the plan as a single JSON object whose format is kept stable for other tools",
+    },
+    CommandSpec {
+        name: "check",
+        aliases: &[],
+        usage: "",
+        description: "lists every managed file whose deployed copy differs from the one in your local dotfiles repo, exiting with status 1 if there are any",
+    },
+    CommandSpec {
+        name: "restore",
+        aliases: &[],
+        usage: "[<backup> [<file>...]] [--to-repo] | --pre-rodeo",
+        description: "without arguments, lists the backed up versions of every managed file (see backup in [defaults]). Given a backup, or \"latest\" for each file's newest one, copies the files in it (or only the given ones) back into place, or into your local dotfiles repo with --to-repo. With --pre-rodeo, puts back every file that was overwritten by rodeo's very first deploy on this machine, and removes files that didn't exist before it",
+    },
+    CommandSpec {
+        name: "trash",
+        aliases: &[],
+        usage: "[list | restore <id>... | empty]",
+        description: "lists the files deploy, collect, sync, unlink, prune, rollback and revert overwrote or removed, which are kept in the trash (see trash), newest first. restore moves the ones with the given ids back where they were, putting what is there now in the trash, and empty removes them all for good",
+    },
+    CommandSpec {
+        name: "generations",
+        aliases: &[],
+        usage: "",
+        description: "lists the generations rodeo rollback can go back to: one for every deploy that changed something on this machine, and one for every rollback",
+    },
+    CommandSpec {
+        name: "rollback",
+        aliases: &[],
+        usage: "[<generation>]",
+        description: "puts every file rodeo deployed on this machine back the way the given generation (the one before the latest by default) had it, removing files deployed since that haven't changed after. Files it overwrites are backed up first. Your local dotfiles repo isn't touched, so the next deploy brings its versions back",
+    },
+    CommandSpec {
+        name: "tree",
+        aliases: &[],
+        usage: "",
+        description: "shows the managed files grouped by program, marking each as deployed, modified, missing (not on disk), not in repo or absent",
+    },
+    CommandSpec {
+        name: "status",
+        aliases: &[],
+        usage: "",
+        description: "lists every program with where it comes from (personal, base, or override for a base program changed in your own config) and how many of its files are deployed, modified, missing, not in repo or absent, then how each repository stands with git: its branch, commits ahead of or behind its remote, uncommitted changes and any merge or rebase in progress",
+    },
+    CommandSpec {
+        name: "watch",
+        aliases: &[],
+        usage: "",
+        description: "keeps running, keeping the states of every program's files on this machine up to date as they change, so that status and tree answer from them straight away instead of comparing every file. It reads the config again when it changes, and prints the files whose state changes as it goes. Stop it with ctrl-c",
+    },
+    CommandSpec {
+        name: "query",
+        aliases: &[],
+        usage: "<query>",
+        description: "answers a question about the programs, files or groups that would be acted on, e.g. 'programs[hosts=laptop].name', 'files[state=modified].deployed_file' or 'groups[name=desktop].programs'. Filters are field=value or field!=value, separated by commas; a list field matches if any element does. Prints one value per line, or one JSON value per line with --json",
+    },
+    CommandSpec {
+        name: "which",
+        aliases: &[],
+        usage: "<file>",
+        description: "shows which program manages file (a deployed path, repository path or file name), where it is stored in the repository and where it is deployed to",
+    },
+    CommandSpec {
+        name: "history",
+        aliases: &[],
+        usage: "<program> [<file> [<commit>]]",
+        description: "lists the commits (short hash, date and message) that changed program's files in its repository, or only file's (a deployed path, repository path or file name), newest first. Given a commit as well, prints the contents file had as of it, e.g. \"rodeo history nvim init.lua HEAD~3\". Needs vcs = \"git\"",
+    },
+    CommandSpec {
+        name: "revert",
+        aliases: &[],
+        usage: "<file> --to <commit> [--deploy]",
+        description: "puts the repository copy of a managed file (a deployed path, repository path or file name) back the way it was as of commit, and commits that (with --message's message if given). With --deploy the file is deployed as well. Nothing is pushed until the next sync. Needs vcs = \"git\"",
+    },
+    CommandSpec {
+        name: "grep",
+        aliases: &[],
+        usage: "<text>",
+        description: "searches the repository copies of all managed files (or those of the programs given with --program) for lines containing text",
+    },
+    CommandSpec {
+        name: "inspect",
+        aliases: &[],
+        usage: "<config-or-repo>",
+        description: "lists every file a config would write and every command it would run on this machine, on any host, without running or writing anything, e.g. to review a cloned repository of someone else's dotfiles before using it. Given a directory, the config is the rodeo.toml (or .yaml, .yml, .json) in it and the directory is taken to be its repository",
+    },
+    CommandSpec {
+        name: "export-drift",
+        aliases: &[],
+        usage: "",
+        description: "prints a patch, in git format-patch form, that makes your local dotfiles repo match the files deployed on this system; e.g. \"rodeo export-drift > drift.patch\" to carry changes from a machine that can't push",
+    },
+    CommandSpec {
+        name: "apply-drift",
+        aliases: &[],
+        usage: "<patch> [--commit]",
+        description: "applies a patch written by export-drift on another machine to your local dotfiles repo with git and shows which files changed. With --commit the patch is committed (with git am) as well. Deployed files are left alone until the next deploy or sync.",
+    },
+    CommandSpec {
+        name: "audit",
+        aliases: &[],
+        usage: "",
+        description: "shows every hook, git (or other vcs) and registry command rodeo has run on this machine, with its duration, exit status and output",
+    },
+    CommandSpec {
+        name: "migrate",
+        aliases: &[],
+        usage: "",
+        description: "rewrites your config file, and the files it includes, in the current version of the config format, keeping comments and formatting. Older configs are upgraded every time they are read anyway; this makes it permanent",
+    },
+    CommandSpec {
+        name: "version",
+        aliases: &[],
+        usage: "",
+        description: "prints rodeo's version, which requires_rodeo in a config is checked against",
+    },
+    CommandSpec {
+        name: "man",
+        aliases: &[],
+        usage: "",
+        description: "prints rodeo's man page (in roff format) to standard output",
+    },
+    CommandSpec {
+        name: "help",
+        aliases: &["h"],
+        usage: "",
+        description: "print this text",
+    },
+];
+
+pub const FLAGS: &[FlagSpec] = &[
+    FlagSpec {
+        name: "--version",
+        description: "same as the version command",
+    },
+    FlagSpec {
+        name: "--json",
+        description: "emit one JSON object per line (action, source, destination, result, error) instead of text",
+    },
+    FlagSpec {
+        name: "--format <format>",
+        description: "read the config file as toml, yaml or json instead of going by its extension",
+    },
+    FlagSpec {
+        name: "--plain",
+        description: "plain ASCII text without colors. Colors and unicode are already left out when the output isn't a terminal, TERM is dumb, CI or NO_COLOR is set, or the locale isn't UTF-8",
+    },
+    FlagSpec {
+        name: "--no-pager",
+        description: "print the output of plan, check, status, tree, query, which, grep and audit as it is, even if it is longer than the terminal is high. Otherwise it is shown through $PAGER (less by default)",
+    },
+    FlagSpec {
+        name: "--program <name>",
+        description: "only operate on the program called name; may be given more than once",
+    },
+    FlagSpec {
+        name: "--tag <tag>",
+        description: "only operate on the programs tagged tag; may be given more than once to select the programs carrying any of the tags. Combined with --program, only the named programs that carry a tag are selected",
+    },
+    FlagSpec {
+        name: "--profile <name>",
+        description: "use the config's [profile.<name>]: only operate on the programs it selects, with its env variables over those in [env]. Combined with --program and --tag, only programs selected by all of them are",
+    },
+    FlagSpec {
+        name: "--limit-rate <rate>",
+        description: "at most how fast files are copied and the repository is transferred, e.g. 10MB/s, instead of the config's limit_rate",
+    },
+    FlagSpec {
+        name: "--no-escalate",
+        description: "let deploy fail on files this user may not write, rather than offering to copy them through the config's escalate command",
+    },
+    FlagSpec {
+        name: "--jobs <n>",
+        description: "how many programs (or files of a single program) deploy, collect and sync work on at once, instead of the config's jobs",
+    },
+    FlagSpec {
+        name: "--host <name>",
+        description: "act as if this machine were called name when deciding which programs' hosts match",
+    },
+    FlagSpec {
+        name: "--include <pattern>",
+        description: "only operate on files matching pattern; may be given more than once. Patterns containing a / are matched against <program>/<path>, e.g. 'nvim/lua/**', others against the file name, e.g. '*.vim'. * and ? don't match /, ** matches any number of directories.",
+    },
+    FlagSpec {
+        name: "--exclude <pattern>",
+        description: "don't operate on files matching pattern, even if they match --include; may be given more than once",
+    },
+    FlagSpec {
+        name: "--dry-run",
+        description: "used with deploy, collect and sync: show what would be done, as rodeo plan does, instead of doing it",
+    },
+    FlagSpec {
+        name: "--skip-preflight",
+        description: "used with deploy: deploy even if some of the programs' paths match no file in the repository or on this system, which deploy otherwise checks for first and stops at",
+    },
+    FlagSpec {
+        name: "--local-only",
+        description: "used with sync: only sync files between the repository and this system, don't pull or push",
+    },
+    FlagSpec {
+        name: "--no-pull",
+        description: "used with sync: don't pull from the remote before syncing",
+    },
+    FlagSpec {
+        name: "--no-push",
+        description: "used with sync: don't commit and push to the remote after syncing",
+    },
+    FlagSpec {
+        name: "--message <message>",
+        description: "used with sync and revert: commit with this message instead of commit_message from [defaults], or revert's \"rodeo revert <file> to <commit>\"; -m for short",
+    },
+    FlagSpec {
+        name: "--branch <branch>",
+        description: "used with sync: pull from and push to this branch instead of the config's branch, checking it out first",
+    },
+    FlagSpec {
+        name: "--depth <n>",
+        description: "if remote has to be cloned, only fetch its last n commits instead of the config's clone_depth",
+    },
+    FlagSpec {
+        name: "--prefer-repo",
+        description: "used with sync: when a file was changed at the same time in your local dotfiles repo and on this system, keep the repo's copy instead of skipping it",
+    },
+    FlagSpec {
+        name: "--prefer-disk",
+        description: "used with sync: when a file was changed at the same time in your local dotfiles repo and on this system, keep the copy on this system instead of skipping it",
+    },
+    FlagSpec {
+        name: "--ignore-case",
+        description: "used with grep: match regardless of case",
+    },
+    FlagSpec {
+        name: "--scan",
+        description: "used with import: find dotfiles to import by looking for well-known configuration files",
+    },
+    FlagSpec {
+        name: "--yes",
+        description: "used with import, sync, deploy and prune: don't ask for confirmation before changing the config, deploying incoming changes or removing files no longer in the config. Required with --json.",
+    },
+    FlagSpec {
+        name: "--commit",
+        description: "used with apply-drift: commit the applied patch",
+    },
+    FlagSpec {
+        name: "--pre-rodeo",
+        description: "used with restore: restore the backup taken before rodeo's first deploy",
+    },
+    FlagSpec {
+        name: "--to-repo",
+        description: "used with restore: copy the backed up files into your local dotfiles repo instead of where they are deployed",
+    },
+    FlagSpec {
+        name: "--to <commit>",
+        description: "used with revert: the commit to put the file back to",
+    },
+    FlagSpec {
+        name: "--deploy",
+        description: "used with revert: deploy the file once it is put back, instead of leaving that to the next deploy or sync",
+    },
+];
+
+pub const CONFIG_KEYS: &[KeySpec] = &[
+    KeySpec {
+        table: "",
+        key: "dotfiles_directory",
+        kind: "string",
+        required: true,
+        description: "path to your local dotfiles repository; ~ is replaced with your home directory and environment variables are expanded (see ENVIRONMENT)",
+    },
+    KeySpec {
+        table: "",
+        key: "requires_rodeo",
+        kind: "string",
+        required: false,
+        description: "the versions of rodeo the config works with, e.g. \">=1.2\" or \">=1.2, <2\"; older or newer versions refuse to load it with an error saying which version it needs. A version without an operator means at least that version",
+    },
+    KeySpec {
+        table: "",
+        key: "version",
+        kind: "integer",
+        required: false,
+        description: "the version of the config format the config is written in; 1 if not set. Configs in older versions are upgraded as they are read, and rewritten with `rodeo migrate`",
+    },
+    KeySpec {
+        table: "",
+        key: "vcs",
+        kind: "string",
+        required: false,
+        description: "what keeps dotfiles_directory in sync with its remote: \"git\" (default), \"rsync\" or \"none\"",
+    },
+    KeySpec {
+        table: "",
+        key: "remote",
+        kind: "string",
+        required: false,
+        description: "where to clone dotfiles_directory from, e.g. \"git@github.com:me/dots.git\", if it isn't a repository yet. Every command but enable, disable and audit clones it first, so a new machine only needs rodeo.toml",
+    },
+    KeySpec {
+        table: "",
+        key: "branch",
+        kind: "string",
+        required: false,
+        description: "the branch sync pulls from and pushes to, checked out first if another one is, and the one cloning remote checks out; whichever is checked out (the remote's default branch when cloning) if not set",
+    },
+    KeySpec {
+        table: "",
+        key: "remotes",
+        kind: "array of strings",
+        required: false,
+        description: "the git remotes sync pushes to, e.g. [\"origin\", \"backup\"], each reported on its own so one failing doesn't stop the rest; only the remote the checked out branch follows if not set",
+    },
+    KeySpec {
+        table: "",
+        key: "rsync_target",
+        kind: "string",
+        required: false,
+        description: "where the rsync backend mirrors dotfiles_directory to, e.g. \"user@host:dotfiles/\"",
+    },
+    KeySpec {
+        table: "",
+        key: "clone_depth",
+        kind: "integer",
+        required: false,
+        description: "how many commits of history cloning remote fetches, e.g. 1 for only the latest; pulls then stay shallow by only fetching new commits; all of it if not set",
+    },
+    KeySpec {
+        table: "",
+        key: "clone_filter",
+        kind: "string",
+        required: false,
+        description: "the git partial clone filter cloning remote uses, e.g. \"blob:none\" to fetch file contents only as they're checked out, which later fetches keep to",
+    },
+    KeySpec {
+        table: "",
+        key: "lfs",
+        kind: "boolean",
+        required: false,
+        description: "whether sync has Git LFS track binary managed files before pushing, so they stay out of the repository's history; needs vcs = \"git\" and git-lfs installed",
+    },
+    KeySpec {
+        table: "",
+        key: "copy_method",
+        kind: "string",
+        required: false,
+        description: "how file contents are copied: \"std\" (default), \"buffered\" or \"io_uring\". io_uring needs Linux and a rodeo built with the io-uring feature, otherwise it behaves like buffered",
+    },
+    KeySpec {
+        table: "",
+        key: "copy_buffer_size",
+        kind: "integer",
+        required: false,
+        description: "size in bytes of the buffer used by the buffered and io_uring copy methods; 131072 by default",
+    },
+    KeySpec {
+        table: "",
+        key: "limit_rate",
+        kind: "string",
+        required: false,
+        description: "at most how fast files are copied and the repository is pulled, pushed and cloned, e.g. \"10MB/s\" or \"500K\"; K, M and G are multiples of 1024. Copies are buffered while it is set, rsync is given --bwlimit, and git is run through trickle, which has to be installed",
+    },
+    KeySpec {
+        table: "",
+        key: "jobs",
+        kind: "integer",
+        required: false,
+        description: "how many programs (or files of a single program) deploy, collect and sync work on at once; 1 by default. Each program's output is listed in one piece, in the usual order. Files are copied one at a time while limit_rate is set",
+    },
+    KeySpec {
+        table: "",
+        key: "escalate",
+        kind: "string",
+        required: false,
+        description: "the command deploy copies files this user may not write through, all in one go, e.g. \"doas\" or \"pkexec\"; sudo by default. --no-escalate turns it off for a run",
+    },
+    KeySpec {
+        table: "",
+        key: "umask",
+        kind: "string",
+        required: false,
+        description: "permission bits, in octal, that files and directories created by deploy, collect and sync never get, whatever the umask of the shell, e.g. \"077\" to make them private. Copied permissions lose them too; a program's mode still wins",
+    },
+    KeySpec {
+        table: "",
+        key: "trash",
+        kind: "string",
+        required: false,
+        description: "where the files deploy, collect, sync, unlink, prune, rollback and revert overwrite or remove go: \"rodeo\" (default) keeps them in ~/.local/share/rodeo/trash, \"freedesktop\" in the desktop's trash, where file managers can restore them too, and \"off\" throws them away. See rodeo trash",
+    },
+    KeySpec {
+        table: "",
+        key: "trash_days",
+        kind: "integer",
+        required: false,
+        description: "how many days files are kept in the trash, 30 if not set; 0 keeps them until rodeo trash empty",
+    },
+    KeySpec {
+        table: "",
+        key: "trash_size",
+        kind: "string",
+        required: false,
+        description: "at most how much the trash holds, e.g. \"500M\"; the oldest files are removed to stay under it. K, M and G are multiples of 1024. No limit if not set",
+    },
+    KeySpec {
+        table: "",
+        key: "layout",
+        kind: "string",
+        required: false,
+        description: "how files are arranged in dotfiles_directory: \"mirror-home\" (default) keeps them where they are relative to your home directory, \"per-program\" in a directory named after each program, and \"chezmoi\" like mirror-home but with leading dots written as dot_",
+    },
+    KeySpec {
+        table: "",
+        key: "strip_dots",
+        kind: "boolean",
+        required: false,
+        description: "store files and directories at the top of the home directory without their leading dot, e.g. .bashrc as bashrc and .config/nvim as config/nvim, adding it back when they are deployed; ignored with the chezmoi layout",
+    },
+    KeySpec {
+        table: "",
+        key: "deploy_mode",
+        kind: "string",
+        required: false,
+        description: "how deploy puts files in place: \"copy\" (default) copies them, \"symlink\" makes each one a symlink to its copy in dotfiles_directory, so editing it edits the repository, and \"hardlink\" a hard link to it, which must be on the same filesystem. `rodeo unlink` turns the links back into copies",
+    },
+    KeySpec {
+        table: "",
+        key: "base",
+        kind: "string",
+        required: false,
+        description: "a shared config, e.g. a team's, to build on. Its programs and groups come first and keep their files in its dotfiles_directory (or the directory it is in); a program or group of the same name in this config takes precedence, with a program's keys merged over the base's. sync pulls the base's repository but never pushes it",
+    },
+    KeySpec {
+        table: "",
+        key: "include",
+        kind: "array of strings",
+        required: false,
+        description: "more config files holding [[program]] and [group.<name>] tables, e.g. [\"programs/*.toml\"], relative to the directory of rodeo.toml. Every config file in the conf.d directory next to rodeo.toml is included too",
+    },
+    KeySpec {
+        table: "",
+        key: "env",
+        kind: "table of strings",
+        required: false,
+        description: "variables set for every hook, e.g. EDITOR = \"nvim\". Values can refer to environment variables and to each other; a variable referring to itself, as in PATH = \"$HOME/bin:$PATH\", gets its existing value",
+    },
+    KeySpec {
+        table: "",
+        key: "variables",
+        kind: "table of strings",
+        required: false,
+        description: "values to reuse across the config, e.g. font = \"Iosevka\", written $font or ${font} in dotfiles_directory, roots and paths like environment variables, which they take precedence over. Values can refer to environment variables. Hooks get them as environment variables, with [env] taking precedence",
+    },
+    KeySpec {
+        table: "",
+        key: "env_commands",
+        kind: "boolean",
+        required: false,
+        description: "let env values run commands with $(...), which are replaced by what they print",
+    },
+    KeySpec {
+        table: "defaults",
+        key: "shell",
+        kind: "string",
+        required: false,
+        description: "what hooks are run with, as <shell> -c <command>; \"bash\" if not set",
+    },
+    KeySpec {
+        table: "defaults",
+        key: "commit_message",
+        kind: "string",
+        required: false,
+        description: "what sync commits local changes with before pushing them; \"rodeo remote sync\" if not set. {programs}, {files}, {count}, {hostname} and {date} are replaced with the programs and files being committed, how many files there are, the machine's name and the time",
+    },
+    KeySpec {
+        table: "defaults",
+        key: "sign_commits",
+        kind: "boolean",
+        required: false,
+        description: "whether sync signs its commits, and the merges pull makes, with the key from git's user.signingkey and gpg.format; false if not set, unless signing_key is",
+    },
+    KeySpec {
+        table: "defaults",
+        key: "signing_key",
+        kind: "string",
+        required: false,
+        description: "the key sync signs commits with instead of git's user.signingkey: a gpg key id, or with gpg.format = \"ssh\" the path of an ssh key",
+    },
+    KeySpec {
+        table: "defaults",
+        key: "stash_before_pull",
+        kind: "boolean",
+        required: false,
+        description: "whether sync puts uncommitted changes in the repository aside (git stash) while it pulls, bringing them back afterwards; false if not set",
+    },
+    KeySpec {
+        table: "defaults",
+        key: "pull_strategy",
+        kind: "string",
+        required: false,
+        description: "how sync pulls when both the remote and this machine have new commits: \"merge\", \"rebase\" or \"ff-only\" (which fails instead); as git's pull.rebase and pull.ff say if not set",
+    },
+    KeySpec {
+        table: "defaults",
+        key: "backup",
+        kind: "boolean",
+        required: false,
+        description: "whether deploy archives the files it overwrites: the first deploy on a machine everything, for rodeo restore --pre-rodeo, and later ones every file that differs, in ~/.local/share/rodeo/backups; true if not set",
+    },
+    KeySpec {
+        table: "defaults",
+        key: "sync_direction",
+        kind: "string",
+        required: false,
+        description: "which copy sync-local keeps when a file exists on both sides: \"newest\" (the default), \"repo-to-system\" or \"system-to-repo\"",
+    },
+    KeySpec {
+        table: "program",
+        key: "name",
+        kind: "string",
+        required: true,
+        description: "name of the program the files belong to",
+    },
+    KeySpec {
+        table: "program",
+        key: "root",
+        kind: "string",
+        required: true,
+        description: "directory the program's files are deployed to, e.g. \"~/.config/nvim\", \"${XDG_CONFIG_HOME:-~/.config}/nvim\" or \"/etc/X11\" outside the home directory; environment variables are expanded",
+    },
+    KeySpec {
+        table: "program",
+        key: "paths",
+        kind: "array of strings and { src, dest, mode, direction, template } tables",
+        required: true,
+        description: "files, relative to root, that rodeo manages. Entries may be glob patterns such as \"*.vim\" or \"lua/**/*.lua\", which deploy expands against the repository, collect against root and every other command against both. Directories, such as \"lua/\", stand for every file below them. A file or directory kept in the repository under another name is written { src = \"bashrc\", dest = \".bashrc\" }; an absolute dest such as \"/etc/hosts\" deploys it there instead of below root. A file deployed with a mode of its own is written { src = \"config\", mode = \"600\" }. A file only ever copied one way is written { src = \"recently-used.xbel\", direction = \"deploy_only\" } (or \"collect_only\"); other files are \"bidirectional\". A template, which deploy renders with {{ host }}, {{ os }}, {{ user }}, {{ home }} and [variables] filled in and collect leaves alone, is written { src = \"gitconfig\", template = true }",
+    },
+    KeySpec {
+        table: "program",
+        key: "mode",
+        kind: "string",
+        required: false,
+        description: "permissions, in octal like chmod takes them, that deploy gives every file of the program unless its entry in paths has a mode of its own, e.g. \"600\". collect warns about files whose mode was changed since",
+    },
+    KeySpec {
+        table: "program",
+        key: "dotfiles_directory",
+        kind: "string",
+        required: false,
+        description: "a separate repository for this program's files (e.g. a private one for ssh or gpg configs) instead of the top level dotfiles_directory. sync pulls and pushes it with the same vcs; export-drift and apply-drift only cover the top level one",
+    },
+    KeySpec {
+        table: "program",
+        key: "repo_subdir",
+        kind: "string",
+        required: false,
+        description: "directory inside dotfiles_directory holding the program's files, e.g. \"nvim\"; defaults to where layout puts them",
+    },
+    KeySpec {
+        table: "program",
+        key: "layout",
+        kind: "string",
+        required: false,
+        description: "how the program's files are arranged in its repository, overriding layout at the top level; mostly useful with a dotfiles_directory of its own",
+    },
+    KeySpec {
+        table: "program",
+        key: "strip_dots",
+        kind: "boolean",
+        required: false,
+        description: "whether the program's files are stored without the leading dot of their top-level name, overriding strip_dots at the top level",
+    },
+    KeySpec {
+        table: "program",
+        key: "deploy_mode",
+        kind: "string",
+        required: false,
+        description: "how deploy puts the program's files in place, \"copy\", \"symlink\" or \"hardlink\", overriding deploy_mode at the top level",
+    },
+    KeySpec {
+        table: "program",
+        key: "symlinks",
+        kind: "string",
+        required: false,
+        description: "what deploy, collect and sync do with a file they copy that is a symlink: \"follow\" (default) copies what it points to, \"preserve\" copies the symlink itself, and \"refuse\" skips it with a warning",
+    },
+    KeySpec {
+        table: "program",
+        key: "flavor",
+        kind: "string",
+        required: false,
+        description: "\"unix\" (default) or \"windows\". Roots of windows programs may use %APPDATA%, %LOCALAPPDATA% and %USERPROFILE%, which are stored in the repository as AppData/Roaming, AppData/Local and your home directory respectively",
+    },
+    KeySpec {
+        table: "program",
+        key: "registry",
+        kind: "array of strings",
+        required: false,
+        description: "windows programs only: registry keys, e.g. \"HKCU\\Software\\SimonTatham\\PuTTY\", that collect exports to .reg files in the program's registry directory and deploy imports again; recorded in the audit log",
+    },
+    KeySpec {
+        table: "program",
+        key: "tags",
+        kind: "array of strings",
+        required: false,
+        description: "labels for selecting the program on the command line with --tag, e.g. [\"gui\", \"work\"]",
+    },
+    KeySpec {
+        table: "program",
+        key: "hosts",
+        kind: "array of strings",
+        required: false,
+        description: "host names of the machines the program is managed on, e.g. [\"laptop\", \"work-desktop\"]; on every other machine it is left alone. Empty (the default) means every machine",
+    },
+    KeySpec {
+        table: "program",
+        key: "os",
+        kind: "array of strings",
+        required: false,
+        description: "operating systems the program is managed on, e.g. [\"linux\", \"macos\"] (others are \"windows\", \"freebsd\", ...); on every other platform it is left alone. Empty (the default) means every platform",
+    },
+    KeySpec {
+        table: "program",
+        key: "when",
+        kind: "string",
+        required: false,
+        description: "condition this machine has to meet for the program to be managed, built from exists(path), command(name), env(NAME) and env(NAME=value) with !, &&, || and parentheses, e.g. \"command(sway) && !exists(~/.nosway)\"",
+    },
+    KeySpec {
+        table: "program",
+        key: "enabled",
+        kind: "boolean",
+        required: false,
+        description: "set to false to keep the program in the config without any command touching it; true by default. Changed by rodeo enable and rodeo disable",
+    },
+    KeySpec {
+        table: "program",
+        key: "post_deploy_cmd",
+        kind: "string",
+        required: false,
+        description: "command run through bash after the program's files are deployed; recorded in the audit log",
+    },
+    KeySpec {
+        table: "program",
+        key: "hook_when",
+        kind: "string",
+        required: false,
+        description: "when post_deploy_cmd runs: \"always\" (the default), \"changed\" to only run it if the deploy changes at least one of the program's files, or \"never-dry-run\", which is the same as always since dry runs never run hooks",
+    },
+    KeySpec {
+        table: "program",
+        key: "shell",
+        kind: "string",
+        required: false,
+        description: "what post_deploy_cmd is run with, overriding shell in [defaults]",
+    },
+    KeySpec {
+        table: "program",
+        key: "depends_on",
+        kind: "array of strings",
+        required: false,
+        description: "names of programs to deploy, and run the post_deploy_cmd of, before this one, e.g. [\"zsh\"]. Programs are otherwise handled in config order",
+    },
+    KeySpec {
+        table: "program",
+        key: "backup",
+        kind: "boolean",
+        required: false,
+        description: "whether deploy archives the program's files before overwriting them, overriding backup in [defaults]",
+    },
+    KeySpec {
+        table: "program",
+        key: "sync_direction",
+        kind: "string",
+        required: false,
+        description: "which copy of the program's files sync-local keeps, overriding sync_direction in [defaults]",
+    },
+    KeySpec {
+        table: "group.<name>",
+        key: "programs",
+        kind: "array of strings",
+        required: true,
+        description: "names of the programs in the group",
+    },
+    KeySpec {
+        table: "group.<name>",
+        key: "pre_deploy_cmd",
+        kind: "string",
+        required: false,
+        description: "command run through bash once before the first of the group's programs is deployed; recorded in the audit log",
+    },
+    KeySpec {
+        table: "group.<name>",
+        key: "post_deploy_cmd",
+        kind: "string",
+        required: false,
+        description: "command run through bash once after the last of the group's programs is deployed, e.g. to restart a compositor; recorded in the audit log",
+    },
+    KeySpec {
+        table: "group.<name>",
+        key: "hook_when",
+        kind: "string",
+        required: false,
+        description: "when pre_deploy_cmd and post_deploy_cmd run, as for programs; with \"changed\", they run if the deploy changes a file of any of the group's programs",
+    },
+    KeySpec {
+        table: "profile.<name>",
+        key: "programs",
+        kind: "array of strings",
+        required: false,
+        description: "names of the programs used with --profile name; all of them if not set",
+    },
+    KeySpec {
+        table: "profile.<name>",
+        key: "tags",
+        kind: "array of strings",
+        required: false,
+        description: "only use the programs carrying at least one of these tags with --profile name",
+    },
+    KeySpec {
+        table: "profile.<name>",
+        key: "env",
+        kind: "table of strings",
+        required: false,
+        description: "variables set for hooks with --profile name, replacing or adding to those in [env]",
+    },
+];
+
+pub const EXIT_CODES: &[(i32, &str)] = &[
+    (EXIT_OK, "success"),
+    (EXIT_SYNC_ERROR, "at least one file could not be copied or synced"),
+    (EXIT_CONFIG_ERROR, "the config file or command line could not be understood"),
+    (EXIT_VCS_ERROR, "at least one git (or other vcs) operation failed"),
+];
+
+// finds the command called (or aliased) name
+pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|c| c.name == name || c.aliases.contains(&name))
+}
+
+// the text printed by `rodeo help`
+pub fn help_text() -> String {
+    let mut text = String::new();
+
+    for command in COMMANDS {
+        let mut names = vec![command.name];
+        names.extend_from_slice(command.aliases);
+        let mut usage = names.join(" | ");
+        if !command.usage.is_empty() {
+            usage = format!("{} {}", usage, command.usage);
+        }
+        text.push_str(&format!("{}:\n\t{}\n\n", usage, command.description));
+    }
+
+    text.push_str("exit codes:\n");
+    for (code, description) in EXIT_CODES {
+        text.push_str(&format!("\t{}: {}\n", code, description));
+    }
+
+    text.push_str("\nflags:\n");
+    for flag in FLAGS {
+        text.push_str(&format!("{}:\n\t{}\n", flag.name, flag.description));
+    }
+
+    text
+}
+
+// escapes text for use in a roff document: backslashes and hyphens need escaping, and lines may
+// not begin with a control character
+fn roff_escape(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    if escaped.starts_with('.') || escaped.starts_with('\'') {
+        format!("\\&{}", escaped)
+    } else {
+        escaped
+    }
+}
+
+// the man page printed by `rodeo man`, in roff format
+pub fn man_page() -> String {
+    let mut page = String::new();
+
+    page.push_str(&format!(".TH RODEO 1 \"\" \"rodeo {}\" \"User Commands\"\n", env!("CARGO_PKG_VERSION")));
+    page.push_str(".SH NAME\nrodeo \\- dotfile manager configured with a single, simple .toml file\n");
+    page.push_str(".SH SYNOPSIS\n.B rodeo\n.I command\n[\\fIflags\\fR]\n");
+
+    page.push_str(".SH COMMANDS\n");
+    for command in COMMANDS {
+        let mut names = vec![command.name];
+        names.extend_from_slice(command.aliases);
+        let names: Vec<String> = names.iter().map(|n| format!("\\fB{}\\fR", roff_escape(n))).collect();
+        page.push_str(".TP\n");
+        page.push_str(&names.join(" | "));
+        if !command.usage.is_empty() {
+            page.push_str(&format!(" {}", roff_escape(command.usage)));
+        }
+        page.push_str(&format!("\n{}\n", roff_escape(command.description)));
+    }
+
+    page.push_str(".SH OPTIONS\n");
+    for flag in FLAGS {
+        page.push_str(&format!(".TP\n.B {}\n{}\n", roff_escape(flag.name), roff_escape(flag.description)));
+    }
+
+    page.push_str(".SH CONFIGURATION\n");
+    page.push_str("rodeo reads its configuration from the file named by \\fBRODEO_PATH\\fR, or else the first of \\fI$XDG_CONFIG_HOME/rodeo/rodeo.toml\\fR, \\fI~/.config/rodeo/rodeo.toml\\fR, \\fI~/.rodeo.toml\\fR and \\fI/etc/rodeo/rodeo.toml\\fR that exists (each also as rodeo.yaml, rodeo.yml or rodeo.json, which hold the same keys). Only that one file is read, along with what it includes and its base.\n");
+    let mut table = None;
+    for key in CONFIG_KEYS {
+        if table != Some(key.table) {
+            table = Some(key.table);
+            if key.table.is_empty() {
+                page.push_str(".SS Top level keys\n");
+            } else if key.table != "program" {
+                // plain and named tables, such as [defaults] and [group.<name>]
+                page.push_str(&format!(".SS [{}] keys\n", roff_escape(key.table)));
+            } else {
+                page.push_str(&format!(".SS [[{}]] keys\n", roff_escape(key.table)));
+            }
+        }
+        let required = if key.required { "required" } else { "optional" };
+        page.push_str(&format!(
+            ".TP\n.B {}\n({}, {}) {}\n",
+            roff_escape(key.key),
+            roff_escape(key.kind),
+            required,
+            roff_escape(key.description)
+        ));
+    }
+
+    page.push_str(".SH EXIT STATUS\n");
+    for (code, description) in EXIT_CODES {
+        page.push_str(&format!(".TP\n.B {}\n{}\n", code, roff_escape(description)));
+    }
+
+    page.push_str(".SH ENVIRONMENT\n");
+    page.push_str(".PP\n$NAME and ${NAME} in dotfiles_directory, root and paths are replaced with the environment variable's value, ${NAME:\\-default} with default if it is unset or empty, and $$ with $. Using a variable that isn't set without a default is an error.\n");
+    page.push_str(".TP\n.B HOSTNAME\nthe machine's name, matched against programs' hosts; /etc/hostname or the hostname command are used if unset\n");
+    page.push_str(".TP\n.B HOME\nthe user's home directory; ~ in the config file is replaced with it\n");
+    page.push_str(".TP\n.B RODEO_PATH\npath of the config file to use instead of looking for one\n");
+    page.push_str(".TP\n.B RODEO_PROFILE\nthe profile to use when --profile isn't given\n");
+    page.push_str(".TP\n.B XDG_CONFIG_HOME\nthe config file is looked for in $XDG_CONFIG_HOME/rodeo first, and new ones are created there\n");
+    page.push_str(".TP\n.B XDG_DATA_HOME\nrodeo keeps its state (such as backups, the audit log and the journal of the running operation) in $XDG_DATA_HOME/rodeo, or ~/.local/share/rodeo if unset\n");
+
+    page
+}
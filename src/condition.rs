@@ -0,0 +1,273 @@
+use std::env;
+use std::path::Path;
+use crate::paths;
+
+// a program's `when` condition, e.g. "command(sway) && !env(WAYLAND_DISPLAY)". The checks are
+//
+//   exists(path)     a file or directory exists at path (~ is the home directory)
+//   command(name)    an executable called name is on $PATH
+//   env(NAME)        the environment variable NAME is set and not empty
+//   env(NAME=value)  the environment variable NAME is set to value
+//
+// combined with !, && and || (in decreasing order of precedence) and parentheses. Arguments are
+// taken literally up to the closing parenthesis, and may be quoted.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    Literal(bool),
+    Exists(String),
+    Command(String),
+    Env(String, Option<String>),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        let mut parser = Parser { text, pos: 0 };
+        let condition = parser.or()?;
+        parser.skip_whitespace();
+        if parser.pos < text.len() {
+            return Err(format!("unexpected \"{}\" at position {}", &text[parser.pos..], parser.pos + 1));
+        }
+        Ok(condition)
+    }
+
+    // the names command checks look for on $PATH
+    pub fn commands(&self) -> Vec<String> {
+        match self {
+            Condition::Command(name) => vec![name.clone()],
+            Condition::Not(inner) => inner.commands(),
+            Condition::And(a, b) | Condition::Or(a, b) => a.commands().into_iter().chain(b.commands()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn eval(&self, home_dir: &str) -> bool {
+        match self {
+            Condition::Literal(val) => *val,
+            Condition::Exists(path) => {
+                Path::new(&paths::expand_home(path, home_dir)).exists()
+            }
+            Condition::Command(name) => command_exists(name),
+            Condition::Env(name, None) => env::var(name).map(|v| !v.is_empty()).unwrap_or(false),
+            Condition::Env(name, Some(value)) => env::var(name).map(|v| &v == value).unwrap_or(false),
+            Condition::Not(inner) => !inner.eval(home_dir),
+            Condition::And(a, b) => a.eval(home_dir) && b.eval(home_dir),
+            Condition::Or(a, b) => a.eval(home_dir) || b.eval(home_dir),
+        }
+    }
+}
+
+// whether name is an executable on $PATH, or a path to one
+fn command_exists(name: &str) -> bool {
+    if name.contains('/') {
+        return Path::new(name).is_file();
+    }
+
+    let path = env::var_os("PATH").unwrap_or_default();
+    env::split_paths(&path).any(|dir| {
+        let candidate = dir.join(name);
+        // on Windows, commands are found without their extension
+        candidate.is_file() || (cfg!(windows) && dir.join(format!("{}.exe", name)).is_file())
+    })
+}
+
+// recursive descent over the grammar
+//
+//   or      := and ("||" and)*
+//   and     := unary ("&&" unary)*
+//   unary   := "!" unary | primary
+//   primary := "(" or ")" | "true" | "false" | name "(" argument ")"
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut condition = self.and()?;
+        while self.eat("||") {
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut condition = self.unary()?;
+        while self.eat("&&") {
+            condition = Condition::And(Box::new(condition), Box::new(self.unary()?));
+        }
+        Ok(condition)
+    }
+
+    fn unary(&mut self) -> Result<Condition, String> {
+        if self.eat("!") {
+            return Ok(Condition::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Condition, String> {
+        if self.eat("(") {
+            let condition = self.or()?;
+            if !self.eat(")") {
+                return Err(format!("missing \")\" at position {}", self.pos + 1));
+            }
+            return Ok(condition);
+        }
+
+        self.skip_whitespace();
+        let start = self.pos;
+        let name_len = self.rest().find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(self.rest().len());
+        let name = &self.text[start..start + name_len];
+        self.pos += name_len;
+
+        match name {
+            "true" => return Ok(Condition::Literal(true)),
+            "false" => return Ok(Condition::Literal(false)),
+            "" => return Err(format!("expected a check such as exists(...) at position {}", start + 1)),
+            _ => (),
+        }
+
+        if !self.eat("(") {
+            return Err(format!("expected \"(\" after \"{}\" at position {}", name, self.pos + 1));
+        }
+        let end = match self.rest().find(')') {
+            Some(val) => val,
+            None => return Err(format!("missing \")\" after \"{}(\"", name)),
+        };
+        let argument = self.rest()[..end].trim().trim_matches(|c| c == '"' || c == '\'').to_owned();
+        self.pos += end + 1;
+        if argument.is_empty() {
+            return Err(format!("{}() needs an argument", name));
+        }
+
+        match name {
+            "exists" => Ok(Condition::Exists(argument)),
+            "command" => Ok(Condition::Command(argument)),
+            "env" => Ok(match argument.split_once('=') {
+                Some((var, value)) => Condition::Env(var.trim().to_owned(), Some(value.trim().to_owned())),
+                None => Condition::Env(argument, None),
+            }),
+            _ => Err(format!("unknown check \"{}\", expected exists, command or env", name)),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.pos += self.rest().len() - self.rest().trim_start().len();
+    }
+
+    // consumes token if it comes next (after any whitespace)
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(text: &str) -> Condition {
+        Condition::parse(text).unwrap()
+    }
+
+    fn not(c: Condition) -> Condition {
+        Condition::Not(Box::new(c))
+    }
+
+    fn and(a: Condition, b: Condition) -> Condition {
+        Condition::And(Box::new(a), Box::new(b))
+    }
+
+    fn or(a: Condition, b: Condition) -> Condition {
+        Condition::Or(Box::new(a), Box::new(b))
+    }
+
+    fn exists(path: &str) -> Condition {
+        Condition::Exists(path.to_owned())
+    }
+
+    #[test]
+    fn parses_checks() {
+        assert_eq!(parsed("exists(~/.config/sway)"), exists("~/.config/sway"));
+        assert_eq!(parsed("command( \"sway\" )"), Condition::Command("sway".to_owned()));
+        assert_eq!(parsed("env(TERM)"), Condition::Env("TERM".to_owned(), None));
+        assert_eq!(parsed("env(TERM = xterm)"), Condition::Env("TERM".to_owned(), Some("xterm".to_owned())));
+        assert_eq!(parsed(" true "), Condition::Literal(true));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_which_binds_tighter_than_or() {
+        assert_eq!(
+            parsed("exists(a) || !exists(b) && exists(c)"),
+            or(exists("a"), and(not(exists("b")), exists("c")))
+        );
+        assert_eq!(
+            parsed("exists(a) && exists(b) || exists(c)"),
+            or(and(exists("a"), exists("b")), exists("c"))
+        );
+        assert_eq!(parsed("!exists(a) || exists(b)"), or(not(exists("a")), exists("b")));
+        assert_eq!(parsed("!!exists(a)"), not(not(exists("a"))));
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        assert_eq!(
+            parsed("(exists(a) || exists(b)) && exists(c)"),
+            and(or(exists("a"), exists("b")), exists("c"))
+        );
+        assert_eq!(parsed("!(exists(a) && exists(b))"), not(and(exists("a"), exists("b"))));
+    }
+
+    #[test]
+    fn evaluates_with_the_same_precedence() {
+        assert!(parsed("true || false && false").eval("/home"));
+        assert!(!parsed("(true || false) && false").eval("/home"));
+        assert!(parsed("!false && true").eval("/home"));
+        assert!(!parsed("!(false || true)").eval("/home"));
+    }
+
+    #[test]
+    fn chains_combine_from_the_left() {
+        assert_eq!(
+            parsed("exists(a) || exists(b) || exists(c)"),
+            or(or(exists("a"), exists("b")), exists("c"))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_conditions() {
+        for text in [
+            "",
+            "exists",
+            "exists(a",
+            "exists()",
+            "(exists(a)",
+            "exists(a))",
+            "exists(a) &&",
+            "|| exists(a)",
+            "exists(a) | exists(b)",
+            "exists(a) exists(b)",
+            "!",
+            "maybe(a)",
+        ] {
+            assert!(Condition::parse(text).is_err(), "{:?} should not parse", text);
+        }
+    }
+
+    #[test]
+    fn lists_the_commands_it_looks_for() {
+        assert_eq!(parsed("command(sway) && !(env(X) || command(foot))").commands(), vec!["sway", "foot"]);
+    }
+}
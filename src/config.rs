@@ -0,0 +1,348 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use crate::event::OutputMode;
+use crate::copy;
+use crate::defaults::Preference;
+use crate::format::{self, ConfigFormat};
+use crate::SyncScope;
+
+// everything rodeo was told on the command line (and through the environment), as opposed to
+// Settings, which is everything read from rodeo.toml.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub home: String,
+    pub config_file_path: String,
+    // the config file's language if given with --format; otherwise its extension decides
+    pub format: Option<ConfigFormat>,
+    pub command: String,
+    // any further non-flag arguments, in order
+    pub args: Vec<String>,
+    pub output: OutputMode,
+    // only operate on the programs with these names; empty means all of them
+    pub programs: Vec<String>,
+    // only operate on the programs with at least one of these tags; empty means all of them
+    pub tags: Vec<String>,
+    // the machine's name, which programs' hosts are matched against
+    pub host: String,
+    // the [profile.<name>] to use; none if empty
+    pub profile: String,
+    // replaces the config's limit_rate if given
+    pub limit_rate: Option<String>,
+    // replaces the config's jobs if given
+    pub jobs: Option<usize>,
+    // only operate on files matching one of these patterns; empty means all of them
+    pub include: Vec<String>,
+    // never operate on files matching one of these patterns
+    pub exclude: Vec<String>,
+    // which phases `rodeo sync` runs
+    pub sync_scope: SyncScope,
+    // which copy sync keeps when both were changed at the same time
+    pub prefer: Option<Preference>,
+    // make text searches case insensitive
+    pub ignore_case: bool,
+    // restore the snapshot taken before rodeo's first deploy
+    pub pre_rodeo: bool,
+    // restore backups into the repository instead of where the files are deployed
+    pub to_repo: bool,
+    // the commit revert puts a file back to
+    pub to: Option<String>,
+    // deploy the file revert put back
+    pub deploy: bool,
+    // look for well-known dotfiles to import
+    pub scan: bool,
+    // don't ask for confirmation
+    pub yes: bool,
+    // commit what apply-drift applies
+    pub commit: bool,
+    // replaces the config's commit_message for sync if given
+    pub message: Option<String>,
+    // replaces the config's branch for sync and cloning if given
+    pub branch: Option<String>,
+    // replaces the config's clone_depth if given
+    pub depth: Option<u32>,
+    // show what deploy, collect or sync would do instead of doing it
+    pub dry_run: bool,
+    // plain ASCII text output without colors, whatever the terminal supports
+    pub plain: bool,
+    // deploy even if some paths match no file
+    pub skip_preflight: bool,
+    // print long output as it is rather than through $PAGER
+    pub no_pager: bool,
+    // fail on files this user may not write instead of copying them through escalate
+    pub no_escalate: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        // reads $HOME variable, returns home directory's location without a trailing slash; empty
+        // if it isn't set, which from_args reports
+        let home = env::var("HOME").unwrap_or_default();
+        // get config file path: $RODEO_PATH, or the first config file that exists (see
+        // config_candidates), or where a new one would go
+        let config_file_path = match env::var("RODEO_PATH") {
+            Ok(val) => val,
+            Err(_) => find_config(&home).unwrap_or_else(|| new_config_path(&home)),
+        };
+
+        Config {
+            home,
+            config_file_path,
+            format: None,
+            command: "none".to_owned(),
+            args: Vec::new(),
+            output: OutputMode::Text,
+            programs: Vec::new(),
+            tags: Vec::new(),
+            host: detect_hostname(),
+            profile: env::var("RODEO_PROFILE").unwrap_or_default(),
+            limit_rate: None,
+            jobs: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            sync_scope: SyncScope::full(),
+            prefer: None,
+            ignore_case: false,
+            pre_rodeo: false,
+            to_repo: false,
+            to: None,
+            deploy: false,
+            scan: false,
+            yes: false,
+            commit: false,
+            message: None,
+            branch: None,
+            depth: None,
+            dry_run: false,
+            plain: false,
+            skip_preflight: false,
+            no_pager: false,
+            no_escalate: false,
+        }
+    }
+}
+
+impl Config {
+    // builds a Config from the process' arguments. The first argument (the binary's name) must
+    // already have been removed. Flags may appear anywhere; the first non-flag argument is taken
+    // as the command and the rest are kept in args. Flags that take a value accept it either as
+    // the next argument or after an equals sign, e.g. "--program nvim" or "--program=nvim".
+    pub fn from_args<I: Iterator<Item = String>>(args: I) -> Result<Config, String> {
+        let mut config = Config::default();
+        if config.home.is_empty() {
+            return Err("could not get the path of your home directory; set HOME to it".to_owned());
+        }
+        let mut command: Option<String> = None;
+        let mut args = args;
+
+        while let Some(arg) = args.next() {
+            // split "--flag=value" into its two halves
+            let (flag, mut value) = match arg.find('=') {
+                Some(i) if arg.starts_with("--") => (arg[..i].to_owned(), Some(arg[i + 1..].to_owned())),
+                _ => (arg.clone(), None),
+            };
+
+            match &flag[..] {
+                "--json" => config.output = OutputMode::Json,
+                "--version" => command = Some("version".to_owned()),
+                "--pre-rodeo" => config.pre_rodeo = true,
+                "--to-repo" => config.to_repo = true,
+                "--deploy" => config.deploy = true,
+                "--scan" => config.scan = true,
+                "--yes" => config.yes = true,
+                "--commit" => config.commit = true,
+                "--dry-run" => config.dry_run = true,
+                "--plain" => config.plain = true,
+                "--skip-preflight" => config.skip_preflight = true,
+                "--no-pager" => config.no_pager = true,
+                "--no-escalate" => config.no_escalate = true,
+                "--ignore-case" => config.ignore_case = true,
+                "--local-only" => config.sync_scope = SyncScope::local_only(),
+                "--no-pull" => config.sync_scope.pull = false,
+                "--no-push" => config.sync_scope.push = false,
+                "--prefer-repo" | "--prefer-disk" => {
+                    let prefer = if flag == "--prefer-repo" { Preference::Repo } else { Preference::Disk };
+                    match config.prefer {
+                        Some(other) if other != prefer => return Err("flags \"--prefer-repo\" and \"--prefer-disk\" can't be used together".to_owned()),
+                        _ => config.prefer = Some(prefer),
+                    }
+                }
+                "--format" => {
+                    if value.is_none() {
+                        value = args.next();
+                    }
+                    match value.as_deref().map(ConfigFormat::from_name) {
+                        Some(Some(val)) => config.format = Some(val),
+                        Some(None) => return Err("flag \"--format\" must be toml, yaml or json".to_owned()),
+                        None => return Err("flag \"--format\" needs a format".to_owned()),
+                    }
+                }
+                "--message" | "-m" => {
+                    if value.is_none() {
+                        value = args.next();
+                    }
+                    match value {
+                        Some(val) if !val.trim().is_empty() => config.message = Some(val),
+                        _ => return Err(format!("flag \"{}\" needs a commit message", flag)),
+                    }
+                }
+                "--to" => {
+                    if value.is_none() {
+                        value = args.next();
+                    }
+                    match value {
+                        Some(val) if !val.trim().is_empty() => config.to = Some(val.trim().to_owned()),
+                        _ => return Err("flag \"--to\" needs a commit, e.g. HEAD~1".to_owned()),
+                    }
+                }
+                "--branch" => {
+                    if value.is_none() {
+                        value = args.next();
+                    }
+                    match value {
+                        Some(val) if !val.trim().is_empty() => config.branch = Some(val.trim().to_owned()),
+                        _ => return Err("flag \"--branch\" needs a branch name".to_owned()),
+                    }
+                }
+                "--host" => {
+                    if value.is_none() {
+                        value = args.next();
+                    }
+                    match value {
+                        Some(val) => config.host = val,
+                        None => return Err("flag \"--host\" needs a host name".to_owned()),
+                    }
+                }
+                "--profile" => {
+                    if value.is_none() {
+                        value = args.next();
+                    }
+                    match value {
+                        Some(val) => config.profile = val,
+                        None => return Err("flag \"--profile\" needs a profile name".to_owned()),
+                    }
+                }
+                "--limit-rate" => {
+                    if value.is_none() {
+                        value = args.next();
+                    }
+                    match value {
+                        Some(val) => match copy::parse_rate(&val) {
+                            Ok(_) => config.limit_rate = Some(val),
+                            Err(e) => return Err(format!("flag \"--limit-rate\": {}", e)),
+                        },
+                        None => return Err("flag \"--limit-rate\" needs a rate, e.g. 10MB/s".to_owned()),
+                    }
+                }
+                "--depth" => {
+                    if value.is_none() {
+                        value = args.next();
+                    }
+                    match value.map(|v| v.parse::<u32>()) {
+                        Some(Ok(n)) if n > 0 => config.depth = Some(n),
+                        Some(_) => return Err("flag \"--depth\" needs a number of commits to clone, at least 1".to_owned()),
+                        None => return Err("flag \"--depth\" needs a number, e.g. 1".to_owned()),
+                    }
+                }
+                "--jobs" => {
+                    if value.is_none() {
+                        value = args.next();
+                    }
+                    match value.map(|v| v.parse::<usize>()) {
+                        Some(Ok(n)) if n > 0 => config.jobs = Some(n),
+                        Some(_) => return Err("flag \"--jobs\" needs a number of programs or files to work on at once, at least 1".to_owned()),
+                        None => return Err("flag \"--jobs\" needs a number, e.g. 8".to_owned()),
+                    }
+                }
+                "--program" | "--tag" | "--include" | "--exclude" => {
+                    if value.is_none() {
+                        value = args.next();
+                    }
+                    let list = match &flag[..] {
+                        "--program" => &mut config.programs,
+                        "--tag" => &mut config.tags,
+                        "--include" => &mut config.include,
+                        _ => &mut config.exclude,
+                    };
+                    match value {
+                        Some(val) => list.push(val),
+                        None if flag == "--program" => return Err("flag \"--program\" needs a program name".to_owned()),
+                        None if flag == "--tag" => return Err("flag \"--tag\" needs a tag".to_owned()),
+                        None => return Err(format!("flag \"{}\" needs a pattern", flag)),
+                    }
+                }
+                flag if flag.starts_with("--") => return Err(format!("unknown flag \"{}\"", flag)),
+                _ => {
+                    if command.is_none() {
+                        command = Some(arg);
+                    } else {
+                        config.args.push(arg);
+                    }
+                }
+            }
+        }
+
+        if let Some(command) = command {
+            config.command = command;
+        }
+
+        Ok(config)
+    }
+}
+
+// the config files rodeo looks for when RODEO_PATH isn't set, from most to least specific:
+// $XDG_CONFIG_HOME/rodeo/rodeo.toml (if XDG_CONFIG_HOME is set), ~/.config/rodeo/rodeo.toml,
+// ~/.rodeo.toml and /etc/rodeo/rodeo.toml, each also as .yaml, .yml and .json after .toml. Only
+// the first that exists is read; a config can build on another one with base.
+pub fn config_candidates(home: &str) -> Vec<String> {
+    let home = home.trim_end_matches('/');
+    let mut stems = Vec::new();
+    match env::var("XDG_CONFIG_HOME") {
+        Ok(val) if !val.is_empty() => stems.push(format!("{}/rodeo/rodeo", val.trim_end_matches('/'))),
+        _ => (),
+    }
+    stems.push(format!("{}/.config/rodeo/rodeo", home));
+    stems.push(format!("{}/.rodeo", home));
+    stems.push("/etc/rodeo/rodeo".to_owned());
+
+    stems.iter()
+        .flat_map(|s| format::EXTENSIONS.iter().map(move |e| format!("{}.{}", s, e)))
+        .collect()
+}
+
+// the first of config_candidates that exists
+pub fn find_config(home: &str) -> Option<String> {
+    config_candidates(home).into_iter().find(|p| Path::new(p).is_file())
+}
+
+// where a new config file goes: rodeo.toml in $XDG_CONFIG_HOME/rodeo, or ~/.config/rodeo
+pub fn new_config_path(home: &str) -> String {
+    match env::var("XDG_CONFIG_HOME") {
+        Ok(val) if !val.is_empty() => format!("{}/rodeo/rodeo.toml", val.trim_end_matches('/')),
+        _ => format!("{}/.config/rodeo/rodeo.toml", home.trim_end_matches('/')),
+    }
+}
+
+// works out the name of this machine: $HOSTNAME (or %COMPUTERNAME% on Windows) if set, then
+// /etc/hostname, then whatever the hostname command says. Empty if all of them fail.
+pub fn detect_hostname() -> String {
+    for var in ["HOSTNAME", "COMPUTERNAME"] {
+        if let Ok(val) = env::var(var) {
+            if !val.trim().is_empty() {
+                return val.trim().to_owned();
+            }
+        }
+    }
+
+    if let Ok(val) = fs::read_to_string("/etc/hostname") {
+        if !val.trim().is_empty() {
+            return val.trim().to_owned();
+        }
+    }
+
+    match Command::new("hostname").output() {
+        Ok(val) if val.status.success() => String::from_utf8_lossy(&val.stdout).trim().to_owned(),
+        _ => String::new(),
+    }
+}
@@ -0,0 +1,376 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use serde::{Deserialize, Serialize};
+use crate::mode;
+
+// the buffer size used by the buffered and io_uring methods unless copy_buffer_size says otherwise
+pub const DEFAULT_BUFFER_SIZE: usize = 128 * 1024;
+
+// files at least this big are copied a buffer at a time when their progress is being reported,
+// whatever the copy method, so that a long copy can be followed and cancelled part way through
+pub const LARGE_FILE: u64 = 16 * 1024 * 1024;
+
+// how file contents are copied, chosen in rodeo.toml with e.g. `copy_method = "buffered"`. Std
+// (the default) leaves it to std::fs::copy, buffered reads and writes through a single buffer of
+// copy_buffer_size bytes, and io_uring does the same through an io_uring on Linux. io_uring is only
+// available when rodeo is built with the "io-uring" feature; otherwise, or if the kernel doesn't
+// support it, it falls back to buffered.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CopyMethod {
+    #[default]
+    Std,
+    Buffered,
+    IoUring,
+}
+
+// copies files using the configured method
+#[derive(Clone, Copy, Debug)]
+pub struct Copier {
+    pub method: CopyMethod,
+    pub buffer_size: usize,
+    // at most how many bytes a second are copied, from limit_rate; unlimited if None
+    pub rate_limit: Option<u64>,
+    // permission bits taken away from those copied from the source, from umask
+    pub umask: Option<u32>,
+    // the mode copies get instead of the source's, from a path's mode
+    pub mode: Option<u32>,
+    // told how copies of large files are getting on; None if nobody is watching
+    pub progress: Option<Progress>,
+}
+
+impl Default for Copier {
+    fn default() -> Self {
+        Copier {
+            method: CopyMethod::Std,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            rate_limit: None,
+            umask: None,
+            mode: None,
+            progress: None,
+        }
+    }
+}
+
+// how far a copy of a large file has got
+#[derive(Clone, Copy, Debug)]
+pub struct Transfer<'a> {
+    pub source: &'a str,
+    pub copied: u64,
+    pub total: u64,
+}
+
+// called after every buffer of a large file is written, from whichever thread is copying it. If
+// it returns false the copy stops there and fails with ErrorKind::Interrupted; replace then
+// removes what was written, leaving the destination as it was.
+#[derive(Clone, Copy)]
+pub struct Progress(pub &'static (dyn Fn(&Transfer) -> bool + Sync));
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Progress")
+    }
+}
+
+// a transfer rate such as "10MB/s", "500K" or "65536", in bytes per second. K, M and G are
+// multiples of 1024, like curl's --limit-rate takes them, and a trailing "B" or "/s" is optional.
+pub fn parse_rate(text: &str) -> Result<u64, String> {
+    let invalid = || format!("\"{}\" is not a transfer rate; use a number of bytes a second like \"10MB/s\" or \"500K\"", text);
+    let number = text.trim().trim_end_matches("/s").trim_end_matches(['B', 'b']);
+    let (number, unit) = match number.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&number[..i], c.to_ascii_uppercase()),
+        _ => (number, ' '),
+    };
+    let multiple: f64 = match unit {
+        ' ' => 1.0,
+        'K' => 1024.0,
+        'M' => 1024.0 * 1024.0,
+        'G' => 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(invalid()),
+    };
+    match number.trim().parse::<f64>() {
+        Ok(val) if val.is_finite() && val * multiple >= 1.0 => Ok((val * multiple) as u64),
+        _ => Err(invalid()),
+    }
+}
+
+// whether the file at path holds binary data rather than text, decided as git does: by a zero byte
+// among its first 8000. A file that can't be read isn't binary.
+pub fn is_binary(path: &str) -> bool {
+    let mut start = Vec::with_capacity(8000);
+    match File::open(path) {
+        Ok(file) => file.take(8000).read_to_end(&mut start).is_ok() && start.contains(&0),
+        Err(_) => false,
+    }
+}
+
+// whether the files at a and b have the same contents: the same length, and the same hash of what
+// is in them, read a block at a time so that large files aren't held in memory
+pub fn same_contents(a: &str, b: &str) -> bool {
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(x), Ok(y)) if x.is_file() && y.is_file() && x.len() == y.len() => (),
+        _ => return false,
+    }
+    match (hash(a), hash(b)) {
+        (Ok(x), Ok(y)) => x == y,
+        _ => false,
+    }
+}
+
+// a 64-bit FNV-1a hash of path's contents. Unlike std's hasher it is the same in every build of
+// rodeo, so it can be kept on disk.
+pub fn hash(path: &str) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut buffer = vec![0; DEFAULT_BUFFER_SIZE];
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => return Ok(hash),
+            Ok(read) => {
+                for byte in &buffer[..read] {
+                    hash = (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// where a file on its way to to is written before it is renamed over it
+pub fn partial_path(to: &str) -> String {
+    format!("{}.rodeo-partial", to)
+}
+
+impl Copier {
+    // replaces to with a copy of from without it ever being half written: the copy is made next to
+    // to, given its permissions, flushed to disk and only then renamed over to, so that to is
+    // either untouched or completely replaced, however rodeo is stopped
+    pub fn replace(&self, from: &str, to: &str) -> io::Result<u64> {
+        let partial = partial_path(to);
+        let _ = fs::remove_file(&partial);
+        let replaced = self.copy(from, &partial).and_then(|copied| {
+            flush(&partial)?;
+            fs::rename(&partial, to)?;
+            Ok(copied)
+        });
+        if replaced.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        replaced
+    }
+
+    // replaces to with contents the way replace replaces it with a copy of from, giving it from's
+    // permissions and modification time; for files made from from rather than copied, such as
+    // rendered templates
+    pub fn replace_with(&self, contents: &[u8], from: &str, to: &str) -> io::Result<u64> {
+        let source = fs::metadata(from)?;
+        let partial = partial_path(to);
+        let _ = fs::remove_file(&partial);
+        let replaced = fs::write(&partial, contents)
+            .and_then(|_| set_modified(&partial, source.modified()?))
+            .and_then(|_| fs::set_permissions(&partial, source.permissions()))
+            .and_then(|_| self.apply_mode(&partial))
+            .and_then(|_| flush(&partial))
+            .and_then(|_| fs::rename(&partial, to));
+        if replaced.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        replaced.map(|_| contents.len() as u64)
+    }
+
+    // replaces to with contents the same way, for contents with no file of their own, such as a
+    // file as of an earlier commit. to keeps its permissions and gets a fresh modification time, as
+    // if it had just been edited.
+    pub fn write(&self, contents: &[u8], to: &str) -> io::Result<u64> {
+        let existing = fs::metadata(to).ok();
+        let partial = partial_path(to);
+        let _ = fs::remove_file(&partial);
+        let replaced = fs::write(&partial, contents)
+            .and_then(|_| existing.map_or(Ok(()), |i| fs::set_permissions(&partial, i.permissions())))
+            .and_then(|_| self.apply_mode(&partial))
+            .and_then(|_| flush(&partial))
+            .and_then(|_| fs::rename(&partial, to));
+        if replaced.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        replaced.map(|_| contents.len() as u64)
+    }
+
+    // copies the contents, permissions and modification time of from to to, returning the number of
+    // bytes copied. Keeping the time means a copy doesn't look newer than what it was copied from,
+    // which sync goes by.
+    pub fn copy(&self, from: &str, to: &str) -> io::Result<u64> {
+        let source = fs::metadata(from)?;
+        let copied = match self.method {
+            // only buffered copies can be slowed down
+            _ if self.rate_limit.is_some() => self.copy_buffered(from, to)?,
+            // nor can fs::copy be followed or stopped part way
+            _ if self.progress.is_some() && source.len() >= LARGE_FILE => self.copy_buffered(from, to)?,
+            // fs::copy carries the permissions over itself
+            CopyMethod::Std => {
+                let copied = fs::copy(from, to)?;
+                set_modified(to, source.modified()?)?;
+                self.apply_mode(to)?;
+                return Ok(copied);
+            }
+            CopyMethod::Buffered => self.copy_buffered(from, to)?,
+            CopyMethod::IoUring => match self.copy_io_uring(from, to) {
+                Some(result) => result?,
+                None => self.copy_buffered(from, to)?,
+            },
+        };
+
+        // fs::copy carries the permissions over, so the other methods do too
+        set_modified(to, source.modified()?)?;
+        fs::set_permissions(to, source.permissions())?;
+        self.apply_mode(to)?;
+        Ok(copied)
+    }
+
+    // copying permissions sidesteps the process' umask, so the configured one is applied by hand,
+    // unless the copy is to have a mode of its own
+    fn apply_mode(&self, to: &str) -> io::Result<()> {
+        if let Some(wanted) = self.mode {
+            return mode::set(to, wanted);
+        }
+        match (self.umask, mode::get(to)) {
+            (Some(mask), Some(current)) if current & mask != 0 => mode::set(to, current & !mask),
+            _ => Ok(()),
+        }
+    }
+
+    fn copy_buffered(&self, from: &str, to: &str) -> io::Result<u64> {
+        let mut source = File::open(from)?;
+        let total = source.metadata()?.len();
+        let mut destination = File::create(to)?;
+        // with a rate limit, no more than a second's worth is copied at once
+        let buffer_size = match self.rate_limit {
+            Some(rate) => self.buffer_size.min(rate as usize),
+            None => self.buffer_size,
+        };
+        let mut buffer = vec![0; buffer_size.max(1)];
+        let mut copied = 0;
+        let started = Instant::now();
+
+        loop {
+            let read = match source.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(val) => val,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            destination.write_all(&buffer[..read])?;
+            copied += read as u64;
+
+            if let Some(Progress(report)) = self.progress {
+                if total >= LARGE_FILE && !report(&Transfer { source: from, copied, total }) {
+                    return Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"));
+                }
+            }
+
+            // wait until copying this much would have taken as long at the limit
+            if let Some(rate) = self.rate_limit {
+                let due = Duration::from_secs_f64(copied as f64 / rate as f64);
+                if let Some(wait) = due.checked_sub(started.elapsed()) {
+                    thread::sleep(wait);
+                }
+            }
+        }
+
+        Ok(copied)
+    }
+
+    // None if io_uring isn't available, in which case the caller falls back to another method
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    fn copy_io_uring(&self, from: &str, to: &str) -> Option<io::Result<u64>> {
+        use io_uring::{opcode, types, IoUring};
+        use std::os::unix::io::AsRawFd;
+
+        let mut ring = IoUring::new(2).ok()?;
+        let result = (|| {
+            let source = File::open(from)?;
+            let destination = File::create(to)?;
+            let mut buffer = vec![0u8; self.buffer_size.max(1)];
+            let mut offset = 0u64;
+
+            // submits a single operation and waits for its result
+            let mut run = |entry: io_uring::squeue::Entry| -> io::Result<usize> {
+                // safe because the buffer the entry points into outlives the operation, which is
+                // waited for before returning
+                unsafe {
+                    ring.submission()
+                        .push(&entry)
+                        .map_err(io::Error::other)?;
+                }
+                ring.submit_and_wait(1)?;
+                let result = ring.completion()
+                    .next()
+                    .ok_or_else(|| io::Error::other("io_uring returned no completion"))?
+                    .result();
+                if result < 0 {
+                    Err(io::Error::from_raw_os_error(-result))
+                } else {
+                    Ok(result as usize)
+                }
+            };
+
+            loop {
+                let read = run(opcode::Read::new(types::Fd(source.as_raw_fd()), buffer.as_mut_ptr(), buffer.len() as u32)
+                    .offset(offset)
+                    .build())?;
+                if read == 0 {
+                    break;
+                }
+
+                let mut written = 0;
+                while written < read {
+                    let n = run(opcode::Write::new(
+                        types::Fd(destination.as_raw_fd()),
+                        buffer[written..].as_ptr(),
+                        (read - written) as u32,
+                    )
+                    .offset(offset + written as u64)
+                    .build())?;
+                    if n == 0 {
+                        return Err(io::Error::from(io::ErrorKind::WriteZero));
+                    }
+                    written += n;
+                }
+                offset += read as u64;
+            }
+
+            Ok(offset)
+        })();
+
+        Some(result)
+    }
+
+    #[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+    fn copy_io_uring(&self, _from: &str, _to: &str) -> Option<io::Result<u64>> {
+        None
+    }
+}
+
+// sets the modification time of the file at path. Only its owner needs to open it to do that on
+// unix, so read-only files work too; Windows wants it opened for writing.
+pub fn set_modified(path: &str, time: SystemTime) -> io::Result<()> {
+    let file = if cfg!(unix) { File::open(path)? } else { OpenOptions::new().write(true).open(path)? };
+    file.set_modified(time)
+}
+
+// makes sure what was written to path is on disk, not just in the page cache, so a crash right
+// after it is renamed into place can't leave an empty file behind
+#[cfg(unix)]
+fn flush(path: &str) -> io::Result<()> {
+    File::open(path)?.sync_all()
+}
+
+// Windows only flushes files opened for writing, which a read-only copy can't be
+#[cfg(not(unix))]
+fn flush(_path: &str) -> io::Result<()> {
+    Ok(())
+}
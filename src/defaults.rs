@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use crate::{is_default, Program};
+use crate::vcs::PullStrategy;
+
+// behaviour that used to be hard-coded, set for the whole config in its [defaults] table. Programs
+// can override shell, backup and sync_direction with keys of the same name.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Defaults {
+    // what hooks are run with, as <shell> -c <command>; bash if empty
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub shell: String,
+    // what sync commits local changes with before pushing them
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub commit_message: String,
+    // whether sync's commits are signed, as git's own config says (gpg.format, user.signingkey)
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sign_commits: Option<bool>,
+    // the key sync signs its commits with instead of user.signingkey; implies sign_commits
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub signing_key: String,
+    // how sync's pulls bring in the remote's commits when both sides have new ones; git's own
+    // config (pull.rebase, pull.ff) decides if it isn't set
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pull_strategy: Option<PullStrategy>,
+    // whether sync puts uncommitted changes in the repository aside while it pulls
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_default")]
+    pub stash_before_pull: bool,
+    // whether the first deploy on a machine archives the files it overwrites; see
+    // backup::snapshot_pre_rodeo
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup: Option<bool>,
+    // which copy sync-local keeps when both have been changed
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_default")]
+    pub sync_direction: SyncDirection,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncDirection {
+    // whichever was modified last, as sync-local has always done
+    #[default]
+    Newest,
+    // the repository's copy, as if deploying
+    RepoToSystem,
+    // the deployed copy, as if collecting
+    SystemToRepo,
+}
+
+// which copy of a file sync keeps when it can't tell which is newer, chosen on the command line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Preference {
+    // the repository's copy, from --prefer-repo
+    Repo,
+    // the deployed copy, from --prefer-disk
+    Disk,
+}
+
+pub const DEFAULT_SHELL: &str = "bash";
+pub const DEFAULT_COMMIT_MESSAGE: &str = "rodeo remote sync";
+
+impl Defaults {
+    // the shell program's hooks run with, or the one group hooks run with if program is None
+    pub fn shell<'a>(&'a self, program: Option<&'a Program>) -> &'a str {
+        match program {
+            Some(program) if !program.shell.is_empty() => &program.shell,
+            _ if !self.shell.is_empty() => &self.shell,
+            _ => DEFAULT_SHELL,
+        }
+    }
+
+    pub fn commit_message(&self) -> &str {
+        if self.commit_message.is_empty() { DEFAULT_COMMIT_MESSAGE } else { &self.commit_message }
+    }
+
+    // None if sync's commits aren't signed, otherwise the key they're signed with, which is empty
+    // for the one git is configured to use
+    pub fn signing(&self) -> Option<&str> {
+        match self.sign_commits {
+            Some(false) => None,
+            Some(true) => Some(self.signing_key.trim()),
+            None if !self.signing_key.trim().is_empty() => Some(self.signing_key.trim()),
+            None => None,
+        }
+    }
+
+    pub fn backup(&self, program: &Program) -> bool {
+        program.backup.or(self.backup).unwrap_or(true)
+    }
+
+    pub fn sync_direction(&self, program: &Program) -> SyncDirection {
+        program.sync_direction.unwrap_or(self.sync_direction)
+    }
+}
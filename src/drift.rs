@@ -0,0 +1,82 @@
+use std::process::Command;
+use crate::mapping::{FileState, Target};
+use crate::state;
+
+// a patch, in the format git format-patch produces, that turns the repository's copies of targets
+// into what is currently deployed. It can be applied to the dotfiles directory on another machine
+// with git am (or git apply, which ignores the mail headers). Empty if nothing has drifted.
+pub fn drift_patch(targets: &[Target], dotfiles_dir: &str, host: &str) -> String {
+    let mut diffs = String::new();
+    let mut drifted = Vec::new();
+
+    for target in targets {
+        // a rendered template's changes can't go back into the template
+        if target.template.is_some() || !matches!(target.state(), FileState::Modified | FileState::NotInRepo) {
+            continue;
+        }
+        let relative = match target.repo_relative(dotfiles_dir) {
+            Some(val) => val,
+            None => continue,
+        };
+        if let Some(diff) = file_diff(target, dotfiles_dir, relative) {
+            diffs.push_str(&diff);
+            drifted.push(relative.to_owned());
+        }
+    }
+
+    if drifted.is_empty() {
+        return String::new();
+    }
+
+    let host = if host.is_empty() { "localhost" } else { host };
+    let mut patch = String::new();
+    patch.push_str("From 0000000000000000000000000000000000000000 Mon Sep 17 00:00:00 2001\n");
+    patch.push_str(&format!("From: rodeo <rodeo@{}>\n", host));
+    patch.push_str(&format!("Date: {} +0000\n", state::format_timestamp(state::now())));
+    patch.push_str(&format!("Subject: [PATCH] rodeo drift from {}\n\n", host));
+    patch.push_str(&format!("{} file(s) deployed on {} differ from the repository:\n\n", drifted.len(), host));
+    for path in drifted.iter() {
+        patch.push_str(&format!("  {}\n", path));
+    }
+    patch.push_str("---\n");
+    patch.push_str(&diffs);
+    patch.push_str(&format!("-- \nrodeo {}\n", env!("CARGO_PKG_VERSION")));
+    patch
+}
+
+// the diff from target's repository copy to its deployed copy, with both sides named after the
+// file's path in the repository so that the patch applies there
+fn file_diff(target: &Target, dotfiles_dir: &str, relative: &str) -> Option<String> {
+    // run from the repository so that side of the diff is already named relative to it
+    let from = if target.state() == FileState::NotInRepo { "/dev/null" } else { relative };
+    let output = Command::new("git")
+        .current_dir(dotfiles_dir)
+        .args(["diff", "--no-index", "--no-color", "--binary", "--", from, &target.deployed_file])
+        .output()
+        .ok()?;
+    if output.stdout.is_empty() {
+        return None;
+    }
+
+    // the deployed side shows up under its absolute path (without the leading slash), which only
+    // needs replacing in the header lines
+    let deployed = target.deployed_file.trim_start_matches('/');
+    let diff = String::from_utf8_lossy(&output.stdout);
+    let mut in_header = true;
+    let mut fixed = String::new();
+    for line in diff.lines() {
+        if line.starts_with("@@") || line.starts_with("GIT binary patch") {
+            in_header = false;
+        } else if line.starts_with("diff --git ") {
+            in_header = true;
+        }
+
+        if in_header {
+            fixed.push_str(&line.replace(deployed, relative));
+        } else {
+            fixed.push_str(line);
+        }
+        fixed.push('\n');
+    }
+    Some(fixed)
+}
@@ -0,0 +1,76 @@
+// changes to rodeo.toml made by rodeo itself. These go through toml_edit rather than
+// re-serializing Settings, so the user's comments, ordering and formatting survive.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use toml_edit::DocumentMut;
+use crate::copy;
+
+// a fingerprint of a config file's contents, taken when it is read so a later rewrite can tell
+// whether someone else changed the file in the meantime
+pub fn content_hash(contents: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+// rewrites the file at path with whatever edit makes of its contents. If expected is given and the
+// file no longer hashes to it, someone edited the file after rodeo read it and nothing is written,
+// rather than clobbering their changes. The new contents are written next to the file and renamed
+// over it, so the file is never left half written.
+pub fn rewrite<F>(path: &str, expected: Option<u64>, edit: F) -> Result<(), String>
+where
+    F: FnOnce(&str) -> Result<String, String>,
+{
+    let changed = || format!("\"{}\" was changed since rodeo read it; run the command again to apply it to the new contents", path);
+
+    let contents = fs::read_to_string(path).map_err(|e| format!("could not read \"{}\": {}", path, e))?;
+    if expected.is_some_and(|hash| hash != content_hash(&contents)) {
+        return Err(changed());
+    }
+    let edited = edit(&contents)?;
+
+    let partial = copy::partial_path(path);
+    let written = fs::write(&partial, edited)
+        .and_then(|_| fs::metadata(path))
+        .and_then(|m| fs::set_permissions(&partial, m.permissions()));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&partial);
+        return Err(format!("could not write \"{}\": {}", partial, e));
+    }
+
+    // the file may have been saved by an editor while the new contents were being worked out
+    if fs::read_to_string(path).map(|c| c != contents).unwrap_or(true) {
+        let _ = fs::remove_file(&partial);
+        return Err(changed());
+    }
+    fs::rename(&partial, path).map_err(|e| format!("could not replace \"{}\": {}", path, e))
+}
+
+// sets key to value (which must already be valid TOML, e.g. "false" or "\"text\"") in the
+// [[program]] table of the program called name, replacing the key's existing value if there is one
+// and otherwise adding it to the end of the table.
+pub fn set_program_key(contents: &str, name: &str, key: &str, value: &str) -> Result<String, String> {
+    let mut document: DocumentMut = contents.parse().map_err(|e| format!("could not parse the config: {}", e))?;
+    let value: toml_edit::Value = value.parse().map_err(|e| format!("invalid value \"{}\": {}", value, e))?;
+
+    let program = document.get_mut("program")
+        .and_then(|p| p.as_array_of_tables_mut())
+        .and_then(|p| p.iter_mut().find(|t| t.get("name").and_then(|n| n.as_str()) == Some(name)))
+        .ok_or_else(|| format!("no program named \"{}\" in the config", name))?;
+
+    match program.get_mut(key).and_then(|i| i.as_value_mut()) {
+        // keep whatever comment or spacing surrounds the old value
+        Some(existing) => {
+            let decor = existing.decor().clone();
+            *existing = value;
+            *existing.decor_mut() = decor;
+        }
+        None => {
+            program.insert(key, toml_edit::Item::Value(value));
+        }
+    }
+
+    Ok(document.to_string())
+}
@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+use crate::audit::{self, AuditLog};
+use crate::expand;
+
+// works out the values of the config's [env] table, which hooks run with. Values are expanded like
+// the rest of the config, except that they can also refer to the table's other variables; a
+// variable referring to itself, as in PATH = "$HOME/.local/bin:$PATH", gets the value rodeo was
+// started with. With commands set, $(command) is replaced by what command prints, without trailing
+// newlines. Commands run first, with rodeo's own environment, and are recorded in audit. Without
+// commands set they are an error, so that a config never runs anything it wasn't allowed to.
+pub fn resolve(vars: &BTreeMap<String, String>, commands: bool, audit: &AuditLog) -> Result<Vec<(String, String)>, String> {
+    let mut substituted = BTreeMap::new();
+    for (name, value) in vars {
+        substituted.insert(name.clone(), substitute_commands(name, value, commands, audit)?);
+    }
+
+    let mut resolved = BTreeMap::new();
+    for name in vars.keys() {
+        resolve_one(name, &substituted, &mut resolved, &mut Vec::new())?;
+    }
+    Ok(resolved.into_iter().collect())
+}
+
+fn resolve_one(
+    name: &str,
+    vars: &BTreeMap<String, String>,
+    resolved: &mut BTreeMap<String, String>,
+    stack: &mut Vec<String>,
+) -> Result<String, String> {
+    if let Some(val) = resolved.get(name) {
+        return Ok(val.clone());
+    }
+    if stack.iter().any(|s| s == name) {
+        return Err(format!("env variables refer to each other in a circle: {} -> {}", stack.join(" -> "), name));
+    }
+    stack.push(name.to_owned());
+
+    let mut failed = None;
+    let value = expand::expand_with(&vars[name], &mut |other: &str| {
+        if other == name || !vars.contains_key(other) {
+            return expand::lookup(other);
+        }
+        match resolve_one(other, vars, resolved, stack) {
+            Ok(val) => Some(val),
+            Err(e) => {
+                failed = Some(e);
+                Some(String::new())
+            }
+        }
+    });
+    if let Some(e) = failed {
+        return Err(e);
+    }
+    let value = value.map_err(|e| format!("env variable \"{}\": {}", name, e))?;
+
+    stack.pop();
+    resolved.insert(name.to_owned(), value.clone());
+    Ok(value)
+}
+
+// value with every $(command) replaced by the command's output. $$ is left for expand_vars to turn
+// into a single $, and any $ in the output is doubled so that it isn't taken for a variable.
+fn substitute_commands(name: &str, value: &str, commands: bool, audit: &AuditLog) -> Result<String, String> {
+    let mut substituted = String::new();
+    let mut rest = value;
+
+    while let Some(found) = split_command(rest) {
+        let (before, command, after) = found.map_err(|_| format!("env variable \"{}\" has a \"$(\" without a matching \")\"", name))?;
+        if !commands {
+            return Err(format!("env variable \"{}\" runs a command with $(...); set env_commands = true to allow that", name));
+        }
+        substituted.push_str(before);
+
+        let output = audit.run("env", command).map_err(|e| format!("could not run \"{}\" for env variable \"{}\": {}", command, name, e))?;
+        if !output.status.success() {
+            return Err(format!("\"{}\" for env variable \"{}\" failed ({})", command, name, audit::describe_failure(&output)));
+        }
+        substituted.push_str(&String::from_utf8_lossy(&output.stdout).trim_end_matches('\n').replace('$', "$$"));
+        rest = after;
+    }
+
+    substituted.push_str(rest);
+    Ok(substituted)
+}
+
+// the commands value would run with $(...), without running them
+pub fn commands(value: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut rest = value;
+    while let Some(Ok((_, command, after))) = split_command(rest) {
+        found.push(command.to_owned());
+        rest = after;
+    }
+    found
+}
+
+// splits text around its first $(command): the text before it, the command and the text after it.
+// $$ is an escaped $ rather than the start of one. Err if the command's ")" is missing.
+fn split_command(text: &str) -> Option<Result<(&str, &str, &str), ()>> {
+    let mut from = 0;
+    while let Some(i) = text[from..].find('$') {
+        let start = from + i;
+        let rest = &text[start..];
+        if rest.starts_with("$$") {
+            from = start + 2;
+            continue;
+        }
+        let inner = match rest.strip_prefix("$(") {
+            Some(val) => val,
+            None => {
+                from = start + 1;
+                continue;
+            }
+        };
+
+        // commands can have parentheses of their own
+        let mut depth = 1;
+        let end = inner.char_indices().find(|(_, c)| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => (),
+            }
+            depth == 0
+        });
+        return Some(match end {
+            Some((end, _)) => Ok((&text[..start], &inner[..end], &inner[end + 1..])),
+            None => Err(()),
+        });
+    }
+    None
+}
@@ -0,0 +1,56 @@
+use std::process::{Command, Stdio};
+use crate::audit::AuditLog;
+use crate::copy;
+use crate::mapping::Target;
+use crate::mode;
+
+// what copies to files rodeo may not write go through unless escalate says otherwise
+pub const DEFAULT_HELPER: &str = "sudo";
+
+// copies each file it is given over its destination, through a partial file like Copier::replace,
+// creating missing directories and giving it a mode if one follows. Arguments come in threes:
+// from, to and mode, which is empty to keep the one cp gives it.
+const SCRIPT: &str = r#"while [ $# -gt 0 ]; do
+    mkdir -p -- "$(dirname -- "$2")" &&
+    cp -- "$1" "$2.rodeo-partial" &&
+    { [ -z "$3" ] || chmod "$3" "$2.rodeo-partial"; } &&
+    mv -f -- "$2.rodeo-partial" "$2" || { rm -f -- "$2.rodeo-partial"; exit 1; }
+    shift 3
+done"#;
+
+// whether target can only be deployed through the helper: it isn't in place yet, and can't be put
+// there by this user
+pub fn needed(target: &Target) -> bool {
+    target.template.is_none()
+        && !copy::same_contents(&target.repo_file, &target.deployed_file)
+        && !mode::writable(&target.deployed_file)
+}
+
+// copies every target's repository file over its deployed one with a single run of helper (e.g.
+// "sudo" or "doas -n"), so it asks for a password once at most. The helper gets the terminal to
+// ask on, and is logged like any other command.
+pub fn copy_all(helper: &str, targets: &[Target], audit: &AuditLog) -> Result<(), String> {
+    let mut words = helper.split_whitespace();
+    let program = match words.next() {
+        Some(val) => val,
+        None => return Err("escalate is empty; set it to a command such as \"sudo\", or use --no-escalate".to_owned()),
+    };
+    let mut command = Command::new(program);
+    command.args(words).args(["sh", "-c", SCRIPT, "sh"]);
+    for target in targets {
+        let mode = mode::parse(&target.mode).map(mode::format).unwrap_or_default();
+        command.arg(&target.repo_file).arg(&target.deployed_file).arg(mode);
+    }
+    command.stdin(Stdio::inherit());
+
+    let description = format!("{} cp ({} files)", helper, targets.len());
+    match audit.run_command("escalate", &description, &mut command) {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => Err(format!(
+            "\"{}\" could not copy the files: {}",
+            helper,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Err(e) => Err(format!("could not run \"{}\": {}", program, e)),
+    }
+}
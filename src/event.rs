@@ -0,0 +1,320 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use serde::Serialize;
+use crate::copy::{Progress, Transfer};
+use crate::manifest::Orphan;
+use crate::mapping::Target;
+use crate::preview::IncomingChange;
+use crate::term::{Style, Terminal};
+use crate::Program;
+
+// how rodeo reports what it is doing. Text is the classic human-readable output, Json emits a
+// single JSON object per line so rodeo can be driven from scripts.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputMode {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventResult {
+    Ok,
+    // there was nothing to do, e.g. a file that is already identical to what would be copied
+    Unchanged,
+    Skipped,
+    Error,
+}
+
+// what an event is about: a managed file, a program's hook, or the vcs backend
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    File,
+    Hook,
+    Vcs,
+}
+
+// a single thing rodeo did (or tried to do). Every piece of output produced while running a
+// command goes through one of these rather than straight to println!, so that it can be rendered
+// either as text or as JSON.
+#[derive(Clone, Debug, Serialize)]
+pub struct Event {
+    pub kind: EventKind,
+    pub action: String,
+    pub source: String,
+    pub destination: String,
+    pub result: EventResult,
+    pub error: Option<String>,
+    // captured output of external commands (git, hooks), if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+}
+
+impl Event {
+    pub fn new<T: Into<String>>(kind: EventKind, action: T, source: T, destination: T) -> Event {
+        Event {
+            kind,
+            action: action.into(),
+            source: source.into(),
+            destination: destination.into(),
+            result: EventResult::Ok,
+            error: None,
+            output: None,
+        }
+    }
+
+    pub fn unchanged(mut self) -> Event {
+        self.result = EventResult::Unchanged;
+        self
+    }
+
+    pub fn skipped<T: Into<String>>(mut self, reason: T) -> Event {
+        self.result = EventResult::Skipped;
+        self.error = Some(reason.into());
+        self
+    }
+
+    pub fn failed<T: Into<String>>(mut self, reason: T) -> Event {
+        self.result = EventResult::Error;
+        self.error = Some(reason.into());
+        self
+    }
+
+    pub fn with_output<T: Into<String>>(mut self, output: T) -> Event {
+        self.output = Some(output.into());
+        self
+    }
+
+    // prints the event in the requested output mode
+    pub fn emit(&self, mode: OutputMode) {
+        match mode {
+            OutputMode::Text => println!("{}", self.to_text()),
+            OutputMode::Json => match serde_json::to_string(self) {
+                Ok(val) => println!("{}", val),
+                Err(e) => eprintln!("could not serialize event: {}", e),
+            },
+        }
+    }
+
+    // the human readable form of the event, matching rodeo's traditional output
+    pub fn to_text(&self) -> String {
+        // external command output is shown as-is, same as before events existed
+        if let Some(output) = &self.output {
+            return match (&self.result, &self.error) {
+                (EventResult::Error, Some(reason)) if output.trim().is_empty() => reason.clone(),
+                (EventResult::Error, Some(reason)) => format!("{}\n{}", output, reason),
+                _ => output.clone(),
+            };
+        }
+
+        match (&self.result, &self.error) {
+            (EventResult::Ok, _) if self.destination.is_empty() => format!("{} {}", self.action, self.source),
+            (EventResult::Ok, _) if self.action == "merge" => format!("{} <=> {} (merged)", self.source, self.destination),
+            (EventResult::Ok, _) if self.action == "render" => format!("{} => {} (rendered)", self.source, self.destination),
+            (EventResult::Ok, _) => format!("{} => {}", self.source, self.destination),
+            (EventResult::Unchanged, _) => format!("unchanged {}", self.destination),
+            (_, Some(reason)) => reason.clone(),
+            (_, None) => format!("{} \"{}\" failed", self.action, self.source),
+        }
+    }
+}
+
+// receives everything rodeo does while running an operation, as it happens. The CLI's text and
+// JSON output are both implemented as observers; front-ends embedding rodeo can provide their own
+// to render progress however they like.
+pub trait Observer {
+    // called before any of a program's files are handled
+    fn program_started(&mut self, _program: &Program) {}
+    // called right before a program's post-deploy command is run
+    fn hook_started(&mut self, _program: &Program) {}
+    // called right before one of a group's hooks ("pre-deploy" or "post-deploy") is run
+    fn group_hook_started(&mut self, _group: &str, _hook: &str) {}
+    // called for every file action, hook and vcs step once it has finished
+    fn event(&mut self, event: &Event);
+    // called when a sync is about to pull in changes that will overwrite deployed files. Returning
+    // false cancels the sync before anything is pulled.
+    fn confirm_incoming(&mut self, _changes: &[IncomingChange]) -> bool {
+        true
+    }
+    // called at the end of a deploy that found files it deployed before which the config no
+    // longer has. Returning true removes them; removing files needs someone to agree to it, so
+    // unless asked they stay.
+    fn confirm_prune(&mut self, _orphans: &[Orphan]) -> bool {
+        false
+    }
+    // called during a deploy with the files of a program that this user may not write, before
+    // they are copied through helper (e.g. sudo). Returning true lets them be; otherwise they are
+    // skipped, as running something as another user needs someone to agree to it.
+    fn confirm_escalation(&mut self, _helper: &str, _targets: &[Target]) -> bool {
+        false
+    }
+    // asked once per operation for what to tell about copies of large files as they go, see
+    // copy::Progress. None, the default, copies them without reporting anything.
+    fn copy_progress(&self) -> Option<Progress> {
+        None
+    }
+}
+
+// what a Buffer was told, in order
+#[derive(Clone, Debug)]
+enum Buffered {
+    HookStarted(Box<Program>),
+    GroupHookStarted(String, String),
+    Event(Event),
+}
+
+// an observer that keeps what it is told, for programs worked on alongside others: their output
+// is handed to the real observer in one piece once they are done, so it isn't interleaved. It
+// can't ask anything, so it answers every question no, and large copies aren't shown going.
+#[derive(Clone, Debug, Default)]
+pub struct Buffer {
+    told: Vec<Buffered>,
+}
+
+impl Buffer {
+    // tells observer everything this was told, in the same order
+    pub fn replay(self, observer: &mut dyn Observer) {
+        for told in self.told {
+            match told {
+                Buffered::HookStarted(program) => observer.hook_started(&program),
+                Buffered::GroupHookStarted(group, hook) => observer.group_hook_started(&group, &hook),
+                Buffered::Event(event) => observer.event(&event),
+            }
+        }
+    }
+}
+
+impl Observer for Buffer {
+    fn hook_started(&mut self, program: &Program) {
+        self.told.push(Buffered::HookStarted(Box::new(program.clone())));
+    }
+
+    fn group_hook_started(&mut self, group: &str, hook: &str) {
+        self.told.push(Buffered::GroupHookStarted(group.to_owned(), hook.to_owned()));
+    }
+
+    fn event(&mut self, event: &Event) {
+        self.told.push(Buffered::Event(event.clone()));
+    }
+}
+
+// the observer used by the command line: prints every event as text or JSON
+pub struct Printer {
+    pub mode: OutputMode,
+    // answer yes to every question instead of asking
+    pub yes: bool,
+    // what the text output may use; plain ASCII unless told otherwise
+    pub term: Terminal,
+}
+
+impl Printer {
+    pub fn new(mode: OutputMode) -> Printer {
+        Printer { mode, yes: false, term: Terminal::plain() }
+    }
+}
+
+impl Observer for Printer {
+    fn hook_started(&mut self, program: &Program) {
+        // inform user which program's post-deploy command is being attempted
+        if self.mode == OutputMode::Text {
+            println!("attempting to run post-deploy command for \"{}\"...", program.name);
+        }
+    }
+
+    fn group_hook_started(&mut self, group: &str, hook: &str) {
+        if self.mode == OutputMode::Text {
+            println!("attempting to run {} command for group \"{}\"...", hook, group);
+        }
+    }
+
+    fn event(&mut self, event: &Event) {
+        match (self.mode, event.result) {
+            // only what changed is worth reading about; JSON still gets everything
+            (OutputMode::Text, EventResult::Unchanged) => (),
+            (OutputMode::Text, EventResult::Skipped) => println!("{}", self.term.paint(Style::Warning, &event.to_text())),
+            (OutputMode::Text, EventResult::Error) => println!("{}", self.term.paint(Style::Error, &event.to_text())),
+            _ => event.emit(self.mode),
+        }
+    }
+
+    fn confirm_incoming(&mut self, changes: &[IncomingChange]) -> bool {
+        for change in changes {
+            match self.mode {
+                OutputMode::Text => print!("{}", change.diff),
+                OutputMode::Json => println!("{}", serde_json::to_string(change).unwrap_or_default()),
+            }
+        }
+
+        // JSON output is meant for scripts, which can't answer a prompt
+        self.yes || (self.mode == OutputMode::Text && confirm("deploy these incoming changes?"))
+    }
+
+    fn confirm_prune(&mut self, orphans: &[Orphan]) -> bool {
+        match self.mode {
+            OutputMode::Text => println!("no longer in the config:"),
+            OutputMode::Json => (),
+        }
+        for orphan in orphans {
+            match self.mode {
+                OutputMode::Text => println!("  {}", orphan.to_text()),
+                OutputMode::Json => println!("{}", serde_json::to_string(orphan).unwrap_or_default()),
+            }
+        }
+
+        self.yes || (self.mode == OutputMode::Text && confirm("remove the files that didn't change since rodeo deployed them?"))
+    }
+
+    fn confirm_escalation(&mut self, helper: &str, targets: &[Target]) -> bool {
+        match self.mode {
+            OutputMode::Text => println!("this user may not write:"),
+            OutputMode::Json => (),
+        }
+        for target in targets {
+            match self.mode {
+                OutputMode::Text => println!("  {}", target.deployed_file),
+                OutputMode::Json => println!("{}", serde_json::to_string(target).unwrap_or_default()),
+            }
+        }
+
+        self.yes || (self.mode == OutputMode::Text && confirm(&format!("copy them with {}?", helper)))
+    }
+
+    // only worth showing to someone watching; the line goes to stderr and is gone once the copy is
+    fn copy_progress(&self) -> Option<Progress> {
+        match self.mode == OutputMode::Text && io::stderr().is_terminal() {
+            true => Some(Progress(&show_progress)),
+            false => None,
+        }
+    }
+}
+
+// the percentage last shown, so the line is only rewritten when it changes
+static SHOWN: AtomicU64 = AtomicU64::new(u64::MAX);
+
+fn show_progress(transfer: &Transfer) -> bool {
+    let percent = transfer.copied * 100 / transfer.total.max(1);
+    if SHOWN.swap(percent, Ordering::Relaxed) == percent && transfer.copied < transfer.total {
+        return true;
+    }
+    let line = format!("{}: {}% of {} MiB", transfer.source, percent, transfer.total / (1024 * 1024));
+    match transfer.copied < transfer.total {
+        true => eprint!("\r{}", line),
+        false => eprint!("\r{}\r", " ".repeat(line.chars().count())),
+    }
+    let _ = io::stderr().flush();
+    true
+}
+
+// asks a yes/no question on the terminal, defaulting to no
+pub fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    let _ = io::stdout().flush();
+
+    let mut answer = String::new();
+    match io::stdin().read_line(&mut answer) {
+        Ok(_) => matches!(answer.trim(), "y" | "Y" | "yes"),
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,67 @@
+use std::env;
+use crate::config;
+
+// expands environment variables in a config value. $NAME and ${NAME} are replaced with the
+// variable's value, ${NAME:-default} with default if the variable is unset or empty, and $$ with a
+// single $. HOSTNAME falls back to the detected host name, since shells often don't export it.
+// Any other variable that isn't set is an error.
+pub fn expand_vars(text: &str) -> Result<String, String> {
+    expand_with(text, &mut lookup)
+}
+
+// same as expand_vars, but with variables looked up through lookup rather than in the environment
+pub fn expand_with(text: &str, lookup: &mut dyn FnMut(&str) -> Option<String>) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = text;
+
+    while let Some(i) = rest.find('$') {
+        expanded.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('{') {
+            let end = after.find('}').ok_or_else(|| format!("missing \"}}\" in \"{}\"", text))?;
+            let (name, default) = match after[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&after[..end], None),
+            };
+            match (lookup(name), default) {
+                (Some(val), _) => expanded.push_str(&val),
+                (None, Some(default)) => expanded.push_str(default),
+                (None, None) => return Err(undefined(name, text)),
+            }
+            rest = &after[end + 1..];
+        } else {
+            let len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            if len == 0 {
+                // a lone $ isn't a variable
+                expanded.push('$');
+                continue;
+            }
+            let name = &rest[..len];
+            expanded.push_str(&lookup(name).ok_or_else(|| undefined(name, text))?);
+            rest = &rest[len..];
+        }
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+// the value of an environment variable as expand_vars sees it
+pub fn lookup(name: &str) -> Option<String> {
+    match env::var(name) {
+        Ok(val) if !val.is_empty() => Some(val),
+        _ if name == "HOSTNAME" => Some(config::detect_hostname()).filter(|h| !h.is_empty()),
+        _ => None,
+    }
+}
+
+fn undefined(name: &str, text: &str) -> String {
+    format!(
+        "environment variable \"{}\" used in \"{}\" is not set (define it in [variables], or use ${{{}:-default}} to give it a default)",
+        name, text, name
+    )
+}
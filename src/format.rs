@@ -0,0 +1,70 @@
+use std::path::Path;
+use serde::Serialize;
+
+// the languages a config file (rodeo.toml, an include or a file in conf.d) can be written in.
+// Whatever the language, the keys and tables are the ones TOML uses, e.g. in YAML:
+//
+//   dotfiles_directory: ~/dotfiles
+//   program:
+//     - name: nvim
+//       root: ~/.config/nvim
+//       paths: [init.vim]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ConfigFormat {
+    #[default]
+    Toml,
+    Yaml,
+    Json,
+}
+
+// the file extensions of every format, in the order the default config file is looked for
+pub const EXTENSIONS: &[&str] = &["toml", "yaml", "yml", "json"];
+
+impl ConfigFormat {
+    // the format given by name, as in --format
+    pub fn from_name(name: &str) -> Option<ConfigFormat> {
+        match name {
+            "toml" => Some(ConfigFormat::Toml),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            "json" => Some(ConfigFormat::Json),
+            _ => None,
+        }
+    }
+
+    // the format going by the file's extension; anything unknown is taken to be TOML
+    pub fn from_path(path: &str) -> ConfigFormat {
+        Path::new(path).extension()
+            .and_then(|e| ConfigFormat::from_name(&e.to_string_lossy().to_lowercase()))
+            .unwrap_or_default()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConfigFormat::Toml => "toml",
+            ConfigFormat::Yaml => "yaml",
+            ConfigFormat::Json => "json",
+        }
+    }
+
+    // parses contents into the same tree a TOML file would give, so the rest of rodeo doesn't need
+    // to care which format the file was in
+    pub fn parse(&self, contents: &str) -> Result<toml::Value, String> {
+        match self {
+            ConfigFormat::Toml => toml::from_str(contents).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml::from_str(contents).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::from_str(contents).map_err(|e| e.to_string()),
+        }
+    }
+
+    // the text of value as a config file in this format
+    pub fn serialize<T: Serialize>(&self, value: &T) -> Result<String, String> {
+        match self {
+            // every table starts after a blank line, which toml doesn't do on its own for all of them
+            ConfigFormat::Toml => toml::to_string(value)
+                .map(|s| s.replace("\n[", "\n\n[").replace("\n\n\n[", "\n\n["))
+                .map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::to_string_pretty(value).map(|s| s + "\n").map_err(|e| e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::copy::Copier;
+use crate::manifest::{Deployment, StateManifest};
+use crate::state;
+
+// what the system looked like after a deploy: every file rodeo had deployed on the machine, as
+// listed in the state manifest, with its contents kept alongside. Generations are numbered from 1
+// and kept as <state_dir>/generations/<number>.json; the contents are stored once per hash under
+// <state_dir>/generations/objects, so a file that didn't change between deploys costs nothing.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Generation {
+    pub number: u64,
+    // seconds since the unix epoch
+    pub created: u64,
+    // what made it: "deploy", or "rollback to <n>"
+    pub action: String,
+    // by deployed file
+    pub files: BTreeMap<String, Deployment>,
+}
+
+impl Generation {
+    pub fn to_text(&self) -> String {
+        format!(
+            "{}\t{}\t{} ({} files)",
+            self.number,
+            state::format_timestamp(self.created),
+            self.action,
+            self.files.len()
+        )
+    }
+}
+
+pub fn generations_dir(state_dir: &str) -> String {
+    format!("{}/generations", state_dir)
+}
+
+// where the contents with the given hash are kept
+pub fn object_path(state_dir: &str, hash: &str) -> String {
+    format!("{}/objects/{}", generations_dir(state_dir), hash)
+}
+
+// every generation there is, oldest first. Generations that can't be read are left out.
+pub fn list(state_dir: &str) -> Vec<Generation> {
+    let mut generations: Vec<Generation> = fs::read_dir(generations_dir(state_dir))
+        .map(|entries| entries.flatten().map(|e| e.path()).collect())
+        .unwrap_or_else(|_| Vec::new())
+        .into_iter()
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .filter_map(|p| serde_json::from_str(&fs::read_to_string(p).ok()?).ok())
+        .collect();
+    generations.sort_by_key(|g: &Generation| g.number);
+    generations
+}
+
+// records the files in manifest that are still as rodeo deployed them as a new generation made by
+// action. A deploy that leaves everything as the last generation has it doesn't make a new one, so
+// this returns the number of the generation made, if any.
+pub fn record(state_dir: &str, manifest: &StateManifest, action: &str) -> std::io::Result<Option<u64>> {
+    let files: BTreeMap<String, Deployment> = manifest.files.iter()
+        .filter(|(destination, deployment)| deployment.matches(destination))
+        .map(|(destination, deployment)| (destination.clone(), deployment.clone()))
+        .collect();
+    let last = list(state_dir).pop();
+    let hashes = |files: &BTreeMap<String, Deployment>| files.iter().map(|(d, f)| (d.clone(), f.hash.clone())).collect::<Vec<_>>();
+    if action == "deploy" && last.as_ref().map(|l| hashes(&l.files)).unwrap_or_default() == hashes(&files) {
+        return Ok(None);
+    }
+
+    for (destination, deployment) in files.iter() {
+        let object = object_path(state_dir, &deployment.hash);
+        if !Path::new(&object).exists() {
+            if let Some(parent) = Path::new(&object).parent() {
+                fs::create_dir_all(parent)?;
+            }
+            Copier::default().replace(destination, &object)?;
+        }
+    }
+
+    fs::create_dir_all(generations_dir(state_dir))?;
+    let number = last.map_or(1, |l| l.number + 1);
+    let generation = Generation { number, created: state::now(), action: action.to_owned(), files };
+    let contents = serde_json::to_string_pretty(&generation)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    fs::write(format!("{}/{}.json", generations_dir(state_dir), number), contents)?;
+    Ok(Some(number))
+}
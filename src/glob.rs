@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::Path;
+
+// shell-style wildcard patterns over /-separated paths. "*" matches any run of characters within
+// one path component, "?" matches a single character other than "/", and "**" matches any number
+// of whole components, so "nvim/**" matches everything below nvim and "**/*.lua" matches lua files
+// at any depth (including the top level).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    chars: Vec<char>,
+}
+
+impl Pattern {
+    pub fn new(text: &str) -> Pattern {
+        Pattern { chars: text.chars().collect() }
+    }
+
+    // whether the pattern contains any wildcards at all
+    pub fn is_glob(&self) -> bool {
+        self.chars.iter().any(|c| *c == '*' || *c == '?')
+    }
+
+    // whether the pattern refers to a path (as opposed to just a file name)
+    pub fn has_separator(&self) -> bool {
+        self.chars.contains(&'/')
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        let path: Vec<char> = path.chars().collect();
+        matches_from(&self.chars, &path)
+    }
+}
+
+// every file below base_dir whose path relative to base_dir matches pattern, sorted. Directories
+// are only walked as deep as the pattern could reach.
+pub fn expand(base_dir: &str, pattern: &str) -> Vec<String> {
+    let compiled = Pattern::new(pattern);
+
+    // components before the first wildcard don't need searching
+    let components: Vec<&str> = pattern.split('/').collect();
+    let fixed = components.iter()
+        .take_while(|c| !Pattern::new(c).is_glob())
+        .count()
+        .min(components.len() - 1);
+    let prefix = components[..fixed].join("/");
+    let depth = if pattern.contains("**") { None } else { Some(components.len() - fixed) };
+
+    let mut found = Vec::new();
+    walk(base_dir, &prefix, depth, &mut |path| {
+        if compiled.matches(path) {
+            found.push(path.to_owned());
+        }
+    });
+    found.sort();
+    found
+}
+
+// calls visit with the relative path of every file below base_dir/relative, descending at most
+// depth levels (or without limit if None)
+fn walk(base_dir: &str, relative: &str, depth: Option<usize>, visit: &mut dyn FnMut(&str)) {
+    if depth == Some(0) {
+        return;
+    }
+
+    let dir = Path::new(base_dir).join(relative);
+    let entries = match fs::read_dir(&dir) {
+        Ok(val) => val,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // a submodule's .git (or a whole repository's) belongs to git, not to the program
+        if name == ".git" {
+            continue;
+        }
+        let path = if relative.is_empty() { name } else { format!("{}/{}", relative, name) };
+        match entry.file_type() {
+            // symlinked directories aren't followed, so links back up the tree can't loop
+            Ok(kind) if kind.is_dir() => walk(base_dir, &path, depth.map(|d| d - 1), visit),
+            _ if dir.join(entry.file_name()).is_file() => visit(&path),
+            _ => (),
+        }
+    }
+}
+
+fn matches_from(pattern: &[char], path: &[char]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        ['*', '*', '/', rest @ ..] => {
+            // zero components, or skip up to and including any later "/"
+            matches_from(rest, path)
+                || path.iter().enumerate().any(|(i, c)| *c == '/' && matches_from(rest, &path[i + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=path.len()).any(|i| matches_from(rest, &path[i..])),
+        ['*', rest @ ..] => {
+            // stop at the end of the current component
+            let component = path.iter().position(|c| *c == '/').unwrap_or(path.len());
+            (0..=component).any(|i| matches_from(rest, &path[i..]))
+        }
+        ['?', rest @ ..] => match path {
+            [c, path_rest @ ..] if *c != '/' => matches_from(rest, path_rest),
+            _ => false,
+        },
+        [p, rest @ ..] => match path {
+            [c, path_rest @ ..] if c == p => matches_from(rest, path_rest),
+            _ => false,
+        },
+    }
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use crate::audit::{self, AuditLog};
+use crate::event::{Event, EventKind, Observer};
+use crate::HookWhen;
+
+// a set of programs that share hooks, defined in rodeo.toml as e.g. [group.desktop]. The group's
+// hooks run once around the deploy of all its members instead of once per program, so something
+// like restarting the compositor happens a single time however many of its configs changed.
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+pub struct Group {
+    // names of the member programs
+    #[serde(default)]
+    pub programs: Vec<String>,
+    // run through bash before the first member program is deployed
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub pre_deploy_cmd: String,
+    // run through bash after the last member program is deployed
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub post_deploy_cmd: String,
+    // when pre_deploy_cmd and post_deploy_cmd run
+    #[serde(default)]
+    #[serde(skip_serializing_if = "crate::is_default")]
+    pub hook_when: HookWhen,
+    // the config file the group was read from, like Program::source
+    #[serde(skip)]
+    #[serde(default)]
+    pub source: String,
+}
+
+impl Group {
+    // runs the group's pre-deploy command, if it has one
+    pub fn run_pre_deploy_cmd(&self, name: &str, audit: &AuditLog, observer: &mut dyn Observer) -> Option<Event> {
+        Group::run_hook(name, "pre-deploy", &self.pre_deploy_cmd, audit, observer)
+    }
+
+    // runs the group's post-deploy command, if it has one
+    pub fn run_post_deploy_cmd(&self, name: &str, audit: &AuditLog, observer: &mut dyn Observer) -> Option<Event> {
+        Group::run_hook(name, "post-deploy", &self.post_deploy_cmd, audit, observer)
+    }
+
+    // the event for a hook left out because none of the group's files changed, if it has one
+    pub fn skip_hook(name: &str, hook: &str, command: &str) -> Option<Event> {
+        if command.is_empty() {
+            return None;
+        }
+        Some(Event::new(EventKind::Hook, hook, name, "").skipped(format!(
+            "not running the {} command for group \"{}\", none of its programs' files changed",
+            hook, name
+        )))
+    }
+
+    fn run_hook(name: &str, hook: &str, command: &str, audit: &AuditLog, observer: &mut dyn Observer) -> Option<Event> {
+        if command.is_empty() {
+            return None;
+        }
+
+        observer.group_hook_started(name, hook);
+
+        let event = Event::new(EventKind::Hook, hook, name, "");
+        let output = match audit.run("hook", command) {
+            Ok(val) => val,
+            Err(e) => return Some(event.failed(format!("could not run {} command for group \"{}\": {}", hook, name, e))),
+        };
+
+        let event = event.with_output(String::from_utf8_lossy(output.stdout.as_slice()));
+        if output.status.success() {
+            Some(event)
+        } else {
+            Some(event.failed(format!(
+                "{} command for group \"{}\" failed ({})",
+                hook,
+                name,
+                audit::describe_failure(&output)
+            )))
+        }
+    }
+}
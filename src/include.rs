@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use serde::Serialize;
+use crate::format::{self, ConfigFormat};
+use crate::{glob, paths, schema, version};
+
+// the file each program and group was read from
+pub struct Sources {
+    // in the same order as the merged program list
+    pub programs: Vec<String>,
+    // by group name
+    pub groups: BTreeMap<String, String>,
+    // which layer each program belongs to, in the same order as programs
+    pub layers: Vec<Layer>,
+}
+
+// where a program comes from when the config is layered on a shared base config (see merge_base)
+#[derive(Clone, Copy, Debug, Default, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Layer {
+    // only in the user's own config
+    #[default]
+    Personal,
+    // only in the base config
+    Base,
+    // in the base config, with some of its keys changed by the user's own config
+    Override,
+}
+
+impl Layer {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Layer::Personal => "personal",
+            Layer::Base => "base",
+            Layer::Override => "override",
+        }
+    }
+}
+
+// merges the files named by the config's include list, and every config file (.toml, .yaml, .yml
+// or .json) in the conf.d directory next to the config, into config. Each file's format goes by
+// its extension. Included files may only hold [[program]] and [group.<name>] tables; programs are
+// appended in the order the files are read (include list first, then conf.d, each sorted by name)
+// and group names must be unique across all of them. Returns where each program and group came
+// from, and adds the contents of every file read to read.
+pub fn merge_includes(
+    config: &mut toml::Value,
+    config_path: &str,
+    home_dir: &str,
+    read: &mut BTreeMap<String, String>,
+) -> Result<Sources, String> {
+    let base_dir = Path::new(config_path).parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+
+    let table = config.as_table_mut().ok_or("the config file must be a table")?;
+    let mut sources = Sources {
+        programs: vec![config_path.to_owned(); table.get("program").and_then(|p| p.as_array()).map_or(0, |p| p.len())],
+        groups: table.get("group")
+            .and_then(|g| g.as_table())
+            .map(|g| g.keys().map(|k| (k.clone(), config_path.to_owned())).collect())
+            .unwrap_or_default(),
+        layers: Vec::new(),
+    };
+
+    let mut files = Vec::new();
+    let patterns = match table.get("include") {
+        None => Vec::new(),
+        Some(toml::Value::Array(patterns)) => patterns.clone(),
+        Some(_) => return Err("include must be an array of strings".to_owned()),
+    };
+    for pattern in patterns {
+        let pattern = pattern.as_str().ok_or("include must be an array of strings")?;
+        files.extend(resolve(pattern, &base_dir, home_dir)?);
+    }
+    let conf_d = format!("{}/conf.d", base_dir);
+    let mut in_conf_d: Vec<String> = format::EXTENSIONS.iter()
+        .flat_map(|e| glob::expand(&conf_d, &format!("*.{}", e)))
+        .map(|f| format!("{}/{}", conf_d, f))
+        .collect();
+    in_conf_d.sort();
+    files.extend(in_conf_d);
+
+    let mut seen = vec![config_path.to_owned()];
+    for file in files {
+        if seen.contains(&file) {
+            continue;
+        }
+        seen.push(file.clone());
+
+        let contents = fs::read_to_string(&file).map_err(|e| format!("could not read included file \"{}\": {}", file, e))?;
+        let included = ConfigFormat::from_path(&file).parse(&contents).map_err(|e| format!("could not parse included file \"{}\": {}", file, e))?;
+        read.insert(file.clone(), contents);
+        let included = match included {
+            toml::Value::Table(val) => val,
+            _ => continue,
+        };
+
+        for (key, value) in included {
+            match (&key[..], value) {
+                ("program", toml::Value::Array(programs)) => {
+                    sources.programs.extend(programs.iter().map(|_| file.clone()));
+                    let list = table.entry("program").or_insert_with(|| toml::Value::Array(Vec::new()));
+                    if let toml::Value::Array(list) = list {
+                        list.extend(programs);
+                    }
+                }
+                ("group", toml::Value::Table(groups)) => {
+                    let list = table.entry("group").or_insert_with(|| toml::Value::Table(Default::default()));
+                    if let toml::Value::Table(list) = list {
+                        for (name, group) in groups {
+                            if list.contains_key(&name) {
+                                return Err(format!("group \"{}\" in \"{}\" is already defined elsewhere", name, file));
+                            }
+                            sources.groups.insert(name.clone(), file.clone());
+                            list.insert(name, group);
+                        }
+                    }
+                }
+                (key, _) => {
+                    return Err(format!(
+                        "\"{}\" in included file \"{}\": only [[program]] and [group.<name>] tables may be included",
+                        key, file
+                    ))
+                }
+            }
+        }
+    }
+
+    sources.layers = vec![Layer::Personal; sources.programs.len()];
+    Ok(sources)
+}
+
+// layers config on the shared config named by its base key, e.g. a team's rodeo.toml in a
+// repository everyone clones. The base's programs and groups (including those it includes) come
+// first; a program or group of the same name in config takes precedence, with a program's keys
+// merged over the base's one by one, so `name = "tmux"` and `enabled = false` are enough to turn a
+// base program off. Base programs keep their files in the base's dotfiles_directory, or the
+// directory the base config is in if it has none, arranged by the base's layout. The base's other
+// settings are ignored.
+// sources is updated to match, and the contents of every file read are added to read.
+pub fn merge_base(
+    config: &mut toml::Value,
+    config_path: &str,
+    home_dir: &str,
+    sources: &mut Sources,
+    read: &mut BTreeMap<String, String>,
+) -> Result<(), String> {
+    let base_dir = Path::new(config_path).parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+    let table = config.as_table_mut().ok_or("the config file must be a table")?;
+    let base_path = match table.get("base") {
+        None => return Ok(()),
+        Some(toml::Value::String(val)) => absolute(val, &base_dir, home_dir)?,
+        Some(_) => return Err("base must be a string".to_owned()),
+    };
+    if base_path == config_path {
+        return Err("base must be another config file than the config itself".to_owned());
+    }
+
+    let contents = fs::read_to_string(&base_path).map_err(|e| format!("could not read base config \"{}\": {}", base_path, e))?;
+    let mut base = ConfigFormat::from_path(&base_path).parse(&contents).map_err(|e| format!("could not parse base config \"{}\": {}", base_path, e))?;
+    version::check_config(&base).map_err(|e| format!("base config \"{}\": {}", base_path, e))?;
+    read.insert(base_path.clone(), contents);
+    let base_sources = merge_includes(&mut base, &base_path, home_dir, read)?;
+    schema::upgrade(&mut base).map_err(|e| format!("base config \"{}\": {}", base_path, e))?;
+
+    let repo = match base.get("dotfiles_directory").and_then(|d| d.as_str()) {
+        Some(val) => val.to_owned(),
+        None => Path::new(&base_path).parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default(),
+    };
+    let mut base_programs = match base.get("program") {
+        None => Vec::new(),
+        Some(toml::Value::Array(val)) => val.clone(),
+        Some(_) => return Err(format!("program in base config \"{}\" must be a list of [[program]] tables", base_path)),
+    };
+    let arrangement: Vec<(&str, toml::Value)> = ["layout", "strip_dots"].iter()
+        .filter_map(|key| Some((*key, base.get(*key)?.clone())))
+        .collect();
+    for program in base_programs.iter_mut().filter_map(|p| p.as_table_mut()) {
+        program.entry("dotfiles_directory").or_insert_with(|| toml::Value::String(repo.clone()));
+        // and are arranged the way the base arranges its repository
+        for (key, value) in arrangement.iter() {
+            program.entry(*key).or_insert_with(|| value.clone());
+        }
+    }
+
+    let personal = match table.remove("program") {
+        None => Vec::new(),
+        Some(toml::Value::Array(val)) => val,
+        // left for validation to report
+        Some(other) => {
+            table.insert("program".to_owned(), other);
+            return Ok(());
+        }
+    };
+    let name = |program: &toml::Value| program.get("name").and_then(|n| n.as_str()).map(|n| n.to_owned());
+    let overridden = |program: &toml::Value| name(program).is_some() && personal.iter().any(|p| name(p) == name(program));
+
+    let mut programs = Vec::new();
+    let mut program_sources = Vec::new();
+    let mut layers = Vec::new();
+    for (program, source) in base_programs.iter().zip(base_sources.programs) {
+        if !overridden(program) {
+            programs.push(program.clone());
+            program_sources.push(source);
+            layers.push(Layer::Base);
+        }
+    }
+    for (program, source) in personal.iter().zip(sources.programs.drain(..)) {
+        match base_programs.iter().find(|b| name(b).is_some() && name(b) == name(program)) {
+            Some(base_program) => {
+                let mut merged = base_program.clone();
+                if let (Some(merged), Some(keys)) = (merged.as_table_mut(), program.as_table()) {
+                    merged.extend(keys.clone());
+                }
+                programs.push(merged);
+                layers.push(Layer::Override);
+            }
+            None => {
+                programs.push(program.clone());
+                layers.push(Layer::Personal);
+            }
+        }
+        program_sources.push(source);
+    }
+    table.insert("program".to_owned(), toml::Value::Array(programs));
+    sources.programs = program_sources;
+    sources.layers = layers;
+
+    if let Some(toml::Value::Table(groups)) = base.get("group") {
+        let list = table.entry("group").or_insert_with(|| toml::Value::Table(Default::default()));
+        if let toml::Value::Table(list) = list {
+            for (name, group) in groups.iter().filter(|(name, _)| !list.contains_key(*name)).collect::<Vec<_>>() {
+                sources.groups.insert(name.clone(), base_sources.groups.get(name).cloned().unwrap_or_default());
+                list.insert(name.clone(), group.clone());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// the files an include pattern names. Relative patterns are relative to the config file's
+// directory, and ~ and environment variables are expanded. A pattern without wildcards must name
+// an existing file; a glob may match nothing.
+fn resolve(pattern: &str, base_dir: &str, home_dir: &str) -> Result<Vec<String>, String> {
+    let pattern = paths::resolve(pattern, home_dir)?;
+    let (base, relative) = match pattern.strip_prefix('/') {
+        Some(rest) => ("".to_owned(), rest.to_owned()),
+        None => (base_dir.to_owned(), pattern),
+    };
+
+    if !glob::Pattern::new(&relative).is_glob() {
+        let file = format!("{}/{}", base, relative);
+        if !Path::new(&file).is_file() {
+            return Err(format!("included file \"{}\" does not exist", file));
+        }
+        return Ok(vec![file]);
+    }
+
+    let root = if base.is_empty() { "/".to_owned() } else { base.clone() };
+    Ok(glob::expand(&root, &relative).into_iter().map(|f| format!("{}/{}", base, f)).collect())
+}
+
+// path with environment variables and ~ expanded, and relative to base_dir if it isn't absolute
+fn absolute(path: &str, base_dir: &str, home_dir: &str) -> Result<String, String> {
+    let path = paths::resolve(path, home_dir)?;
+    if path.starts_with('/') {
+        Ok(path)
+    } else {
+        Ok(paths::join(base_dir, &path))
+    }
+}
@@ -0,0 +1,232 @@
+use std::path::{Component, Path};
+use serde::Serialize;
+use crate::condition::Condition;
+use crate::environ;
+use crate::escalate;
+use crate::include::Layer;
+use crate::link::DeployMode;
+use crate::mapping::Side;
+use crate::plan::SCHEMA_VERSION;
+use crate::vcs::{self, VcsKind};
+use crate::windows::{self, Flavor};
+use crate::{HookWhen, Program, Settings};
+
+// what `rodeo inspect` reports: everything a config can make rodeo write or run on this machine,
+// worked out by reading the config and the repository only. It's meant for reviewing someone
+// else's dotfiles before trusting them, so programs are reported whichever machines they are
+// limited to, with those limits as notes. Like the types in plan.rs, the JSON form follows the
+// rules described there.
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ActivityKind {
+    // a file written outside the repository
+    Write,
+    // a command run through a shell or directly
+    Command,
+    // a config file read along with the config
+    Read,
+    // an executable looked for on $PATH, without running it
+    Lookup,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Activity {
+    pub schema_version: u32,
+    pub kind: ActivityKind,
+    // what in the config asks for it, e.g. "program \"ssh\"", "group \"desktop\"" or "env.EDITOR"
+    pub owner: String,
+    // the file written, or the command run
+    pub target: String,
+    // when it happens, e.g. "on deploy" or "after deploy"
+    pub when: String,
+    // what limits it to some machines or situations, e.g. "only on hosts laptop"
+    pub notes: Vec<String>,
+    // anything that deserves a closer look, e.g. a file outside the home directory
+    pub warnings: Vec<String>,
+}
+
+impl Activity {
+    fn new(kind: ActivityKind, owner: String, target: String, when: &str, notes: Vec<String>) -> Activity {
+        Activity { schema_version: SCHEMA_VERSION, kind, owner, target, when: when.to_owned(), notes, warnings: Vec::new() }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = match self.kind {
+            ActivityKind::Write => format!("{} writes {} {}", self.owner, self.target, self.when),
+            ActivityKind::Command => format!("{} runs \"{}\" {}", self.owner, self.target, self.when),
+            ActivityKind::Read => format!("{} reads {} {}", self.owner, self.target, self.when),
+            ActivityKind::Lookup => format!("{} looks for \"{}\" on $PATH {}", self.owner, self.target, self.when),
+        };
+        let remarks: Vec<&String> = self.warnings.iter().chain(self.notes.iter()).collect();
+        if !remarks.is_empty() {
+            let remarks: Vec<&str> = remarks.iter().map(|r| &r[..]).collect();
+            text.push_str(&format!(" ({})", remarks.join("; ")));
+        }
+        text
+    }
+}
+
+// everything the config behind settings would read, write and run, in the order a new machine
+// would see it: the config files it is made of, cloning the repository, then each program's
+// conditions, files and hooks, then syncing. Files are
+// looked for in repository (normally settings' dotfiles_directory, but a clone being inspected may
+// not be where its config expects to be) unless a program has a repository of its own.
+pub fn inspect(settings: &Settings, repository: &str) -> Vec<Activity> {
+    let mut found = Vec::new();
+    let home = settings.home();
+
+    for (file, layer) in settings.read_files() {
+        let owner = if layer == Layer::Base { "base" } else { "include" };
+        found.push(Activity::new(ActivityKind::Read, owner.to_owned(), file, "whenever the config is loaded", Vec::new()));
+    }
+
+    let git = settings.vcs == VcsKind::Git;
+    if git && !settings.remote.is_empty() {
+        let options = vcs::clone_options(&settings.branch, settings.clone_depth, &settings.clone_filter);
+        found.push(Activity::new(
+            ActivityKind::Command,
+            "remote".to_owned(),
+            format!("git clone{} {} {}", options, settings.remote, settings.dotfiles_directory),
+            "when the repository is missing",
+            Vec::new(),
+        ));
+    }
+    if git && Path::new(repository).join(".gitmodules").is_file() {
+        found.push(Activity::new(
+            ActivityKind::Command,
+            ".gitmodules".to_owned(),
+            "git submodule sync --recursive && git submodule update --init --recursive".to_owned(),
+            "after each pull",
+            vec!["fetches and checks out every submodule the repository lists".to_owned()],
+        ));
+    }
+
+    for (name, value) in settings.env.iter() {
+        for command in environ::commands(value) {
+            let notes = if settings.env_commands { Vec::new() } else { vec!["refused unless env_commands = true".to_owned()] };
+            found.push(Activity::new(ActivityKind::Command, format!("env.{}", name), command, "on deploy", notes));
+        }
+    }
+
+    for (name, group) in settings.groups.iter() {
+        let owner = format!("group \"{}\"", name);
+        let notes = hook_notes(group.hook_when);
+        if !group.pre_deploy_cmd.is_empty() {
+            found.push(Activity::new(ActivityKind::Command, owner.clone(), group.pre_deploy_cmd.clone(), "before its programs deploy", notes.clone()));
+        }
+        if !group.post_deploy_cmd.is_empty() {
+            found.push(Activity::new(ActivityKind::Command, owner, group.post_deploy_cmd.clone(), "after its programs deploy", notes));
+        }
+    }
+
+    for program in settings.programs.iter() {
+        let owner = format!("program \"{}\"", program.name);
+        let limits = limits(program);
+
+        if let Ok(condition) = Condition::parse(&program.when) {
+            for command in condition.commands() {
+                found.push(Activity::new(ActivityKind::Lookup, owner.clone(), command, "before every command", Vec::new()));
+            }
+        }
+
+        for target in program.targets_on(home, repository, Side::Repo) {
+            let mut notes = limits.clone();
+            if !target.mode.is_empty() {
+                notes.insert(0, format!("mode {}", target.mode));
+            }
+            let mut activity = Activity::new(ActivityKind::Write, owner.clone(), target.deployed_file.clone(), "on deploy", notes);
+            if !inside(&target.deployed_file, home) {
+                activity.warnings.push("outside the home directory".to_owned());
+            }
+            if Path::new(&target.deployed_file).exists() {
+                activity.warnings.push("replaces an existing file".to_owned());
+            }
+            if let Some(helper) = settings.escalation().filter(|_| program.deploy_with == DeployMode::Copy && escalate::needed(&target)) {
+                activity.warnings.push(format!("through {}, as this user may not write it", helper));
+            }
+            found.push(activity);
+        }
+
+        if program.flavor == Flavor::Windows {
+            let repo_dir = program.repo_dir(home, repository);
+            for key in program.registry.iter() {
+                let file = format!("{}/{}", repo_dir, windows::registry_file(key));
+                let mut notes = limits.clone();
+                notes.insert(0, format!("into {}", key));
+                found.push(Activity::new(ActivityKind::Command, owner.clone(), format!("reg import {}", file), "on deploy", notes));
+            }
+        }
+
+        if !program.post_deploy_cmd.is_empty() {
+            let notes = hook_notes(program.hook_when).into_iter().chain(limits).collect();
+            found.push(Activity::new(ActivityKind::Command, owner, program.post_deploy_cmd.clone(), "after deploy", notes));
+        }
+    }
+
+    if git && settings.lfs {
+        found.push(Activity::new(
+            ActivityKind::Command,
+            "lfs".to_owned(),
+            "git lfs track --filename -- <file> && git add --renormalize -- <file>".to_owned(),
+            "before pushing, for each binary managed file that isn't tracked yet",
+            Vec::new(),
+        ));
+    }
+
+    if settings.vcs == VcsKind::Rsync && !settings.rsync_target.is_empty() {
+        found.push(Activity::new(
+            ActivityKind::Command,
+            "rsync_target".to_owned(),
+            format!("rsync -a --update {}/ {}/", settings.rsync_target.trim_end_matches('/'), settings.dotfiles_directory),
+            "on sync",
+            vec!["and the other way around to push".to_owned()],
+        ));
+    }
+
+    found
+}
+
+// what keeps a program from being deployed everywhere
+fn limits(program: &Program) -> Vec<String> {
+    let mut limits = Vec::new();
+    if !program.enabled {
+        limits.push("disabled".to_owned());
+    }
+    if program.layer == Layer::Base {
+        limits.push("from the base config".to_owned());
+    }
+    if !program.hosts.is_empty() {
+        limits.push(format!("only on hosts {}", program.hosts.join(", ")));
+    }
+    if !program.os.is_empty() {
+        limits.push(format!("only on {}", program.os.join(", ")));
+    }
+    if !program.when.is_empty() {
+        limits.push(format!("only when {}", program.when));
+    }
+    limits
+}
+
+fn hook_notes(when: HookWhen) -> Vec<String> {
+    match when {
+        HookWhen::Changed => vec!["only if files changed".to_owned()],
+        HookWhen::Always | HookWhen::NeverDryRun => Vec::new(),
+    }
+}
+
+// whether path is below dir once every ".." in it is resolved, so that "~/../../etc/passwd" isn't
+// taken to be inside the home directory
+fn inside(path: &str, dir: &str) -> bool {
+    let mut resolved = Vec::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::CurDir => (),
+            other => resolved.push(other),
+        }
+    }
+    resolved.iter().collect::<std::path::PathBuf>().starts_with(dir)
+}
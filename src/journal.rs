@@ -0,0 +1,229 @@
+use std::collections::BTreeSet;
+use std::fs::{self, OpenOptions};
+use std::io::prelude::*;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use serde::{Deserialize, Serialize};
+use crate::event::{Event, EventKind, EventResult};
+use crate::copy::{self, Copier};
+use crate::state;
+use crate::SyncScope;
+
+// the operations that write files and so are journaled
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Deploy,
+    Collect,
+    Sync { pull: bool, push: bool },
+    Unlink,
+    Prune,
+    Revert,
+    Rollback,
+}
+
+impl Operation {
+    pub fn sync(scope: SyncScope) -> Operation {
+        Operation::Sync { pull: scope.pull, push: scope.push }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Deploy => "deploy",
+            Operation::Collect => "collect",
+            Operation::Sync { .. } => "sync",
+            Operation::Unlink => "unlink",
+            Operation::Prune => "prune",
+            Operation::Revert => "revert",
+            Operation::Rollback => "rollback",
+        }
+    }
+}
+
+// one line of the journal file
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Entry {
+    // always the first line: what was being done, and since when
+    Begin { operation: Operation, created: u64 },
+    // written before destination is touched. backup holds a copy of what was there beforehand,
+    // or is None if destination didn't exist.
+    Write { destination: String, backup: Option<String> },
+    // written once destination has been completely written
+    Done { destination: String },
+}
+
+// intent log for an operation that writes files. Every write is recorded (along with a backup of
+// whatever it overwrites) before it happens, and the whole journal is removed once the operation
+// finishes. If rodeo is killed part way through, the journal is left behind and the next
+// invocation can either run the operation again (resume) or put back everything it had
+// overwritten (abort). Files can be written from several threads at once, each noting its writes.
+pub struct Journal {
+    dir: String,
+    operation: Operation,
+    writes: AtomicUsize,
+    // held while a line is appended, so that lines from different threads don't run together
+    log: Mutex<()>,
+}
+
+impl Journal {
+    fn dir(state_dir: &str) -> String {
+        format!("{}/journal", state_dir)
+    }
+
+    fn log_path(dir: &str) -> String {
+        format!("{}/journal.log", dir)
+    }
+
+    // starts journaling an operation. Fails if the journal can't be written, in which case the
+    // operation must not go ahead.
+    pub fn begin(state_dir: &str, operation: Operation) -> std::io::Result<Journal> {
+        if let Some(pending) = Journal::pending(state_dir) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("an interrupted {} has to be resumed or aborted first", pending.name()),
+            ));
+        }
+
+        let dir = Journal::dir(state_dir);
+        fs::create_dir_all(format!("{}/files", dir))?;
+
+        let journal = Journal { dir, operation, writes: AtomicUsize::new(0), log: Mutex::new(()) };
+        journal.append(&Entry::Begin { operation, created: state::now() })?;
+        Ok(journal)
+    }
+
+    // the operation of a journal left behind by an interrupted run, if there is one
+    pub fn pending(state_dir: &str) -> Option<Operation> {
+        match Journal::read(&Journal::dir(state_dir)).ok()?.first() {
+            Some(Entry::Begin { operation, .. }) => Some(*operation),
+            _ => None,
+        }
+    }
+
+    // records that destination is about to be overwritten, backing up its current contents first
+    pub fn before_write(&self, destination: &str) -> std::io::Result<()> {
+        let write = self.writes.fetch_add(1, Ordering::Relaxed);
+        let backup = if Path::new(destination).is_file() {
+            let backup = format!("{}/files/{}", self.dir, write);
+            // with its time, so that undoing the write leaves the file exactly as it was
+            Copier::default().copy(destination, &backup)?;
+            Some(backup)
+        } else {
+            None
+        };
+
+        self.append(&Entry::Write { destination: destination.to_owned(), backup })
+    }
+
+    // records that destination has been written in full
+    pub fn after_write(&self, destination: &str) -> std::io::Result<()> {
+        self.append(&Entry::Done { destination: destination.to_owned() })
+    }
+
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    // every file the operation wrote, overwrote or removed so far, each once
+    pub fn written(&self) -> std::io::Result<Vec<String>> {
+        let written: BTreeSet<String> = Journal::read(&self.dir)?.into_iter()
+            .filter_map(|entry| match entry {
+                Entry::Write { destination, .. } => Some(destination),
+                _ => None,
+            })
+            .collect();
+        Ok(written.into_iter().collect())
+    }
+
+    // every file the operation overwrote or removed so far, as (destination, backup) pairs, where
+    // backup holds what was there before
+    pub fn replaced(&self) -> std::io::Result<Vec<(String, String)>> {
+        Ok(Journal::read(&self.dir)?.into_iter()
+            .filter_map(|entry| match entry {
+                Entry::Write { destination, backup: Some(backup) } => Some((destination, backup)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    // the operation completed, so nothing needs recovering: throw the journal away
+    pub fn finish(self) -> std::io::Result<()> {
+        fs::remove_dir_all(&self.dir)
+    }
+
+    // undoes every write recorded in an interrupted run's journal, newest first, then removes the
+    // journal. Writes that never finished are undone too, since they may have left a partial file.
+    pub fn roll_back(state_dir: &str) -> Vec<Event> {
+        let dir = Journal::dir(state_dir);
+        let entries = match Journal::read(&dir) {
+            Ok(val) => val,
+            Err(e) => {
+                return vec![Event::new(EventKind::File, "abort", &dir[..], "")
+                    .failed(format!("could not read journal: {}", e))]
+            }
+        };
+
+        let mut events = Vec::new();
+        for entry in entries.iter().rev() {
+            if let Entry::Write { destination, backup } = entry {
+                // the copy the interrupted write was making, if it got that far
+                let _ = fs::remove_file(copy::partial_path(destination));
+                events.push(match backup {
+                    Some(backup) => {
+                        let event = Event::new(EventKind::File, "restore", &backup[..], &destination[..]);
+                        // renamed into place rather than copied onto destination, which would write
+                        // through a symlink or hard link to the file it is linked to
+                        match Copier::default().replace(backup, destination) {
+                            Ok(_) => event,
+                            Err(e) => event.failed(format!("could not restore \"{}\": {}", destination, e)),
+                        }
+                    }
+                    None => {
+                        let event = Event::new(EventKind::File, "remove", &destination[..], "");
+                        match fs::remove_file(destination) {
+                            Ok(_) => event,
+                            Err(e) if e.kind() == std::io::ErrorKind::NotFound => event.skipped(format!(
+                                "\"{}\" was never written, nothing to undo",
+                                destination
+                            )),
+                            Err(e) => event.failed(format!("could not remove \"{}\": {}", destination, e)),
+                        }
+                    }
+                });
+            }
+        }
+
+        // only forget the journal if everything was put back, so a failed abort can be retried
+        if events.iter().all(|e| e.result != EventResult::Error) {
+            if let Err(e) = fs::remove_dir_all(&dir) {
+                events.push(Event::new(EventKind::File, "abort", &dir[..], "")
+                    .failed(format!("could not remove journal: {}", e)));
+            }
+        }
+
+        events
+    }
+
+    // removes an interrupted run's journal without undoing anything, once it has been resumed
+    pub fn discard(state_dir: &str) -> std::io::Result<()> {
+        fs::remove_dir_all(Journal::dir(state_dir))
+    }
+
+    fn append(&self, entry: &Entry) -> std::io::Result<()> {
+        let line = serde_json::to_string(entry)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let _log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+        let mut file = OpenOptions::new().create(true).append(true).open(Journal::log_path(&self.dir))?;
+        writeln!(file, "{}", line)?;
+        // the entry has to actually be on disk before the write it describes happens
+        file.sync_data()
+    }
+
+    fn read(dir: &str) -> std::io::Result<Vec<Entry>> {
+        let contents = fs::read_to_string(Journal::log_path(dir))?;
+        // a line cut short by the interruption can't be parsed and is ignored
+        Ok(contents.lines().filter_map(|l| serde_json::from_str(l).ok()).collect())
+    }
+}
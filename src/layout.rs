@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+
+// how files are arranged in a repository, chosen in rodeo.toml with e.g. `layout = "per-program"`,
+// or on a program kept in a repository of its own. Mirror-home is the default for compatibility
+// with older configs.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LayoutKind {
+    // files are stored where they are relative to the home directory, so ~/.config/nvim/init.lua
+    // is kept at .config/nvim/init.lua
+    #[default]
+    MirrorHome,
+    // each program's files are kept in a directory named after it, e.g. nvim/init.lua
+    PerProgram,
+    // like mirror-home, but with the leading dot of every name written as "dot_", the way chezmoi
+    // stores files: dot_config/nvim/init.lua
+    Chezmoi,
+}
+
+// where in its repository each of a program's files is kept. Program::targets_on, which deploy,
+// collect, sync and status all get their paths from, asks the program's layout for them.
+pub trait LayoutStrategy {
+    // the program's directory, relative to the repository. root is the program's root relative to
+    // the home directory, e.g. ".config/nvim".
+    fn program_dir(&self, name: &str, root: &str) -> String;
+    // where the file (or glob) at path below the program's root is kept below program_dir
+    fn repo_path(&self, path: &str) -> String {
+        path.to_owned()
+    }
+    // where the file kept at repo_path below program_dir is deployed below the program's root;
+    // the opposite of repo_path
+    fn deployed_path(&self, repo_path: &str) -> String {
+        repo_path.to_owned()
+    }
+    // the layout of what is inside a file or directory kept under a name of its own, such as the
+    // src of a renamed entry
+    fn below(&self) -> &dyn LayoutStrategy;
+}
+
+pub fn from_kind(kind: LayoutKind) -> Box<dyn LayoutStrategy> {
+    match kind {
+        LayoutKind::MirrorHome => Box::new(MirrorHome),
+        LayoutKind::PerProgram => Box::new(PerProgram),
+        LayoutKind::Chezmoi => Box::new(Chezmoi),
+    }
+}
+
+pub struct MirrorHome;
+
+impl LayoutStrategy for MirrorHome {
+    fn program_dir(&self, _name: &str, root: &str) -> String {
+        root.to_owned()
+    }
+
+    fn below(&self) -> &dyn LayoutStrategy {
+        self
+    }
+}
+
+pub struct PerProgram;
+
+impl LayoutStrategy for PerProgram {
+    fn program_dir(&self, name: &str, _root: &str) -> String {
+        name.to_owned()
+    }
+
+    fn below(&self) -> &dyn LayoutStrategy {
+        self
+    }
+}
+
+pub struct Chezmoi;
+
+impl LayoutStrategy for Chezmoi {
+    fn program_dir(&self, _name: &str, root: &str) -> String {
+        self.repo_path(root)
+    }
+
+    fn repo_path(&self, path: &str) -> String {
+        map_names(path, |name| match name.strip_prefix('.') {
+            Some(rest) if !rest.is_empty() && rest != "." => format!("dot_{}", rest),
+            _ => name.to_owned(),
+        })
+    }
+
+    fn deployed_path(&self, repo_path: &str) -> String {
+        map_names(repo_path, |name| match name.strip_prefix("dot_") {
+            Some(rest) => format!(".{}", rest),
+            None => name.to_owned(),
+        })
+    }
+
+    fn below(&self) -> &dyn LayoutStrategy {
+        self
+    }
+}
+
+// another layout with the leading dot left out of the names at the top of the repository, so that
+// ~/.bashrc is kept as bashrc and ~/.config/nvim as config/nvim, chosen with `strip_dots = true`.
+// Deployed, those names get their dot back. The top is the program's directory when that is its
+// root below the home directory (mirror-home), and the files in it otherwise.
+pub struct Undotted {
+    pub inner: Box<dyn LayoutStrategy>,
+    pub dir_at_top: bool,
+}
+
+impl LayoutStrategy for Undotted {
+    fn program_dir(&self, name: &str, root: &str) -> String {
+        let dir = self.inner.program_dir(name, root);
+        if self.dir_at_top { map_first(&dir, undot) } else { dir }
+    }
+
+    fn repo_path(&self, path: &str) -> String {
+        let path = self.inner.repo_path(path);
+        if self.dir_at_top { path } else { map_first(&path, undot) }
+    }
+
+    fn deployed_path(&self, repo_path: &str) -> String {
+        if self.dir_at_top {
+            return self.inner.deployed_path(repo_path);
+        }
+        self.inner.deployed_path(&map_first(repo_path, |name| format!(".{}", name)))
+    }
+
+    fn below(&self) -> &dyn LayoutStrategy {
+        self.inner.below()
+    }
+}
+
+fn undot(name: &str) -> String {
+    match name.strip_prefix('.') {
+        Some(rest) if !rest.is_empty() && rest != "." => rest.to_owned(),
+        _ => name.to_owned(),
+    }
+}
+
+// path with f applied to its first name, keeping everything else as it is
+pub fn map_first(path: &str, f: impl Fn(&str) -> String) -> String {
+    let start = path.len() - path.trim_start_matches('/').len();
+    let end = path[start..].find('/').map_or(path.len(), |i| start + i);
+    match &path[start..end] {
+        "" => path.to_owned(),
+        name => format!("{}{}{}", &path[..start], f(name), &path[end..]),
+    }
+}
+
+// path with f applied to each of its names, keeping its separators (including a trailing one) as
+// they are
+fn map_names(path: &str, f: impl Fn(&str) -> String) -> String {
+    let names: Vec<String> = path.split('/').map(|n| if n.is_empty() { String::new() } else { f(n) }).collect();
+    names.join("/")
+}
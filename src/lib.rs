@@ -1,10 +1,85 @@
+pub mod audit;
+pub mod backup;
+pub mod cache;
+pub mod cli;
+pub mod condition;
+pub mod config;
+pub mod copy;
+pub mod defaults;
+pub mod drift;
+pub mod edit;
+pub mod environ;
+pub mod escalate;
+pub mod event;
+pub mod expand;
+pub mod format;
+pub mod generation;
+pub mod glob;
+pub mod group;
+pub mod include;
+pub mod inspect;
+pub mod journal;
+pub mod layout;
+#[cfg(all(feature = "libgit2", not(feature = "system-git")))]
+pub mod libgit;
+pub mod link;
+pub mod manifest;
+pub mod mapping;
+pub mod merge;
+pub mod migrate;
+pub mod mode;
+pub mod order;
+pub mod pager;
+pub mod parallel;
+pub mod paths;
+pub mod plan;
+pub mod preview;
+pub mod profile;
+pub mod query;
+pub mod scan;
+pub mod schema;
+pub mod search;
+pub mod state;
+pub mod summary;
+pub mod template;
+pub mod term;
+pub mod trash;
+pub mod validate;
+pub mod vcs;
+pub mod version;
+pub mod watch;
+pub mod windows;
+
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path;
+use std::sync::Arc;
 use std::io::prelude::*;
-use std::process::Command;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use audit::AuditLog;
+use cache::StatusCache;
+use condition::Condition;
+use copy::{Copier, CopyMethod};
+use defaults::{Defaults, Preference, SyncDirection};
+use group::Group;
+use include::Layer;
+use event::{Buffer, Event, EventKind, EventResult, Observer, OutputMode, Printer};
+use term::Terminal;
+use format::ConfigFormat;
+use journal::{Journal, Operation};
+use layout::{LayoutKind, LayoutStrategy};
+use link::{DeployMode, SourceLinks};
+use profile::Profile;
+use generation::Generation;
+use manifest::{Deployment, Orphan, StateManifest};
+use mapping::{FileState, PathEntry, PathFilter, ReverseIndex, Side, Target};
+use merge::{Resolution, Resolver};
+use summary::Summary;
+use trash::TrashKind;
+use vcs::VcsKind;
+use windows::Flavor;
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct Settings {
     #[serde(skip)]
     #[serde(default)]
@@ -12,352 +87,3189 @@ pub struct Settings {
     #[serde(skip)]
     #[serde(default)]
     config_path: String,
+    #[serde(skip)]
+    #[serde(default)]
+    state_dir: String,
+    // the language config_path is written in
+    #[serde(skip)]
+    #[serde(default)]
+    format: ConfigFormat,
+    // every config file read, with the hash of its contents at the time, so files rodeo rewrites
+    // can be checked for changes made in the meantime
+    #[serde(skip)]
+    #[serde(default)]
+    loaded: BTreeMap<String, u64>,
+    // how output should be presented; set from the command line rather than the config file
+    #[serde(skip)]
+    #[serde(default)]
+    pub output: OutputMode,
+    // answer yes to every question (such as whether to deploy incoming changes) instead of
+    // asking; also set from the command line
+    #[serde(skip)]
+    #[serde(default)]
+    pub yes: bool,
+    // what the text output may use (colors, unicode); also set from the command line
+    #[serde(skip)]
+    #[serde(default)]
+    pub terminal: Terminal,
+    // which copy sync keeps when both were changed at the same time, from --prefer-repo or
+    // --prefer-disk; neither is kept if None
+    #[serde(skip)]
+    #[serde(default)]
+    pub prefer: Option<Preference>,
+    // the versions of rodeo the config works with, e.g. ">=1.2"; see version::check
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub requires_rodeo: String,
+    // the version of the config format the config is written in; see schema. Once loaded, it is
+    // always the current one.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<i64>,
+    // the version the config file was actually written in, and the files making it up apart from
+    // the base config: the config itself and the files it includes. rodeo migrate rewrites these.
+    #[serde(skip)]
+    #[serde(default)]
+    written_in: i64,
+    #[serde(skip)]
+    #[serde(default)]
+    own_files: Vec<String>,
+    // the profile in use, if any; see select_profile
+    #[serde(skip)]
+    #[serde(default)]
+    profile: String,
+    // the name of this machine programs were selected for, the detected one if empty; see
+    // select_host
+    #[serde(skip)]
+    #[serde(default)]
+    host: String,
+    // the names of every program in the config, selected or not
+    #[serde(skip)]
+    #[serde(default)]
+    configured: BTreeSet<String>,
     pub dotfiles_directory: String,
+    // what keeps dotfiles_directory in sync with its remote: "git" (default), "rsync" or "none"
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_default")]
+    pub vcs: VcsKind,
+    // where dotfiles_directory is cloned from if it isn't a repository yet, e.g.
+    // "git@github.com:me/dots.git"
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub remote: String,
+    // the branch sync pulls from and pushes to, and the one checked out when cloning remote. If
+    // empty, sync uses whichever is checked out and cloning the remote's default branch.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub branch: String,
+    // the git remotes sync pushes dotfiles_directory to, e.g. ["origin", "backup"], each one on
+    // its own; empty to push only to the one the checked out branch follows
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub remotes: Vec<String>,
+    // how many commits of history cloning remote fetches, e.g. 1 for only the latest; all of it
+    // if None. Pulls then only fetch what's new, so the repository stays shallow.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clone_depth: Option<u32>,
+    // the git partial clone filter cloning remote uses, e.g. "blob:none" to fetch file contents
+    // only as they're checked out. Later fetches keep using it.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub clone_filter: String,
+    // where the rsync backend mirrors dotfiles_directory to, e.g. "user@host:dotfiles/"
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub rsync_target: String,
+    // whether binary managed files are stored in the repository through Git LFS, which sync sets
+    // up for them before pushing
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    pub lfs: bool,
+    // how file contents are copied: "std" (default), "buffered" or "io_uring"
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_default")]
+    pub copy_method: CopyMethod,
+    // size in bytes of the buffer used by the buffered and io_uring copy methods
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub copy_buffer_size: Option<usize>,
+    // at most how fast files are copied and the repository is pulled and pushed, e.g. "10MB/s";
+    // see copy::parse_rate. --limit-rate overrides it.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub limit_rate: String,
+    // how many programs, or files of a single program, deploy, collect and sync work on at once;
+    // one if not set. --jobs overrides it.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<usize>,
+    // what deploy copies the files this user may not write through, e.g. "doas"; sudo if empty
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub escalate: String,
+    // from --no-escalate: files this user may not write fail to deploy instead
+    #[serde(skip)]
+    #[serde(default)]
+    pub no_escalate: bool,
+    // permission bits, in octal, that nothing rodeo creates while deploying, collecting or syncing
+    // gets, whatever the umask of the shell it was started from, e.g. "077" to keep it all private
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub umask: String,
+    // where the files deploy, collect, sync, unlink, prune, rollback and revert overwrite or
+    // remove go: "rodeo" (default), "freedesktop" or "off"
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_default")]
+    pub trash: TrashKind,
+    // how many days trashed files are kept, 30 if not set; 0 keeps them until the trash is emptied
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trash_days: Option<u64>,
+    // at most how much the trash holds before its oldest files are removed, e.g. "500M"; see
+    // copy::parse_rate. No limit if empty.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub trash_size: String,
+    // how files are arranged in dotfiles_directory: "mirror-home" (default), "per-program" or
+    // "chezmoi"
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_default")]
+    pub layout: LayoutKind,
+    // whether names at the top of the repository are stored without their leading dot, e.g.
+    // bashrc for ~/.bashrc; see layout::Undotted
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    pub strip_dots: bool,
+    // how deploy puts files in place: "copy" (default) or "symlink"
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_default")]
+    pub deploy_mode: DeployMode,
+    // a shared config, e.g. a team's, whose programs and groups this one builds on; relative to
+    // the directory rodeo.toml is in
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub base: String,
+    // more config files holding programs and groups, e.g. "programs/*.toml"; relative to the
+    // directory rodeo.toml is in
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    // lets values in env run commands with $(...)
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    pub env_commands: bool,
+    // variables set for every hook, e.g. EDITOR = "nvim"; see environ::resolve
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    // values used in several places of the config, e.g. font = "Iosevka", referred to as $font in
+    // roots and paths like environment variables, which they take precedence over. Hooks get them
+    // as environment variables.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_default")]
+    pub defaults: Defaults,
     // difference in names here isn't huge, but naming a vector with a name that
     // implies a single value goes against my naming conventions
     #[serde(rename = "program")]
     pub programs: Vec<Program>,
+    // groups of programs sharing hooks, by name
+    #[serde(rename = "group")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<String, Group>,
+    // ways of using the config chosen with --profile, by name
+    #[serde(rename = "profile")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct Program {
     pub name: String,
     pub root: String,
-    pub paths: Vec<String>,
+    pub paths: Vec<PathEntry>,
+    // permissions every deployed file of the program is given, e.g. "600" for ~/.ssh, unless its
+    // entry in paths has a mode of its own. Empty leaves them as the copy made them.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub mode: String,
+    // a repository of its own for the program's files, e.g. a private one for ssh and gpg
+    // configs, instead of the top level dotfiles_directory. It is pulled and pushed with the same
+    // vcs as the main one.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub dotfiles_directory: String,
+    // where the program's files live inside dotfiles_directory, e.g. "nvim". Defaults to root
+    // relative to the home directory.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub repo_subdir: String,
+    // how the files are arranged in the program's repository, overriding the config's layout
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<LayoutKind>,
+    // whether the program's top-level files are stored without their leading dot, overriding the
+    // config's strip_dots
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strip_dots: Option<bool>,
+    // how deploy puts the program's files in place, overriding the config's deploy_mode
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deploy_mode: Option<DeployMode>,
+    // what happens to files copied from a symlink: "follow" (default), "preserve" or "refuse"
     #[serde(default)]
+    #[serde(skip_serializing_if = "is_default")]
+    pub symlinks: SourceLinks,
+    // labels for selecting the program with --tag, e.g. ["gui", "work"]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // names of the machines the program is managed on; empty means all of them
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+    // operating systems the program is managed on, as named by std::env::consts::OS (e.g.
+    // "linux", "macos", "windows"); empty means all of them
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub os: Vec<String>,
+    // condition the machine has to meet for the program to be managed, e.g.
+    // "command(sway)"; see Condition. Empty means always.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub when: String,
+    // disabled programs stay in the config but are left alone by every command
+    #[serde(default = "default_enabled")]
+    #[serde(skip_serializing_if = "is_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
     pub post_deploy_cmd: String,
+    // when post_deploy_cmd runs
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_default")]
+    pub hook_when: HookWhen,
+    // what post_deploy_cmd runs with, overriding the one in [defaults]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub shell: String,
+    // names of programs that have to be deployed (and their post_deploy_cmd run) before this one
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    // whether the first deploy archives the program's files, overriding [defaults]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup: Option<bool>,
+    // which copy sync-local keeps when both have changed, overriding [defaults]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sync_direction: Option<SyncDirection>,
+    // "unix" (default) or "windows". Windows programs can use %APPDATA%, %LOCALAPPDATA% and
+    // %USERPROFILE% in root, and manage the registry keys listed in registry.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_default")]
+    pub flavor: Flavor,
+    // registry keys, e.g. "HKCU\Software\SimonTatham\PuTTY", exported to .reg files by collect
+    // and imported by deploy. Only used by windows programs.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub registry: Vec<String>,
+    // set from --include and --exclude on the command line rather than the config file
+    #[serde(skip)]
+    #[serde(default)]
+    pub filter: PathFilter,
+    // the config file the program was read from, which is rodeo.toml unless it came from an
+    // include or conf.d
+    #[serde(skip)]
+    #[serde(default)]
+    pub source: String,
+    // whether the program comes from the base config, the user's own one, or both
+    #[serde(skip)]
+    #[serde(default)]
+    pub layer: Layer,
+    // the layout in effect: layout, or the config's if it isn't set
+    #[serde(skip)]
+    #[serde(default)]
+    pub repo_layout: LayoutKind,
+    // whether top-level names are stored without their leading dot: strip_dots, or the config's
+    // if it isn't set
+    #[serde(skip)]
+    #[serde(default)]
+    pub undotted: bool,
+    // the deploy mode in effect: deploy_mode, or the config's if it isn't set
+    #[serde(skip)]
+    #[serde(default)]
+    pub deploy_with: DeployMode,
+    // what the program's templates are rendered with; see template::variables
+    #[serde(skip)]
+    #[serde(default)]
+    pub template_vars: Arc<BTreeMap<String, String>>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+// keys at their default values are left out when a config is saved
+fn is_enabled(enabled: &bool) -> bool {
+    *enabled
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+// when a program's or group's deploy hooks run, chosen in rodeo.toml with e.g.
+// `hook_when = "changed"`
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookWhen {
+    // on every deploy
+    #[default]
+    Always,
+    // only if the deploy changes at least one of the program's (or group's) files
+    Changed,
+    // on every deploy except dry runs. Dry runs never run hooks, so this is the same as always; it
+    // exists to say so explicitly.
+    NeverDryRun,
+}
+
+impl HookWhen {
+    // whether the hook runs, given whether the deploy changes any files
+    pub fn allows(&self, changed: bool) -> bool {
+        *self != HookWhen::Changed || changed
+    }
+}
+
+// which phases of a sync should run; see Settings::sync
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SyncScope {
+    pub pull: bool,
+    pub push: bool,
+}
+
+impl SyncScope {
+    pub fn full() -> SyncScope {
+        SyncScope { pull: true, push: true }
+    }
+
+    pub fn local_only() -> SyncScope {
+        SyncScope { pull: false, push: false }
+    }
+}
+
+impl Default for SyncScope {
+    fn default() -> Self {
+        SyncScope::full()
+    }
 }
 
 impl Settings {
     // instantiation methods //
 
-    pub fn new_from_file(mut file: fs::File, home: String, config_path: String) -> std::io::Result<Settings> {
+    pub fn new_from_file(file: fs::File, home: String, config_path: String) -> std::io::Result<Settings> {
+        let format = ConfigFormat::from_path(&config_path);
+        Settings::new_from_file_with_format(file, home, config_path, format)
+    }
+
+    // like new_from_file, for a config file in the given format rather than the one its extension
+    // suggests
+    pub fn new_from_file_with_format(mut file: fs::File, home: String, config_path: String, format: ConfigFormat) -> std::io::Result<Settings> {
         // read the contents of the given file into a string
         let mut file_contents = String::new();
         file.read_to_string(&mut file_contents)?;
+        Settings::new_from_str(file_contents, home, config_path, format)
+    }
 
+    // like new_from_file_with_format, for a config already read into contents. config_path is still
+    // where included files, conf.d and the base config are looked for relative to, and what
+    // problems are reported against.
+    pub fn new_from_str(file_contents: String, home: String, config_path: String, format: ConfigFormat) -> std::io::Result<Settings> {
         // using serde + toml-rs, move the config into a struct
-        let mut settings: Settings = toml::from_str(&file_contents).unwrap();
+        let mut value = match format.parse(&file_contents) {
+            Ok(val) => val,
+            Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+        };
+
+        let invalid = |e: String| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+        version::check_config(&value).map_err(invalid)?;
+
+        // programs and groups may also come from included files and conf.d
+        let mut files = BTreeMap::new();
+        files.insert(config_path.clone(), file_contents);
+        let mut sources = include::merge_includes(&mut value, &config_path, &home, &mut files).map_err(invalid)?;
+        let own_files: Vec<String> = files.keys().cloned().collect();
+        // older configs are brought up to date before anything looks at their keys
+        let written_in = schema::upgrade(&mut value).map_err(invalid)?;
+        include::merge_base(&mut value, &config_path, &home, &mut sources, &mut files).map_err(invalid)?;
+
+        // catch typos and wrong types with their location before serde gets to them
+        let problems = validate::check_keys(&value, &config_path, &sources.programs, &files);
+        if !problems.is_empty() {
+            return Err(invalid(validate::to_text(&problems)));
+        }
+        let mut settings: Settings = match value.try_into() {
+            Ok(val) => val,
+            Err(e) => return Err(invalid(e.to_string())),
+        };
+        for ((program, source), layer) in settings.programs.iter_mut().zip(sources.programs).zip(sources.layers) {
+            program.source = source;
+            program.layer = layer;
+            program.repo_layout = program.layout.unwrap_or(settings.layout);
+            program.undotted = program.strip_dots.unwrap_or(settings.strip_dots);
+            program.deploy_with = program.deploy_mode.unwrap_or(settings.deploy_mode);
+        }
+        for (name, group) in settings.groups.iter_mut() {
+            group.source = sources.groups.get(name).cloned().unwrap_or_default();
+        }
+
+        let problems = validate::check_settings(&settings, &config_path, &files);
+        if !problems.is_empty() {
+            return Err(invalid(validate::to_text(&problems)));
+        }
+        // every operation works through the programs in this order
+        if let Ok(order) = order::dependency_order(&settings.programs) {
+            let programs = order.iter().filter_map(|p| settings.programs.get(*p).cloned()).collect();
+            settings.programs = programs;
+        }
+
         settings.home = home.clone();
         settings.config_path = config_path;
-        settings.dotfiles_directory = settings.dotfiles_directory.replace("~", &home[..]);
+        settings.format = format;
+        settings.loaded = files.iter().map(|(f, c)| (f.clone(), edit::content_hash(c))).collect();
+        settings.state_dir = state::state_dir(&home);
+        settings.written_in = written_in;
+        settings.own_files = own_files;
+        settings.configured = settings.programs.iter().map(|i| i.name.clone()).collect();
+        settings.expand_vars().map_err(invalid)?;
+        settings.set_template_vars("");
+        settings.dotfiles_directory = paths::expand_home(&settings.dotfiles_directory, &home);
+        for i in settings.programs.iter_mut() {
+            i.dotfiles_directory = paths::expand_home(&i.dotfiles_directory, &home);
+        }
         Ok(settings)
     }
 
-    // methods for interacting with Program structs //
-    
-    pub fn deploy(self) {
-        for i in self.programs.into_iter() {
-            i.deploy(&self.home, &self.dotfiles_directory);
+    // expands the values of [variables], which may use environment variables, then variables in
+    // dotfiles_directory and every program's root and paths
+    fn expand_vars(&mut self) -> Result<(), String> {
+        for (name, value) in self.variables.iter_mut() {
+            *value = expand::expand_vars(value).map_err(|e| format!("variable \"{}\": {}", name, e))?;
+        }
+        let variables = self.variables.clone();
+        let expand = |text: &str| expand::expand_with(text, &mut |name| variables.get(name).cloned().or_else(|| expand::lookup(name)));
+
+        self.dotfiles_directory = expand(&self.dotfiles_directory)?;
+        for i in self.programs.iter_mut() {
+            let name = i.name.clone();
+            let in_program = |e: String| format!("program \"{}\": {}", name, e);
+            i.root = expand(&i.root).map_err(in_program)?;
+            i.dotfiles_directory = expand(&i.dotfiles_directory).map_err(in_program)?;
+            i.paths = i.paths.iter()
+                .map(|p| p.try_map(expand))
+                .collect::<Result<_, _>>()
+                .map_err(in_program)?;
         }
+        Ok(())
     }
 
-    pub fn collect(self) {
-        for i in self.programs.into_iter() {
-            i.collect(&self.home, &self.dotfiles_directory);
+    // switches to the profile called name: its variables go over those in env, and the programs are
+    // narrowed down to the ones it uses. An empty name leaves everything as it is.
+    pub fn select_profile(&mut self, name: &str) -> Result<(), String> {
+        if name.is_empty() {
+            return Ok(());
         }
+        let profile = match self.profiles.get(name) {
+            Some(val) => val.clone(),
+            None if self.profiles.is_empty() => {
+                return Err(format!("no profile named \"{}\", the config has no [profile.<name>] tables", name))
+            }
+            None => {
+                let names: Vec<&str> = self.profiles.keys().map(|n| &n[..]).collect();
+                return Err(format!("no profile named \"{}\" in the config, expected one of {}", name, names.join(", ")));
+            }
+        };
+
+        self.programs.retain(|i| profile.programs.is_empty() || profile.programs.contains(&i.name));
+        self.programs.retain(|i| profile.tags.is_empty() || i.tags.iter().any(|t| profile.tags.contains(t)));
+        self.env.extend(profile.env);
+        self.profile = name.to_owned();
+        Ok(())
     }
 
-    pub fn sync_local(self) {
-        for i in self.programs.into_iter() {
-            i.sync_local(&self.home, &self.dotfiles_directory);
+    // narrows the programs this Settings operates on down to the ones named. An empty list leaves
+    // every enabled program selected. Disabled programs are never selected.
+    pub fn select_programs(&mut self, names: &[String]) -> Result<(), String> {
+        for name in names {
+            match self.programs.iter().find(|i| &i.name == name) {
+                None if !self.profile.is_empty() => return Err(format!("no program named \"{}\" in profile \"{}\"", name, self.profile)),
+                None => return Err(format!("no program named \"{}\" in the config", name)),
+                Some(i) if !i.enabled => {
+                    return Err(format!("program \"{}\" is disabled, enable it with \"rodeo enable {}\"", name, name))
+                }
+                Some(_) => (),
+            }
         }
+
+        self.programs.retain(|i| i.enabled && (names.is_empty() || names.contains(&i.name)));
+        Ok(())
     }
 
-    pub fn sync_remote(self) {
-        for i in self.clone().programs.into_iter() {
-            i.sync_local(&self.home, &self.dotfiles_directory);
+    // narrows the selected programs down to those carrying at least one of tags. An empty list
+    // leaves them all. A tag none of them carries is most likely a typo, so it is an error.
+    pub fn select_tags(&mut self, tags: &[String]) -> Result<(), String> {
+        for tag in tags {
+            if !self.programs.iter().any(|i| i.tags.contains(tag)) {
+                return Err(format!("none of the selected programs is tagged \"{}\"", tag));
+            }
         }
 
-        self.git_pull();
-        self.git_push();
+        self.programs.retain(|i| tags.is_empty() || i.tags.iter().any(|t| tags.contains(t)));
+        Ok(())
     }
 
-    pub fn sync_full(self) {
-        // pull before doing anything
-        self.git_pull();
+    // drops the programs whose hosts don't include host, i.e. that aren't meant for this machine
+    pub fn select_host(&mut self, host: &str) {
+        self.programs.retain(|i| i.hosts.is_empty() || i.hosts.iter().any(|h| h == host));
+        self.host = host.to_owned();
+        self.set_template_vars(host);
+    }
 
-        for i in self.clone().programs.into_iter() {
-            i.sync_local(&self.home, &self.dotfiles_directory);
+    // gives every program the variables its templates are rendered with, with host as the name of
+    // this machine (the detected one if empty)
+    fn set_template_vars(&mut self, host: &str) {
+        let variables = Arc::new(template::variables(host, &self.home, &self.variables));
+        for i in self.programs.iter_mut() {
+            i.template_vars = variables.clone();
         }
+    }
 
-        self.git_push();
+    // drops the programs whose os doesn't include os, i.e. that don't apply to this platform
+    pub fn select_os(&mut self, os: &str) {
+        self.programs.retain(|i| i.os.is_empty() || i.os.iter().any(|o| o == os));
     }
 
-    // helper methods //
-    fn git_pull(&self) {
-        println!(
-            "{}",
-            String::from_utf8_lossy(
-                Command::new("bash")
-                    .arg("-c")
-                    .arg("git pull")
-                    .output()
-                    .unwrap()
-                    .stdout
-                    .as_slice()
-            )
-        );
+    // drops the programs whose when condition doesn't hold on this machine
+    pub fn select_conditions(&mut self) {
+        let home = &self.home;
+        self.programs.retain(|i| {
+            i.when.is_empty() || Condition::parse(&i.when).map(|c| c.eval(home)).unwrap_or(false)
+        });
     }
 
-    fn git_push(&self) {
-        // pulling, committing, and pushing are all done via bash commands - while this is
-        // admittedly not ideal, it has the advantage of being simple to write and simple to use,
-        // automatically respecting user's git configs and, more importantly, making it very simple
-        // to use features such as authentication via ssh. Additionally, it results in very
-        // graceful handling of failures to push/pull/commit/etc, respecting user's git configs and
-        // git's own internal logic and expectations.
-
-        // initialize the git command outside of the command build for legibility
-        let git_command = format!(
-                "cd {} && \
-                git pull && \
-                find . -not -path \"./git\" -not -name \".\" -name \".*\" -not -name \".git*\" -not -name \"$(basename $(cat .gitmodules | grep -i \"path\" | xargs | cut -c7- | xargs))*\" -exec git add {{}} \\; && \
-                git commit -m \"rodeo remote sync\" && \
-                git push",
-                self.dotfiles_directory
-            );
-       // since i can't figure out how to put a comment between the lines of a multiline string,
-       // the explanation of the bash is as follows:
-       //   "cd {}" (where {} is replaced by dotfiles_dir) changes the working directory to the
-       //   local dotfiles repo
-       //
-       //   "git pull" is presumably self explanatory: performs a pull operation on the repo.
-       //
-       //   "find ..." is pretty chunky, but essentially it looks for every file whose name begins
-       //   with a dot and doesn't match the pattern .git*, as well as trying to ignore directories
-       //   that are in .gitmodules. Note that this ignores .gitignore.
-       //
-       //   "git commit -m \"Rodeo remote sync\" will commit all changes with the message "Rodeo
-       //   remote sync"
-       //
-       //   finally, "git push" is probably also pretty self explanatory, as it simply pushes the
-       //   changes to the remote repository.
-
-        // build the command, simply piping git_command into the bash shell
-        let command = Command::new("bash")
-            .arg("-c")
-            .arg(git_command)
-            .output()
-            .unwrap()
-            .stdout;
-
-        // print the output of git_command to terminal
-        println!("{}", String::from_utf8_lossy(command.as_slice()));
+    // narrows the files of the selected programs down to those passing --include and --exclude.
+    // Programs left without any files are dropped altogether, so that their hooks don't run.
+    pub fn select_paths(&mut self, filter: PathFilter) {
+        if filter.is_empty() {
+            return;
+        }
+
+        for i in self.programs.iter_mut() {
+            i.filter = filter.clone();
+        }
+        let (home, dotfiles_dir) = (&self.home, &self.dotfiles_directory);
+        self.programs.retain(|i| !i.deploy_targets(home, dotfiles_dir).is_empty());
     }
-}
 
-impl Program {
-    
-    // interprets the post-deploy command in the bash shell
-    pub fn run_post_deploy_cmd(&self) -> std::io::Result<()> {
-        // don't execute this method if there is no post-deploy command
-        if self.post_deploy_cmd.len() < 1 {
-            return Ok(())
+    // methods for interacting with Program structs //
+    //
+    // each operation comes in two flavours: the plain one prints its progress according to
+    // self.output, the _with_observer one hands every event to the given Observer instead.
+
+    pub fn deploy(self) -> Summary {
+        self.deploy_with_observer(&mut self.printer())
+    }
+
+    pub fn deploy_with_observer(&self, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        let _umask = mode::Umask::set(self.umask());
+
+        // the very first deploy on a machine archives everything it is about to overwrite, so
+        // that adopting rodeo can be undone with `rodeo restore --pre-rodeo`
+        if backup::needs_pre_rodeo(&self.state_dir) {
+            let destinations: Vec<String> = self.programs.iter()
+                .filter(|i| self.defaults.backup(i))
+                .flat_map(|i| i.deploy_targets(&self.home, &self.dotfiles_directory))
+                .filter(|target| target.direction.deploys())
+                .map(|target| target.deployed_file)
+                .collect();
+            for event in backup::snapshot_pre_rodeo(&self.state_dir, &destinations) {
+                summary.record(event, observer);
+            }
+        // after that, each deploy keeps the old version of every file it overwrites with something
+        // different, so that edits made to a deployed file since the last deploy aren't lost
+        } else {
+            let overwritten: Vec<String> = self.programs.iter()
+                .filter(|i| self.defaults.backup(i))
+                .flat_map(|i| plan::plan(Operation::Deploy, std::slice::from_ref(i), &self.home, &self.dotfiles_directory).actions)
+                .filter(|a| a.kind == plan::ActionKind::Overwrite && path::Path::new(&a.destination).is_file())
+                .map(|a| a.destination)
+                .collect();
+            let events = backup::snapshot(&self.state_dir, &overwritten);
+            let failed = events.iter().any(|e| e.error.is_some());
+            for event in events {
+                summary.record(event, observer);
+            }
+            if failed {
+                summary.record(
+                    Event::new(EventKind::File, "deploy", "", "")
+                        .failed("not deploying, since not every file it would overwrite could be backed up; fix that, or turn backups off with backup = false"),
+                    observer,
+                );
+                return summary;
+            }
         }
 
-        // inform user which program's post-deploy command is being attempted
-        println!("attempting to run post-deploy command for \"{}\"...", self.name);
+        // worked out once, before anything is copied, so that a broken [env] table stops the deploy
+        let audit = match environ::resolve(&self.env, self.env_commands, &self.audit_log()) {
+            // [env] goes over [variables] of the same name
+            Ok(env) => {
+                let variables = self.variables.iter().map(|(name, value)| (name.clone(), value.clone()));
+                self.audit_log().with_env(variables.chain(env).collect()).with_shell(self.defaults.shell(None))
+            }
+            Err(e) => {
+                summary.record(Event::new(EventKind::Hook, "env", "", "").failed(e), observer);
+                return summary;
+            }
+        };
 
-        // run the post-deploy command, collect output into a Vec<u8>.
-        // if this command fails, the error will be handled in main.
-        let post_deploy_cmd = Command::new("bash")
-            .arg("-c")
-            .arg(&self.post_deploy_cmd[..])
-            .output()?.stdout;
+        let journal = match self.begin_journal(Operation::Deploy, &mut summary, observer) {
+            Some(val) => val,
+            None => return summary,
+        };
 
-        // convert the post-deploy command's output from a Vec<u8> into a String
-        let post_deploy_cmd_output = String::from_utf8_lossy(&post_deploy_cmd.as_slice()).to_owned();
+        // where each group's first and last selected members are, so its hooks can run around them
+        let groups: Vec<(&String, &Group, usize, usize)> = self.groups.iter()
+            .filter_map(|(name, group)| {
+                let members: Vec<usize> = self.programs.iter()
+                    .enumerate()
+                    .filter(|(_, i)| group.programs.contains(&i.name))
+                    .map(|(p, _)| p)
+                    .collect();
+                Some((name, group, *members.first()?, *members.last()?))
+            })
+            .collect();
 
-        // give user post-deploy command's output
-        println!("{}", post_deploy_cmd_output);
+        // whether deploying each program changes any of its files, worked out before anything is
+        // copied, for the hooks with hook_when = "changed"
+        let changes: Vec<bool> = self.programs.iter()
+            .map(|i| plan::plan(Operation::Deploy, std::slice::from_ref(i), &self.home, &self.dotfiles_directory).has_changes())
+            .collect();
+        let group_changes = |group: &Group| self.programs.iter().zip(changes.iter()).any(|(i, c)| *c && group.programs.contains(&i.name));
 
-        Ok(())
-    }
-    
-    // replaces all "active-duty" dotfiles from the user's system with the dotfiles in the
-    // repository folder
-    pub fn deploy(&self, home_dir: &String, dotfiles_dir: &String) {
-        // standardize source/output dir paths
-        let source_dir = Program::standardize_path(dotfiles_dir, &home_dir);
-        let output_dir = Program::standardize_path(&self.root, &home_dir);
+        let changed = |i: &Program| self.programs.iter().zip(changes.iter()).any(|(j, c)| *c && j.name == i.name);
 
-        // ensure output folder exists
-        fs::create_dir_all(format!("{}/{}", output_dir, self.root.replace("~/", ""))).unwrap_or_default();
+        // programs that can be deployed alongside each other when there are jobs to spare: runs of
+        // them in order, none depending on another in the same run. A run ends before a program
+        // with group hooks to run ahead of it and after one with group hooks to run after it, and
+        // a program with files to copy through the escalation helper, which asks first, runs alone.
+        let alone = |i: &Program| {
+            self.escalation().is_some() && i.deploy_with == DeployMode::Copy
+                && i.targets_on(&self.home, &self.dotfiles_directory, Side::Repo).iter().any(|t| t.direction.deploys() && escalate::needed(t))
+        };
+        let mut runs: Vec<Vec<usize>> = Vec::new();
+        for (p, i) in self.programs.iter().enumerate() {
+            let joins = runs.last().is_some_and(|run| {
+                let last = run[run.len() - 1];
+                !groups.iter().any(|g| g.2 == p || g.3 == last)
+                    && !alone(i)
+                    && !alone(&self.programs[last])
+                    && !run.iter().any(|r| i.depends_on.contains(&self.programs[*r].name))
+            });
+            match runs.last_mut() {
+                Some(run) if joins => run.push(p),
+                _ => runs.push(vec![p]),
+            }
+        }
 
-        // deploy all the files
-        for i in self.paths.clone().into_iter() {
-            // append the file names to the directory paths
-            let in_file = format!("{}/{}/{}", source_dir, self.root.replace("~/", ""), i);
-            let out_file = format!("{}/{}", output_dir, i);
+        for run in runs {
+            let (first, last) = (run[0], run[run.len() - 1]);
+            for (name, group, _, _) in groups.iter().filter(|g| g.2 == first) {
+                let event = if group.hook_when.allows(group_changes(group)) {
+                    group.run_pre_deploy_cmd(name, &audit, observer)
+                } else {
+                    Group::skip_hook(name, "pre-deploy", &group.pre_deploy_cmd)
+                };
+                if let Some(event) = event {
+                    summary.record(event, observer);
+                }
+            }
 
-            // copy the file
-            Program::copy_file(in_file, out_file);
+            let programs: Vec<&Program> = run.iter().map(|p| &self.programs[*p]).collect();
+            self.each_program(&programs, &mut summary, observer, |i, jobs, observer| {
+                let mut done = i.deploy(&self.home, &self.dotfiles_directory, &self.watched_copier(observer), &journal, jobs, self.escalation().is_some(), observer);
+                self.deploy_escalated(i, &journal, &mut done, observer);
+                done.merge(i.deploy_registry(&self.home, &self.dotfiles_directory, &self.audit_log(), observer));
+                let event = if i.hook_when.allows(changed(i)) {
+                    i.run_post_deploy_cmd(&audit.clone().with_shell(self.defaults.shell(Some(i))), observer)
+                } else if i.post_deploy_cmd.is_empty() {
+                    None
+                } else {
+                    Some(Event::new(EventKind::Hook, "post-deploy", &i.name[..], "").skipped(format!(
+                        "not running the post-deploy command for \"{}\", none of its files changed",
+                        i.name
+                    )))
+                };
+                if let Some(event) = event {
+                    done.record(event, observer);
+                }
+                done
+            });
+
+            for (name, group, _, _) in groups.iter().filter(|g| g.3 == last) {
+                let event = if group.hook_when.allows(group_changes(group)) {
+                    group.run_post_deploy_cmd(name, &audit, observer)
+                } else {
+                    Group::skip_hook(name, "post-deploy", &group.post_deploy_cmd)
+                };
+                if let Some(event) = event {
+                    summary.record(event, observer);
+                }
+            }
         }
-    }
-    
-    // replaces all dotfiles in repository folder with the "active-duty" dotfiles from the user's
-    // system
-    pub fn collect(&self, home_dir: &String, dotfiles_dir: &String) {
-        // standardize source/output dir paths
-        let source_dir = Program::standardize_path(&self.root, &home_dir);
-        let output_dir = Program::standardize_path(dotfiles_dir, &home_dir);
 
-        // ensure output_dir exists
-        fs::create_dir_all(format!("{}/{}", output_dir, self.root.replace("~/", ""))).unwrap_or_default();
+        // files dropped from the config since they were deployed are offered for removal
+        let orphans = self.orphans();
+        if !orphans.is_empty() && observer.confirm_prune(&orphans) {
+            self.remove_orphans(&orphans, &journal, &mut summary, observer);
+        }
 
-        for i in self.paths.clone().into_iter() {
-            // append the file name to the directory's path
-            let in_file = format!("{}/{}", source_dir, i);
-            let out_file = format!("{}/{}/{}", output_dir, self.root.replace("~/", ""), i);
-            
-            // copy the file
-            Program::copy_file(in_file, out_file);
+        self.finish_journal(journal, &mut summary, observer);
+        // what the deploy left behind becomes a generation, for rodeo rollback
+        if let Err(e) = generation::record(&self.state_dir, &self.manifest(), "deploy") {
+            summary.record(
+                Event::new(EventKind::File, "generation", &self.state_dir[..], "")
+                    .failed(format!("could not record this deploy for rodeo rollback: {}", e)),
+                observer,
+            );
         }
+        summary
     }
 
-    // the in-between of copy and deploy, in which the oldest files are overwritten with the
-    // newest.
-    pub fn sync_local<T: Into<String>>(&self, home_dir: T, dotfiles_dir: T) {
-        // convert generics to Strings
-        let home_dir: String = home_dir.into();
-        let dotfiles_dir: String = dotfiles_dir.into();
+    pub fn collect(self) -> Summary {
+        self.collect_with_observer(&mut self.printer())
+    }
 
-        // standardize paths
-        let program_files_root = Program::standardize_path(&self.root, &home_dir);
-        let dotfiles_dir = Program::standardize_path(dotfiles_dir, home_dir);
+    pub fn collect_with_observer(&self, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        let _umask = mode::Umask::set(self.umask());
+        let journal = match self.begin_journal(Operation::Collect, &mut summary, observer) {
+            Some(val) => val,
+            None => return summary,
+        };
 
-        // ensure directories exist
-        fs::create_dir_all(&program_files_root).unwrap_or_default();
-        fs::create_dir_all(format!("{}/{}", dotfiles_dir, self.root.replace("~/", ""))).unwrap_or_default();
-
-        for i in self.paths.clone().into_iter() {
-            // append the file name to the directories path. Repo file is the designation given to
-            // the dotfile being pulled from the folder containing all the other dotfiles;
-            // working file is the designation given to files actively in the user's filesystem in
-            // their proper locations.
-            let repo_file = format!("{}/{}/{}", dotfiles_dir, self.root.replace("~/", ""), i);
-            let working_file = format!("{}/{}", program_files_root, i);
-
-            // check for both files existence
-            let repo_file_exists = path::Path::new(&repo_file).exists();
-            let working_file_exists = path::Path::new(&working_file).exists();
-
-            // if neither exist, don't sync
-            if !repo_file_exists && !working_file_exists {
-                println!("file {} does not exist in dotfiles repo or its intended place in the system, not syncing", i);
-                
-            // if only the repo file exists, copy the working file to repo directory
-            } else if !path::Path::new(&repo_file).exists() {
-                Program::copy_file(working_file, repo_file);
-                continue;
+        let programs: Vec<&Program> = self.programs.iter().collect();
+        self.each_program(&programs, &mut summary, observer, |i, jobs, observer| {
+            let mut done = i.collect(&self.home, &self.dotfiles_directory, &self.watched_copier(observer), &journal, jobs, observer);
+            done.merge(i.collect_registry(&self.home, &self.dotfiles_directory, &self.audit_log(), &journal, observer));
+            done
+        });
 
-            // if only the working file exists, copy the repo file to the working directory
-            } else if !path::Path::new(&working_file).exists() {
-                Program::copy_file(repo_file, working_file);
-                continue;
-            }
+        self.finish_journal(journal, &mut summary, observer);
+        summary
+    }
 
-            // get metadata structs for both files
-            let repo_file_metadata = match fs::metadata(&repo_file) {
-                Ok(val) => val,
-                Err(_) => {
-                    println!("error syncing file \"{}\": could not access file metadata.", repo_file);
-                    continue
-                },
-            };
-            let working_file_metadata = match fs::metadata(&working_file) {
-                Ok(val) => val,
-                Err(_) => {
-                    println!("error syncing file \"{}\": could not access file metadata.", working_file);
-                    continue
-                }
-            };
+    pub fn unlink(self) -> Summary {
+        self.unlink_with_observer(&mut self.printer())
+    }
 
-            // get a systemtime struct for both files based on their time last modified, then
-            // immediately pull the time elapsed from them
-            let repo_file_modified_elapsed = match repo_file_metadata.modified() {
-                Ok(val) => match val.elapsed() {
-                    Ok(elapsed) => elapsed,
-                    Err(_) => {
-                        println!("error syncing file \"{}\": could not determine time of last modification.", i);
-                        continue
-                    }
-                },
-                Err(_) => {
-                    println!("error syncing file \"{}\": could not determine time of last modification.", i);
-                    continue
-                }
-            };
+    // replaces every deployed file that is a symlink to its repository copy with a copy of it, for
+    // leaving symlink mode (or rodeo) behind without breaking anything. Deploying a program whose
+    // deploy_mode is still symlink links its files again.
+    pub fn unlink_with_observer(&self, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        let _umask = mode::Umask::set(self.umask());
+        let journal = match self.begin_journal(Operation::Unlink, &mut summary, observer) {
+            Some(val) => val,
+            None => return summary,
+        };
 
-            let working_file_modified_elapsed = match working_file_metadata.modified() {
-                Ok(val) => match val.elapsed() {
-                    Ok(elapsed) => elapsed,
-                    Err(_) => {
-                        println!("error syncing file \"{}\": could not determine time of last modification.", i);
-                        continue
-                    }
-                },
-                Err(_) => {
-                    println!("error syncing file \"{}\": could not determine time of last modification.", i);
-                    continue
+        for i in self.programs.iter() {
+            observer.program_started(i);
+            for target in i.targets_on(&self.home, &self.dotfiles_directory, Side::Repo) {
+                if link::linked(&target.deployed_file, &target.repo_file) {
+                    // the copy gets the file's mode before it is renamed into place, as in deploy
+                    let copier = Copier { mode: mode::parse(&target.mode).ok(), ..self.watched_copier(observer) };
+                    summary.record(Program::copy_file(target.repo_file, target.deployed_file, &copier, &journal), observer);
                 }
-            };
-
-            // overwrite whichever file was modified a longer time ago with the more recently
-            // modified file
-            if repo_file_modified_elapsed < working_file_modified_elapsed {
-                Program::copy_file(repo_file, working_file);
-            } else if repo_file_modified_elapsed > working_file_modified_elapsed {
-                Program::copy_file(working_file, repo_file);
-            } else {
-                println!("file \"{}\" appears to have been modified at the same time at both locations. Not syncing.", i);
             }
         }
+
+        self.finish_journal(journal, &mut summary, observer);
+        summary
     }
 
-    //
-    // helper functions
-    //
+    pub fn prune(self) -> Summary {
+        self.prune_with_observer(&mut self.printer())
+    }
 
-    // copies "from" file to "to" file, outputting the given error_message string on error.
-    fn copy_file<T: Into<String>>(from: T, to: T) {
-        // convert all generics into Strings
-        let from: String = from.into();
-        let to: String = to.into();
+    // removes the files rodeo deployed that the config no longer has; see orphans
+    pub fn prune_with_observer(&self, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        let journal = match self.begin_journal(Operation::Prune, &mut summary, observer) {
+            Some(val) => val,
+            None => return summary,
+        };
 
-        // ensure double slashes are ignored
-        let from = from.replace("//", "/");
-        let to = to.replace("//", "/");
+        self.remove_orphans(&self.orphans(), &journal, &mut summary, observer);
 
-        // copy "from" file to "to" file location
-        match fs::copy(&from, &to) {
-            Ok(_) => {
-                println!("{} => {}", from, to);
-                ()
-            },
-            Err(_) => println!("Error: could not perform copy operation \"{} => {}\"", from, to),
+        self.finish_journal(journal, &mut summary, observer);
+        summary
+    }
+
+    // the files rodeo deployed on this machine that no program in the config has anymore. Only
+    // the files of selected programs and of programs gone from the config are looked at, so that
+    // working on part of the config doesn't make the rest look abandoned.
+    pub fn orphans(&self) -> Vec<Orphan> {
+        let (owned, managed) = self.ownership();
+        self.manifest().orphans(&owned, &managed)
+    }
+
+    // which programs' deployed files orphans looks at, and every file those programs still have
+    // in either direction, whatever --include and --exclude say
+    fn ownership(&self) -> (impl Fn(&str) -> bool + '_, BTreeSet<String>) {
+        let owned = move |program: &str| !self.configured.contains(program) || self.programs.iter().any(|i| i.name == program);
+        let managed = self.programs.iter()
+            .flat_map(|i| Program { filter: PathFilter::default(), ..i.clone() }.deploy_targets(&self.home, &self.dotfiles_directory))
+            .map(|t| t.deployed_file)
+            .collect();
+        (owned, managed)
+    }
+
+    // whether files of the program named program are backed up before they are overwritten or
+    // removed, going by the defaults for programs no longer in the config
+    fn backs_up(&self, program: &str) -> bool {
+        match self.programs.iter().find(|i| i.name == program) {
+            Some(val) => self.defaults.backup(val),
+            None => self.defaults.backup.unwrap_or(true),
         }
     }
 
-    // replaces ~ with the literal path of the user's home directory, and ensures that there is no
-    // trailing slash.
-    fn standardize_path<T: Into<String>>(path: T, home_dir: T) -> String {
-        // convert both args to Strings
-        let home_dir: String = home_dir.into();
-        let mut path: String = path.into();
+    // removes the orphans that are still as rodeo deployed them, backing them up first unless
+    // their program has backups off, and forgets about all of them
+    fn remove_orphans(&self, orphans: &[Orphan], journal: &Journal, summary: &mut Summary, observer: &mut dyn Observer) {
+        let removed: Vec<String> = orphans.iter()
+            .filter(|o| !o.changed && !link::is_link(&o.destination) && self.backs_up(&o.program))
+            .map(|o| o.destination.clone())
+            .collect();
+        let events = backup::snapshot(&self.state_dir, &removed);
+        let failed = events.iter().any(|e| e.error.is_some());
+        for event in events {
+            summary.record(event, observer);
+        }
+        if failed {
+            summary.record(
+                Event::new(EventKind::File, "prune", "", "")
+                    .failed("not removing anything, since not every file could be backed up; fix that, or turn backups off with backup = false"),
+                observer,
+            );
+            return;
+        }
 
-        // replace ~ with the literal home dir String, I.E. /home/<user>
-        path = path.replace("~", home_dir.as_ref());
-        // ensure that the final character in the String isn't a trailing slash
-        match path.chars().last().unwrap_or_default() {
-            '/' => path.pop().unwrap_or_default(),
-            _ => '?',
-        };
+        let mut manifest = self.manifest();
+        for orphan in orphans {
+            let destination = &orphan.destination;
+            let event = Event::new(EventKind::File, "remove", &destination[..], "");
+            if orphan.changed {
+                summary.record(
+                    event.skipped(format!("leaving \"{}\" where it is: it is no longer in the config, but changed after rodeo deployed it", destination)),
+                    observer,
+                );
+            } else {
+                let removed = journal.before_write(destination)
+                    .and_then(|_| fs::remove_file(destination))
+                    .and_then(|_| journal.after_write(destination));
+                if let Err(e) = removed {
+                    summary.record(event.failed(format!("Error: could not remove \"{}\": {}", destination, e)), observer);
+                    continue;
+                }
+                summary.record(event, observer);
+            }
+            manifest.forget(destination);
+        }
+
+        let (owned, managed) = self.ownership();
+        manifest.forget_gone(&owned, &managed);
+        if let Err(e) = manifest.save() {
+            summary.record(
+                Event::new(EventKind::File, "manifest", &self.state_dir[..], "")
+                    .failed(format!("could not save the list of deployed files: {}", e)),
+                observer,
+            );
+        }
+    }
+
+    // every file in the trash, newest first
+    pub fn trash_items(&self) -> Vec<trash::Item> {
+        trash::list(&self.state_dir)
+    }
+
+    pub fn restore_trashed(self, ids: &[String]) -> Summary {
+        self.restore_trashed_with_observer(ids, &mut self.printer())
+    }
+
+    // moves the trashed files with the given ids back to where they were
+    pub fn restore_trashed_with_observer(&self, ids: &[String], observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        let items = self.trash_items();
+        for id in ids {
+            match items.iter().find(|i| &i.id == id) {
+                Some(item) => summary.record(trash::restore(&self.state_dir, &self.home, self.trash, item), observer),
+                None => summary.record(
+                    Event::new(EventKind::File, "restore", &id[..], "")
+                        .failed(format!("there is nothing called \"{}\" in the trash; run rodeo trash list to see what is", id)),
+                    observer,
+                ),
+            }
+        }
+        summary
+    }
+
+    pub fn empty_trash(self) -> Summary {
+        self.empty_trash_with_observer(&mut self.printer())
+    }
+
+    // removes every file rodeo put in the trash for good
+    pub fn empty_trash_with_observer(&self, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        for item in self.trash_items() {
+            summary.record(trash::remove(&self.state_dir, &item), observer);
+        }
+        summary
+    }
+
+    // every deploy this machine has seen that changed something, oldest first
+    pub fn generations(&self) -> Vec<Generation> {
+        generation::list(&self.state_dir)
+    }
+
+    pub fn rollback(self, number: Option<u64>) -> Summary {
+        self.rollback_with_observer(number, &mut self.printer())
+    }
+
+    // puts every file rodeo deployed back the way generation number had it, or the one before the
+    // latest if None: files that differ get the generation's contents, and files deployed since
+    // are removed unless they changed after. The files this overwrites are backed up first, what it
+    // overwrites and removes is journaled and goes to the trash like a prune's, and the rollback is
+    // a generation of its own, so it can be rolled back in turn. The repository isn't touched, so
+    // the next deploy undoes it.
+    pub fn rollback_with_observer(&self, number: Option<u64>, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        let generations = self.generations();
+        let wanted = match number {
+            Some(n) => generations.iter().find(|g| g.number == n).ok_or_else(|| {
+                format!("there is no generation {}; run rodeo generations to list them", n)
+            }),
+            None => generations.iter().rev().nth(1).ok_or_else(|| "there is no earlier generation to roll back to".to_owned()),
+        };
+        let wanted = match wanted {
+            Ok(val) => val,
+            Err(e) => {
+                summary.record(Event::new(EventKind::File, "rollback", "", "").failed(e), observer);
+                return summary;
+            }
+        };
+
+        let mut manifest = self.manifest();
+        let replaced: Vec<(&String, &Deployment)> = wanted.files.iter()
+            .filter(|(destination, deployment)| manifest::hash_of(destination).as_ref() != Some(&deployment.hash))
+            .collect();
+        let mut kept: BTreeMap<String, Deployment> = BTreeMap::new();
+        let mut removed: Vec<String> = Vec::new();
+        for (destination, deployment) in manifest.files.iter().filter(|(d, _)| !wanted.files.contains_key(*d)) {
+            if deployment.matches(destination) {
+                removed.push(destination.clone());
+            } else if fs::symlink_metadata(destination).is_ok() {
+                summary.record(
+                    Event::new(EventKind::File, "remove", &destination[..], "").skipped(format!(
+                        "leaving \"{}\" where it is: generation {} doesn't have it, but it changed after rodeo deployed it",
+                        destination, wanted.number
+                    )),
+                    observer,
+                );
+                kept.insert(destination.clone(), deployment.clone());
+            }
+        }
+
+        let overwritten: Vec<String> = replaced.iter()
+            .map(|(destination, deployment)| ((*destination).clone(), &deployment.program))
+            .chain(removed.iter().map(|destination| (destination.clone(), &manifest.files[destination].program)))
+            .filter(|(destination, program)| !link::is_link(destination) && path::Path::new(destination).is_file() && self.backs_up(program))
+            .map(|(destination, _)| destination)
+            .collect();
+        let events = backup::snapshot(&self.state_dir, &overwritten);
+        let failed = events.iter().any(|e| e.error.is_some());
+        for event in events {
+            summary.record(event, observer);
+        }
+        if failed {
+            summary.record(
+                Event::new(EventKind::File, "rollback", "", "")
+                    .failed("not rolling back, since not every file it would overwrite could be backed up; fix that, or turn backups off with backup = false"),
+                observer,
+            );
+            return summary;
+        }
+
+        let journal = match self.begin_journal(Operation::Rollback, &mut summary, observer) {
+            Some(val) => val,
+            None => return summary,
+        };
+        for (destination, deployment) in replaced {
+            let object = generation::object_path(&self.state_dir, &deployment.hash);
+            if let Err(e) = journal.before_write(destination) {
+                summary.record(
+                    Event::new(EventKind::File, "restore", &object[..], &destination[..])
+                        .failed(format!("Error: could not record \"{}\" in the journal: {}", destination, e)),
+                    observer,
+                );
+                continue;
+            }
+            let event = backup::put_back(&object, destination);
+            if event.result == EventResult::Error {
+                summary.record(event, observer);
+                continue;
+            }
+            summary.record(
+                match journal.after_write(destination) {
+                    Ok(_) => event,
+                    Err(e) => event.failed(format!("Error: could not record \"{}\" in the journal: {}", destination, e)),
+                },
+                observer,
+            );
+        }
+        for destination in removed {
+            let event = Event::new(EventKind::File, "remove", &destination[..], "");
+            let gone = journal.before_write(&destination)
+                .and_then(|_| fs::remove_file(&destination))
+                .and_then(|_| journal.after_write(&destination));
+            summary.record(
+                match gone {
+                    Ok(_) => event,
+                    Err(e) => event.failed(format!("Error: could not remove \"{}\": {}", destination, e)),
+                },
+                observer,
+            );
+        }
+        self.finish_journal(journal, &mut summary, observer);
+
+        kept.extend(wanted.files.clone());
+        manifest.set_files(kept);
+        let recorded = manifest.save()
+            .and_then(|_| generation::record(&self.state_dir, &manifest, &format!("rollback to {}", wanted.number)));
+        if let Err(e) = recorded {
+            summary.record(
+                Event::new(EventKind::File, "generation", &self.state_dir[..], "")
+                    .failed(format!("could not record the rollback: {}", e)),
+                observer,
+            );
+        }
+        summary
+    }
+
+    pub fn restore_pre_rodeo(self) -> Summary {
+        self.restore_pre_rodeo_with_observer(&mut self.printer())
+    }
+
+    // undoes rodeo's adoption of this machine by putting back every file that was archived before
+    // the first deploy
+    pub fn restore_pre_rodeo_with_observer(&self, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        for event in backup::restore_pre_rodeo(&self.state_dir) {
+            summary.record(event, observer);
+        }
+        summary
+    }
+
+    // every backed up version of the selected programs' files, for `rodeo restore` to list. Files
+    // without any are left out.
+    pub fn backups(&self) -> Vec<backup::FileBackups> {
+        let sets = backup::sets(&self.state_dir);
+        self.targets().into_iter()
+            .map(|target| backup::FileBackups {
+                versions: backup::versions(&sets, &target.deployed_file),
+                program: target.program,
+                path: target.path,
+                deployed_file: target.deployed_file,
+            })
+            .filter(|b| !b.versions.is_empty())
+            .collect()
+    }
+
+    pub fn restore(self, set: &str, files: &[String], to_repo: bool) -> Summary {
+        self.restore_with_observer(set, files, to_repo, &mut self.printer())
+    }
+
+    // copies the versions of files (every selected file if empty) in the backup set named set back
+    // into place, or into the repository if to_repo. "latest" picks each file's newest version.
+    // What is in place is backed up first like a deploy would, so a restore can be undone too.
+    pub fn restore_with_observer(&self, set: &str, files: &[String], to_repo: bool, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        let _umask = mode::Umask::set(self.umask());
+        let sets = backup::sets(&self.state_dir);
+        if set != "latest" && !sets.iter().any(|s| s.name == set) {
+            summary.record(
+                Event::new(EventKind::File, "restore", set, "")
+                    .failed(format!("there is no backup \"{}\"; run rodeo restore without arguments to list them", set)),
+                observer,
+            );
+            return summary;
+        }
+
+        let targets = self.targets();
+        let index = ReverseIndex::new(targets.clone());
+        let mut chosen: Vec<&Target> = Vec::new();
+        for file in files {
+            let found = index.lookup(file, &self.home);
+            if found.is_empty() {
+                summary.record(Event::new(EventKind::File, "restore", &file[..], "").failed(format!("\"{}\" is not managed by rodeo", file)), observer);
+            }
+            chosen.extend(found);
+        }
+        if files.is_empty() {
+            chosen = targets.iter().collect();
+        }
+
+        let mut restores = Vec::new();
+        for target in chosen {
+            let version = backup::versions(&sets, &target.deployed_file).into_iter().find(|v| set == "latest" || v.set == set);
+            let destination = if to_repo { &target.repo_file } else { &target.deployed_file };
+            match version {
+                Some(version) => {
+                    let keep = !to_repo && self.programs.iter().any(|i| i.name == target.program && self.defaults.backup(i));
+                    restores.push((version.backup, destination.clone(), keep));
+                }
+                // a set only holds the files its deploy overwrote, so the others aren't worth a mention
+                None if files.is_empty() => (),
+                None => summary.record(
+                    Event::new(EventKind::File, "restore", "", &destination[..]).skipped(format!("there is no backup of \"{}\" in {}", target.deployed_file, set)),
+                    observer,
+                ),
+            }
+        }
+
+        // the repository has its own history, so only deployed files are backed up
+        let overwritten: Vec<String> = restores.iter()
+            .filter(|(backup, destination, keep)| *keep && path::Path::new(destination).is_file() && fs::read(backup).ok() != fs::read(destination).ok())
+            .map(|(_, destination, _)| destination.clone())
+            .collect();
+        let events = backup::snapshot(&self.state_dir, &overwritten);
+        let failed = events.iter().any(|e| e.error.is_some());
+        for event in events {
+            summary.record(event, observer);
+        }
+        if failed {
+            summary.record(
+                Event::new(EventKind::File, "restore", "", "")
+                    .failed("not restoring, since not every file it would overwrite could be backed up; fix that, or turn backups off with backup = false"),
+                observer,
+            );
+            return summary;
+        }
+
+        for (backup, destination, _) in restores {
+            summary.record(backup::put_back(&backup, &destination), observer);
+        }
+        summary
+    }
+
+    pub fn revert(self, file: &str, commit: &str, message: Option<&str>, deploy: bool) -> Summary {
+        self.revert_with_observer(file, commit, message, deploy, &mut self.printer())
+    }
+
+    // puts the repository copy of file (a path as `rodeo which` takes it) back the way it was as
+    // of commit and commits just that, with message if given, then deploys it if deploy is set.
+    // Nothing is pushed until the next sync.
+    pub fn revert_with_observer(&self, file: &str, commit: &str, message: Option<&str>, deploy: bool, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        let _umask = mode::Umask::set(self.umask());
+        let index = ReverseIndex::new(self.targets());
+        let target = match index.lookup(file, &self.home)[..] {
+            [target] => target.clone(),
+            [] => {
+                summary.record(Event::new(EventKind::File, "revert", commit, file).failed(format!("\"{}\" is not managed by rodeo", file)), observer);
+                return summary;
+            }
+            ref found => {
+                let files: Vec<String> = found.iter().map(|t| t.deployed_file.clone()).collect();
+                summary.record(
+                    Event::new(EventKind::File, "revert", commit, file)
+                        .failed(format!("\"{}\" could mean any of {}; give its full path", file, vcs::short_list(&files, "no files"))),
+                    observer,
+                );
+                return summary;
+            }
+        };
+        let repo = self.programs.iter()
+            .find(|i| i.name == target.program)
+            .map(|i| i.repository(&self.home, &self.dotfiles_directory))
+            .unwrap_or_else(|| self.dotfiles_directory.clone());
+        let relative = target.repo_relative(&repo).unwrap_or_default().to_owned();
+
+        let message = message.map(str::to_owned).unwrap_or_else(|| format!("rodeo revert {} to {}", relative, commit));
+        let backend = self.backend_committing(&message);
+        let event = Event::new(EventKind::File, "revert", &format!("{}:{}", commit, relative)[..], &target.repo_file[..]);
+        let contents = match backend.show_file(&repo, commit, &relative) {
+            Ok(val) => val,
+            Err(e) => {
+                summary.record(event.failed(e), observer);
+                return summary;
+            }
+        };
+        if fs::read(&target.repo_file).ok().as_ref() == Some(&contents) {
+            summary.record(event.unchanged(), observer);
+        } else {
+            let journal = match self.begin_journal(Operation::Revert, &mut summary, observer) {
+                Some(val) => val,
+                None => return summary,
+            };
+            let written = Program::revert_file(&contents, &target.repo_file, &self.watched_copier(observer), &journal);
+            self.finish_journal(journal, &mut summary, observer);
+            if let Err(reason) = written {
+                summary.record(event.failed(reason), observer);
+                return summary;
+            }
+            summary.record(event, observer);
+        }
+
+        // the file may have been put back by hand before, so it is committed either way
+        let committed = backend.commit(&repo, &[relative]);
+        let failed = committed.result == EventResult::Error;
+        summary.record(committed, observer);
+        if failed || !deploy {
+            return summary;
+        }
+
+        let mut narrowed = self.clone();
+        narrowed.programs.retain(|i| i.name == target.program);
+        narrowed.select_paths(PathFilter::new(&[format!("{}/{}", target.program, target.path)], &[]));
+        summary.merge(narrowed.deploy_with_observer(observer));
+        summary
+    }
+
+    // sync runs in three phases: pull from the remote, sync the repository folder with the files
+    // on this system, then push the result back to the remote. scope says which phases run.
+    pub fn sync(self, scope: SyncScope) -> Summary {
+        self.sync_with_observer(scope, &mut self.printer())
+    }
+
+    pub fn sync_with_observer(&self, scope: SyncScope, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        let _umask = mode::Umask::set(self.umask());
+        let journal = match self.begin_journal(Operation::sync(scope), &mut summary, observer) {
+            Some(val) => val,
+            None => return summary,
+        };
+
+        // sync works on the config's branch, if it names one; should another one be checked out
+        // and it can't be switched to, nothing is synced
+        if scope.pull || scope.push {
+            if let Some(event) = self.switch_branch() {
+                let failed = event.result == EventResult::Error;
+                summary.record(event, observer);
+                if failed {
+                    self.finish_journal(journal, &mut summary, observer);
+                    return summary;
+                }
+            }
+        }
+
+        // phase 1: pull before doing anything, so the local sync sees the remote's changes. What
+        // the pull would deploy is shown first, and the whole sync is off if it's turned down.
+        if scope.pull {
+            for repo in self.repositories() {
+                if !self.review_incoming(&repo, &mut summary, observer) {
+                    self.finish_journal(journal, &mut summary, observer);
+                    return summary;
+                }
+            }
+            for repo in self.repositories() {
+                if !self.pull_protected(&repo, &mut summary, observer) {
+                    self.finish_journal(journal, &mut summary, observer);
+                    return summary;
+                }
+            }
+        }
+
+        // phase 2: whichever copy of each file changed since the last sync wins, or was modified
+        // last if rodeo doesn't know
+        let programs: Vec<&Program> = self.programs.iter().collect();
+        self.each_program(&programs, &mut summary, observer, |i, jobs, observer| {
+            i.sync_local(&self.home, &self.dotfiles_directory, self.defaults.sync_direction(i), &self.resolver(), &self.watched_copier(observer), &journal, jobs, observer)
+        });
+
+        // phase 3: send the result back up, binary files through Git LFS if that's on. Should
+        // that fail they aren't pushed, as they would go into the history as they are.
+        if scope.push {
+            for repo in self.repositories() {
+                if let Some(tracked) = self.track_binary(&repo) {
+                    let failed = tracked.result == EventResult::Error;
+                    summary.record(tracked, observer);
+                    if failed {
+                        continue;
+                    }
+                }
+                for event in self.vcs_push(&repo, &journal) {
+                    summary.record(event, observer);
+                }
+            }
+        }
+
+        self.finish_journal(journal, &mut summary, observer);
+        summary
+    }
+
+    pub fn ensure_repository(&self) -> Summary {
+        self.ensure_repository_with_observer(&mut self.printer())
+    }
+
+    // whether ensure_repository would clone remote, i.e. there is one and dotfiles_directory isn't
+    // a repository yet
+    pub fn needs_clone(&self) -> bool {
+        !self.remote.is_empty() && !path::Path::new(&self.dotfiles_directory).join(".git").exists()
+    }
+
+    // clones remote into dotfiles_directory if it isn't a repository yet, so that a new machine
+    // only needs rodeo.toml. Does nothing without a remote. A directory that already holds other
+    // files is left alone rather than cloned over.
+    pub fn ensure_repository_with_observer(&self, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        if !self.needs_clone() {
+            return summary;
+        }
+        let dir = path::Path::new(&self.dotfiles_directory);
+
+        let event = Event::new(EventKind::Vcs, "clone", &self.remote[..], &self.dotfiles_directory[..]);
+        let occupied = fs::read_dir(dir).map(|mut entries| entries.next().is_some()).unwrap_or(false);
+        let event = if occupied {
+            event.failed(format!(
+                "{} isn't a repository but isn't empty either, so {} wasn't cloned into it; move it out of the way or clone it by hand",
+                self.dotfiles_directory, self.remote
+            ))
+        } else {
+            self.backend().clone_repo(&self.remote, &self.branch, self.clone_depth, &self.clone_filter, &self.dotfiles_directory)
+        };
+        summary.record(event, observer);
+        summary
+    }
+
+    // the operation left unfinished by an interrupted run, if any. It has to be resumed or
+    // aborted before anything else can write files.
+    pub fn interrupted(&self) -> Option<Operation> {
+        Journal::pending(&self.state_dir)
+    }
+
+    pub fn resume(self) -> Summary {
+        self.resume_with_observer(&mut self.printer())
+    }
+
+    // finishes an interrupted operation by running it again from the start. Every operation only
+    // ever makes the destination match the source, so repeating the steps that did complete is
+    // harmless.
+    pub fn resume_with_observer(&self, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        let operation = match self.interrupted() {
+            Some(val) => val,
+            None => {
+                summary.record(
+                    Event::new(EventKind::File, "resume", &self.state_dir[..], "")
+                        .skipped("no interrupted operation to resume"),
+                    observer,
+                );
+                return summary;
+            }
+        };
+
+        if let Err(e) = Journal::discard(&self.state_dir) {
+            summary.record(
+                Event::new(EventKind::File, "resume", &self.state_dir[..], "")
+                    .failed(format!("could not clear the interrupted {}'s journal: {}", operation.name(), e)),
+                observer,
+            );
+            return summary;
+        }
+
+        summary.merge(match operation {
+            Operation::Deploy => self.deploy_with_observer(observer),
+            Operation::Collect => self.collect_with_observer(observer),
+            Operation::Sync { pull, push } => self.sync_with_observer(SyncScope { pull, push }, observer),
+            Operation::Unlink => self.unlink_with_observer(observer),
+            Operation::Prune => self.prune_with_observer(observer),
+            // the file and commit a revert was given and the generation a rollback went back to
+            // aren't kept, so those can only be undone
+            Operation::Revert | Operation::Rollback => self.abort_with_observer(observer),
+        });
+        summary
+    }
+
+    pub fn abort(self) -> Summary {
+        self.abort_with_observer(&mut self.printer())
+    }
+
+    // undoes everything an interrupted operation had written, putting files back the way they were
+    // before it started
+    pub fn abort_with_observer(&self, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        if self.interrupted().is_none() {
+            summary.record(
+                Event::new(EventKind::File, "abort", &self.state_dir[..], "")
+                    .skipped("no interrupted operation to abort"),
+                observer,
+            );
+            return summary;
+        }
+
+        for event in Journal::roll_back(&self.state_dir) {
+            summary.record(event, observer);
+        }
+        summary
+    }
+
+    // the old entry points, kept for compatibility. sync_remote and sync_full used to differ only
+    // in whether the local sync happened before or after pulling; both now pull first.
+
+    pub fn sync_local(self) -> Summary {
+        self.sync(SyncScope::local_only())
+    }
+
+    pub fn sync_local_with_observer(&self, observer: &mut dyn Observer) -> Summary {
+        self.sync_with_observer(SyncScope::local_only(), observer)
+    }
+
+    pub fn sync_remote(self) -> Summary {
+        self.sync(SyncScope::full())
+    }
+
+    pub fn sync_remote_with_observer(&self, observer: &mut dyn Observer) -> Summary {
+        self.sync_with_observer(SyncScope::full(), observer)
+    }
+
+    pub fn sync_full(self) -> Summary {
+        self.sync(SyncScope::full())
+    }
+
+    pub fn sync_full_with_observer(&self, observer: &mut dyn Observer) -> Summary {
+        self.sync_with_observer(SyncScope::full(), observer)
+    }
+
+    // the commits that changed program's files in its repository, or only file's (a path as
+    // `rodeo which` takes it) if given, newest first
+    pub fn history(&self, program: &str, file: Option<&str>) -> Result<Vec<plan::Revision>, String> {
+        let (repo, files) = self.history_files(program, file)?;
+        self.backend().history(&repo, &files)
+    }
+
+    // the contents program's file had as of commit
+    pub fn file_at(&self, program: &str, file: &str, commit: &str) -> Result<Vec<u8>, String> {
+        let (repo, files) = self.history_files(program, Some(file))?;
+        match &files[..] {
+            [file] => self.backend().show_file(&repo, commit, file),
+            _ => Err(format!("\"{}\" could mean any of {}; give its path in the repository", file, vcs::short_list(&files, "no files"))),
+        }
+    }
+
+    // the repository program's files are kept in, and the ones meant (relative to it)
+    fn history_files(&self, program: &str, file: Option<&str>) -> Result<(String, Vec<String>), String> {
+        let found = self.programs.iter().find(|i| i.name == program)
+            .ok_or_else(|| format!("no program named \"{}\" in the config", program))?;
+        let repo = found.repository(&self.home, &self.dotfiles_directory);
+        let targets = found.deploy_targets(&self.home, &self.dotfiles_directory);
+        let relative = |t: &&Target| t.repo_relative(&repo).map(str::to_owned);
+        let files: Vec<String> = match file {
+            Some(query) => ReverseIndex::new(targets).lookup(query, &self.home).iter().filter_map(relative).collect(),
+            None => targets.iter().filter_map(|t| relative(&t)).collect(),
+        };
+        if files.is_empty() {
+            return Err(match file {
+                Some(query) => format!("\"{}\" is not one of {}'s files", query, program),
+                None => format!("{} has no files in {}", program, repo),
+            });
+        }
+        Ok((repo, files))
+    }
+
+    // finds every line containing pattern in the repository copies of the selected programs' files
+    pub fn grep(&self, pattern: &str, ignore_case: bool) -> Vec<search::Match> {
+        search::grep(&self.programs, &self.home, &self.dotfiles_directory, pattern, ignore_case)
+    }
+
+    // what operation would do to the selected programs' files, without doing it
+    pub fn plan(&self, operation: Operation) -> plan::Plan {
+        plan::plan(operation, &self.programs, &self.home, &self.dotfiles_directory)
+    }
+
+    // every managed file of the selected programs whose deployed copy differs from the repository
+    pub fn drift(&self) -> Vec<plan::Drift> {
+        plan::drift(&self.programs, &self.home, &self.dotfiles_directory)
+    }
+
+    // keeps the status cache up to date as the selected programs' files change, so that status and
+    // tree needn't look at every file; see watch.rs. Runs until stopped.
+    pub fn watch(self) -> Summary {
+        self.watch_with_observer(&mut self.printer())
+    }
+
+    pub fn watch_with_observer(&self, observer: &mut dyn Observer) -> Summary {
+        watch::run(self, observer)
+    }
+
+    // where each selected program comes from and how its files stand, base programs first, with
+    // how the repository it is kept in stands
+    pub fn status(&self) -> Vec<plan::ProgramStatus> {
+        let mut cache = self.status_cache();
+        let backend = self.backend();
+        let mut repositories: BTreeMap<String, Option<plan::RepoStatus>> = BTreeMap::new();
+        let status = self.programs.iter()
+            .map(|i| {
+                let source = if i.source.is_empty() { &self.config_path } else { &i.source };
+                let mut status = plan::ProgramStatus::new(i, &self.home, &self.dotfiles_directory, source, &mut cache);
+                status.git = repositories.entry(status.repository.clone())
+                    .or_insert_with(|| backend.repo_status(&status.repository))
+                    .clone();
+                status
+            })
+            .collect();
+        cache.save();
+        status
+    }
+
+    // the states of managed files as last worked out on this machine; see cache.rs
+    pub fn status_cache(&self) -> StatusCache {
+        StatusCache::load(&self.state_dir)
+    }
+
+    // a patch that makes the repository match the deployed copies of the selected programs' files,
+    // for carrying changes from a machine that can't push. host names the machine in the patch.
+    pub fn export_drift(&self, host: &str) -> String {
+        drift::drift_patch(&self.targets(), &self.dotfiles_directory, host)
+    }
+
+    pub fn apply_drift(self, patch_file: &str, commit: bool) -> Summary {
+        self.apply_drift_with_observer(patch_file, commit, &mut self.printer())
+    }
+
+    // applies a patch written by export_drift (on another machine) to the dotfiles directory, and
+    // commits it if asked to. Deployed files aren't touched; that's up to a later deploy.
+    pub fn apply_drift_with_observer(&self, patch_file: &str, commit: bool, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+
+        // the vcs runs from inside the dotfiles directory, so relative paths would break
+        let patch_file = match fs::canonicalize(patch_file) {
+            Ok(val) => val.to_string_lossy().into_owned(),
+            Err(e) => {
+                summary.record(
+                    Event::new(EventKind::Vcs, "apply", patch_file, &self.dotfiles_directory[..])
+                        .failed(format!("could not read patch \"{}\": {}", patch_file, e)),
+                    observer,
+                );
+                return summary;
+            }
+        };
+
+        let vcs = self.backend();
+        summary.record(vcs.apply_patch(&self.dotfiles_directory, &patch_file, commit), observer);
+        summary
+    }
+
+    // well-known dotfiles in the home directory that no program in the config manages yet
+    pub fn scan(&self) -> Vec<scan::Candidate> {
+        scan::scan(&self.programs, &self.home, &self.dotfiles_directory)
+    }
+
+    pub fn import(self, candidates: &[scan::Candidate]) -> Summary {
+        self.import_with_observer(candidates, &mut self.printer())
+    }
+
+    // appends a [[program]] entry for each candidate to the config file, then collects their files
+    // into the repository
+    pub fn import_with_observer(&self, candidates: &[scan::Candidate], observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+
+        let event = Event::new(EventKind::File, "import", &self.config_path[..], "");
+        if self.format != ConfigFormat::Toml {
+            summary.record(event.failed(format!("can't add programs to \"{}\": only TOML config files can be edited", self.config_path)), observer);
+            return summary;
+        }
+
+        let entries: Vec<String> = candidates.iter().map(|c| c.to_toml()).collect();
+        let appended = edit::rewrite(&self.config_path, self.loaded.get(&self.config_path).copied(), |contents| {
+            Ok(format!("{}\n{}", contents, entries.join("\n")))
+        });
+        if let Err(e) = appended {
+            summary.record(
+                event.failed(format!("could not add the new programs to \"{}\": {}", self.config_path, e)),
+                observer,
+            );
+            return summary;
+        }
+        summary.record(event, observer);
+
+        let mut imported = self.clone();
+        imported.programs = candidates.iter()
+            .map(|c| Program { repo_layout: self.layout, undotted: self.strip_dots, deploy_with: self.deploy_mode, ..c.to_program() })
+            .collect();
+        summary.merge(imported.collect_with_observer(observer));
+        summary
+    }
+
+    pub fn set_enabled(self, name: &str, enabled: bool) -> Summary {
+        self.set_enabled_with_observer(name, enabled, &mut self.printer())
+    }
+
+    // rewrites the config file so that the program called name is enabled or disabled, leaving the
+    // rest of the file as it was
+    pub fn set_enabled_with_observer(&self, name: &str, enabled: bool, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        let action = if enabled { "enable" } else { "disable" };
+        // the program may live in an included file rather than rodeo.toml itself
+        let path = self.programs.iter()
+            .find(|i| i.name == name && !i.source.is_empty())
+            .map_or(&self.config_path, |i| &i.source);
+        let event = Event::new(EventKind::File, action, name, &path[..]);
+
+        // the base config is shared, so the program is switched off in the user's own config instead
+        if self.programs.iter().any(|i| i.name == name && i.layer == Layer::Base) {
+            summary.record(
+                event.failed(format!(
+                    "could not {} \"{}\": it comes from the base config; add [[program]] with name = \"{}\" and enabled = {} to your own config instead",
+                    action, name, name, enabled
+                )),
+                observer,
+            );
+            return summary;
+        }
+
+        if ConfigFormat::from_path(path) != ConfigFormat::Toml || (path == &self.config_path && self.format != ConfigFormat::Toml) {
+            summary.record(event.failed(format!("could not {} \"{}\": only TOML config files can be edited", action, name)), observer);
+            return summary;
+        }
+
+        let edited = edit::rewrite(path, self.loaded.get(path).copied(), |contents| {
+            edit::set_program_key(contents, name, "enabled", &enabled.to_string())
+        });
+
+        match edited {
+            Ok(_) => summary.record(event, observer),
+            Err(e) => summary.record(event.failed(format!("could not {} \"{}\": {}", action, name, e)), observer),
+        }
+        summary
+    }
+
+    pub fn migrate(self) -> Summary {
+        self.migrate_with_observer(&mut self.printer())
+    }
+
+    // rewrites the config file and the files it includes in the current version of the config
+    // format, leaving their comments and formatting as they were. The base config is left alone;
+    // it is upgraded as it is read, until whoever looks after it migrates it.
+    pub fn migrate_with_observer(&self, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        if self.written_in >= schema::CURRENT {
+            let event = Event::new(EventKind::File, "migrate", &self.config_path[..], "");
+            summary.record(event.skipped(format!("already written in version {} of the config format", schema::CURRENT)), observer);
+            return summary;
+        }
+
+        for path in self.own_files.iter() {
+            let event = Event::new(EventKind::File, "migrate", &path[..], "");
+            if ConfigFormat::from_path(path) != ConfigFormat::Toml || (path == &self.config_path && self.format != ConfigFormat::Toml) {
+                summary.record(event.failed(format!("could not migrate \"{}\": only TOML config files can be edited", path)), observer);
+                continue;
+            }
+            let edited = edit::rewrite(path, self.loaded.get(path).copied(), |contents| {
+                schema::upgrade_toml(contents, self.written_in, path == &self.config_path)
+            });
+            match edited {
+                Ok(_) => summary.record(event, observer),
+                Err(e) => summary.record(event.failed(format!("could not migrate \"{}\": {}", path, e)), observer),
+            }
+        }
+        summary
+    }
+
+    // writes the config to path, in the format path's extension asks for, with a canonical layout:
+    // top level keys, then programs, then groups, leaving out keys at their default values.
+    // Programs and groups read from included files are left out, as that is where they live.
+    // Comments and formatting aren't kept (edit does that for single keys), and values are written
+    // as loaded, i.e. with environment variables expanded. Overwriting a config file that changed
+    // since it was loaded fails rather than losing those changes.
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut saved = self.clone();
+        saved.programs.retain(|p| p.source.is_empty() || p.source == self.config_path);
+        saved.groups.retain(|_, g| g.source.is_empty() || g.source == self.config_path);
+        // ~ is how the home directory was most likely written in the first place
+        let home = self.home.clone();
+        let contract = |dir: &mut String| *dir = paths::contract_home(dir, &home);
+        contract(&mut saved.dotfiles_directory);
+        for i in saved.programs.iter_mut() {
+            contract(&mut i.dotfiles_directory);
+        }
+
+        // toml can't write the { src, dest, mode, direction, template } tables of paths in the middle of a
+        // table, so paths holding any are written as plain strings first and replaced afterwards
+        let format = ConfigFormat::from_path(path);
+        let renamed: Vec<(String, String)> = saved.programs.iter()
+            .filter(|i| format == ConfigFormat::Toml && i.paths.iter().any(|p| matches!(p, PathEntry::Table { .. })))
+            .map(|i| (i.name.clone(), i.paths_toml()))
+            .collect();
+        for i in saved.programs.iter_mut().filter(|i| renamed.iter().any(|(name, _)| name == &i.name)) {
+            i.paths = i.paths.iter().map(|p| PathEntry::Plain(p.src().to_owned())).collect();
+        }
+        let mut contents = format.serialize(&saved)
+            .map_err(|e| format!("could not serialize the config: {}", e))?;
+        for (name, paths) in renamed.iter() {
+            contents = edit::set_program_key(&contents, name, "paths", paths)?;
+        }
+        if path::Path::new(path).exists() {
+            edit::rewrite(path, self.loaded.get(path).copied(), |_| Ok(contents))
+        } else {
+            fs::write(path, contents).map_err(|e| format!("could not write \"{}\": {}", path, e))
+        }
+    }
+
+    // the observer the op(self) forms of the operations report to
+    pub fn printer(&self) -> Printer {
+        Printer { mode: self.output, yes: self.yes, term: self.terminal }
+    }
+
+    // copies the files of program that this user may not write, which deploy left out, with one run
+    // of the escalation helper, before the program's post-deploy command. That needs the
+    // observer's agreement; without it they are skipped.
+    fn deploy_escalated(&self, program: &Program, journal: &Journal, summary: &mut Summary, observer: &mut dyn Observer) {
+        let helper = match self.escalation() {
+            Some(val) if program.deploy_with == DeployMode::Copy => val,
+            _ => return,
+        };
+        let targets: Vec<Target> = program.targets_on(&self.home, &self.dotfiles_directory, Side::Repo).into_iter()
+            .filter(|t| t.direction.deploys() && escalate::needed(t))
+            .collect();
+        if targets.is_empty() {
+            return;
+        }
+
+        let event = |t: &Target| Event::new(EventKind::File, "copy".to_owned(), paths::normalize(&t.repo_file), paths::normalize(&t.deployed_file));
+        if !observer.confirm_escalation(helper, &targets) {
+            for target in targets.iter() {
+                let reason = format!("not copying to \"{}\", which this user may not write; only through {}", target.deployed_file, helper);
+                summary.record(event(target).skipped(reason), observer);
+            }
+            return;
+        }
+
+        for target in targets.iter() {
+            if let Err(e) = journal.before_write(&target.deployed_file) {
+                summary.record(event(target).failed(format!("Error: could not record \"{}\" in the journal: {}", target.deployed_file, e)), observer);
+                return;
+            }
+        }
+        let copied = escalate::copy_all(helper, &targets, &self.audit_log());
+        for target in targets.iter() {
+            let event = match (&copied, journal.after_write(&target.deployed_file)) {
+                (Ok(_), Ok(_)) => event(target),
+                (Ok(_), Err(e)) => event(target).failed(format!("Error: could not record \"{}\" in the journal: {}", target.deployed_file, e)),
+                (Err(e), _) => event(target).failed(format!("Error: could not copy to \"{}\": {}", target.deployed_file, e)),
+            };
+            summary.record(event, observer);
+        }
+    }
+
+    // copies files the way the config asks for
+    pub fn copier(&self) -> Copier {
+        Copier {
+            method: self.copy_method,
+            buffer_size: self.copy_buffer_size.unwrap_or(copy::DEFAULT_BUFFER_SIZE),
+            rate_limit: self.rate_limit(),
+            umask: self.umask(),
+            mode: None,
+            progress: None,
+        }
+    }
+
+    // the copier, reporting the progress of large copies to observer
+    fn watched_copier(&self, observer: &dyn Observer) -> Copier {
+        Copier { progress: observer.copy_progress(), ..self.copier() }
+    }
+
+    // how many files deploy, collect and sync work on at once. While limit_rate is set they go one
+    // at a time, as the limit is kept per copy.
+    pub fn jobs(&self) -> usize {
+        match self.rate_limit() {
+            Some(_) => 1,
+            None => self.jobs.unwrap_or(1).max(1),
+        }
+    }
+
+    // works on each of programs with work, which is handed how many of the program's files it may
+    // work on at once and what to report to. With jobs, up to that many programs are worked on at
+    // once, a file at a time each, and what each one reported is handed on in one piece, in the
+    // programs' order, so the output stays grouped by program. A single program gets all the jobs.
+    fn each_program<W>(&self, programs: &[&Program], summary: &mut Summary, observer: &mut dyn Observer, work: W)
+    where
+        W: Fn(&Program, usize, &mut dyn Observer) -> Summary + Sync,
+    {
+        let jobs = self.jobs();
+        if jobs <= 1 || programs.len() <= 1 {
+            for i in programs {
+                observer.program_started(i);
+                summary.merge(work(i, jobs, observer));
+            }
+            return;
+        }
+
+        let run = |i: &&Program| {
+            let mut buffer = Buffer::default();
+            (work(i, 1, &mut buffer), buffer)
+        };
+        // the results come in the programs' order
+        let mut started = programs.iter();
+        parallel::for_each(programs, jobs, run, |(done, buffer)| {
+            if let Some(i) = started.next() {
+                observer.program_started(i);
+            }
+            buffer.replay(observer);
+            summary.merge(done);
+        });
+    }
+
+    // the umask setting, if there is one
+    fn umask(&self) -> Option<u32> {
+        if self.umask.is_empty() { None } else { mode::parse(&self.umask).ok() }
+    }
+
+    // the command files this user may not write are deployed through, unless --no-escalate was given
+    pub fn escalation(&self) -> Option<&str> {
+        match (self.no_escalate, self.escalate.trim()) {
+            (true, _) => None,
+            (false, "") => Some(escalate::DEFAULT_HELPER),
+            (false, helper) => Some(helper),
+        }
+    }
+
+    // limit_rate in bytes a second, if there is one
+    fn rate_limit(&self) -> Option<u64> {
+        if self.limit_rate.is_empty() { None } else { copy::parse_rate(&self.limit_rate).ok() }
+    }
+
+    // every repository the selected programs' files live in: dotfiles_directory, then the
+    // programs' own ones in config order
+    pub fn repositories(&self) -> Vec<String> {
+        let mut repos = vec![self.dotfiles_directory.clone()];
+        for i in self.programs.iter() {
+            let repo = i.repository(&self.home, &self.dotfiles_directory);
+            if !repos.contains(&repo) {
+                repos.push(repo);
+            }
+        }
+        repos
+    }
+
+    // the user's home directory, which ~ in the config expands to
+    pub fn home(&self) -> &str {
+        &self.home
+    }
+
+    // the config files read along with the config itself: those it includes (Personal), and the
+    // base config with the files that includes (Base)
+    pub fn read_files(&self) -> Vec<(String, Layer)> {
+        self.loaded.keys()
+            .filter(|f| **f != self.config_path)
+            .map(|f| (f.clone(), if self.own_files.contains(f) { Layer::Personal } else { Layer::Base }))
+            .collect()
+    }
+
+    // every managed file of the selected programs, in config order
+    pub fn targets(&self) -> Vec<Target> {
+        self.programs.iter()
+            .flat_map(|i| i.deploy_targets(&self.home, &self.dotfiles_directory))
+            .collect()
+    }
+
+    // starts the journal for an operation. If that fails the operation must not run, which is
+    // reported through summary and signalled by returning None.
+    fn begin_journal(&self, operation: Operation, summary: &mut Summary, observer: &mut dyn Observer) -> Option<Journal> {
+        match Journal::begin(&self.state_dir, operation) {
+            Ok(val) => Some(val),
+            Err(e) => {
+                summary.record(
+                    Event::new(EventKind::File, operation.name(), &self.state_dir[..], "")
+                        .failed(format!("could not start {}: {}", operation.name(), e)),
+                    observer,
+                );
+                None
+            }
+        }
+    }
+
+    // also remembers every file the operation left the same on both sides, as the base the next
+    // sync goes by and in the state manifest
+    fn finish_journal(&self, journal: Journal, summary: &mut Summary, observer: &mut dyn Observer) {
+        self.trash_replaced(&journal, summary, observer);
+        if let Err(e) = journal.finish() {
+            summary.record(
+                Event::new(EventKind::File, "journal", &self.state_dir[..], "")
+                    .failed(format!("could not remove the journal: {}", e)),
+                observer,
+            );
+        }
+        let resolver = self.resolver();
+        for target in self.targets().iter().filter(|t| !link::linked(&t.deployed_file, &t.repo_file)) {
+            resolver.record(target);
+        }
+
+        let mut manifest = self.manifest();
+        for i in self.programs.iter() {
+            for target in i.deploy_targets(&self.home, &self.dotfiles_directory).iter().filter(|t| t.direction.deploys()) {
+                manifest.record(target, i.deploy_with);
+            }
+        }
+        if let Err(e) = manifest.save() {
+            summary.record(
+                Event::new(EventKind::File, "manifest", &self.state_dir[..], "")
+                    .failed(format!("could not save the list of deployed files: {}", e)),
+                observer,
+            );
+        }
+    }
+
+    // every file rodeo has deployed on this machine
+    pub fn manifest(&self) -> StateManifest {
+        StateManifest::load(&self.state_dir)
+    }
+
+    // how sync settles files that differ on both sides
+    pub fn resolver(&self) -> Resolver {
+        Resolver::new(&self.state_dir, self.prefer)
+    }
+
+    // moves the copies journal kept of the files its operation overwrote or removed into the trash,
+    // then removes what has been there longer than trash_days or doesn't fit in trash_size. Only
+    // failures are reported; the files themselves were already listed as they were written.
+    fn trash_replaced(&self, journal: &Journal, summary: &mut Summary, observer: &mut dyn Observer) {
+        if self.trash == TrashKind::Off {
+            return;
+        }
+        let operation = journal.operation().name();
+        for (destination, backup) in journal.replaced().unwrap_or_default() {
+            if let Err(e) = trash::put(&self.state_dir, &self.home, self.trash, &backup, &destination, operation) {
+                summary.record(
+                    Event::new(EventKind::File, "trash", &destination[..], "")
+                        .failed(format!("could not put the old \"{}\" in the trash: {}", destination, e)),
+                    observer,
+                );
+            }
+        }
+
+        let max_size = if self.trash_size.is_empty() { None } else { copy::parse_rate(&self.trash_size).ok() };
+        for event in trash::expire(&self.state_dir, self.trash_days.unwrap_or(trash::DEFAULT_DAYS), max_size) {
+            if event.result == EventResult::Error {
+                summary.record(event, observer);
+            }
+        }
+    }
+
+    // the vcs backend the config selects
+    fn backend(&self) -> Box<dyn vcs::Vcs> {
+        self.backend_committing(self.defaults.commit_message())
+    }
+
+    // the vcs backend, committing with message
+    fn backend_committing(&self, message: &str) -> Box<dyn vcs::Vcs> {
+        let signing = self.defaults.signing().map(|key| paths::expand_home(key, self.home()));
+        vcs::from_kind(self.vcs, &self.rsync_target, message, signing.as_deref(), self.defaults.pull_strategy, self.rate_limit(), self.audit_log())
+    }
+
+    // the log of every hook and vcs command rodeo has run on this machine
+    pub fn audit_log(&self) -> AuditLog {
+        AuditLog::new(&self.state_dir)
+    }
+
+    // helper methods //
+
+    // checks out the remote's revision of repo next to it and asks observer whether the files it
+    // would overwrite may be overwritten. Backends that can't preview are always allowed through.
+    fn review_incoming(&self, repo: &str, summary: &mut Summary, observer: &mut dyn Observer) -> bool {
+        let vcs = self.backend();
+        let worktree_dir = preview::worktree_dir(&self.state_dir);
+
+        let fetched = match vcs.fetch_incoming(repo, &worktree_dir) {
+            Some(val) => val,
+            None => return true,
+        };
+        if fetched.result == EventResult::Error {
+            summary.record(fetched, observer);
+            vcs.remove_incoming(repo, &worktree_dir);
+            return false;
+        }
+
+        let changes = preview::incoming_changes(&self.targets(), repo, &worktree_dir);
+        let accepted = changes.is_empty() || observer.confirm_incoming(&changes);
+        vcs.remove_incoming(repo, &worktree_dir);
+
+        if !accepted {
+            summary.record(
+                Event::new(EventKind::Vcs, "pull", repo, "")
+                    .skipped("incoming changes were not accepted, not syncing"),
+                observer,
+            );
+        }
+        accepted
+    }
+
+    // checks out the branch the config names in dotfiles_directory, if another one is. None if
+    // it names none or it already is checked out.
+    fn switch_branch(&self) -> Option<Event> {
+        if self.branch.is_empty() || self.vcs == VcsKind::None {
+            return None;
+        }
+        self.backend().switch_branch(&self.dotfiles_directory, &self.branch)
+    }
+
+    // pulls into repo, first putting uncommitted changes in it aside if stash_before_pull is on and
+    // bringing them back afterwards. False if they can't be brought back cleanly, or are in the
+    // way of a pull that failed, in which case nothing more should be synced.
+    fn pull_protected(&self, repo: &str, summary: &mut Summary, observer: &mut dyn Observer) -> bool {
+        let backend = self.backend();
+        let uncommitted = if self.base_repository(repo) { Vec::new() } else { backend.changed_files(repo) };
+        let stash = !uncommitted.is_empty() && self.defaults.stash_before_pull;
+        if stash {
+            let stashed = backend.stash(repo);
+            let failed = stashed.result == EventResult::Error;
+            summary.record(stashed, observer);
+            if failed {
+                return false;
+            }
+        }
+
+        let pulled = self.vcs_pull(repo);
+        let pull_failed = pulled.result == EventResult::Error;
+        summary.record(pulled, observer);
+        // submodules (such as plugins) follow the commits the pull brought in for them
+        if !pull_failed {
+            if let Some(updated) = backend.update_submodules(repo) {
+                summary.record(updated, observer);
+            }
+        }
+
+        if stash {
+            let unstashed = backend.unstash(repo);
+            let failed = unstashed.result == EventResult::Error;
+            summary.record(unstashed, observer);
+            return !failed;
+        }
+        if pull_failed && !uncommitted.is_empty() {
+            summary.record(
+                Event::new(EventKind::Vcs, "pull", repo, "").failed(format!(
+                    "{} has uncommitted changes to {}, which may have kept the pull from going through; commit them or set stash_before_pull = true in [defaults]. Not syncing",
+                    repo,
+                    vcs::short_list(&uncommitted, "no files")
+                )),
+                observer,
+            );
+            return false;
+        }
+        true
+    }
+
+    fn vcs_pull(&self, repo: &str) -> Event {
+        match self.extra_rsync_repo(repo, "pull") {
+            Some(skipped) => skipped,
+            None => self.backend().pull(repo),
+        }
+    }
+
+    // commits local changes in repo and pushes them, to each of remotes in turn if they're set
+    // and repo is dotfiles_directory. One remote failing doesn't keep the others from being
+    // pushed to.
+    fn vcs_push(&self, repo: &str, journal: &Journal) -> Vec<Event> {
+        if self.base_repository(repo) {
+            return vec![Event::new(EventKind::Vcs, "push", repo, "")
+                .skipped("the base config's repository is shared, so it is only pulled")];
+        }
+        match self.extra_rsync_repo(repo, "push") {
+            Some(skipped) => vec![skipped],
+            None => {
+                let files = self.managed_changes(repo, journal);
+                let mut programs: Vec<String> = Vec::new();
+                for target in self.targets() {
+                    if target.repo_relative(repo).is_some_and(|r| files.iter().any(|f| f == r)) && !programs.contains(&target.program) {
+                        programs.push(target.program);
+                    }
+                }
+                let host = if self.host.is_empty() { config::detect_hostname() } else { self.host.clone() };
+                let message = vcs::commit_message(self.defaults.commit_message(), &host, &programs, &files);
+                let backend = self.backend_committing(&message);
+                if self.remotes.is_empty() || repo != self.dotfiles_directory {
+                    return vec![backend.push(repo, &files)];
+                }
+
+                let committed = backend.commit(repo, &files);
+                if committed.result == EventResult::Error {
+                    return vec![committed];
+                }
+                let mut events = vec![committed];
+                events.extend(self.remotes.iter().map(|remote| backend.push_to(repo, remote)));
+                events
+            }
+        }
+    }
+
+    // the changed files in repo, relative to it, that sync commits: the selected programs' files,
+    // whatever this run wrote to or removed from repo, and .gitattributes if Git LFS is on. Other
+    // changes in the repository are left for the user to commit.
+    fn managed_changes(&self, repo: &str, journal: &Journal) -> Vec<String> {
+        let mut managed: BTreeSet<String> = self.targets().iter()
+            .filter_map(|t| t.repo_relative(repo))
+            .map(str::to_owned)
+            .collect();
+        managed.extend(journal.written().unwrap_or_default().iter().filter_map(|w| paths::relative_to(w, repo)));
+        if self.lfs {
+            managed.insert(".gitattributes".to_owned());
+        }
+        self.backend().changed_files(repo).into_iter().filter(|f| managed.contains(f)).collect()
+    }
+
+    // has the binary files in repo that Git LFS doesn't track yet tracked by it, if lfs is on and
+    // there are any
+    fn track_binary(&self, repo: &str) -> Option<Event> {
+        if !self.lfs || self.base_repository(repo) {
+            return None;
+        }
+        let attributes = fs::read_to_string(path::Path::new(repo).join(".gitattributes")).unwrap_or_default();
+        let files: Vec<String> = self.targets().iter()
+            .filter(|t| t.template.is_none() && copy::is_binary(&t.repo_file))
+            .filter_map(|t| t.repo_relative(repo))
+            .filter(|relative| !vcs::lfs_tracked(&attributes, relative))
+            .map(str::to_owned)
+            .collect();
+        if files.is_empty() {
+            return None;
+        }
+        Some(self.backend().track_binary(repo, &files))
+    }
+
+    // whether repo holds the base config's programs, and none of the user's own
+    fn base_repository(&self, repo: &str) -> bool {
+        let mut using = self.programs.iter()
+            .filter(|i| i.repository(&self.home, &self.dotfiles_directory) == repo)
+            .peekable();
+        repo != self.dotfiles_directory && using.peek().is_some() && using.all(|i| i.layer != Layer::Personal)
+    }
+
+    // rsync_target is a mirror of the main dotfiles_directory only, so programs' own repositories
+    // can't be synced through it
+    fn extra_rsync_repo(&self, repo: &str, action: &str) -> Option<Event> {
+        if self.vcs == VcsKind::Rsync && repo != self.dotfiles_directory {
+            Some(Event::new(EventKind::Vcs, action, repo, "").skipped(format!(
+                "rsync_target only mirrors {}, so {} isn't synced",
+                self.dotfiles_directory, repo
+            )))
+        } else {
+            None
+        }
+    }
+}
+
+impl Program {
+    
+    // interprets the post-deploy command in the bash shell, recording it in the audit log.
+    // Returns None if the program has no post-deploy command.
+    pub fn run_post_deploy_cmd(&self, audit: &AuditLog, observer: &mut dyn Observer) -> Option<Event> {
+        // don't execute this method if there is no post-deploy command
+        if self.post_deploy_cmd.is_empty() {
+            return None
+        }
+
+        // inform user which program's post-deploy command is being attempted
+        observer.hook_started(self);
+
+        // run the post-deploy command, collect output into a Vec<u8>
+        let event = Event::new(EventKind::Hook, "post-deploy", &self.name[..], "");
+        let post_deploy_cmd = match audit.run("hook", &self.post_deploy_cmd) {
+            Ok(val) => val,
+            Err(e) => return Some(event.failed(format!("could not run post-deploy command for \"{}\": {}", self.name, e))),
+        };
+
+        // convert the post-deploy command's output from a Vec<u8> into a String
+        let post_deploy_cmd_output = String::from_utf8_lossy(post_deploy_cmd.stdout.as_slice());
+
+        // give user post-deploy command's output
+        let event = event.with_output(post_deploy_cmd_output);
+        if post_deploy_cmd.status.success() {
+            Some(event)
+        } else {
+            Some(event.failed(format!(
+                "post-deploy command for \"{}\" failed ({})",
+                self.name,
+                audit::describe_failure(&post_deploy_cmd)
+            )))
+        }
+    }
+    
+    // replaces all "active-duty" dotfiles on the user's system with the dotfiles in the repository
+    // folder, up to jobs of them at once. With escalate, files this user may not write are left
+    // out, for Settings::deploy_escalated to copy through the escalation helper.
+    #[allow(clippy::too_many_arguments)]
+    pub fn deploy(&self, home_dir: &str, dotfiles_dir: &str, copier: &Copier, journal: &Journal, jobs: usize, escalate: bool, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+
+        // ensure output folder exists
+        fs::create_dir_all(self.deployed_dir(home_dir)).unwrap_or_default();
+
+        // deploy all the files
+        let escalated = |t: &Target| escalate && self.deploy_with == DeployMode::Copy && escalate::needed(t);
+        let targets: Vec<Target> = self.targets_on(home_dir, dotfiles_dir, Side::Repo).into_iter()
+            .filter(|t| t.direction.deploys() && !escalated(t))
+            .collect();
+        let deploy = |target: &Target| {
+            // copy (or link) the file; a copy gets its mode before it is renamed into place, so it
+            // is never readable by more than it should be
+            let copier = Copier { mode: mode::parse(&target.mode).ok(), ..*copier };
+            let event = match target.template {
+                Some(_) => Program::render_file(target, &copier, journal),
+                None => self.place_file(target.repo_file.clone(), target.deployed_file.clone(), &copier, journal),
+            };
+            // chmod follows symlinks, so a linked file's mode would be set on its repository copy
+            match event.result {
+                EventResult::Ok | EventResult::Unchanged if !target.mode.is_empty() && !link::is_link(&target.deployed_file) => Program::set_mode(event, target),
+                _ => event,
+            }
+        };
+        parallel::for_each(&targets, jobs, deploy, |event| summary.record(event, observer));
+
+        summary
+    }
+
+    // works out where each of the program's files lives in the repository folder and where it is
+    // deployed to
+    pub fn deploy_targets(&self, home_dir: &str, dotfiles_dir: &str) -> Vec<Target> {
+        self.targets_on(home_dir, dotfiles_dir, Side::Both)
+    }
+
+    // same as deploy_targets, but glob entries in paths are only expanded against the given side
+    pub fn targets_on(&self, home_dir: &str, dotfiles_dir: &str, side: Side) -> Vec<Target> {
+        let source_dir = self.repo_dir(home_dir, dotfiles_dir);
+        let output_dir = self.deployed_dir(home_dir);
+
+        self.expand_paths(&source_dir, &output_dir, side, &*self.layout(home_dir)).into_iter()
+            .filter(|(_, i, _)| self.filter.allows(&self.name, i))
+            .map(|(src, i, entry)| {
+                let mode = if entry.mode().is_empty() { &self.mode } else { entry.mode() };
+                Target {
+                    program: self.name.clone(),
+                    repo_file: paths::join(&source_dir, &src),
+                    deployed_file: paths::locate(&output_dir, &i),
+                    path: i,
+                    mode: mode.to_owned(),
+                    direction: entry.direction(),
+                    template: entry.template().then(|| self.template_vars.clone()),
+                }
+            })
+            .collect()
+    }
+
+    // paths with every glob entry replaced by the files it matches, and every directory entry by
+    // all the files below it, in order and without duplicates. Other entries are kept as they are,
+    // whether they exist or not. Each file comes as its path in the repository and its path once
+    // deployed, which only differ for renamed entries, along with the entry it came from.
+    fn expand_paths(&self, repo_dir: &str, deployed_dir: &str, side: Side, layout: &dyn LayoutStrategy) -> Vec<(String, String, &PathEntry)> {
+        let mut expanded: Vec<(String, String, &PathEntry)> = Vec::new();
+
+        for i in self.paths.iter() {
+            for (src, dest) in Program::expand_path_entry(i, repo_dir, deployed_dir, side, layout) {
+                if !expanded.iter().any(|(s, d, _)| s == &src && d == &dest) {
+                    expanded.push((src, dest, i));
+                }
+            }
+        }
+
+        expanded
+    }
+
+    // the files a single entry of paths stands for, as pairs of repository and deployed paths. The
+    // src of a renamed entry is where it is kept whatever the layout, but the files below it aren't.
+    fn expand_path_entry(entry: &PathEntry, repo_dir: &str, deployed_dir: &str, side: Side, layout: &dyn LayoutStrategy) -> Vec<(String, String)> {
+        if !entry.is_renamed() {
+            return Program::expand_entry(entry.src(), repo_dir, deployed_dir, side, layout).into_iter()
+                .map(|p| (layout.repo_path(&p), p))
+                .collect();
+        }
+        let (src, dest) = (entry.src().trim_end_matches('/'), entry.dest().trim_end_matches('/'));
+        let layout = layout.below();
+
+        // the entry is expanded as if it were the root of a program of its own, which is either
+        // the file itself ("") or a directory holding files
+        let is_dir = entry.src().ends_with('/') || entry.dest().ends_with('/');
+        Program::expand_entry(
+            if is_dir { "/" } else { "" },
+            &format!("{}/{}", repo_dir, src),
+            &paths::locate(deployed_dir, dest),
+            side,
+            layout,
+        )
+        .into_iter()
+        .map(|p| match &p[..] {
+            "" => (src.to_owned(), dest.to_owned()),
+            p => (format!("{}/{}", src, layout.repo_path(p)), format!("{}/{}", dest, p)),
+        })
+        .collect()
+    }
+
+    // the files a single entry of paths stands for, as paths below the program's root. Files found
+    // in the repository are looked for, and named, the way layout keeps them there.
+    fn expand_entry(entry: &str, repo_dir: &str, deployed_dir: &str, side: Side, layout: &dyn LayoutStrategy) -> Vec<String> {
+        // a directory is either marked with a trailing slash or already is one on disk
+        let is_dir = entry.ends_with('/')
+            || (side != Side::Deployed && path::Path::new(repo_dir).join(layout.repo_path(entry)).is_dir())
+            || (side != Side::Repo && path::Path::new(deployed_dir).join(entry).is_dir());
+        let pattern = match entry.trim_end_matches('/') {
+            dir if !is_dir => dir.to_owned(),
+            "" => "**".to_owned(),
+            dir => format!("{}/**", dir),
+        };
+
+        let mut found = Vec::new();
+        if !is_dir && !glob::Pattern::new(&pattern).is_glob() {
+            found.push(entry.to_owned());
+        } else {
+            if side != Side::Deployed {
+                // a layout can only guess the names it changed back; the first one is taken from
+                // the entry when it spells it out
+                let first = entry.trim_start_matches('/').split('/').next()
+                    .filter(|n| !n.is_empty() && !glob::Pattern::new(n).is_glob());
+                found.extend(glob::expand(repo_dir, &layout.repo_path(&pattern)).iter()
+                    .map(|p| layout.deployed_path(p))
+                    .map(|p| match first {
+                        Some(name) => layout::map_first(&p, |_| name.to_owned()),
+                        None => p,
+                    }));
+            }
+            if side != Side::Repo {
+                found.extend(glob::expand(deployed_dir, &pattern));
+            }
+        }
+        found
+    }
+
+    // paths as a TOML array, with table entries as inline tables
+    fn paths_toml(&self) -> String {
+        let quote = |text: &str| toml::Value::String(text.to_owned()).to_string();
+        let entries: Vec<String> = self.paths.iter()
+            .map(|i| match i {
+                PathEntry::Plain(path) => quote(path),
+                PathEntry::Table { src, dest, mode, direction, template } => {
+                    let mut keys: Vec<String> = [("src", src), ("dest", dest), ("mode", mode), ("direction", direction)].iter()
+                        .filter(|(_, value)| !value.is_empty())
+                        .map(|(key, value)| format!("{} = {}", key, quote(value)))
+                        .collect();
+                    if *template {
+                        keys.push("template = true".to_owned());
+                    }
+                    format!("{{ {} }}", keys.join(", "))
+                }
+            })
+            .collect();
+        format!("[{}]", entries.join(", "))
+    }
+
+    // the entries of paths that match no file, neither in the repository nor on this system
+    pub fn dangling_paths(&self, home_dir: &str, dotfiles_dir: &str) -> Vec<String> {
+        let repo_dir = self.repo_dir(home_dir, dotfiles_dir);
+        let deployed_dir = self.deployed_dir(home_dir);
+        let layout = self.layout(home_dir);
+
+        self.paths.iter()
+            .filter(|i| {
+                !Program::expand_path_entry(i, &repo_dir, &deployed_dir, Side::Both, &*layout).iter()
+                    .any(|(src, dest)| path::Path::new(&repo_dir).join(src).exists() || path::Path::new(&deployed_dir).join(dest).exists())
+            })
+            .map(|i| i.to_string())
+            .collect()
+    }
+
+    // the repository the program's files are kept in: its own dotfiles_directory if it has one,
+    // otherwise the top level one
+    pub fn repository(&self, home_dir: &str, dotfiles_dir: &str) -> String {
+        let dotfiles_dir = if self.dotfiles_directory.is_empty() { dotfiles_dir } else { &self.dotfiles_directory };
+        paths::standardize(dotfiles_dir, home_dir)
+    }
+
+    // the directory the program's files are deployed to, i.e. its root
+    pub fn deployed_dir(&self, home_dir: &str) -> String {
+        match self.flavor {
+            Flavor::Unix => paths::standardize(&self.root, home_dir),
+            Flavor::Windows => paths::standardize(&windows::expand_root(&self.root, home_dir), home_dir),
+        }
+    }
+
+    // the directory in the repository folder holding the program's files. This is repo_subdir if
+    // set, otherwise wherever the program's layout puts it; with the default one, the root's path
+    // relative to the home directory, so a root of ~/.config/nvim is stored at
+    // <dotfiles_directory>/.config/nvim.
+    pub fn repo_dir(&self, home_dir: &str, dotfiles_dir: &str) -> String {
+        let dotfiles_dir = self.repository(home_dir, dotfiles_dir);
+        let subdir = if self.repo_subdir.is_empty() {
+            self.layout(home_dir).program_dir(&self.name, &self.stored_root(home_dir))
+        } else {
+            self.repo_subdir.clone()
+        };
+        paths::join(&dotfiles_dir, &subdir)
+    }
+
+    // the program's root the way layouts see it. Roots inside the home directory, whether written
+    // with ~ or built from variables such as $XDG_CONFIG_HOME, are relative to it, and others are
+    // by their full path.
+    fn stored_root(&self, home_dir: &str) -> String {
+        let root = match self.flavor {
+            Flavor::Unix => self.root.clone(),
+            Flavor::Windows => windows::portable_root(&self.root),
+        };
+        paths::relative_to(&paths::standardize(&root, home_dir), home_dir).unwrap_or(root)
+    }
+
+    // how the program's files are arranged in its repository. Chezmoi has no leading dots to strip.
+    fn layout(&self, home_dir: &str) -> Box<dyn LayoutStrategy> {
+        let layout = layout::from_kind(self.repo_layout);
+        if !self.undotted || self.repo_layout == LayoutKind::Chezmoi {
+            return layout;
+        }
+        let dir_at_top = self.repo_subdir.is_empty() && self.repo_layout == LayoutKind::MirrorHome && !self.stored_root(home_dir).is_empty();
+        Box::new(layout::Undotted { inner: layout, dir_at_top })
+    }
+    
+    // imports the .reg files of a windows program's registry keys into the registry. Keys that
+    // have never been collected are skipped.
+    pub fn deploy_registry(&self, home_dir: &str, dotfiles_dir: &str, audit: &AuditLog, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        if self.flavor != Flavor::Windows {
+            return summary;
+        }
+
+        let repo_dir = self.repo_dir(home_dir, dotfiles_dir);
+        for key in self.registry.iter() {
+            let file = format!("{}/{}", repo_dir, windows::registry_file(key));
+            if !path::Path::new(&file).exists() {
+                summary.record(
+                    Event::new(EventKind::File, "reg-import", &file[..], &key[..])
+                        .skipped(format!("registry key \"{}\" has not been collected yet", key)),
+                    observer,
+                );
+                continue;
+            }
+            summary.record(windows::import(audit, key, &file), observer);
+        }
+
+        summary
+    }
+
+    // replaces all dotfiles in the repository folder with the "active-duty" dotfiles from the
+    // user's system, up to jobs of them at once
+    pub fn collect(&self, home_dir: &str, dotfiles_dir: &str, copier: &Copier, journal: &Journal, jobs: usize, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+
+        // ensure output_dir exists
+        fs::create_dir_all(self.repo_dir(home_dir, dotfiles_dir)).unwrap_or_default();
+
+        // deploy_only files are never collected, and a file linked to the repository copy is the
+        // repository copy
+        let targets: Vec<Target> = self.targets_on(home_dir, dotfiles_dir, Side::Deployed).into_iter()
+            .filter(|t| t.direction.collects() && !link::linked(&t.deployed_file, &t.repo_file))
+            .collect();
+        let collect = |target: &Target| {
+            // what was rendered from a template can't be put back into it
+            if target.template.is_some() {
+                let event = Event::new(EventKind::File, "copy", &target.deployed_file[..], &target.repo_file[..]);
+                return vec![match target.state() {
+                    FileState::Deployed => event.unchanged(),
+                    _ => event.skipped(format!(
+                        "not collecting \"{}\", which is rendered from the template \"{}\"; make the change in the template instead",
+                        target.deployed_file, target.repo_file
+                    )),
+                }];
+            }
+            // the repository can't hold permissions, so a mode changed on this machine would be
+            // silently undone by the next deploy
+            let drift = Program::mode_drift(target);
+            // copy the file
+            let copied = self.copy_source(target.deployed_file.clone(), target.repo_file.clone(), copier, journal);
+            drift.into_iter().chain(std::iter::once(copied)).collect::<Vec<Event>>()
+        };
+        parallel::for_each(&targets, jobs, collect, |events| {
+            for event in events {
+                summary.record(event, observer);
+            }
+        });
+
+        summary
+    }
+
+    // exports each of a windows program's registry keys to its .reg file in the repository
+    pub fn collect_registry(&self, home_dir: &str, dotfiles_dir: &str, audit: &AuditLog, journal: &Journal, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        if self.flavor != Flavor::Windows {
+            return summary;
+        }
+
+        let repo_dir = self.repo_dir(home_dir, dotfiles_dir);
+        fs::create_dir_all(format!("{}/registry", repo_dir)).unwrap_or_default();
+        for key in self.registry.iter() {
+            let file = format!("{}/{}", repo_dir, windows::registry_file(key));
+            let event = Event::new(EventKind::File, "reg-export", &key[..], &file[..]);
+
+            // reg export overwrites the file, so it's journaled like any other write
+            if let Err(e) = journal.before_write(&file) {
+                summary.record(event.failed(format!("could not record \"{}\" in the journal: {}", file, e)), observer);
+                continue;
+            }
+            let event = windows::export(audit, key, &file);
+            let event = match journal.after_write(&file) {
+                Err(e) if event.result != EventResult::Error => {
+                    event.failed(format!("could not record \"{}\" in the journal: {}", file, e))
+                }
+                _ => event,
+            };
+            summary.record(event, observer);
+        }
+
+        summary
+    }
+
+    // the in-between of copy and deploy, in which files that only exist on one side are copied to
+    // the other and, by default, the oldest files are overwritten with the newest. direction can
+    // make one side win instead. resolver tells which side changed since the last sync, which beats
+    // the modification times, and what to do when both did. Up to jobs files are synced at once.
+    #[allow(clippy::too_many_arguments)]
+    pub fn sync_local<T: Into<String>>(
+        &self,
+        home_dir: T,
+        dotfiles_dir: T,
+        direction: SyncDirection,
+        resolver: &Resolver,
+        copier: &Copier,
+        journal: &Journal,
+        jobs: usize,
+        observer: &mut dyn Observer,
+    ) -> Summary {
+        let mut summary = Summary::default();
+
+        // convert generics to Strings
+        let home_dir: String = home_dir.into();
+        let dotfiles_dir: String = dotfiles_dir.into();
+
+        // ensure directories exist
+        fs::create_dir_all(self.deployed_dir(&home_dir)).unwrap_or_default();
+        fs::create_dir_all(self.repo_dir(&home_dir, &dotfiles_dir)).unwrap_or_default();
+
+        let targets = self.deploy_targets(&home_dir, &dotfiles_dir);
+        let sync = |target: &Target| self.sync_file(target, direction, resolver, copier, journal);
+        parallel::for_each(&targets, jobs, sync, |event| {
+            if let Some(event) = event {
+                summary.record(event, observer);
+            }
+        });
+
+        summary
+    }
+
+    // syncs one of the program's files the way sync_local says, returning what happened if
+    // anything did
+    fn sync_file(&self, target: &Target, direction: SyncDirection, resolver: &Resolver, copier: &Copier, journal: &Journal) -> Option<Event> {
+        // Repo file is the designation given to the dotfile being pulled from the folder
+        // containing all the other dotfiles; working file is the designation given to files
+        // actively in the user's filesystem in their proper locations.
+        let i = &target.path;
+        let repo_file = target.repo_file.clone();
+        let working_file = target.deployed_file.clone();
+        // copies the other way than the path's direction are left out
+        let flows = target.direction;
+
+        // nothing to sync between a file and a link to it, or two hard links to one file
+        if link::linked(&working_file, &repo_file) {
+            return None;
+        }
+        // templates only ever go from the repository to the system, rendered
+        if target.template.is_some() {
+            return Program::sync_template(target, copier, journal);
+        }
+
+        // check for both files existence
+        let repo_file_exists = path::Path::new(&repo_file).exists();
+        let working_file_exists = path::Path::new(&working_file).exists();
+
+        // if neither exist, don't sync
+        if !repo_file_exists && !working_file_exists {
+            return Some(
+                Event::new(EventKind::File, "sync", &repo_file[..], &working_file[..])
+                    .skipped(format!("file {} does not exist in dotfiles repo or its intended place in the system, not syncing", i)),
+            );
+
+        // if only the repo file exists, copy the working file to repo directory
+        } else if !repo_file_exists {
+            return flows.collects().then(|| self.copy_source(working_file, repo_file, copier, journal));
+
+        // if only the working file exists, copy the repo file to the working directory
+        } else if !working_file_exists {
+            return flows.deploys().then(|| self.place_file(repo_file, working_file, copier, journal));
+        }
+
+        match direction {
+            SyncDirection::RepoToSystem => {
+                return flows.deploys().then(|| self.place_file(repo_file, working_file, copier, journal));
+            }
+            SyncDirection::SystemToRepo => {
+                return flows.collects().then(|| self.copy_source(working_file, repo_file, copier, journal));
+            }
+            SyncDirection::Newest => (),
+        }
+
+        // what changed since the last sync decides, if rodeo remembers what that was
+        match resolver.resolve(&repo_file, &working_file) {
+            Resolution::Repo => {
+                return flows.deploys().then(|| self.place_file(repo_file, working_file, copier, journal));
+            }
+            Resolution::Disk => {
+                return flows.collects().then(|| self.copy_source(working_file, repo_file, copier, journal));
+            }
+            Resolution::Merged(merged) if flows.is_bidirectional() => {
+                return Some(Program::merge_file(&merged, repo_file, working_file, resolver, copier, journal));
+            }
+            Resolution::Conflict => {
+                return match resolver.prefer {
+                    Some(Preference::Repo) => flows.deploys().then(|| self.place_file(repo_file, working_file, copier, journal)),
+                    Some(Preference::Disk) => flows.collects().then(|| self.copy_source(working_file, repo_file, copier, journal)),
+                    None => Some(
+                        Event::new(EventKind::File, "sync", &repo_file[..], &working_file[..]).skipped(format!(
+                            "file \"{}\" was changed in the same places in the repo and on this system since the last sync. Not syncing; merge them by hand (the last synced version is {}), or pass --prefer-repo or --prefer-disk to keep one.",
+                            i,
+                            resolver.base_path(&working_file)
+                        )),
+                    ),
+                };
+            }
+            // the modification times decide the rest
+            _ => (),
+        }
+
+        // get metadata structs for both files
+        let repo_file_metadata = match fs::metadata(&repo_file) {
+            Ok(val) => val,
+            Err(_) => {
+                return Some(
+                    Event::new(EventKind::File, "sync", &repo_file[..], &working_file[..])
+                        .failed(format!("error syncing file \"{}\": could not access file metadata.", repo_file)),
+                );
+            },
+        };
+        let working_file_metadata = match fs::metadata(&working_file) {
+            Ok(val) => val,
+            Err(_) => {
+                return Some(
+                    Event::new(EventKind::File, "sync", &repo_file[..], &working_file[..])
+                        .failed(format!("error syncing file \"{}\": could not access file metadata.", working_file)),
+                );
+            }
+        };
+
+        // get a systemtime struct for both files based on their time last modified
+        let repo_file_modified = match repo_file_metadata.modified() {
+            Ok(val) => val,
+            Err(_) => {
+                return Some(
+                    Event::new(EventKind::File, "sync", &repo_file[..], &working_file[..])
+                        .failed(format!("error syncing file \"{}\": could not determine time of last modification.", i)),
+                );
+            }
+        };
+
+        let working_file_modified = match working_file_metadata.modified() {
+            Ok(val) => val,
+            Err(_) => {
+                return Some(
+                    Event::new(EventKind::File, "sync", &repo_file[..], &working_file[..])
+                        .failed(format!("error syncing file \"{}\": could not determine time of last modification.", i)),
+                );
+            }
+        };
+
+        // overwrite whichever file was modified a longer time ago with the more recently
+        // modified file
+        if repo_file_modified > working_file_modified {
+            flows.deploys().then(|| self.place_file(repo_file, working_file, copier, journal))
+        } else if repo_file_modified < working_file_modified {
+            flows.collects().then(|| self.copy_source(working_file, repo_file, copier, journal))
+        // rodeo's copies keep the time of what they were copied from, so a file synced before
+        // and not changed since has the same time on both sides
+        } else if copy::same_contents(&repo_file, &working_file) {
+            None
+        // otherwise only --prefer-repo or --prefer-disk can say which one to keep
+        } else if resolver.prefer == Some(Preference::Repo) {
+            flows.deploys().then(|| self.place_file(repo_file, working_file, copier, journal))
+        } else if resolver.prefer == Some(Preference::Disk) {
+            flows.collects().then(|| self.copy_source(working_file, repo_file, copier, journal))
+        } else {
+            Some(
+                Event::new(EventKind::File, "sync", &repo_file[..], &working_file[..])
+                    .skipped(format!("file \"{}\" appears to have been modified at the same time at both locations. Not syncing; pass --prefer-repo or --prefer-disk to pick one.", i)),
+            )
+        }
+    }
+
+    // renders a template again, unless the deployed file was changed after the template was, as
+    // those changes can't be collected into it
+    fn sync_template(target: &Target, copier: &Copier, journal: &Journal) -> Option<Event> {
+        if !target.direction.deploys() || target.state() == FileState::Deployed {
+            return None;
+        }
+        let modified = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+        match (modified(&target.repo_file), modified(&target.deployed_file)) {
+            (Some(repo), Some(deployed)) if deployed > repo => Some(
+                Event::new(EventKind::File, "sync", &target.repo_file[..], &target.deployed_file[..]).skipped(format!(
+                    "file \"{}\" was changed since it was rendered from the template \"{}\", which rodeo can't collect it into. Not syncing; make the change in the template, or deploy to overwrite it.",
+                    target.deployed_file, target.repo_file
+                )),
+            ),
+            _ => Some(Program::render_file(target, copier, journal)),
+        }
+    }
+
+    //
+    // helper functions
+    //
+
+    // gives a freshly deployed file the mode its target asks for, failing event if that can't be done
+    fn set_mode(event: Event, target: &Target) -> Event {
+        match mode::parse(&target.mode).and_then(|val| mode::set(&target.deployed_file, val).map_err(|e| e.to_string())) {
+            Ok(_) => event,
+            Err(e) => event.failed(format!("Error: could not set the mode of \"{}\" to {}: {}", target.deployed_file, target.mode, e)),
+        }
+    }
+
+    // a warning if the deployed file's permissions aren't the mode the config asks for
+    fn mode_drift(target: &Target) -> Option<Event> {
+        let wanted = mode::parse(&target.mode).ok()?;
+        let found = mode::get(&target.deployed_file)?;
+        if found == wanted {
+            return None;
+        }
+        Some(Event::new(EventKind::File, "mode", &target.deployed_file[..], "").skipped(format!(
+            "\"{}\" has mode {}, but the config says {}; deploy will set it back, so change mode in the config to keep it",
+            target.deployed_file,
+            mode::format(found),
+            target.mode
+        )))
+    }
+
+    // writes merged, the result of merging the changes to both copies of a file, to both of them
+    fn merge_file(
+        merged: &[u8],
+        repo_file: String,
+        working_file: String,
+        resolver: &Resolver,
+        copier: &Copier,
+        journal: &Journal,
+    ) -> Event {
+        let event = Event::new(EventKind::File, "merge", &repo_file[..], &working_file[..]);
+        let scratch = format!("{}.merged", resolver.base_path(&working_file));
+        if let Err(e) = path::Path::new(&scratch).parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&scratch, merged)) {
+            return event.failed(format!("Error: could not merge \"{}\" and \"{}\": {}", repo_file, working_file, e));
+        }
+        // the merged file is written with the permissions of the deployed one
+        let _ = mode::get(&working_file).map(|val| mode::set(&scratch, val));
+
+        let written = vec![
+            Program::copy_file(scratch.clone(), working_file, copier, journal),
+            Program::copy_file(scratch.clone(), repo_file, copier, journal),
+        ];
+        let _ = fs::remove_file(&scratch);
+        match written.into_iter().find(|e| e.result == EventResult::Error) {
+            Some(failed) => failed,
+            None => event,
+        }
+    }
+
+    // puts the repository copy from in place at to the way the program is deployed
+    fn place_file(&self, from: String, to: String, copier: &Copier, journal: &Journal) -> Event {
+        match self.deploy_with {
+            DeployMode::Copy => self.copy_source(from, to, copier, journal),
+            mode => Program::link_file(&from, &to, mode, journal),
+        }
+    }
+
+    // copies from to to, unless from is a symlink, in which case the program's symlinks setting
+    // says what to do
+    fn copy_source(&self, from: String, to: String, copier: &Copier, journal: &Journal) -> Event {
+        if !link::is_link(&from) {
+            return Program::copy_file(from, to, copier, journal);
+        }
+        match self.symlinks {
+            SourceLinks::Follow => Program::copy_file(from, to, copier, journal),
+            SourceLinks::Preserve => Program::copy_link(&from, &to, journal),
+            SourceLinks::Refuse => Event::new(EventKind::File, "copy", &from[..], &to[..]).skipped(format!(
+                "not copying \"{}\", which is a symlink, as program \"{}\" has symlinks = \"refuse\"",
+                from, self.name
+            )),
+        }
+    }
+
+    // makes to a symlink pointing wherever the symlink from does, for symlinks = "preserve". Relative
+    // links stay relative, so they mean the same on both sides if the files around them are there.
+    fn copy_link(from: &str, to: &str, journal: &Journal) -> Event {
+        let from = paths::normalize(from);
+        let to = paths::normalize(to);
+        let event = Event::new(EventKind::File, "copy", &from[..], &to[..]);
+
+        let points_to = match fs::read_link(&from) {
+            Ok(val) => val,
+            Err(e) => return event.failed(format!("Error: could not read the symlink \"{}\": {}", from, e)),
+        };
+        if fs::read_link(&to).is_ok_and(|p| p == points_to) {
+            return event.unchanged();
+        }
+        if !link::is_link(&to) && path::Path::new(&to).is_dir() {
+            return event.failed(format!("Error: could not copy the symlink \"{}\" to \"{}\", which is a directory", from, to));
+        }
+        if let Err(reason) = Program::create_parents(&to) {
+            return event.failed(reason);
+        }
+        if let Err(e) = journal.before_write(&to) {
+            return event.failed(format!("Error: could not record \"{} => {}\" in the journal: {}", from, to, e));
+        }
+
+        // made next to "to" and renamed into place, like a copy
+        let partial = copy::partial_path(&to);
+        let _ = fs::remove_file(&partial);
+        let linked = link::create(&points_to.to_string_lossy(), &partial).and_then(|_| fs::rename(&partial, &to));
+        if linked.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        match linked.and_then(|_| journal.after_write(&to)) {
+            Ok(_) => event,
+            Err(e) => event.failed(format!("Error: could not copy the symlink \"{} => {}\": {}", from, to, e)),
+        }
+    }
+
+    // makes to a symlink or hard link to from, as mode says, replacing whatever is there the same
+    // way copy_file does. A link that is already in place is left alone.
+    fn link_file(from: &str, to: &str, mode: DeployMode, journal: &Journal) -> Event {
+        let from = paths::normalize(from);
+        let to = paths::normalize(to);
+        let event = Event::new(EventKind::File, "link", &from[..], &to[..]);
+
+        if link::in_place(mode, &to, &from) {
+            return event.unchanged();
+        }
+        if let Some(reason) = Program::kind_mismatch(&from, &to) {
+            return event.failed(reason);
+        }
+        // a link to a file that doesn't exist would only be a broken link
+        if !path::Path::new(&from).is_file() {
+            return event.failed(format!("Error: could not link \"{} => {}\": \"{}\" doesn't exist", from, to, from));
+        }
+        if let Err(reason) = Program::create_parents(&to) {
+            return event.failed(reason);
+        }
+
+        if let Err(e) = journal.before_write(&to) {
+            return event.failed(format!("Error: could not record \"{} => {}\" in the journal: {}", from, to, e));
+        }
+
+        // the link is made next to "to" and renamed into place, like a copy
+        let partial = copy::partial_path(&to);
+        let _ = fs::remove_file(&partial);
+        let created = match mode {
+            DeployMode::Hardlink => link::create_hard(&from, &partial),
+            _ => link::create(&from, &partial),
+        };
+        let linked = created.and_then(|_| fs::rename(&partial, &to));
+        if linked.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+
+        match linked {
+            Ok(_) => match journal.after_write(&to) {
+                Ok(_) => event,
+                Err(e) => event.failed(format!("Error: could not record \"{} => {}\" in the journal: {}", from, to, e)),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => event.failed(format!(
+                "Error: no permission to write \"{}\"; run rodeo as a user who may, e.g. with sudo for files outside the home directory",
+                to
+            )),
+            Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => event.failed(format!(
+                "Error: could not hard link \"{} => {}\": they are on different filesystems; use deploy_mode = \"symlink\" or \"copy\" for this program",
+                from, to
+            )),
+            Err(e) => event.failed(format!("Error: could not link \"{} => {}\": {}", from, to, e)),
+        }
+    }
+
+    // entries standing for a directory are expanded into the files below it before anything is
+    // copied, so a directory can only turn up here where the other side has a file of that name:
+    // at one of the two paths, or in place of a directory to's file would go in
+    fn kind_mismatch(from: &str, to: &str) -> Option<String> {
+        let reason = if path::Path::new(from).is_dir() {
+            format!("\"{}\" is a directory, but \"{}\" is a file", from, to)
+        } else if path::Path::new(to).is_dir() {
+            format!("\"{}\" is a directory, but \"{}\" is a file", to, from)
+        } else {
+            let file = path::Path::new(to).ancestors().skip(1).find(|p| p.is_file())?;
+            format!("\"{}\" is a file, but the other side has a directory of that name", file.display())
+        };
+        Some(format!(
+            "Error: could not copy \"{} => {}\": {}. Move one of them out of the way, or mark the entry as a directory with a trailing slash if both should be.",
+            from, to, reason
+        ))
+    }
+
+    // renders the template at target's repo_file into its deployed_file, replacing it the same way
+    // copy_file does. The result keeps the template's time, like a copy.
+    fn render_file(target: &Target, copier: &Copier, journal: &Journal) -> Event {
+        let from = paths::normalize(&target.repo_file);
+        let to = paths::normalize(&target.deployed_file);
+        let event = Event::new(EventKind::File, "render", &from[..], &to[..]);
+
+        let rendered = match target.deployed_contents() {
+            Ok(val) => val,
+            Err(e) => return event.failed(format!("Error: {}", e)),
+        };
+        if !link::is_link(&to) && fs::read(&to).is_ok_and(|c| c == rendered) {
+            return event.unchanged();
+        }
+        if let Some(reason) = Program::kind_mismatch(&from, &to) {
+            return event.failed(reason);
+        }
+        if let Err(reason) = Program::create_parents(&to) {
+            return event.failed(reason);
+        }
+        if let Err(e) = journal.before_write(&to) {
+            return event.failed(format!("Error: could not record \"{} => {}\" in the journal: {}", from, to, e));
+        }
+
+        match copier.replace_with(&rendered, &from, &to) {
+            Ok(_) => match journal.after_write(&to) {
+                Ok(_) => event,
+                Err(e) => event.failed(format!("Error: could not record \"{} => {}\" in the journal: {}", from, to, e)),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => event.failed(format!(
+                "Error: no permission to write \"{}\"; run rodeo as a user who may, e.g. with sudo for files outside the home directory",
+                to
+            )),
+            Err(e) => event.failed(format!("Error: could not write \"{}\" rendered from \"{}\": {}", to, from, e)),
+        }
+    }
+
+    // replaces to with contents the same way copy_file replaces a file with a copy, journaled so
+    // that it can be undone if rodeo is interrupted
+    fn revert_file(contents: &[u8], to: &str, copier: &Copier, journal: &Journal) -> Result<(), String> {
+        Program::create_parents(to)?;
+        journal.before_write(to)
+            .map_err(|e| format!("Error: could not record \"{}\" in the journal: {}", to, e))?;
+        match copier.write(contents, to) {
+            Ok(_) => journal.after_write(to).map_err(|e| format!("Error: could not record \"{}\" in the journal: {}", to, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => Err(format!("Error: no permission to write \"{}\"", to)),
+            Err(e) => Err(format!("Error: could not write \"{}\": {}", to, e)),
+        }
+    }
+
+    // creates every directory missing above to, so a file can be put there
+    fn create_parents(to: &str) -> Result<(), String> {
+        match path::Path::new(to).parent() {
+            Some(parent) => fs::create_dir_all(parent)
+                .map_err(|e| format!("Error: could not create the directory \"{}\" for \"{}\": {}", parent.display(), to, e)),
+            None => Ok(()),
+        }
+    }
+
+    // copies "from" file to "to" file, returning an event describing the outcome. The copy is
+    // recorded in the journal so that it can be undone if rodeo is interrupted.
+    fn copy_file<T: Into<String>>(from: T, to: T, copier: &Copier, journal: &Journal) -> Event {
+        // convert all generics into Strings
+        let from: String = from.into();
+        let to: String = to.into();
+
+        let from = paths::normalize(&from);
+        let to = paths::normalize(&to);
+
+        let event = Event::new(EventKind::File, "copy", &from[..], &to[..]);
+
+        // rewriting a file that is already identical would only change its time, which sync goes
+        // by. A link is replaced even so, as that is what unlink is for.
+        if !link::is_link(&to) && !link::same_file(&from, &to) && copy::same_contents(&from, &to) {
+            return event.unchanged();
+        }
+        if let Some(reason) = Program::kind_mismatch(&from, &to) {
+            return event.failed(reason);
+        }
+
+        // nested entries like "lua/options.lua", and files matched by globs, can sit in
+        // subdirectories that don't exist on this side yet
+        if let Err(reason) = Program::create_parents(&to) {
+            return event.failed(reason);
+        }
+
+        // nothing gets touched unless the journal knows about it first
+        if let Err(e) = journal.before_write(&to) {
+            return event.failed(format!("Error: could not record \"{} => {}\" in the journal: {}", from, to, e));
+        }
+
+        // an interruption never leaves a half-written file at "to"
+        match copier.replace(&from, &to) {
+            Ok(_) => match journal.after_write(&to) {
+                Ok(_) => event,
+                Err(e) => event.failed(format!("Error: could not record \"{} => {}\" in the journal: {}", from, to, e)),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => event.failed(format!(
+                "Error: no permission to write \"{}\"; run rodeo as a user who may, e.g. with sudo for files outside the home directory",
+                to
+            )),
+            Err(e) => event.failed(format!("Error: could not perform copy operation \"{} => {}\": {}", from, to, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an observer that ignores everything
+    struct Quiet;
+
+    impl Observer for Quiet {
+        fn event(&mut self, _event: &Event) {}
+    }
+
+    // an observer that writes down which program started and every file event's destination
+    #[derive(Default)]
+    struct Log(Vec<String>);
+
+    impl Observer for Log {
+        fn program_started(&mut self, program: &Program) {
+            self.0.push(format!("program {}", program.name));
+        }
+
+        fn event(&mut self, event: &Event) {
+            self.0.push(event.destination.clone());
+        }
+    }
+
+    // settings for config in a fresh directory standing in for the home directory, with the state
+    // kept in it too
+    fn settings(name: &str, config: &str) -> Settings {
+        let home = std::env::temp_dir().join(format!("rodeo-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&home);
+        fs::create_dir_all(&home).unwrap();
+        let home = home.to_string_lossy().into_owned();
+        let mut settings = Settings::new_from_str(config.to_owned(), home.clone(), format!("{}/rodeo.toml", home), ConfigFormat::Toml).unwrap();
+        settings.state_dir = format!("{}/state", home);
+        settings
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unlink_gives_copies_the_files_mode() {
+        let settings = settings("unlink-mode", "dotfiles_directory = \"~/dotfiles\"\n\n[[program]]\nname = \"ssh\"\nroot = \"~/.ssh\"\npaths = [\"config\"]\nmode = \"600\"\ndeploy_mode = \"symlink\"\n");
+        let target = settings.targets().remove(0);
+        fs::create_dir_all(path::Path::new(&target.repo_file).parent().unwrap()).unwrap();
+        fs::write(&target.repo_file, "Host *\n").unwrap();
+        mode::set(&target.repo_file, 0o644).unwrap();
+        fs::create_dir_all(path::Path::new(&target.deployed_file).parent().unwrap()).unwrap();
+        std::os::unix::fs::symlink(&target.repo_file, &target.deployed_file).unwrap();
+
+        let summary = settings.unlink_with_observer(&mut Quiet);
+
+        assert_eq!(summary.sync_errors, 0);
+        assert!(!link::is_link(&target.deployed_file));
+        assert_eq!(fs::read_to_string(&target.deployed_file).unwrap(), "Host *\n");
+        assert_eq!(mode::get(&target.deployed_file), Some(0o600));
+        assert_eq!(mode::get(&target.repo_file), Some(0o644));
+        let _ = fs::remove_dir_all(&settings.home);
+    }
+
+    #[test]
+    fn jobs_keep_each_programs_output_together() {
+        let mut config = "dotfiles_directory = \"~/dotfiles\"\njobs = 4\n".to_owned();
+        for name in ["a", "b", "c", "d"] {
+            config.push_str(&format!("\n[[program]]\nname = \"{0}\"\nroot = \"~/{0}\"\npaths = [\"1\", \"2\", \"3\"]\n", name));
+        }
+        let settings = settings("jobs-grouped", &config);
+        for target in settings.targets() {
+            fs::create_dir_all(path::Path::new(&target.repo_file).parent().unwrap()).unwrap();
+            fs::write(&target.repo_file, &target.deployed_file).unwrap();
+        }
+
+        let mut log = Log::default();
+        let summary = settings.deploy_with_observer(&mut log);
+
+        assert_eq!(summary.succeeded, 12);
+        let expected: Vec<String> = ["a", "b", "c", "d"].iter()
+            .flat_map(|name| {
+                let files = ["1", "2", "3"].map(|file| format!("{}/{}/{}", settings.home, name, file));
+                std::iter::once(format!("program {}", name)).chain(files)
+            })
+            .collect();
+        let logged: Vec<String> = log.0.into_iter().filter(|l| l.starts_with("program") || l.starts_with(&settings.home)).collect();
+        assert_eq!(logged, expected);
+        for target in settings.targets() {
+            assert_eq!(fs::read_to_string(&target.deployed_file).unwrap(), target.deployed_file);
+        }
+        let _ = fs::remove_dir_all(&settings.home);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn jobs_deploy_programs_at_once() {
+        // a's hook only succeeds if b's runs while it waits, which it can't one program at a time
+        let home = std::env::temp_dir().join(format!("rodeo-test-jobs-at-once-{}", std::process::id()));
+        let flag = home.join("b-ran").to_string_lossy().into_owned();
+        let config = format!(
+            "dotfiles_directory = \"~/dotfiles\"\njobs = 2\n\n\
+             [[program]]\nname = \"a\"\nroot = \"~/a\"\npaths = [\"1\"]\npost_deploy_cmd = \"for i in $(seq 100); do [ -e '{0}' ] && exit 0; sleep 0.1; done; exit 1\"\n\n\
+             [[program]]\nname = \"b\"\nroot = \"~/b\"\npaths = [\"1\"]\npost_deploy_cmd = \"touch '{0}'\"\n",
+            flag
+        );
+        let settings = settings("jobs-at-once", &config);
+        for target in settings.targets() {
+            fs::create_dir_all(path::Path::new(&target.repo_file).parent().unwrap()).unwrap();
+            fs::write(&target.repo_file, "x").unwrap();
+        }
+
+        let summary = settings.deploy_with_observer(&mut Quiet);
+
+        assert_eq!(summary.sync_errors, 0);
+        assert!(path::Path::new(&flag).exists());
+        let _ = fs::remove_dir_all(&settings.home);
+    }
+
+    #[test]
+    fn status_cache_is_trusted_while_watch_runs() {
+        let settings = settings("watch-trusted", "dotfiles_directory = \"~/dotfiles\"\n\n[[program]]\nname = \"sh\"\nroot = \"~\"\npaths = [\".profile\"]\n");
+        let target = settings.targets().remove(0);
+        fs::create_dir_all(path::Path::new(&target.repo_file).parent().unwrap()).unwrap();
+        let an_hour_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        for file in [&target.repo_file, &target.deployed_file] {
+            fs::write(file, "PATH=~/bin:$PATH\n").unwrap();
+            copy::set_modified(file, an_hour_ago).unwrap();
+        }
+        let mut cache = settings.status_cache();
+        assert_eq!(cache.state(&target), FileState::Deployed);
+        cache.save();
 
-        // remove all instances of double slashes
-        path = path.replace("//", "/");
+        // with a watcher running, what it last saved stands, even though the file changed since
+        fs::write(watch::pid_path(&settings.state_dir), format!("{}\n", std::process::id())).unwrap();
+        fs::write(&target.deployed_file, "PATH=~/.local/bin:$PATH\n").unwrap();
+        assert_eq!(settings.status_cache().state(&target), FileState::Deployed);
 
-        path
+        fs::remove_file(watch::pid_path(&settings.state_dir)).unwrap();
+        assert_eq!(settings.status_cache().state(&target), FileState::Modified);
+        let _ = fs::remove_dir_all(&settings.home);
     }
 }
@@ -0,0 +1,725 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+use git2::{
+    build::CheckoutBuilder, BranchType, Cred, CredentialType, DiffOptions, ErrorCode, FetchOptions, Index, PushOptions,
+    Commit, Oid, RemoteCallbacks, Repository, RepositoryState, Signature, Sort, StashApplyOptions, StashFlags, StatusOptions, SubmoduleUpdateOptions, Tree,
+};
+use crate::audit::{self, AuditRecord};
+use crate::event::{Event, EventKind};
+use crate::plan::{InProgress, RepoStatus, Revision};
+use crate::state;
+use crate::vcs::{self, unstash_conflict, Git, PullStrategy, Vcs, DIVERGED};
+
+// the git backend on top of libgit2, so pulling and pushing need neither bash nor git and fail
+// with what actually went wrong. Previewing, cloning, applying patches and Git LFS are left to the
+// system's git, which libgit2 can't stand in for there.
+pub struct LibGit {
+    pub git: Git,
+}
+
+// what a pull or push stopped at, as shown to the user
+type Outcome = Result<String, String>;
+
+impl LibGit {
+    // logs an operation the way commands run through AuditLog::run are, so `rodeo audit` shows
+    // them too. There is no exit status, so success is logged as 0 and failure as 1.
+    fn logged(&self, action: &str, dotfiles_dir: &str, operation: impl FnOnce() -> Outcome) -> Event {
+        let timestamp = state::now();
+        let started = Instant::now();
+        let outcome = operation();
+
+        let record = AuditRecord {
+            timestamp,
+            kind: "vcs".to_owned(),
+            command: format!("libgit2 {} in {}", action, dotfiles_dir),
+            duration_ms: started.elapsed().as_millis(),
+            exit_status: Some(if outcome.is_ok() { 0 } else { 1 }),
+            output: match &outcome {
+                Ok(val) | Err(val) => val.clone(),
+            },
+        };
+        if let Err(e) = self.git.audit.append(&record) {
+            eprintln!("could not write to audit log \"{}\": {}", self.git.audit.path, e);
+        }
+
+        let event = Event::new(EventKind::Vcs, &format!("git-{}", action)[..], dotfiles_dir, "");
+        match outcome {
+            Ok(output) => event.with_output(output),
+            Err(e) => event.failed(format!("{} failed: {}", action, e)),
+        }
+    }
+}
+
+impl Vcs for LibGit {
+    fn pull(&self, dotfiles_dir: &str) -> Event {
+        if trimmed(dotfiles_dir) {
+            return self.git.pull(dotfiles_dir);
+        }
+        let signing = self.git.signing.clone();
+        let strategy = self.git.pull_strategy;
+        self.logged("pull", dotfiles_dir, || pull(dotfiles_dir, strategy, signing.as_deref()))
+    }
+
+    fn push(&self, dotfiles_dir: &str, files: &[String]) -> Event {
+        // libgit2 can't run Git LFS' filters, so files it tracks would be committed as they are
+        if uses_lfs(dotfiles_dir) || trimmed(dotfiles_dir) {
+            return self.git.push(dotfiles_dir, files);
+        }
+        let message = self.git.commit_message.clone();
+        let signing = self.git.signing.clone();
+        self.logged("push", dotfiles_dir, || push(dotfiles_dir, files, &message, signing.as_deref()))
+    }
+
+    fn commit(&self, dotfiles_dir: &str, files: &[String]) -> Event {
+        if uses_lfs(dotfiles_dir) {
+            return self.git.commit(dotfiles_dir, files);
+        }
+        let message = self.git.commit_message.clone();
+        let signing = self.git.signing.clone();
+        self.logged("commit", dotfiles_dir, || {
+            let repo = Repository::open(dotfiles_dir).map_err(|e| e.message().to_owned())?;
+            let branch = checked_out(&repo)?;
+            commit_changes(&repo, &branch, files, &message, signing.as_deref())
+        })
+    }
+
+    fn push_to(&self, dotfiles_dir: &str, remote: &str) -> Event {
+        // Git LFS' objects are sent by its pre-push hook, which only the system's git runs
+        if uses_lfs(dotfiles_dir) || trimmed(dotfiles_dir) {
+            return self.git.push_to(dotfiles_dir, remote);
+        }
+        let mut event = self.logged("push", dotfiles_dir, || push_to(dotfiles_dir, remote));
+        event.destination = remote.to_owned();
+        event
+    }
+
+    fn stash(&self, dotfiles_dir: &str) -> Event {
+        self.logged("stash", dotfiles_dir, || {
+            let mut repo = Repository::open(dotfiles_dir).map_err(|e| e.message().to_owned())?;
+            let signature = repo.signature().map_err(|e| signature_error(&e))?;
+            match repo.stash_save(&signature, "rodeo: before pulling", Some(StashFlags::INCLUDE_UNTRACKED)) {
+                Ok(id) => Ok(format!("Put uncommitted changes aside as {}.", short(id))),
+                Err(e) if e.code() == ErrorCode::NotFound => Ok("Nothing to put aside.".to_owned()),
+                Err(e) => Err(format!("could not put uncommitted changes aside: {}", e.message())),
+            }
+        })
+    }
+
+    fn unstash(&self, dotfiles_dir: &str) -> Event {
+        if trimmed(dotfiles_dir) {
+            return self.git.unstash(dotfiles_dir);
+        }
+        self.logged("unstash", dotfiles_dir, || unstash(dotfiles_dir))
+    }
+
+    fn repo_status(&self, dotfiles_dir: &str) -> Option<RepoStatus> {
+        let repo = Repository::open(dotfiles_dir).ok()?;
+        let branch = checked_out(&repo).ok();
+        let upstream = branch.as_ref()
+            .and_then(|b| repo.find_branch(b, BranchType::Local).ok())
+            .and_then(|b| b.upstream().ok());
+        let (ahead, behind) = match (repo.head().ok().and_then(|h| h.target()), upstream.as_ref().and_then(|u| u.get().target())) {
+            (Some(local), Some(remote)) => repo.graph_ahead_behind(local, remote).unwrap_or_default(),
+            _ => (0, 0),
+        };
+        let in_progress = match repo.state() {
+            RepositoryState::Clean => None,
+            RepositoryState::Merge => Some(InProgress::Merge),
+            RepositoryState::Revert | RepositoryState::RevertSequence => Some(InProgress::Revert),
+            RepositoryState::CherryPick | RepositoryState::CherryPickSequence => Some(InProgress::CherryPick),
+            RepositoryState::Bisect => Some(InProgress::Bisect),
+            _ => Some(InProgress::Rebase),
+        };
+        Some(RepoStatus {
+            branch,
+            upstream: upstream.and_then(|u| u.name().ok().flatten().map(str::to_owned)),
+            ahead,
+            behind,
+            uncommitted: self.changed_files(dotfiles_dir),
+            in_progress,
+        })
+    }
+
+    fn history(&self, dotfiles_dir: &str, files: &[String]) -> Result<Vec<Revision>, String> {
+        let error = |e: git2::Error| e.message().to_owned();
+        let repo = Repository::open(dotfiles_dir).map_err(error)?;
+        let mut walk = repo.revwalk().map_err(error)?;
+        walk.set_sorting(Sort::TIME).map_err(error)?;
+        walk.push_head().map_err(|e| format!("could not read HEAD: {}", e.message()))?;
+        let mut options = DiffOptions::new();
+        options.disable_pathspec_match(true);
+        for file in files {
+            options.pathspec(file);
+        }
+
+        let mut revisions = Vec::new();
+        for id in walk {
+            let commit = repo.find_commit(id.map_err(error)?).map_err(error)?;
+            let tree = commit.tree().map_err(error)?;
+            let mut changed = |parent: Option<&Tree>| -> Result<Vec<String>, String> {
+                let diff = repo.diff_tree_to_tree(parent, Some(&tree), Some(&mut options)).map_err(error)?;
+                Ok(diff.deltas()
+                    .filter_map(|d| d.new_file().path().or_else(|| d.old_file().path()))
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .collect())
+            };
+            // like git log, a merge only counts if it doesn't take the files as they were from
+            // one of its parents, and is shown with what it changed from the first
+            let parents: Vec<Tree> = commit.parents().filter_map(|p| p.tree().ok()).collect();
+            let changes = match parents.split_first() {
+                None => changed(None)?,
+                Some((first, rest)) => {
+                    let changes = changed(Some(first))?;
+                    let mut unchanged = false;
+                    for parent in rest {
+                        unchanged |= changed(Some(parent))?.is_empty();
+                    }
+                    if unchanged { Vec::new() } else { changes }
+                }
+            };
+            if changes.is_empty() {
+                continue;
+            }
+            revisions.push(Revision {
+                commit: commit.id().to_string(),
+                time: commit.time().seconds().max(0) as u64,
+                author: commit.author().name().unwrap_or_default().to_owned(),
+                message: commit.summary().unwrap_or_default().to_owned(),
+                files: changes,
+            });
+        }
+        Ok(revisions)
+    }
+
+    fn show_file(&self, dotfiles_dir: &str, commit: &str, file: &str) -> Result<Vec<u8>, String> {
+        if trimmed(dotfiles_dir) {
+            return self.git.show_file(dotfiles_dir, commit, file);
+        }
+        let repo = Repository::open(dotfiles_dir).map_err(|e| e.message().to_owned())?;
+        let tree = repo.revparse_single(commit)
+            .and_then(|o| o.peel_to_commit())
+            .and_then(|c| c.tree())
+            .map_err(|e| format!("no commit \"{}\": {}", commit, e.message()))?;
+        let blob = tree.get_path(Path::new(file))
+            .and_then(|entry| entry.to_object(&repo))
+            .and_then(|o| o.peel_to_blob())
+            .map_err(|_| format!("{} doesn't have {}", commit, file))?;
+        Ok(blob.content().to_vec())
+    }
+
+    fn changed_files(&self, dotfiles_dir: &str) -> Vec<String> {
+        let repo = match Repository::open(dotfiles_dir) {
+            Ok(val) => val,
+            Err(_) => return Vec::new(),
+        };
+        let mut options = StatusOptions::new();
+        options.include_untracked(true).recurse_untracked_dirs(true).exclude_submodules(true);
+        repo.statuses(Some(&mut options))
+            .map(|statuses| statuses.iter().filter_map(|s| s.path().map(str::to_owned)).collect())
+            .unwrap_or_default()
+    }
+
+    fn fetch_incoming(&self, dotfiles_dir: &str, worktree_dir: &str) -> Option<Event> {
+        self.git.fetch_incoming(dotfiles_dir, worktree_dir)
+    }
+
+    fn remove_incoming(&self, dotfiles_dir: &str, worktree_dir: &str) {
+        self.git.remove_incoming(dotfiles_dir, worktree_dir)
+    }
+
+    fn update_submodules(&self, dotfiles_dir: &str) -> Option<Event> {
+        if !Path::new(dotfiles_dir).join(".gitmodules").is_file() {
+            return None;
+        }
+        Some(self.logged("submodule-update", dotfiles_dir, || {
+            let repo = Repository::open(dotfiles_dir).map_err(|e| e.message().to_owned())?;
+            let mut updated = Vec::new();
+            update_submodules(&repo, "", &mut updated)?;
+            Ok(format!("Updated submodules {}.", vcs::short_list(&updated, "(none)")))
+        }))
+    }
+
+    fn clone_repo(&self, remote: &str, branch: &str, depth: Option<u32>, filter: &str, dotfiles_dir: &str) -> Event {
+        self.git.clone_repo(remote, branch, depth, filter, dotfiles_dir)
+    }
+
+    fn apply_patch(&self, dotfiles_dir: &str, patch_file: &str, commit: bool) -> Event {
+        self.git.apply_patch(dotfiles_dir, patch_file, commit)
+    }
+
+    fn switch_branch(&self, dotfiles_dir: &str, branch: &str) -> Option<Event> {
+        self.git.switch_branch(dotfiles_dir, branch)
+    }
+
+    fn track_binary(&self, dotfiles_dir: &str, files: &[String]) -> Event {
+        self.git.track_binary(dotfiles_dir, files)
+    }
+}
+
+// checks out the commit repo records for each of its submodules, initializing and cloning them
+// as needed, then does the same inside each of them. updated collects their paths, relative to the
+// outermost repository (which prefix leads to repo).
+fn update_submodules(repo: &Repository, prefix: &str, updated: &mut Vec<String>) -> Result<(), String> {
+    let submodules = repo.submodules().map_err(|e| format!("could not read .gitmodules: {}", e.message()))?;
+    for mut submodule in submodules {
+        let path = format!("{}{}", prefix, submodule.path().display());
+        // picks up a url changed in .gitmodules, like git submodule sync
+        submodule.sync().map_err(|e| format!("submodule {}: {}", path, e.message()))?;
+        let mut fetch = FetchOptions::new();
+        fetch.remote_callbacks(callbacks(repo));
+        let mut options = SubmoduleUpdateOptions::new();
+        options.fetch(fetch);
+        submodule.update(true, Some(&mut options))
+            .map_err(|e| format!("could not update submodule {}: {}", path, e.message()))?;
+        updated.push(path.clone());
+        if let Ok(inner) = submodule.open() {
+            update_submodules(&inner, &format!("{}/", path), updated)?;
+        }
+    }
+    Ok(())
+}
+
+// whether the repository's .gitattributes has Git LFS store any files
+fn uses_lfs(dotfiles_dir: &str) -> bool {
+    fs::read_to_string(Path::new(dotfiles_dir).join(".gitattributes"))
+        .map(|attributes| attributes.contains("filter=lfs"))
+        .unwrap_or(false)
+}
+
+// whether the repository is a shallow or partial clone. libgit2 can't fetch the file contents a
+// partial clone leaves out as they're needed, and its support for shallow history is new, so
+// anything that walks history or reads old files goes through the system's git instead.
+fn trimmed(dotfiles_dir: &str) -> bool {
+    let repo = match Repository::open(dotfiles_dir) {
+        Ok(val) => val,
+        Err(_) => return false,
+    };
+    if repo.is_shallow() {
+        return true;
+    }
+    // git marks the remote a partial clone came from as a promisor, and older versions also set
+    // extensions.partialclone
+    let config = match repo.config() {
+        Ok(val) => val,
+        Err(_) => return false,
+    };
+    let promisor = config.entries(Some(r"remote\..*\.promisor"))
+        .map(|mut entries| {
+            let mut found = false;
+            while let Some(Ok(entry)) = entries.next() {
+                found |= entry.value().is_some_and(|v| v == "true");
+            }
+            found
+        })
+        .unwrap_or(false);
+    promisor || config.get_string("extensions.partialclone").is_ok()
+}
+
+// the checked out branch, its remote, and the branch on the remote it follows, e.g. ("main",
+// "origin", "refs/heads/main"). The branch may not have any commits yet.
+fn tracking(repo: &Repository) -> Result<(String, String, String), String> {
+    let branch = checked_out(repo)?;
+    let config = repo.config().map_err(|e| e.message().to_owned())?;
+    let no_upstream = || format!("branch \"{}\" doesn't follow a branch on a remote; set one with git push -u", branch);
+    let remote = config.get_string(&format!("branch.{}.remote", branch)).map_err(|_| no_upstream())?;
+    let merge = config.get_string(&format!("branch.{}.merge", branch)).map_err(|_| no_upstream())?;
+    Ok((branch, remote, merge))
+}
+
+// the name of the checked out branch, which may not have any commits yet
+fn checked_out(repo: &Repository) -> Result<String, String> {
+    let head = repo.find_reference("HEAD").map_err(|e| format!("could not read HEAD: {}", e.message()))?;
+    match head.symbolic_target().and_then(|t| t.strip_prefix("refs/heads/")) {
+        Some(val) => Ok(val.to_owned()),
+        None => Err("HEAD isn't on a branch; check one out".to_owned()),
+    }
+}
+
+// answers the remote's requests for credentials from the ssh agent, then the usual key files, then
+// git's credential helpers. Each is offered once, as libgit2 keeps asking while they're refused.
+fn callbacks<'a>(repo: &Repository) -> RemoteCallbacks<'a> {
+    let config = repo.config().ok();
+    let home = env::var("HOME").unwrap_or_default();
+    let keys: Vec<String> = ["id_ed25519", "id_ecdsa", "id_rsa"].iter()
+        .map(|k| format!("{}/.ssh/{}", home, k))
+        .filter(|k| Path::new(k).is_file())
+        .collect();
+    let mut attempt = 0;
+
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        attempt += 1;
+        if allowed.contains(CredentialType::SSH_KEY) {
+            let user = username.unwrap_or("git");
+            if attempt == 1 {
+                return Cred::ssh_key_from_agent(user);
+            }
+            if let Some(key) = keys.get(attempt - 2) {
+                return Cred::ssh_key(user, None, Path::new(key), None);
+            }
+        } else if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) && attempt == 1 {
+            if let Some(config) = &config {
+                return Cred::credential_helper(config, url, username);
+            }
+        } else if allowed.contains(CredentialType::DEFAULT) && attempt == 1 {
+            return Cred::default();
+        }
+        Err(git2::Error::from_str("no credentials were accepted; check that ssh-agent has your key or that a credential helper is set up"))
+    });
+    callbacks
+}
+
+// fetches the branch HEAD follows and brings it in like git pull: fast-forwarding if that's all it
+// takes, merging otherwise. A merge with conflicts is refused before anything is changed, as are
+// incoming changes to files that have local changes.
+fn pull(dotfiles_dir: &str, strategy: Option<PullStrategy>, signing: Option<&str>) -> Outcome {
+    let repo = Repository::open(dotfiles_dir).map_err(|e| e.message().to_owned())?;
+    if repo.state() != RepositoryState::Clean {
+        return Err("the repository is in the middle of a merge, rebase or similar; finish it with git first".to_owned());
+    }
+    let (branch, remote_name, merge) = tracking(&repo)?;
+
+    let mut remote = repo.find_remote(&remote_name)
+        .map_err(|e| format!("remote \"{}\": {}", remote_name, e.message()))?;
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks(&repo));
+    remote.fetch(&[] as &[&str], Some(&mut options), None)
+        .map_err(|e| format!("could not fetch from \"{}\": {}", remote_name, e.message()))?;
+
+    // a remote nobody has pushed to yet has nothing to bring in
+    let upstream_name = repo.branch_upstream_name(&format!("refs/heads/{}", branch))
+        .map_err(|e| e.message().to_owned())?;
+    let upstream_ref = match repo.find_reference(upstream_name.as_str().unwrap_or_default()) {
+        Ok(val) => val,
+        Err(e) if e.code() == ErrorCode::NotFound => {
+            return Ok(format!("\"{}\" has no {} yet, nothing to pull.", remote_name, merge.trim_start_matches("refs/heads/")));
+        }
+        Err(e) => return Err(e.message().to_owned()),
+    };
+    let upstream_ref = &upstream_ref;
+    let incoming = repo.reference_to_annotated_commit(upstream_ref).map_err(|e| e.message().to_owned())?;
+    let (analysis, _) = repo.merge_analysis(&[&incoming]).map_err(|e| e.message().to_owned())?;
+
+    if analysis.is_up_to_date() {
+        return Ok("Already up to date.".to_owned());
+    }
+
+    let theirs = upstream_ref.peel_to_commit().map_err(|e| e.message().to_owned())?;
+    let mut checkout = CheckoutBuilder::new();
+    checkout.safe();
+    let conflict = |e: git2::Error| format!("incoming changes would overwrite local changes that aren't committed: {}", e.message());
+
+    // a branch without commits yet is simply started at the incoming one
+    if analysis.is_fast_forward() || analysis.is_unborn() {
+        repo.checkout_tree(theirs.as_object(), Some(&mut checkout)).map_err(conflict)?;
+        repo.reference(&format!("refs/heads/{}", branch), theirs.id(), true, "rodeo: pull (fast-forward)")
+            .map_err(|e| e.message().to_owned())?;
+        return Ok(format!("Fast-forwarded {} to {}.", branch, short(theirs.id())));
+    }
+
+    let ours = repo.head().and_then(|h| h.peel_to_commit()).map_err(|e| e.message().to_owned())?;
+    match strategy.unwrap_or_else(|| configured_strategy(&repo, &branch)) {
+        PullStrategy::FfOnly => return Err(DIVERGED.to_owned()),
+        PullStrategy::Rebase => return rebase(&repo, &branch, &ours, &theirs, upstream_ref.shorthand().unwrap_or(&merge), signing),
+        PullStrategy::Merge => {}
+    }
+
+    let mut index = repo.merge_commits(&ours, &theirs, None).map_err(|e| e.message().to_owned())?;
+    if index.has_conflicts() {
+        let conflicts: Vec<String> = index.conflicts().map_err(|e| e.message().to_owned())?
+            .flatten()
+            .filter_map(|c| c.our.or(c.their))
+            .map(|entry| String::from_utf8_lossy(&entry.path).into_owned())
+            .collect();
+        return Err(format!(
+            "merging {} would conflict in {}; merge it with git pull instead",
+            upstream_ref.shorthand().unwrap_or(&merge),
+            conflicts.join(", ")
+        ));
+    }
+
+    let tree = index.write_tree_to(&repo).and_then(|t| repo.find_tree(t)).map_err(|e| e.message().to_owned())?;
+    repo.checkout_tree(tree.as_object(), Some(&mut checkout)).map_err(conflict)?;
+    let message = format!("Merge branch '{}' of {}", merge.trim_start_matches("refs/heads/"), remote_name);
+    let merged = commit(&repo, &branch, &message, &tree, &[&ours, &theirs], signing)?;
+    Ok(format!("Merged {} into {} as {}.", upstream_ref.shorthand().unwrap_or(&merge), branch, short(merged)))
+}
+
+// pops the stash rodeo made before pulling, if it is still the newest one. libgit2 applies a stash
+// that conflicts with conflict markers, so it is merged on its own first, and one that would
+// conflict with the pulled changes is left in the stash and the working tree as it is.
+fn unstash(dotfiles_dir: &str) -> Outcome {
+    let mut repo = Repository::open(dotfiles_dir).map_err(|e| e.message().to_owned())?;
+    let error = |e: git2::Error| e.message().to_owned();
+    let mut ours = false;
+    repo.stash_foreach(|index, message, _| {
+        ours = index == 0 && message.ends_with("rodeo: before pulling");
+        false
+    }).map_err(error)?;
+    if !ours {
+        return Ok("Nothing was put aside.".to_owned());
+    }
+
+    {
+        let stash = repo.revparse_single("refs/stash").and_then(|o| o.peel_to_commit()).map_err(error)?;
+        let base = stash.parent(0).and_then(|c| c.tree()).map_err(error)?;
+        let head = repo.head().and_then(|h| h.peel_to_tree()).map_err(error)?;
+        let merged = repo.merge_trees(&base, &head, &stash.tree().map_err(error)?, None).map_err(error)?;
+        if merged.has_conflicts() {
+            let files: Vec<String> = merged.conflicts().map_err(error)?
+                .flatten()
+                .filter_map(|c| c.our.or(c.their))
+                .map(|entry| String::from_utf8_lossy(&entry.path).into_owned())
+                .collect();
+            return Err(unstash_conflict(&files));
+        }
+    }
+
+    let mut checkout = CheckoutBuilder::new();
+    checkout.safe();
+    let mut options = StashApplyOptions::new();
+    options.checkout_options(checkout);
+    repo.stash_apply(0, Some(&mut options))
+        .map_err(|e| format!("could not bring back the uncommitted changes, which are kept in git's stash: {}", e.message()))?;
+    repo.stash_drop(0).map_err(error)?;
+    Ok("Brought back the uncommitted changes.".to_owned())
+}
+
+// the strategy git pull would use: branch.<name>.rebase or pull.rebase if either is set, then
+// pull.ff = only, and a merge otherwise
+fn configured_strategy(repo: &Repository, branch: &str) -> PullStrategy {
+    let config = match repo.config() {
+        Ok(val) => val,
+        Err(_) => return PullStrategy::Merge,
+    };
+    let rebase = config.get_string(&format!("branch.{}.rebase", branch))
+        .or_else(|_| config.get_string("pull.rebase"))
+        .ok();
+    match rebase.as_deref() {
+        Some("false") => return PullStrategy::Merge,
+        Some(_) => return PullStrategy::Rebase,
+        None => {}
+    }
+    match config.get_string("pull.ff").ok().as_deref() {
+        Some("only") => PullStrategy::FfOnly,
+        _ => PullStrategy::Merge,
+    }
+}
+
+// replays the commits ours has and theirs doesn't on top of theirs, one at a time, like git pull
+// --rebase. The branch and the working tree are only moved once every commit went through, so a
+// conflict leaves everything as it was.
+fn rebase(repo: &Repository, branch: &str, ours: &Commit, theirs: &Commit, upstream: &str, signing: Option<&str>) -> Outcome {
+    let error = |e: git2::Error| e.message().to_owned();
+    let base = repo.merge_base(ours.id(), theirs.id()).map_err(error)?;
+    let mut walk = repo.revwalk().map_err(error)?;
+    walk.push(ours.id()).and_then(|_| walk.hide(base)).map_err(error)?;
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE).map_err(error)?;
+
+    let committer = repo.signature().map_err(|e| signature_error(&e))?;
+    let mut onto = theirs.clone();
+    let mut replayed = 0;
+    for id in walk {
+        let commit = repo.find_commit(id.map_err(error)?).map_err(error)?;
+        let summary = commit.summary().unwrap_or_default().to_owned();
+        if commit.parent_count() > 1 {
+            return Err(format!("{} (\"{}\") is a merge, which can't be rebased; set pull_strategy to \"merge\" or rebase it with git", short(commit.id()), summary));
+        }
+        let mut index = repo.cherrypick_commit(&commit, &onto, 0, None).map_err(error)?;
+        if index.has_conflicts() {
+            return Err(format!("replaying {} (\"{}\") on {} would conflict; nothing was changed, rebase it with git pull --rebase instead", short(commit.id()), summary, upstream));
+        }
+        let tree = index.write_tree_to(repo).and_then(|t| repo.find_tree(t)).map_err(error)?;
+        // changes the remote already has drop out, as they do with git rebase
+        if tree.id() == onto.tree_id() {
+            continue;
+        }
+        let message = commit.message().unwrap_or_default();
+        let id = create_commit(repo, &commit.author(), &committer, message, &tree, &[&onto], signing)?;
+        onto = repo.find_commit(id).map_err(error)?;
+        replayed += 1;
+    }
+
+    let mut checkout = CheckoutBuilder::new();
+    checkout.safe();
+    repo.checkout_tree(onto.as_object(), Some(&mut checkout))
+        .map_err(|e| format!("incoming changes would overwrite local changes that aren't committed: {}", e.message()))?;
+    repo.reference(&format!("refs/heads/{}", branch), onto.id(), true, &format!("rodeo: pull (rebase onto {})", upstream))
+        .map_err(error)?;
+    Ok(format!("Rebased {} local commit(s) of {} onto {}.", replayed, branch, upstream))
+}
+
+// commits the changes to files with message if there are any (see commit_changes), and pushes the
+// branch to the one it follows. Commits made earlier that were never pushed go out even if there
+// is nothing new.
+fn push(dotfiles_dir: &str, files: &[String], message: &str, signing: Option<&str>) -> Outcome {
+    let repo = Repository::open(dotfiles_dir).map_err(|e| e.message().to_owned())?;
+    let (branch, remote_name, merge) = tracking(&repo)?;
+    let committed = commit_changes(&repo, &branch, files, message, signing)?;
+    let pushed = push_branch(&repo, &branch, &remote_name, &merge)?;
+    Ok(format!("{}\n{}", committed, pushed))
+}
+
+// pushes the checked out branch to the branch of the same name on remote_name
+fn push_to(dotfiles_dir: &str, remote_name: &str) -> Outcome {
+    let repo = Repository::open(dotfiles_dir).map_err(|e| e.message().to_owned())?;
+    let branch = checked_out(&repo)?;
+    push_branch(&repo, &branch, remote_name, &format!("refs/heads/{}", branch))
+}
+
+// stages files (relative to the repository), removals included, and commits them if any of them
+// changed. The commit is HEAD with just those files changed, so whatever else is staged stays
+// staged and out of it, as with git commit -- <files>.
+fn commit_changes(repo: &Repository, branch: &str, files: &[String], message: &str, signing: Option<&str>) -> Outcome {
+    let error = |e: git2::Error| e.message().to_owned();
+    let workdir = repo.workdir().ok_or("the repository has no working tree")?.to_owned();
+    let mut index = repo.index().map_err(error)?;
+    for file in files {
+        let path = Path::new(file);
+        let staged = if workdir.join(path).symlink_metadata().is_ok() { index.add_path(path) } else { index.remove_path(path) };
+        staged.map_err(|e| format!("could not stage {}: {}", file, e.message()))?;
+    }
+    index.write().map_err(|e| format!("could not stage changes: {}", e.message()))?;
+
+    let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+    let mut committed = Index::new().map_err(error)?;
+    if let Some(parent) = &parent {
+        committed.read_tree(&parent.tree().map_err(error)?).map_err(error)?;
+    }
+    for file in files {
+        let path = Path::new(file);
+        match index.get_path(path, 0) {
+            Some(entry) => committed.add(&entry),
+            None => committed.remove_path(path),
+        }.map_err(error)?;
+    }
+    let tree = committed.write_tree_to(repo).and_then(|t| repo.find_tree(t)).map_err(error)?;
+
+    if parent.as_ref().map(|p| p.tree_id()) == Some(tree.id()) {
+        return Ok("Nothing to commit.".to_owned());
+    }
+    let parents: Vec<_> = parent.iter().collect();
+    let commit = commit(repo, branch, message, &tree, &parents, signing)?;
+    Ok(format!("Committed local changes as {}.", short(commit)))
+}
+
+// pushes branch to merge, a branch on remote_name, failing if the remote rejects it
+fn push_branch(repo: &Repository, branch: &str, remote_name: &str, merge: &str) -> Outcome {
+    let mut remote = repo.find_remote(remote_name)
+        .map_err(|e| format!("remote \"{}\": {}", remote_name, e.message()))?;
+    let mut rejected = None;
+    {
+        let mut callbacks = callbacks(repo);
+        callbacks.push_update_reference(|reference, status| {
+            if let Some(status) = status {
+                rejected = Some(format!("\"{}\" rejected {}: {}", remote_name, reference, status));
+            }
+            Ok(())
+        });
+        let mut options = PushOptions::new();
+        options.remote_callbacks(callbacks);
+        remote.push(&[format!("refs/heads/{}:{}", branch, merge)], Some(&mut options))
+            .map_err(|e| format!("could not push to \"{}\": {}", remote_name, e.message()))?;
+    }
+    if let Some(rejected) = rejected {
+        return Err(rejected);
+    }
+    Ok(format!("Pushed {} to {}.", branch, remote_name))
+}
+
+// commits tree on top of parents as the checked out branch, signed with signing's key if it is
+// set; see sign
+fn commit(repo: &Repository, branch: &str, message: &str, tree: &Tree, parents: &[&Commit], signing: Option<&str>) -> Result<Oid, String> {
+    let author = repo.signature().map_err(|e| signature_error(&e))?;
+    let id = create_commit(repo, &author, &author, message, tree, parents, signing)?;
+    repo.reference(&format!("refs/heads/{}", branch), id, true, &format!("commit: {}", message.lines().next().unwrap_or_default()))
+        .map_err(|e| format!("could not commit: {}", e.message()))?;
+    Ok(id)
+}
+
+// writes a commit without pointing any branch at it
+fn create_commit(repo: &Repository, author: &Signature, committer: &Signature, message: &str, tree: &Tree, parents: &[&Commit], signing: Option<&str>) -> Result<Oid, String> {
+    let failed = |e: git2::Error| format!("could not commit: {}", e.message());
+    let key = match signing {
+        Some(val) => val,
+        None => return repo.commit(None, author, committer, message, tree, parents).map_err(failed),
+    };
+    let contents = repo.commit_create_buffer(author, committer, message, tree, parents).map_err(failed)?;
+    let contents = String::from_utf8_lossy(&contents).into_owned();
+    let signature = sign(repo, &contents, key, committer)?;
+    repo.commit_signed(&contents, &signature, None).map_err(failed)
+}
+
+// signs a commit's contents the way git commit -S does, with the program git's config names for
+// gpg.format: gpg for openpgp (the default), gpgsm for x509 and ssh-keygen for ssh. key is
+// user.signingkey if empty, and for openpgp the committer's name and email if that isn't set
+// either. An ssh key may be a file or, as git allows, "key::" and the public key itself.
+fn sign(repo: &Repository, contents: &str, key: &str, author: &Signature) -> Result<String, String> {
+    let config = repo.config().map_err(|e| e.message().to_owned())?;
+    let setting = |name: &str| config.get_string(name).ok().filter(|v| !v.is_empty());
+    let format = setting("gpg.format").unwrap_or_else(|| "openpgp".to_owned());
+    let key = match (key, setting("user.signingkey")) {
+        ("", Some(configured)) => configured,
+        ("", None) if format == "openpgp" => format!("{} <{}>", author.name().unwrap_or_default(), author.email().unwrap_or_default()),
+        ("", None) => return Err(format!("signing with {} needs a key; set signing_key in [defaults] or user.signingkey with git config", format)),
+        (key, _) => key.to_owned(),
+    };
+
+    // ssh-keygen needs the key in a file; a literal one is written to a temporary one
+    let mut written = None;
+    let mut command = match &format[..] {
+        "ssh" => {
+            let file = match key.strip_prefix("key::") {
+                Some(literal) => {
+                    let file = env::temp_dir().join(format!("rodeo-signing-key-{}.pub", std::process::id()));
+                    fs::write(&file, literal).map_err(|e| format!("could not write the signing key to {}: {}", file.display(), e))?;
+                    written = Some(file.clone());
+                    file.to_string_lossy().into_owned()
+                }
+                None => key.clone(),
+            };
+            let mut command = Command::new(setting("gpg.ssh.program").unwrap_or_else(|| "ssh-keygen".to_owned()));
+            command.args(["-Y", "sign", "-n", "git", "-f", &file]);
+            command
+        }
+        "x509" => {
+            let mut command = Command::new(setting("gpg.x509.program").unwrap_or_else(|| "gpgsm".to_owned()));
+            command.args(["--status-fd=2", "-bsau", &key]);
+            command
+        }
+        _ => {
+            let program = setting("gpg.openpgp.program").or_else(|| setting("gpg.program")).unwrap_or_else(|| "gpg".to_owned());
+            let mut command = Command::new(program);
+            command.args(["--status-fd=2", "-bsau", &key]);
+            command
+        }
+    };
+
+    let signed = command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()
+        .and_then(|mut child| {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(contents.as_bytes())?;
+            }
+            child.wait_with_output()
+        });
+    if let Some(file) = written {
+        let _ = fs::remove_file(file);
+    }
+    match signed {
+        Ok(output) if output.status.success() && !output.stdout.is_empty() => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        Ok(output) => Err(format!("could not sign the commit with {}: {}", key, audit::describe_failure(&output))),
+        Err(e) => Err(format!("could not run {:?} to sign the commit: {}", command.get_program(), e)),
+    }
+}
+
+fn short(id: git2::Oid) -> String {
+    id.to_string()[..7].to_owned()
+}
+
+fn signature_error(e: &git2::Error) -> String {
+    format!("{}; set user.name and user.email with git config", e.message())
+}
@@ -0,0 +1,98 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+// how deploy puts files in place, chosen in rodeo.toml with e.g. `deploy_mode = "symlink"` or on a
+// single program. With symlink, the way GNU stow does it, each deployed file is a symlink to its
+// repository copy, so editing it edits the repository and collect has nothing left to do for it.
+// Hardlink does the same with hard links, for tools that resolve symlinks or backups that don't
+// follow them; a hard link can only be made on the filesystem the repository is on, and an editor
+// that saves by replacing the file breaks it, after which the file is treated as a copy again.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeployMode {
+    #[default]
+    Copy,
+    Symlink,
+    Hardlink,
+}
+
+// what deploy, collect and sync do with a file they are about to copy that turns out to be a
+// symlink, e.g. one another tool manages, chosen per program with `symlinks = "preserve"`. Follow
+// (the default) copies what it points to, preserve makes the copy a symlink pointing the same
+// way, and refuse leaves both files alone with a warning.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SourceLinks {
+    #[default]
+    Follow,
+    Preserve,
+    Refuse,
+}
+
+// whether path itself is a symlink, whether or not what it points at exists
+pub fn is_link(path: &str) -> bool {
+    fs::symlink_metadata(path).map(|m| m.file_type().is_symlink()).unwrap_or(false)
+}
+
+// whether path is a symlink pointing at target
+pub fn links_to(path: &str, target: &str) -> bool {
+    fs::read_link(path).map(|p| p == Path::new(target)).unwrap_or(false)
+}
+
+// whether a and b are the same file, e.g. hard links to each other. Symlinks aren't followed.
+#[cfg(unix)]
+pub fn same_file(a: &str, b: &str) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn same_file(_a: &str, _b: &str) -> bool {
+    false
+}
+
+// whether path is linked to target in either way deploy links files, so that the two are one file
+pub fn linked(path: &str, target: &str) -> bool {
+    links_to(path, target) || same_file(path, target)
+}
+
+// whether path is target put in place the way mode links files
+pub fn in_place(mode: DeployMode, path: &str, target: &str) -> bool {
+    match mode {
+        DeployMode::Copy => false,
+        DeployMode::Symlink => links_to(path, target),
+        DeployMode::Hardlink => same_file(path, target),
+    }
+}
+
+// whether path is a symlink pointing at something that doesn't exist
+pub fn is_broken(path: &str) -> bool {
+    is_link(path) && !Path::new(path).exists()
+}
+
+// creates a symlink at path pointing at target
+#[cfg(unix)]
+pub fn create(target: &str, path: &str) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+// creating symlinks needs developer mode or administrator rights on Windows
+#[cfg(windows)]
+pub fn create(target: &str, path: &str) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, path)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn create(_target: &str, _path: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "symlinks aren't supported on this system"))
+}
+
+// creates a hard link at path to target
+pub fn create_hard(target: &str, path: &str) -> io::Result<()> {
+    fs::hard_link(target, path)
+}
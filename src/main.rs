@@ -1,22 +1,63 @@
 use rodeo::*;
+use rodeo::cli;
+use rodeo::config::{self, Config};
+use rodeo::event::{confirm, Observer, OutputMode};
+use rodeo::format::ConfigFormat;
+use rodeo::journal::Operation;
+use rodeo::plan::Drift;
+use rodeo::query::{self, Query};
+use rodeo::pager;
+use rodeo::mapping::{FileState, PathFilter, ReverseIndex};
+use rodeo::summary::{Summary, EXIT_CONFIG_ERROR};
+use rodeo::term::{Style, Terminal};
+use rodeo::validate;
+use std::fmt::Write;
 use std::fs::File;
 use std::fs;
 use std::env;
+use std::process;
 
-fn main() -> std::io::Result<()> {
+fn main() {
 
     // |--------------------------------------------|
     // | config file creation/reading/deserializing |
     // |--------------------------------------------|
 
-    // reads $HOME variable, returns home directory's location without a trailing slash
-    let user_home = env::var("HOME").expect("Could not get path of user's home directory!");
-    // get config file path, by default ~/.config/rodeo/rodeo.toml
-    let mut config_file_path = match env::var("RODEO_PATH") {
+    // parse command line arguments; this also works out the user's home directory and where the
+    // config file should be
+    let config = match Config::from_args(env::args().skip(1)) {
         Ok(val) => val,
-        Err(_) => format!("{}/.config/rodeo/rodeo.toml", user_home)
+        Err(e) => {
+            println!("{}. Stop.", e);
+            process::exit(EXIT_CONFIG_ERROR);
+        }
     };
 
+    // commands that only print documentation don't need a config file
+    match cli::lookup(&config.command).map(|c| c.name) {
+        Some("help") => {
+            print!("{}", cli::help_text());
+            return;
+        }
+        Some("man") => {
+            print!("{}", cli::man_page());
+            return;
+        }
+        Some("version") => {
+            println!("rodeo {}", version::VERSION);
+            return;
+        }
+        // inspect is for configs that aren't the user's own, so it doesn't load that one
+        Some("inspect") => match config.args.first() {
+            Some(path) => process::exit(inspect(&config, path).exit_code()),
+            None => config_error("inspect needs a config file or repository to inspect, e.g. \"rodeo inspect ~/src/their-dotfiles\". Stop."),
+        },
+        _ => (),
+    }
+
+    let user_home = config.home.clone();
+    let mut config_file_path = config.config_file_path.clone();
+
     // check for existance of config file. If it exists, open it, if not, fall back on default
     // location.
     let config_file = match File::open(&config_file_path) {
@@ -24,62 +65,677 @@ fn main() -> std::io::Result<()> {
         // path
         Ok(val) => val,
         Err(_) => {
-            // inform user of the error, attempt to use the default path. This will ensure the
-            // existance of a folder at ~/.config/rodeo if ~/.config exists and user has
-            // permissions. If ~/.config does not exist or the user does not have permissions, the
-            // program will panic. Finally, if rodeo.toml exists it will be opened and loaded.
-            // If no rodeo.toml file exists at that location, it will be created.
+            // inform user of the error and fall back on the first config file that exists, see
+            // config::config_candidates. If there is none, an empty one is created where new
+            // configs go for the user to fill in.
             println!("no path \"{}\", attempting to use default config path...", &config_file_path);
-            config_file_path = format!("{}/.config/rodeo", user_home);
-            match fs::create_dir(&config_file_path) {
-                Ok(_) => File::create(&config_file_path)?,
-                Err(_) => match File::create(&config_file_path) {
-                    Ok(_) => {
-                        std::thread::sleep(std::time::Duration::from_millis(300));
-                        panic!("created a new config file at ~/.config/rodeo/rodeo.toml, you must populate it for rodeo to function.")
+            match config::find_config(&user_home) {
+                Some(val) => {
+                    config_file_path = val;
+                    match File::open(&config_file_path) {
+                        Ok(val) => val,
+                        Err(e) => config_error(format!("could not read config file \"{}\": {}", config_file_path, e)),
+                    }
+                }
+                None => {
+                    config_file_path = config::new_config_path(&user_home);
+                    let created = std::path::Path::new(&config_file_path).parent()
+                        .map_or(Ok(()), fs::create_dir_all)
+                        .and_then(|_| File::create(&config_file_path));
+                    match created {
+                        Ok(_) => config_error(format!("created a new config file at {}, you must populate it for rodeo to function.", config_file_path)),
+                        Err(e) => config_error(format!("could not create config file \"{}\": {}", config_file_path, e)),
                     }
-                    Err(_) => panic!("could not read config file at $RODEO_PATH, ~/.config/rodeo/rodeo.toml, or create folder ~/.config/rodeo, exiting!")
                 }
             }
         }
     };
 
     // read the user's config file
-    let settings = Settings::new_from_file(config_file, user_home, config_file_path)?;
+    let format = config.format.unwrap_or_else(|| ConfigFormat::from_path(&config_file_path));
+    let mut settings = match Settings::new_from_file_with_format(config_file, user_home, config_file_path.clone(), format) {
+        Ok(val) => val,
+        Err(e) => config_error(format!("could not read config file \"{}\": {}", config_file_path, e)),
+    };
+    settings.output = config.output;
+    settings.yes = config.yes;
+    settings.prefer = config.prefer;
+    settings.terminal = if config.plain { Terminal::plain() } else { Terminal::detect() };
+    if let Some(rate) = &config.limit_rate {
+        settings.limit_rate = rate.clone();
+    }
+    if config.jobs.is_some() {
+        settings.jobs = config.jobs;
+    }
+    if let Some(message) = &config.message {
+        settings.defaults.commit_message = message.clone();
+    }
+    if let Some(branch) = &config.branch {
+        settings.branch = branch.clone();
+    }
+    if config.depth.is_some() {
+        settings.clone_depth = config.depth;
+    }
+    settings.no_escalate = config.no_escalate;
+    if let Err(e) = settings.select_profile(&config.profile) {
+        config_error(e);
+    }
+    if let Err(e) = settings.select_programs(&config.programs) {
+        config_error(e);
+    }
+    if let Err(e) = settings.select_tags(&config.tags) {
+        config_error(e);
+    }
+    settings.select_host(&config.host);
+    settings.select_os(env::consts::OS);
+    settings.select_conditions();
+    settings.select_paths(PathFilter::new(&config.include, &config.exclude));
 
     // |----------------------------------|
     // | command interpretation/execution |
     // |----------------------------------|
 
-    let mut args = env::args();
+    let command = config.command.clone();
+
+    // an interrupted operation has to be dealt with before anything else writes files, otherwise
+    // the journal could no longer tell which files to put back
+    let command_name = cli::lookup(&command).map(|c| c.name);
+    if let Some(operation) = settings.interrupted() {
+        if let Some("deploy") | Some("collect") | Some("sync") | Some("sync-local") | Some("unlink") | Some("prune") | Some("rollback") | Some("restore") | Some("revert") | Some("trash") | Some("import") = command_name {
+            println!(
+                "the last {} was interrupted before it finished. Run \"rodeo resume\" to finish it or \"rodeo abort\" to undo it. Stop.",
+                operation.name()
+            );
+            process::exit(EXIT_CONFIG_ERROR);
+        }
+    }
+
+    // long listings go through the pager, but never JSON, which is meant for other programs
+    let paging = !config.no_pager && config.output == OutputMode::Text;
+
+    // --dry-run turns deploy, collect and sync into the matching plan
+    let plan_operation = |name: &str| match name {
+        "deploy" => Some(Operation::Deploy),
+        "collect" => Some(Operation::Collect),
+        "sync" => Some(Operation::sync(config.sync_scope)),
+        "sync-local" => Some(Operation::sync(SyncScope::local_only())),
+        "unlink" => Some(Operation::Unlink),
+        _ => None,
+    };
+    // a new machine only needs rodeo.toml: the repository is cloned before anything uses it. Dry
+    // runs and commands that only look at things must not change anything, so they just say so.
+    let previewing = (config.dry_run && command_name.and_then(plan_operation).is_some())
+        || matches!(command_name, Some("plan" | "check" | "status" | "tree" | "watch" | "query" | "which" | "grep" | "history" | "inspect"));
+    if previewing && settings.needs_clone() {
+        eprintln!("repository missing, would clone {} into {}", settings.remote, settings.dotfiles_directory);
+    }
+
+    if config.dry_run {
+        if let Some(operation) = command_name.and_then(plan_operation) {
+            let summary = paged(paging, &settings, |out| print_plan(&settings, operation, config.output, out));
+            process::exit(summary.exit_code());
+        }
+    }
+
+    if let Some(name) = command_name {
+        if !previewing && !matches!(name, "enable" | "disable" | "migrate" | "audit" | "trash" | "help" | "man" | "version") {
+            let cloned = settings.ensure_repository();
+            if cloned.has_errors() {
+                process::exit(cloned.exit_code());
+            }
+        }
+    }
+
+    // aliases are resolved through the command table in cli.rs, so only canonical names appear here
+    let summary = match command_name {
+        _ if command == "none" => {
+            println!("no command provided. Stop.");
+            Summary::default()
+        }
+        Some("deploy") => {
+            if !config.skip_preflight {
+                preflight(&settings);
+            }
+            settings.deploy()
+        }
+        Some("collect") => settings.collect(),
+        Some("unlink") => settings.unlink(),
+        Some("prune") => {
+            let orphans = settings.orphans();
+            if orphans.is_empty() {
+                println!("every file rodeo deployed is still in the config, nothing to prune");
+                Summary::default()
+            } else if settings.printer().confirm_prune(&orphans) {
+                settings.prune()
+            } else {
+                Summary::default()
+            }
+        }
+        Some("sync") => settings.sync(config.sync_scope),
+        Some("sync-local") => settings.sync(SyncScope::local_only()),
+        Some("import") => match (config.scan, config.args.first(), config.args.get(1)) {
+            (true, _, _) => import_scan(settings, config.yes, config.output),
+            (false, Some(source), Some(file)) => match migrate::Source::from_name(source) {
+                Some(source) => import_migration(settings, source, file, config.yes, config.output),
+                None => {
+                    println!("can't import from \"{}\", only from dotbot or yadm. Stop.", source);
+                    process::exit(EXIT_CONFIG_ERROR);
+                }
+            },
+            _ => {
+                println!("import needs to be told where to import from, e.g. \"rodeo import --scan\" or \"rodeo import dotbot install.conf.yaml\". Stop.");
+                process::exit(EXIT_CONFIG_ERROR);
+            }
+        },
+        Some(name @ "enable") | Some(name @ "disable") => match config.args.first() {
+            Some(program) => settings.set_enabled(program, name == "enable"),
+            None => {
+                println!("{} needs the name of a program, e.g. \"rodeo {} nvim\". Stop.", name, name);
+                process::exit(EXIT_CONFIG_ERROR);
+            }
+        },
+        Some("migrate") => settings.migrate(),
+        // the watcher reads the config again whenever it changes, which only keeps the programs
+        // meant for this machine, so it can't be narrowed down any further
+        Some("watch") if !config.programs.is_empty() || !config.tags.is_empty() || !config.include.is_empty() || !config.exclude.is_empty() => {
+            println!("watch keeps every program for this machine up to date, it takes no --program, --tag, --include or --exclude. Stop.");
+            process::exit(EXIT_CONFIG_ERROR);
+        }
+        Some("watch") => settings.watch(),
+        Some("resume") => settings.resume(),
+        Some("abort") => settings.abort(),
+        Some("export-drift") => {
+            let patch = settings.export_drift(&config.host);
+            if patch.is_empty() {
+                eprintln!("no deployed files differ from the repository");
+            }
+            print!("{}", patch);
+            Summary::default()
+        }
+        Some("apply-drift") => match config.args.first() {
+            Some(patch_file) => settings.apply_drift(patch_file, config.commit),
+            None => {
+                println!("apply-drift needs a patch to apply, e.g. \"rodeo apply-drift drift.patch\". Stop.");
+                process::exit(EXIT_CONFIG_ERROR);
+            }
+        },
+        Some("plan") => match config.args.first().and_then(|a| cli::lookup(a)).and_then(|c| plan_operation(c.name)) {
+            Some(operation) => paged(paging, &settings, |out| print_plan(&settings, operation, config.output, out)),
+            None => {
+                println!("plan needs an operation to plan, e.g. \"rodeo plan deploy\". Stop.");
+                process::exit(EXIT_CONFIG_ERROR);
+            }
+        },
+        Some("check") => paged(paging, &settings, |out| print_check(&settings, config.output, out)),
+        Some("audit") => paged(paging, &settings, |out| print_audit(&settings, config.output, out)),
+        Some("tree") => paged(paging, &settings, |out| print_tree(&settings, config.output, out)),
+        Some("status") => paged(paging, &settings, |out| print_status(&settings, config.output, out)),
+        Some("query") => match config.args.first() {
+            Some(query) => paged(paging, &settings, |out| print_query(&settings, query, config.output, out)),
+            None => {
+                println!("query needs something to ask, e.g. \"rodeo query 'files[state=modified].deployed_file'\". Stop.");
+                process::exit(EXIT_CONFIG_ERROR);
+            }
+        },
+        Some("which") => match config.args.first() {
+            Some(query) => paged(paging, &settings, |out| print_which(&settings, query, config.output, out)),
+            None => {
+                println!("which needs a file to look up, e.g. \"rodeo which ~/.bashrc\". Stop.");
+                process::exit(EXIT_CONFIG_ERROR);
+            }
+        },
+        Some("grep") => match config.args.first() {
+            Some(pattern) => paged(paging, &settings, |out| print_grep(&settings, pattern, config.ignore_case, config.output, out)),
+            None => {
+                println!("grep needs something to search for, e.g. \"rodeo grep alias\". Stop.");
+                process::exit(EXIT_CONFIG_ERROR);
+            }
+        },
+        Some("history") => match &config.args[..] {
+            [program] => paged(paging, &settings, |out| print_history(&settings, program, None, config.output, out)),
+            [program, file] => paged(paging, &settings, |out| print_history(&settings, program, Some(file), config.output, out)),
+            [program, file, commit] => print_file_at(&settings, program, file, commit),
+            _ => {
+                println!("history needs a program, and optionally one of its files and a commit, e.g. \"rodeo history nvim init.lua\". Stop.");
+                process::exit(EXIT_CONFIG_ERROR);
+            }
+        },
+        Some("generations") => paged(paging, &settings, |out| print_generations(&settings, config.output, out)),
+        Some("rollback") => match config.args.first().map(|n| n.parse::<u64>()) {
+            None => settings.rollback(None),
+            Some(Ok(number)) => settings.rollback(Some(number)),
+            Some(Err(_)) => {
+                println!("rollback takes the number of a generation, see \"rodeo generations\". Stop.");
+                process::exit(EXIT_CONFIG_ERROR);
+            }
+        },
+        Some("trash") => match config.args.split_first().map(|(action, ids)| (&action[..], ids)) {
+            None | Some(("list", [])) => paged(paging, &settings, |out| print_trash(&settings, config.output, out)),
+            Some(("restore", ids)) if !ids.is_empty() => settings.restore_trashed(ids),
+            Some(("empty", [])) => settings.empty_trash(),
+            _ => {
+                println!("trash takes list, restore <id>... or empty, e.g. \"rodeo trash restore 2024-05-01T18-30-00\". Stop.");
+                process::exit(EXIT_CONFIG_ERROR);
+            }
+        },
+        Some("revert") => match (config.args.first(), &config.to) {
+            (Some(file), Some(commit)) => settings.revert(file, commit, config.message.as_deref(), config.deploy),
+            _ => {
+                println!("revert needs a file and the commit to put it back to, e.g. \"rodeo revert ~/.bashrc --to HEAD~1\"; see \"rodeo history\". Stop.");
+                process::exit(EXIT_CONFIG_ERROR);
+            }
+        },
+        Some("restore") => match (config.pre_rodeo, config.args.split_first()) {
+            (true, _) => settings.restore_pre_rodeo(),
+            (false, Some((set, files))) => settings.restore(set, files, config.to_repo),
+            (false, None) => paged(paging, &settings, |out| print_backups(&settings, config.output, out)),
+        },
+        _ => {
+            println!("invalid command \"{}\". Stop.", command);
+            process::exit(EXIT_CONFIG_ERROR);
+        }
+    };
+
+    // the commands that go through every managed file end with a tally, as they only list the
+    // files they changed
+    let tallied = matches!(command_name, Some("deploy" | "collect" | "sync" | "sync-local" | "unlink" | "prune" | "rollback"));
+    if tallied && config.output == OutputMode::Text && !summary.to_text().is_empty() {
+        println!("{}", summary.to_text());
+    }
+    process::exit(summary.exit_code());
+}
+
+// reports a problem with the configuration (or command line) and exits with the matching code
+fn config_error<T: std::fmt::Display>(message: T) -> ! {
+    eprintln!("{}", message);
+    process::exit(EXIT_CONFIG_ERROR);
+}
+
+// runs print with its output collected, then shows the output through the pager if paging is on
+fn paged<F: FnOnce(&mut String) -> Summary>(paging: bool, settings: &Settings, print: F) -> Summary {
+    let mut out = String::new();
+    let summary = print(&mut out);
+    if paging {
+        pager::page(&out, settings.terminal.height);
+    } else {
+        print!("{}", out);
+    }
+    summary
+}
+
+// loads the config at path (or in the repository at path) without selecting programs for this
+// machine, and prints what it would write and run
+fn inspect(config: &Config, path: &str) -> Summary {
+    let repository = fs::metadata(path).map(|m| m.is_dir()).unwrap_or(false);
+    let config_path = if repository {
+        match rodeo::format::EXTENSIONS.iter().map(|e| format!("{}/rodeo.{}", path.trim_end_matches('/'), e)).find(|f| fs::metadata(f).is_ok()) {
+            Some(val) => val,
+            None => config_error(format!("no rodeo.toml (or .yaml, .yml, .json) in \"{}\". Stop.", path)),
+        }
+    } else {
+        path.to_owned()
+    };
 
-    let command = if args.len() > 1 {
-        args.nth(1).unwrap_or("none".to_owned())
+    let file = match File::open(&config_path) {
+        Ok(val) => val,
+        Err(e) => config_error(format!("could not open \"{}\": {}", config_path, e)),
+    };
+    let format = config.format.unwrap_or_else(|| ConfigFormat::from_path(&config_path));
+    let mut settings = match Settings::new_from_file_with_format(file, config.home.clone(), config_path.clone(), format) {
+        Ok(val) => val,
+        Err(e) => config_error(format!("could not read config file \"{}\": {}", config_path, e)),
+    };
+    let repository = if repository {
+        fs::canonicalize(path).map(|p| p.to_string_lossy().into_owned()).unwrap_or_else(|_| path.to_owned())
     } else {
-        println!("no command provided. Stop.");
-        "none".to_owned()
+        settings.dotfiles_directory.clone()
+    };
+    settings.terminal = if config.plain { Terminal::plain() } else { Terminal::detect() };
+
+    let paging = !config.no_pager && config.output == OutputMode::Text;
+    paged(paging, &settings, |out| {
+        let found = inspect::inspect(&settings, &repository);
+        for activity in found.iter() {
+            match config.output {
+                OutputMode::Text => {
+                    let style = if activity.warnings.is_empty() { Style::Ok } else { Style::Warning };
+                    writeln!(out, "{}", settings.terminal.paint(style, &activity.to_text())).unwrap_or_default();
+                }
+                OutputMode::Json => writeln!(out, "{}", serde_json::to_string(activity).unwrap_or_default()).unwrap_or_default(),
+            }
+        }
+        if config.output == OutputMode::Text {
+            let count = |kind| found.iter().filter(|a| a.kind == kind).count();
+            writeln!(
+                out,
+                "{} files written, {} commands run, {} config files read and {} executables looked for in all; nothing was written or run to find out",
+                count(inspect::ActivityKind::Write),
+                count(inspect::ActivityKind::Command),
+                count(inspect::ActivityKind::Read),
+                count(inspect::ActivityKind::Lookup)
+            )
+            .unwrap_or_default();
+        }
+        Summary::default()
+    })
+}
+
+// stops before deploying if any of the programs' paths match no file, can't be written or are
+// templates that can't be rendered, rather than failing on them one at a time in the middle of
+// the deploy
+fn preflight(settings: &Settings) {
+    let problems = validate::check_paths(settings);
+    if !problems.is_empty() {
+        config_error(format!(
+            "{}\nfix those, or deploy anyway with --skip-preflight. Stop.",
+            validate::to_text(&problems)
+        ));
+    }
+}
+
+// prints every hook and vcs command rodeo has recorded in its audit log, oldest first
+fn print_audit(settings: &Settings, output: OutputMode, out: &mut String) -> Summary {
+    let records = match settings.audit_log().read() {
+        Ok(val) => val,
+        Err(e) => config_error(format!("could not read audit log: {}", e)),
+    };
+
+    for record in records {
+        match output {
+            OutputMode::Text => writeln!(out, "{}", record.to_text()).unwrap_or_default(),
+            OutputMode::Json => writeln!(out, "{}", serde_json::to_string(&record).unwrap_or_default()).unwrap_or_default(),
+        }
+    }
+
+    Summary::default()
+}
+
+// prints the backed up versions of the selected programs' files, newest first
+fn print_backups(settings: &Settings, output: OutputMode, out: &mut String) -> Summary {
+    for found in settings.backups() {
+        match output {
+            OutputMode::Text => writeln!(out, "{}", found.to_text()).unwrap_or_default(),
+            OutputMode::Json => writeln!(out, "{}", serde_json::to_string(&found).unwrap_or_default()).unwrap_or_default(),
+        }
+    }
+
+    Summary::default()
+}
+
+// prints every file in the trash, newest first
+fn print_trash(settings: &Settings, output: OutputMode, out: &mut String) -> Summary {
+    for item in settings.trash_items() {
+        match output {
+            OutputMode::Text => writeln!(out, "{}", item.to_text()).unwrap_or_default(),
+            OutputMode::Json => writeln!(out, "{}", serde_json::to_string(&item).unwrap_or_default()).unwrap_or_default(),
+        }
+    }
+
+    Summary::default()
+}
+
+// prints every generation, oldest first
+fn print_generations(settings: &Settings, output: OutputMode, out: &mut String) -> Summary {
+    for generation in settings.generations() {
+        match output {
+            OutputMode::Text => writeln!(out, "{}", generation.to_text()).unwrap_or_default(),
+            OutputMode::Json => writeln!(out, "{}", serde_json::to_string(&generation).unwrap_or_default()).unwrap_or_default(),
+        }
+    }
+
+    Summary::default()
+}
+
+// prints every line of the selected programs' repository files that contains pattern
+fn print_grep(settings: &Settings, pattern: &str, ignore_case: bool, output: OutputMode, out: &mut String) -> Summary {
+    for found in settings.grep(pattern, ignore_case) {
+        match output {
+            OutputMode::Text => writeln!(out, "{}", found.to_text()).unwrap_or_default(),
+            OutputMode::Json => writeln!(out, "{}", serde_json::to_string(&found).unwrap_or_default()).unwrap_or_default(),
+        }
+    }
+
+    Summary::default()
+}
+
+// lists the commits that changed program's files (or only file), newest first
+fn print_history(settings: &Settings, program: &str, file: Option<&String>, output: OutputMode, out: &mut String) -> Summary {
+    let revisions = match settings.history(program, file.map(String::as_str)) {
+        Ok(val) => val,
+        Err(e) => {
+            writeln!(out, "{}", e).unwrap_or_default();
+            return Summary { sync_errors: 1, ..Summary::default() };
+        }
+    };
+
+    for revision in revisions {
+        match output {
+            OutputMode::Text => writeln!(out, "{}", revision.to_text(file.is_none())).unwrap_or_default(),
+            OutputMode::Json => writeln!(out, "{}", serde_json::to_string(&revision).unwrap_or_default()).unwrap_or_default(),
+        }
+    }
+
+    Summary::default()
+}
+
+// prints the contents program's file had as of commit, as they are
+fn print_file_at(settings: &Settings, program: &str, file: &str, commit: &str) -> Summary {
+    match settings.file_at(program, file, commit) {
+        Ok(contents) => {
+            std::io::Write::write_all(&mut std::io::stdout(), &contents).unwrap_or_default();
+            Summary::default()
+        }
+        Err(e) => {
+            println!("{}", e);
+            Summary { sync_errors: 1, ..Summary::default() }
+        }
+    }
+}
+
+// prints the managed layout grouped by program, marking each file with how its deployed copy
+// compares to the one in the repository
+fn print_tree(settings: &Settings, output: OutputMode, out: &mut String) -> Summary {
+    let targets = settings.targets();
+
+    if output == OutputMode::Json {
+        for target in targets {
+            writeln!(out, "{}", serde_json::to_string(&Drift::new(&target)).unwrap_or_default()).unwrap_or_default();
+        }
+        return Summary::default();
+    }
+
+    let term = settings.terminal;
+    let (branch, last_branch, continued, blank) = term.tree_glyphs();
+    writeln!(out, "{}", settings.dotfiles_directory).unwrap_or_default();
+    let mut cache = settings.status_cache();
+    for (p, program) in settings.programs.iter().enumerate() {
+        let last_program = p + 1 == settings.programs.len();
+        let (branch, indent) = if last_program { (last_branch, blank) } else { (branch, continued) };
+        if program.dotfiles_directory.is_empty() {
+            writeln!(out, "{}{} ({})", branch, program.name, term.paint(Style::Dim, &program.root)).unwrap_or_default();
+        } else {
+            let details = format!("{}, from {}", program.root, program.dotfiles_directory);
+            writeln!(out, "{}{} ({})", branch, program.name, term.paint(Style::Dim, &details)).unwrap_or_default();
+        }
+
+        let files: Vec<_> = targets.iter().filter(|t| t.program == program.name).collect();
+        for (f, target) in files.iter().enumerate() {
+            let branch = if f + 1 == files.len() { last_branch } else { branch };
+            let state = cache.state(target);
+            let style = if state == FileState::Deployed { Style::Ok } else { Style::Warning };
+            writeln!(out, "{}{}{} [{}]", indent, branch, target.path, term.paint(style, state.as_str())).unwrap_or_default();
+        }
+    }
+    cache.save();
+
+    Summary::default()
+}
+
+// prints every program with the layer of the config it comes from and how many of its files are in
+// each state, marking programs with files that aren't deployed, then how each repository stands
+// with git. With --json the repository's state is part of each program's.
+fn print_status(settings: &Settings, output: OutputMode, out: &mut String) -> Summary {
+    let statuses = settings.status();
+    for status in statuses.iter() {
+        match output {
+            OutputMode::Text => {
+                let style = if status.files.keys().all(|s| s == "deployed") { Style::Ok } else { Style::Warning };
+                writeln!(out, "{}", settings.terminal.paint(style, &status.to_text())).unwrap_or_default();
+            }
+            OutputMode::Json => writeln!(out, "{}", serde_json::to_string(&status).unwrap_or_default()).unwrap_or_default(),
+        }
+    }
+
+    if output == OutputMode::Text {
+        let mut shown = Vec::new();
+        for status in statuses.iter() {
+            if let Some(git) = status.git.as_ref().filter(|_| !shown.contains(&&status.repository)) {
+                let style = if git.needs_attention() { Style::Warning } else { Style::Ok };
+                writeln!(out, "{}", settings.terminal.paint(style, &git.to_text(&status.repository))).unwrap_or_default();
+                shown.push(&status.repository);
+            }
+        }
+    }
+
+    Summary::default()
+}
+
+// prints the answer to a query: in text, strings as they are and lists one element per line, so
+// the output can be used directly in shell scripts; with --json, one JSON value per line
+fn print_query(settings: &Settings, query: &str, output: OutputMode, out: &mut String) -> Summary {
+    let query = match Query::parse(query) {
+        Ok(val) => val,
+        Err(e) => {
+            println!("invalid query: {}. Stop.", e);
+            process::exit(EXIT_CONFIG_ERROR);
+        }
+    };
+
+    for value in query.run(settings) {
+        match (output, value) {
+            (OutputMode::Json, value) => writeln!(out, "{}", value).unwrap_or_default(),
+            (OutputMode::Text, serde_json::Value::Array(values)) => {
+                for value in values {
+                    writeln!(out, "{}", query::as_text(&value)).unwrap_or_default();
+                }
+            }
+            (OutputMode::Text, value @ serde_json::Value::Object(_)) => writeln!(out, "{}", value).unwrap_or_default(),
+            (OutputMode::Text, value) => writeln!(out, "{}", query::as_text(&value)).unwrap_or_default(),
+        }
+    }
+    Summary::default()
+}
+
+// prints which program owns the given file, and where it lives in the repository and on disk
+fn print_which(settings: &Settings, query: &str, output: OutputMode, out: &mut String) -> Summary {
+    let index = ReverseIndex::new(settings.targets());
+    let found = index.lookup(query, settings.home());
+
+    if found.is_empty() {
+        writeln!(out, "\"{}\" is not managed by rodeo", query).unwrap_or_default();
+        return Summary { sync_errors: 1, ..Summary::default() };
+    }
+
+    for target in found {
+        match output {
+            OutputMode::Text => writeln!(
+                out,
+                "{}:\n\tprogram: {}\n\trepo: {}\n\tdeployed: {}",
+                target.path, target.program, target.repo_file, target.deployed_file
+            ).unwrap_or_default(),
+            OutputMode::Json => writeln!(out, "{}", serde_json::to_string(target).unwrap_or_default()).unwrap_or_default(),
+        }
+    }
+
+    Summary::default()
+}
+
+// lists the well-known dotfiles that aren't managed yet and, once the user agrees (or straight away
+// with --yes), adds them to the config and collects them
+fn import_scan(settings: Settings, yes: bool, output: OutputMode) -> Summary {
+    let candidates = settings.scan();
+
+    for candidate in candidates.iter() {
+        match output {
+            OutputMode::Text => println!("{}", candidate.to_toml()),
+            OutputMode::Json => println!("{}", serde_json::to_string(candidate).unwrap_or_default()),
+        }
+    }
+
+    if candidates.is_empty() {
+        if output == OutputMode::Text {
+            println!("no unmanaged dotfiles found");
+        }
+        return Summary::default();
+    }
+
+    confirm_import(settings, &candidates, yes, output)
+}
+
+// translates another dotfile manager's setup into programs, lists them along with whatever couldn't
+// be translated, then imports them the same way as --scan
+fn import_migration(settings: Settings, source: migrate::Source, file: &str, yes: bool, output: OutputMode) -> Summary {
+    let managed: Vec<String> = settings.targets().into_iter().map(|t| t.deployed_file).collect();
+    let taken: Vec<String> = settings.programs.iter().map(|p| p.name.clone()).collect();
+    let migration = match migrate::migrate(source, file, settings.home(), &managed, &taken) {
+        Ok(val) => val,
+        Err(e) => {
+            println!("{}. Stop.", e);
+            process::exit(EXIT_CONFIG_ERROR);
+        }
     };
 
-    match &command[..] {
-        "deploy" | "d" => settings.deploy(),
-        "collect" | "c" => settings.collect(),
-        "sync-local" | "sync_local" | "local_sync" | "local-sync" | "lsync" => settings.sync_local(),
-        "sync-remote" | "sync_remote" | "remote_sync" | "remote-sync" | "rsync" => settings.sync_remote(),
-        "sync-full" | "sync_full" | "full_sync" | "full-sync" | "fsync" => settings.sync_full(),
-        "help" | "h" => print_help(),
-        _ => println!("invalid command \"{}\". Stop.", command),
+    match output {
+        OutputMode::Text => {
+            for candidate in migration.candidates.iter() {
+                println!("{}", candidate.to_toml());
+            }
+            for note in migration.untranslated.iter() {
+                println!("not imported: {}", note);
+            }
+        }
+        OutputMode::Json => println!("{}", serde_json::to_string(&migration).unwrap_or_default()),
+    }
+
+    if migration.candidates.is_empty() {
+        if output == OutputMode::Text {
+            println!("nothing to import");
+        }
+        return Summary::default();
+    }
+
+    confirm_import(settings, &migration.candidates, yes, output)
+}
+
+fn confirm_import(settings: Settings, candidates: &[scan::Candidate], yes: bool, output: OutputMode) -> Summary {
+    // JSON output is meant for scripts, which can't answer a prompt
+    if !yes && (output == OutputMode::Json || !confirm("add these programs to your config and collect them?")) {
+        return Summary::default();
     }
 
-    Ok(())
+    settings.import(candidates)
 }
 
-pub fn print_help() {
-    println!("\
-        deploy | d:\n\tdeploys all dotfiles referenced in your config in your local dotfiles repo to your system, overwriting existing files\n\n\
-        collect | c:\n\tcollects all dotfiles referenced in your config that are active in your system to your local dotfiles repo, overwriting existing files\n\n\
-        sync[-/_]local | local[-/_]sync | lsync:\n\treplaces all dotfiles referenced in your config, both deployed and collected, with whichever respective file was last modified\n\n\
-        sync[-/_]remote | remote[-/_]sync | rsync:\n\tpulls, commits changes, and pushes in the context of your local repo's directory. Works with any git repository.\n\n\
-        sync[-/_]full | full[-/_]sync | fsync:\n\tpulls from remote git repo, runs a local sync, then commits changes and pushes back up to remote. Works with any git repository.\n\n\
-        help | h:\n\tprint this text\n\
-        ");
+
+// prints what operation would do to every managed file without doing it
+fn print_plan(settings: &Settings, operation: Operation, output: OutputMode, out: &mut String) -> Summary {
+    let plan = settings.plan(operation);
+    match output {
+        OutputMode::Text => write!(out, "{}", plan.to_text()).unwrap_or_default(),
+        OutputMode::Json => writeln!(out, "{}", serde_json::to_string(&plan).unwrap_or_default()).unwrap_or_default(),
+    }
+    Summary::default()
+}
+
+// prints every managed file whose deployed copy differs from the repository copy. Drift counts as a
+// sync error, so scripts can tell from the exit code whether everything is in sync.
+fn print_check(settings: &Settings, output: OutputMode, out: &mut String) -> Summary {
+    let drift = settings.drift();
+    for file in drift.iter() {
+        match output {
+            OutputMode::Text => writeln!(out, "{}", settings.terminal.paint(Style::Warning, &file.to_text())).unwrap_or_default(),
+            OutputMode::Json => writeln!(out, "{}", serde_json::to_string(file).unwrap_or_default()).unwrap_or_default(),
+        }
+    }
+    Summary { sync_errors: drift.len(), ..Summary::default() }
 }
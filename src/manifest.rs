@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::copy;
+use crate::link::{self, DeployMode};
+use crate::mapping::{FileState, Target};
+use crate::state;
+
+// every file rodeo has deployed on this machine, kept as <state_dir>/state.json. The config only
+// says what should be deployed now; this remembers what was, so that files can be told apart from
+// ones rodeo never touched after they leave the config or change by hand.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct StateManifest {
+    #[serde(skip)]
+    path: String,
+    #[serde(skip)]
+    changed: bool,
+    // by deployed file
+    pub files: BTreeMap<String, Deployment>,
+}
+
+// one deployed file
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Deployment {
+    pub program: String,
+    pub source: String,
+    pub mode: DeployMode,
+    // hex FNV-1a hash of the contents as deployed, see copy::hash
+    pub hash: String,
+    // seconds since the unix epoch, when these contents were deployed
+    pub deployed: u64,
+}
+
+// a file rodeo deployed that nothing in the config deploys anymore
+#[derive(Clone, Debug, Serialize)]
+pub struct Orphan {
+    pub destination: String,
+    pub program: String,
+    // whether it was changed after rodeo deployed it, in which case prune leaves it where it is
+    pub changed: bool,
+}
+
+impl Orphan {
+    pub fn to_text(&self) -> String {
+        match self.changed {
+            true => format!("{} (from {}, changed since)", self.destination, self.program),
+            false => format!("{} (from {})", self.destination, self.program),
+        }
+    }
+}
+
+// the hash of path's contents as kept in Deployment, following symlinks
+pub fn hash_of(path: &str) -> Option<String> {
+    copy::hash(path).ok().map(|h| format!("{:016x}", h))
+}
+
+impl Deployment {
+    // whether what is at destination is still what rodeo put there
+    pub fn matches(&self, destination: &str) -> bool {
+        match self.mode {
+            DeployMode::Symlink if link::is_link(destination) => link::links_to(destination, &self.source),
+            _ => !link::is_link(destination) && hash_of(destination).is_some_and(|h| h == self.hash),
+        }
+    }
+}
+
+impl StateManifest {
+    // the manifest kept in state_dir. A missing or unreadable one is empty.
+    pub fn load(state_dir: &str) -> StateManifest {
+        let path = format!("{}/state.json", state_dir);
+        let mut manifest: StateManifest = fs::read_to_string(&path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+        manifest.path = path;
+        manifest
+    }
+
+    // records target as deployed with mode, if it is in place. The time it was deployed only moves
+    // when its contents change. A template is in place when it is deployed as it renders now.
+    pub fn record(&mut self, target: &Target, mode: DeployMode) {
+        let (in_place, contents) = match target.template {
+            Some(_) => (target.state() == FileState::Deployed, &target.deployed_file),
+            None => (
+                link::linked(&target.deployed_file, &target.repo_file) || copy::same_contents(&target.repo_file, &target.deployed_file),
+                &target.repo_file,
+            ),
+        };
+        let hash = match hash_of(contents) {
+            Some(val) if in_place => val,
+            _ => return,
+        };
+
+        let old = self.files.get(&target.deployed_file);
+        let deployed = match old {
+            Some(old) if old.hash == hash => old.deployed,
+            _ => state::now(),
+        };
+        let new = Deployment { program: target.program.clone(), source: target.repo_file.clone(), mode, hash, deployed };
+        if old != Some(&new) {
+            self.files.insert(target.deployed_file.clone(), new);
+            self.changed = true;
+        }
+    }
+
+    // the deployed files that still exist but aren't in managed, out of those deployed by programs
+    // owned says are up to the caller
+    pub fn orphans(&self, owned: &dyn Fn(&str) -> bool, managed: &BTreeSet<String>) -> Vec<Orphan> {
+        self.files.iter()
+            .filter(|(destination, deployment)| owned(&deployment.program) && !managed.contains(*destination))
+            .filter(|(destination, _)| fs::symlink_metadata(destination).is_ok())
+            .map(|(destination, deployment)| Orphan {
+                destination: destination.clone(),
+                program: deployment.program.clone(),
+                changed: !deployment.matches(destination),
+            })
+            .collect()
+    }
+
+    // stops keeping track of the files that aren't in managed and are gone already, out of those
+    // deployed by programs owned says are up to the caller
+    pub fn forget_gone(&mut self, owned: &dyn Fn(&str) -> bool, managed: &BTreeSet<String>) {
+        let before = self.files.len();
+        self.files.retain(|destination, deployment| {
+            !owned(&deployment.program) || managed.contains(destination) || fs::symlink_metadata(destination).is_ok()
+        });
+        self.changed |= self.files.len() != before;
+    }
+
+    // makes files the list of deployed files, e.g. after rolling back to an earlier generation
+    pub fn set_files(&mut self, files: BTreeMap<String, Deployment>) {
+        self.changed |= self.files != files;
+        self.files = files;
+    }
+
+    // stops keeping track of the file deployed at destination
+    pub fn forget(&mut self, destination: &str) {
+        if self.files.remove(destination).is_some() {
+            self.changed = true;
+        }
+    }
+
+    // writes the manifest back if anything in it changed
+    pub fn save(&self) -> std::io::Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        if let Some(dir) = Path::new(&self.path).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        fs::write(&self.path, contents)
+    }
+}
@@ -0,0 +1,356 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::copy;
+use crate::glob::Pattern;
+use crate::link;
+use crate::paths;
+use crate::template;
+
+// where one of a program's managed files lives in the repository folder and where it is deployed
+// to. Every operation that moves files between the two works from these, so they all agree on the
+// layout.
+#[derive(Clone, Debug, Serialize)]
+pub struct Target {
+    pub program: String,
+    // the file's path relative to the program's root: an entry in the program's paths (its dest,
+    // for a renamed one), or one of the files a glob or directory entry expanded to
+    pub path: String,
+    pub repo_file: String,
+    pub deployed_file: String,
+    // permissions deploy gives deployed_file, e.g. "600"; empty leaves them as the copy made them
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub mode: String,
+    #[serde(skip_serializing_if = "Direction::is_bidirectional")]
+    pub direction: Direction,
+    // the variables repo_file is rendered with, if it is a template; see template::render
+    #[serde(skip)]
+    pub template: Option<Arc<BTreeMap<String, String>>>,
+}
+
+// which way a file may be copied, chosen with direction on an entry in paths. Files such as
+// recently used lists, which programs rewrite all the time, can be kept from flowing back into the
+// repository with deploy_only, or from being overwritten on the system with collect_only.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    #[default]
+    Bidirectional,
+    DeployOnly,
+    CollectOnly,
+}
+
+impl Direction {
+    pub fn from_name(name: &str) -> Option<Direction> {
+        match name {
+            "" | "bidirectional" => Some(Direction::Bidirectional),
+            "deploy_only" => Some(Direction::DeployOnly),
+            "collect_only" => Some(Direction::CollectOnly),
+            _ => None,
+        }
+    }
+
+    pub fn is_bidirectional(&self) -> bool {
+        *self == Direction::Bidirectional
+    }
+
+    // whether the file may be copied from the repository to the system
+    pub fn deploys(&self) -> bool {
+        *self != Direction::CollectOnly
+    }
+
+    // whether the file may be copied from the system to the repository
+    pub fn collects(&self) -> bool {
+        *self != Direction::DeployOnly
+    }
+}
+
+// an entry in a program's paths: either just a path, which is the same in the repository and once
+// deployed, or a table such as { src = "bashrc", dest = ".bashrc", mode = "600" } for a file (or
+// directory) stored under another name than it is deployed as, deployed with a mode of its own, or
+// only copied one way. src and dest are relative to the program's repository directory and root
+// respectively.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum PathEntry {
+    Plain(String),
+    Table {
+        src: String,
+        // defaults to src
+        #[serde(default)]
+        #[serde(skip_serializing_if = "String::is_empty")]
+        dest: String,
+        // permissions the deployed files are given, e.g. "600"; defaults to the program's mode
+        #[serde(default)]
+        #[serde(skip_serializing_if = "String::is_empty")]
+        mode: String,
+        // "deploy_only", "collect_only" or "bidirectional" (the default), see Direction
+        #[serde(default)]
+        #[serde(skip_serializing_if = "String::is_empty")]
+        direction: String,
+        // rendered by deploy rather than copied, and never collected
+        #[serde(default)]
+        #[serde(skip_serializing_if = "is_false")]
+        template: bool,
+    },
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+impl PathEntry {
+    // where the entry is in the repository
+    pub fn src(&self) -> &str {
+        match self {
+            PathEntry::Plain(path) => path,
+            PathEntry::Table { src, .. } => src,
+        }
+    }
+
+    // where the entry is deployed to
+    pub fn dest(&self) -> &str {
+        match self {
+            PathEntry::Table { dest, .. } if !dest.is_empty() => dest,
+            _ => self.src(),
+        }
+    }
+
+    pub fn mode(&self) -> &str {
+        match self {
+            PathEntry::Plain(_) => "",
+            PathEntry::Table { mode, .. } => mode,
+        }
+    }
+
+    pub fn template(&self) -> bool {
+        match self {
+            PathEntry::Plain(_) => false,
+            PathEntry::Table { template, .. } => *template,
+        }
+    }
+
+    // which way the entry's files may be copied; an invalid direction, which validation reports,
+    // is taken as bidirectional
+    pub fn direction(&self) -> Direction {
+        match self {
+            PathEntry::Plain(_) => Direction::Bidirectional,
+            PathEntry::Table { direction, .. } => Direction::from_name(direction).unwrap_or_default(),
+        }
+    }
+
+    // whether the entry is deployed under another name than it is stored as
+    pub fn is_renamed(&self) -> bool {
+        self.src() != self.dest()
+    }
+
+    // the entry with f applied to each of its paths
+    pub fn try_map<E>(&self, f: impl Fn(&str) -> Result<String, E>) -> Result<PathEntry, E> {
+        Ok(match self {
+            PathEntry::Plain(path) => PathEntry::Plain(f(path)?),
+            PathEntry::Table { src, dest, mode, direction, template } => PathEntry::Table {
+                src: f(src)?,
+                dest: f(dest)?,
+                mode: mode.clone(),
+                direction: direction.clone(),
+                template: *template,
+            },
+        })
+    }
+}
+
+impl fmt::Display for PathEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_renamed() {
+            write!(f, "{} -> {}", self.src(), self.dest())
+        } else {
+            write!(f, "{}", self.src())
+        }
+    }
+}
+
+// which copies of a program's files glob entries in paths are expanded against. Deploy only cares
+// about what is in the repository, collect only about what is deployed, everything else about both.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Repo,
+    Deployed,
+    Both,
+}
+
+// narrows a program's files down with --include and --exclude patterns. Patterns containing a "/"
+// are matched against "<program>/<path>", e.g. "nvim/lua/**"; the rest only against the file's
+// name, e.g. "*.lock".
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PathFilter {
+    pub include: Vec<Pattern>,
+    pub exclude: Vec<Pattern>,
+}
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> PathFilter {
+        PathFilter {
+            include: include.iter().map(|i| Pattern::new(i)).collect(),
+            exclude: exclude.iter().map(|i| Pattern::new(i)).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    // whether the file at path (an entry in the program's paths) passes the filter. With no
+    // include patterns everything is included.
+    pub fn allows(&self, program: &str, path: &str) -> bool {
+        let full = format!("{}/{}", program, path);
+        let file_name = Path::new(path)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let hit = |pattern: &Pattern| {
+            if pattern.has_separator() {
+                pattern.matches(&full)
+            } else {
+                pattern.matches(&file_name)
+            }
+        };
+
+        (self.include.is_empty() || self.include.iter().any(hit)) && !self.exclude.iter().any(hit)
+    }
+}
+
+// how the deployed copy of a file compares to the repository copy
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileState {
+    // both exist and are identical
+    Deployed,
+    // both exist but their contents differ
+    Modified,
+    // only the repository copy exists
+    Missing,
+    // only the deployed copy exists
+    NotInRepo,
+    // neither exists
+    Absent,
+    // the deployed copy is a symlink to a file that doesn't exist
+    BrokenLink,
+}
+
+impl FileState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileState::Deployed => "deployed",
+            FileState::Modified => "modified",
+            FileState::Missing => "missing",
+            FileState::NotInRepo => "not in repo",
+            FileState::Absent => "absent",
+            FileState::BrokenLink => "broken link",
+        }
+    }
+}
+
+impl Target {
+    // repo_file relative to the dotfiles directory, e.g. ".config/nvim/init.vim"
+    pub fn repo_relative(&self, dotfiles_dir: &str) -> Option<&str> {
+        // "/dotfiles" isn't a prefix of "/dotfiles-private/..."
+        match self.repo_file.strip_prefix(dotfiles_dir.trim_end_matches('/')) {
+            Some(rest) if rest.starts_with('/') => Some(rest.trim_start_matches('/')),
+            _ => None,
+        }
+    }
+
+    // what deploying repo_file puts at deployed_file: its contents, rendered if it is a template
+    pub fn deployed_contents(&self) -> Result<Vec<u8>, String> {
+        let contents = fs::read(&self.repo_file).map_err(|e| format!("could not read \"{}\": {}", self.repo_file, e))?;
+        match &self.template {
+            Some(variables) => {
+                let text = String::from_utf8(contents).map_err(|_| format!("template \"{}\" is not UTF-8 text", self.repo_file))?;
+                template::render(&text, variables)
+                    .map(String::into_bytes)
+                    .map_err(|e| format!("could not render \"{}\": {}", self.repo_file, e))
+            }
+            None => Ok(contents),
+        }
+    }
+
+    // whether either copy of the file holds binary data, which diffs leave out
+    pub fn is_binary(&self) -> bool {
+        copy::is_binary(&self.repo_file) || copy::is_binary(&self.deployed_file)
+    }
+
+    pub fn state(&self) -> FileState {
+        let repo_exists = Path::new(&self.repo_file).exists();
+        let deployed_exists = Path::new(&self.deployed_file).exists();
+
+        match (repo_exists, deployed_exists) {
+            _ if link::is_broken(&self.deployed_file) => FileState::BrokenLink,
+            (true, true) if link::linked(&self.deployed_file, &self.repo_file) => FileState::Deployed,
+            (false, false) => FileState::Absent,
+            (true, false) => FileState::Missing,
+            (false, true) => FileState::NotInRepo,
+            (true, true) => match (self.deployed_contents(), fs::read(&self.deployed_file)) {
+                (Ok(repo), Ok(deployed)) if repo == deployed => FileState::Deployed,
+                _ => FileState::Modified,
+            },
+        }
+    }
+}
+
+// maps every way of referring to a managed file (its deployed path, its repository path, its entry
+// in paths and its file name) to the targets it could mean
+pub struct ReverseIndex {
+    targets: Vec<Target>,
+    keys: HashMap<String, Vec<usize>>,
+}
+
+impl ReverseIndex {
+    pub fn new(targets: Vec<Target>) -> ReverseIndex {
+        let mut keys: HashMap<String, Vec<usize>> = HashMap::new();
+
+        for (i, target) in targets.iter().enumerate() {
+            let file_name = Path::new(&target.path)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            for key in [&target.deployed_file, &target.repo_file, &target.path, &file_name] {
+                let entry = keys.entry(key.clone()).or_default();
+                // the same target can be reached by more than one key which happen to be equal
+                if !entry.contains(&i) {
+                    entry.push(i);
+                }
+            }
+        }
+
+        ReverseIndex { targets, keys }
+    }
+
+    // finds the targets a user-supplied path refers to. ~ is expanded, and relative paths that
+    // exist are resolved against the current directory before being looked up.
+    pub fn lookup(&self, query: &str, home_dir: &str) -> Vec<&Target> {
+        let mut candidates = vec![query.to_owned()];
+
+        let expanded = paths::expand_home(query, home_dir);
+        candidates.push(expanded.clone());
+        if Path::new(&expanded).is_relative() {
+            if let Ok(cwd) = env::current_dir() {
+                candidates.push(cwd.join(&expanded).to_string_lossy().into_owned());
+            }
+        }
+
+        let mut found: Vec<usize> = Vec::new();
+        for candidate in candidates {
+            for i in self.keys.get(&candidate).into_iter().flatten() {
+                if !found.contains(i) {
+                    found.push(*i);
+                }
+            }
+        }
+        found.into_iter().map(|i| &self.targets[i]).collect()
+    }
+}
@@ -0,0 +1,220 @@
+use std::fs;
+use std::path::Path;
+use crate::copy::{self, Copier};
+use crate::defaults::Preference;
+use crate::mapping::Target;
+
+// what sync goes by when both copies of a file differ. After every deploy, collect and sync, the
+// contents of each file whose copies are the same are kept in <state_dir>/sync-base, laid out like
+// the backups. Against that base, sync can tell which side changed since and copy it over, or, if
+// both did, merge the two line by line the way `git merge-file` does. prefer decides what mtimes
+// can't, from --prefer-repo or --prefer-disk.
+pub struct Resolver {
+    pub base_dir: String,
+    pub prefer: Option<Preference>,
+}
+
+// how a file that differs between the repository and the system is settled
+#[derive(Debug, PartialEq)]
+pub enum Resolution {
+    // only the repository copy changed since the last sync
+    Repo,
+    // only the deployed copy changed since the last sync
+    Disk,
+    // both changed, in different places; the merged contents go to both sides
+    Merged(Vec<u8>),
+    // both changed in the same places, or in a file that can't be merged line by line
+    Conflict,
+    // there is no base to go by, so the copies' modification times decide
+    Unknown,
+}
+
+impl Resolver {
+    pub fn new(state_dir: &str, prefer: Option<Preference>) -> Resolver {
+        Resolver { base_dir: format!("{}/sync-base", state_dir), prefer }
+    }
+
+    // where the base of the file deployed at deployed_file is kept
+    pub fn base_path(&self, deployed_file: &str) -> String {
+        format!("{}/files/{}", self.base_dir, deployed_file.trim_start_matches('/'))
+    }
+
+    // remembers the repository copy of target as the base for the next sync, if its deployed copy
+    // is the same
+    pub fn record(&self, target: &Target) {
+        let base = self.base_path(&target.deployed_file);
+        if !copy::same_contents(&target.repo_file, &target.deployed_file) || copy::same_contents(&target.repo_file, &base) {
+            return;
+        }
+        if let Some(parent) = Path::new(&base).parent() {
+            fs::create_dir_all(parent).unwrap_or_default();
+        }
+        let _ = Copier::default().replace(&target.repo_file, &base);
+    }
+
+    // works out which copy of a file changed since the last sync
+    pub fn resolve(&self, repo_file: &str, deployed_file: &str) -> Resolution {
+        let base = match fs::read(self.base_path(deployed_file)) {
+            Ok(val) => val,
+            Err(_) => return Resolution::Unknown,
+        };
+        let (repo, disk) = match (fs::read(repo_file), fs::read(deployed_file)) {
+            (Ok(repo), Ok(disk)) => (repo, disk),
+            _ => return Resolution::Unknown,
+        };
+
+        match (repo != base, disk != base) {
+            (true, false) => Resolution::Repo,
+            (false, true) => Resolution::Disk,
+            (false, false) => Resolution::Unknown,
+            (true, true) => match merge(&base, &repo, &disk) {
+                Some(merged) => Resolution::Merged(merged),
+                None => Resolution::Conflict,
+            },
+        }
+    }
+}
+
+// files with more lines than this on a side aren't merged, as finding what the sides have in
+// common takes time and memory growing with the product of their lengths
+const MAX_LINES: usize = 2000;
+
+// merges the changes ours and theirs made to base, or None if they overlap. Lines are matched up
+// between base and each side; stretches of base that both sides kept are left as they are, and
+// each stretch in between is taken from the side that changed it. If both changed the same
+// stretch differently, the merge fails. Binary files, going by a NUL byte, are never merged.
+pub fn merge(base: &[u8], ours: &[u8], theirs: &[u8]) -> Option<Vec<u8>> {
+    if [base, ours, theirs].iter().any(|f| f.contains(&0)) {
+        return None;
+    }
+    let (base, ours, theirs) = (lines(base), lines(ours), lines(theirs));
+    if [&base, &ours, &theirs].iter().any(|f| f.len() > MAX_LINES) {
+        return None;
+    }
+    let (in_ours, in_theirs) = (matches(&base, &ours), matches(&base, &theirs));
+
+    let mut merged: Vec<u8> = Vec::new();
+    let (mut i, mut o, mut t) = (0, 0, 0);
+    while i < base.len() || o < ours.len() || t < theirs.len() {
+        // a line of base both sides kept, right where both are
+        if i < base.len() && in_ours[i] == Some(o) && in_theirs[i] == Some(t) {
+            merged.extend_from_slice(base[i]);
+            i += 1;
+            o += 1;
+            t += 1;
+            continue;
+        }
+
+        // otherwise everything up to the next line both kept was changed by one side or both
+        let (next, next_o, next_t) = (i..base.len())
+            .find_map(|n| Some((n, in_ours[n]?, in_theirs[n]?)))
+            .unwrap_or((base.len(), ours.len(), theirs.len()));
+        let (was, by_ours, by_theirs) = (&base[i..next], &ours[o..next_o], &theirs[t..next_t]);
+        let taken = if by_ours == was || by_ours == by_theirs {
+            by_theirs
+        } else if by_theirs == was {
+            by_ours
+        } else {
+            return None;
+        };
+        for line in taken {
+            merged.extend_from_slice(line);
+        }
+        i = next;
+        o = next_o;
+        t = next_t;
+    }
+
+    Some(merged)
+}
+
+// contents split after each newline, keeping them, so joining the lines gives the contents back
+fn lines(contents: &[u8]) -> Vec<&[u8]> {
+    contents.split_inclusive(|b| *b == b'\n').collect()
+}
+
+// for each line of base, the line of other it is matched with in a longest common subsequence of
+// the two, if any
+fn matches(base: &[&[u8]], other: &[&[u8]]) -> Vec<Option<usize>> {
+    let (n, m) = (base.len(), other.len());
+    // lengths[i][j]: how long a common subsequence of base[i..] and other[j..] can be
+    let mut lengths = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i][j] = if base[i] == other[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut found = vec![None; n];
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if base[i] == other[j] {
+            found[i] = Some(j);
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(base: &str, ours: &str, theirs: &str) -> Option<String> {
+        merge(base.as_bytes(), ours.as_bytes(), theirs.as_bytes()).map(|m| String::from_utf8(m).unwrap())
+    }
+
+    #[test]
+    fn merges_changes_in_different_places() {
+        let base = "a\nb\nc\nd\ne\n";
+        assert_eq!(merged(base, "A\nb\nc\nd\ne\n", "a\nb\nc\nd\nE\n").as_deref(), Some("A\nb\nc\nd\nE\n"));
+        assert_eq!(merged(base, "a\nb\nc\nd\ne\nf\n", "a\nc\nd\ne\n").as_deref(), Some("a\nc\nd\ne\nf\n"));
+    }
+
+    #[test]
+    fn takes_a_change_made_on_one_side_only() {
+        assert_eq!(merged("a\nb\n", "a\nb\n", "a\nB\n").as_deref(), Some("a\nB\n"));
+        assert_eq!(merged("a\nb\n", "a\nB\n", "a\nb\n").as_deref(), Some("a\nB\n"));
+    }
+
+    #[test]
+    fn takes_the_same_change_made_on_both_sides_once() {
+        assert_eq!(merged("a\nb\nc\n", "a\nB\nc\n", "a\nB\nc\n").as_deref(), Some("a\nB\nc\n"));
+    }
+
+    #[test]
+    fn refuses_changes_to_the_same_lines() {
+        assert_eq!(merged("a\nb\nc\n", "a\nB\nc\n", "a\nX\nc\n"), None);
+        // lines next to each other count as the same stretch, like git merge-file
+        assert_eq!(merged("a\nb\nc\n", "A\nb\nc\n", "a\nB\nc\n"), None);
+    }
+
+    #[test]
+    fn merges_files_without_a_trailing_newline() {
+        assert_eq!(merged("a\nm\nb", "A\nm\nb", "a\nm\nB").as_deref(), Some("A\nm\nB"));
+        // adding the newline is a change to the last line like any other
+        assert_eq!(merged("a\nm\nb", "a\nm\nb\n", "A\nm\nb").as_deref(), Some("A\nm\nb\n"));
+        assert_eq!(merged("a\nm\nb", "a\nm\nb\n", "a\nm\nB"), None);
+    }
+
+    #[test]
+    fn refuses_binary_files() {
+        assert_eq!(merge(b"a\0\nb\n", b"A\0\nb\n", b"a\0\nB\n"), None);
+    }
+
+    #[test]
+    fn lines_keep_their_newlines() {
+        assert_eq!(lines(b"a\nb"), vec![&b"a\n"[..], &b"b"[..]]);
+        assert_eq!(lines(b"a\n").concat(), b"a\n");
+        assert!(lines(b"").is_empty());
+    }
+}
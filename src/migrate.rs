@@ -0,0 +1,237 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use serde::Serialize;
+use crate::scan::Candidate;
+
+// the other dotfile managers rodeo can import a setup from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    // a dotbot install.conf.yaml (or .json)
+    Dotbot,
+    // yadm's repository, or a list of the files it tracks as printed by `yadm list -a`
+    Yadm,
+}
+
+impl Source {
+    pub fn from_name(name: &str) -> Option<Source> {
+        match name {
+            "dotbot" => Some(Source::Dotbot),
+            "yadm" => Some(Source::Yadm),
+            _ => None,
+        }
+    }
+}
+
+// what another manager's setup translates to: programs to add, and everything that couldn't be
+// carried over, for the user to deal with by hand
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Migration {
+    pub candidates: Vec<Candidate>,
+    pub untranslated: Vec<String>,
+}
+
+// reads file as source's configuration. Files in managed (deployed paths of files rodeo already
+// manages) are left out, and programs whose names are in taken get a numbered name instead, so
+// they don't clash with the existing config.
+pub fn migrate(source: Source, file: &str, home_dir: &str, managed: &[String], taken: &[String]) -> Result<Migration, String> {
+    let mut migration = Migration::default();
+    let mut linked = match source {
+        Source::Dotbot => dotbot(file, home_dir, &mut migration.untranslated)?,
+        Source::Yadm => yadm(file, &mut migration.untranslated)?,
+    };
+    linked.retain(|l| {
+        let deployed = format!("{}/{}", home_dir.trim_end_matches('/'), l.path);
+        let inside = format!("{}/", deployed);
+        !managed.iter().any(|m| m == &deployed || (l.is_dir && m.starts_with(&inside)))
+    });
+    migration.candidates = candidates(linked, taken);
+    Ok(migration)
+}
+
+// a file or directory the other manager puts in the home directory, relative to it
+struct Linked {
+    path: String,
+    is_dir: bool,
+}
+
+fn dotbot(file: &str, home_dir: &str, untranslated: &mut Vec<String>) -> Result<Vec<Linked>, String> {
+    let contents = fs::read_to_string(file).map_err(|e| format!("could not read \"{}\": {}", file, e))?;
+    // JSON is YAML too, so this covers both of dotbot's formats
+    let directives: serde_yaml::Value = serde_yaml::from_str(&contents).map_err(|e| format!("could not parse \"{}\": {}", file, e))?;
+    let base_dir = Path::new(file).parent().unwrap_or_else(|| Path::new("."));
+
+    let mut linked = Vec::new();
+    for directive in directives.as_sequence().ok_or("a dotbot config must be a list of directives")? {
+        let directive = match directive.as_mapping() {
+            Some(val) => val,
+            None => continue,
+        };
+        for (name, body) in directive {
+            match name.as_str().unwrap_or("") {
+                "link" => {
+                    for (target, options) in body.as_mapping().into_iter().flatten() {
+                        let target = target.as_str().unwrap_or("");
+                        if let Some(val) = dotbot_link(target, options, base_dir, home_dir, untranslated) {
+                            linked.push(val);
+                        }
+                    }
+                }
+                // only there to make links work, which rodeo does on its own
+                "defaults" | "create" => (),
+                "shell" => {
+                    for command in body.as_sequence().into_iter().flatten() {
+                        let command = match command {
+                            serde_yaml::Value::Sequence(val) => val.first().and_then(|c| c.as_str()).unwrap_or(""),
+                            serde_yaml::Value::Mapping(val) => val.get("command").and_then(|c| c.as_str()).unwrap_or(""),
+                            val => val.as_str().unwrap_or(""),
+                        };
+                        untranslated.push(format!("shell command \"{}\": add it as a program's post_deploy_cmd if it's still needed", command));
+                    }
+                }
+                "clean" => untranslated.push("clean: rodeo never deletes dead links in the home directory".to_owned()),
+                other => untranslated.push(format!("unknown directive \"{}\"", other)),
+            }
+        }
+    }
+    Ok(linked)
+}
+
+fn dotbot_link(
+    target: &str,
+    options: &serde_yaml::Value,
+    base_dir: &Path,
+    home_dir: &str,
+    untranslated: &mut Vec<String>,
+) -> Option<Linked> {
+    // a link is either "target: source" or "target: {path: source, ...}"; without a source, the
+    // source is the target's name without its leading dot
+    let (source, options) = match options {
+        serde_yaml::Value::String(val) => (Some(val.as_str()), None),
+        serde_yaml::Value::Mapping(val) => (val.get("path").and_then(|p| p.as_str()), Some(val)),
+        _ => (None, None),
+    };
+    let target_name = Path::new(target).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let source = source.map(|s| s.to_owned()).unwrap_or_else(|| target_name.trim_start_matches('.').to_owned());
+
+    for key in options.into_iter().flat_map(|o| o.keys()).filter_map(|k| k.as_str()) {
+        match key {
+            "path" | "create" | "relink" | "force" | "relative" | "canonicalize" => (),
+            "if" => untranslated.push(format!("{}: the \"if\" condition isn't imported; add a when condition by hand", target)),
+            "glob" => {
+                untranslated.push(format!("{}: glob links aren't imported; add paths for {} by hand", target, source));
+                return None;
+            }
+            other => untranslated.push(format!("{}: unknown link option \"{}\"", target, other)),
+        }
+    }
+
+    let path = match target.strip_prefix("~/") {
+        Some(rest) => rest.to_owned(),
+        None if target.starts_with(home_dir) => target[home_dir.len()..].trim_start_matches('/').to_owned(),
+        None if target.starts_with('/') => {
+            untranslated.push(format!("{}: outside the home directory, so it wasn't imported", target));
+            return None;
+        }
+        // dotbot runs from the home directory, so relative targets are relative to it
+        None => target.to_owned(),
+    };
+    let is_dir = base_dir.join(&source).is_dir() || Path::new(home_dir).join(&path).is_dir();
+    Some(Linked { path: path.trim_end_matches('/').to_owned(), is_dir })
+}
+
+fn yadm(file: &str, untranslated: &mut Vec<String>) -> Result<Vec<Linked>, String> {
+    // yadm's repository itself, e.g. ~/.local/share/yadm/repo.git, or a saved file list
+    let listing = if Path::new(file).is_dir() {
+        let output = Command::new("git")
+            .args(["--git-dir", file, "ls-files"])
+            .output()
+            .map_err(|e| format!("could not run git: {}", e))?;
+        if !output.status.success() {
+            return Err(format!("could not list the files in \"{}\": {}", file, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    } else {
+        fs::read_to_string(file).map_err(|e| format!("could not read \"{}\": {}", file, e))?
+    };
+
+    let mut linked: Vec<Linked> = Vec::new();
+    let mut alternates: Vec<(String, Vec<String>)> = Vec::new();
+    for line in listing.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+        let path = line.trim_start_matches("~/");
+
+        // alternate files (e.g. ".gitconfig##os.Darwin") are variants of one file that yadm links
+        // into place depending on the machine. Rodeo has no per-file variants, so the version in
+        // place now is imported and the others are reported.
+        let path = match path.split_once("##") {
+            Some((base, conditions)) => {
+                match alternates.iter_mut().find(|(b, _)| b == base) {
+                    Some((_, found)) => found.push(conditions.to_owned()),
+                    None => alternates.push((base.to_owned(), vec![conditions.to_owned()])),
+                }
+                base
+            }
+            None => path,
+        };
+
+        if path.starts_with(".local/share/yadm/") || path.starts_with(".config/yadm/") {
+            untranslated.push(format!("~/{}: yadm's own configuration (bootstrap, encrypt, hooks) isn't imported", path));
+            continue;
+        }
+        if !linked.iter().any(|l| l.path == path) {
+            linked.push(Linked { path: path.to_owned(), is_dir: false });
+        }
+    }
+
+    for (base, conditions) in alternates {
+        untranslated.push(format!(
+            "~/{}: rodeo only manages the version in place now, not its alternates ##{}; use programs with hosts or os for those",
+            base,
+            conditions.join(", ##")
+        ));
+    }
+    Ok(linked)
+}
+
+// groups the linked files into programs: a directory becomes a program of its own, and files are
+// grouped by the directory they're in. Programs are named after that directory, or "home" for files
+// directly in the home directory.
+fn candidates(linked: Vec<Linked>, taken: &[String]) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = Vec::new();
+
+    for linked in linked {
+        let (root, paths) = if linked.is_dir {
+            (linked.path.clone(), vec!["**".to_owned()])
+        } else {
+            match linked.path.rsplit_once('/') {
+                Some((dir, name)) => (dir.to_owned(), vec![name.to_owned()]),
+                None => (String::new(), vec![linked.path.clone()]),
+            }
+        };
+        let root = if root.is_empty() { "~/".to_owned() } else { format!("~/{}", root) };
+
+        if !linked.is_dir {
+            if let Some(existing) = candidates.iter_mut().find(|c| c.root == root && c.paths != ["**"]) {
+                existing.paths.extend(paths);
+                continue;
+            }
+        }
+
+        let base = match root.trim_end_matches('/').rsplit('/').next() {
+            Some("~") | None => "home".to_owned(),
+            Some(dir) => dir.trim_start_matches('.').to_owned(),
+        };
+        let mut name = base.clone();
+        let mut n = 1;
+        while taken.contains(&name) || candidates.iter().any(|c| c.name == name) {
+            n += 1;
+            name = format!("{}-{}", base, n);
+        }
+        candidates.push(Candidate { name, root, paths });
+    }
+
+    // files that are already in a directory imported as a whole would be managed twice
+    let dirs: Vec<String> = candidates.iter().filter(|c| c.paths == ["**"]).map(|c| format!("{}/", c.root)).collect();
+    candidates.retain(|c| c.paths == ["**"] || !dirs.iter().any(|d| format!("{}/", c.root).starts_with(d)));
+    candidates
+}
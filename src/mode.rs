@@ -0,0 +1,110 @@
+use std::io;
+
+// file permissions as written in the config: octal digits like chmod takes them, e.g. "600" or
+// "0755". Only unix systems have them; elsewhere they're accepted and ignored.
+
+pub fn parse(text: &str) -> Result<u32, String> {
+    match u32::from_str_radix(text, 8) {
+        Ok(val) if !text.starts_with('+') && val <= 0o7777 => Ok(val),
+        _ => Err(format!("\"{}\" is not a file mode; use octal digits like \"600\" or \"755\"", text)),
+    }
+}
+
+// mode the way chmod and ls -l people are used to reading it, e.g. "600"
+pub fn format(mode: u32) -> String {
+    format!("{:o}", mode)
+}
+
+// the permission bits of the file at path, if it exists and the system has them
+#[cfg(unix)]
+pub fn get(path: &str) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path).ok().map(|m| m.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+pub fn get(_path: &str) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+pub fn set(path: &str, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+pub fn set(_path: &str, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+// whether the file at path could be written, or created if it doesn't exist yet along with any
+// directories missing above it. Only the nearest part of the path that exists is looked at.
+#[cfg(unix)]
+pub fn writable(path: &str) -> bool {
+    use std::ffi::CString;
+    use std::os::raw::{c_char, c_int};
+    use std::path::Path;
+
+    extern "C" {
+        fn access(path: *const c_char, mode: c_int) -> c_int;
+    }
+    const W_OK: c_int = 2;
+
+    let existing = match Path::new(path).ancestors().find(|p| p.exists()) {
+        Some(val) => val,
+        None => return true,
+    };
+    match CString::new(existing.to_string_lossy().into_owned()) {
+        Ok(val) => unsafe { access(val.as_ptr(), W_OK) == 0 },
+        Err(_) => true,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn writable(_path: &str) -> bool {
+    true
+}
+
+// the process' umask set to mask until the value is dropped, when the one before it is put back.
+// Everything rodeo creates in the meantime, files and directories alike, is created without the
+// bits in mask.
+pub struct Umask {
+    previous: Option<u32>,
+}
+
+impl Umask {
+    pub fn set(mask: Option<u32>) -> Umask {
+        Umask { previous: mask.map(swap_umask) }
+    }
+}
+
+impl Drop for Umask {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous {
+            swap_umask(previous);
+        }
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "dragonfly"))]
+type ModeT = u16;
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "dragonfly"))))]
+type ModeT = u32;
+
+// sets the umask, returning the previous one
+#[cfg(unix)]
+fn swap_umask(mask: u32) -> u32 {
+    extern "C" {
+        fn umask(mask: ModeT) -> ModeT;
+    }
+    // umask can't fail, it only ever swaps one value for another
+    unsafe { umask((mask & 0o777) as ModeT) as u32 }
+}
+
+#[cfg(not(unix))]
+fn swap_umask(mask: u32) -> u32 {
+    mask
+}
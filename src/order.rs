@@ -0,0 +1,40 @@
+use crate::Program;
+
+// the order to work on programs in so that each comes after the programs in its depends_on: config
+// order, except that a program is moved down past the programs it depends on. Dependencies that
+// aren't in programs (e.g. not selected for this machine) are ignored. If some programs depend on
+// each other in a circle, returns the names along the circle instead, starting and ending with the
+// same program.
+pub fn dependency_order(programs: &[Program]) -> Result<Vec<usize>, Vec<String>> {
+    let mut order: Vec<usize> = Vec::new();
+    let position = |name: &String| programs.iter().position(|i| &i.name == name);
+
+    while order.len() < programs.len() {
+        let ready = (0..programs.len()).find(|p| {
+            !order.contains(p) && programs[*p].depends_on.iter().filter_map(position).all(|d| d == *p || order.contains(&d))
+        });
+        match ready {
+            Some(p) => order.push(p),
+            None => {
+                // every program left waits on another one left, so following the first of those
+                // from any of them must come back around
+                let mut circle: Vec<usize> = vec![(0..programs.len()).find(|p| !order.contains(p)).unwrap_or(0)];
+                loop {
+                    let last = circle[circle.len() - 1];
+                    let next = programs[last].depends_on.iter()
+                        .filter_map(position)
+                        .find(|d| !order.contains(d))
+                        .unwrap_or(last);
+                    if let Some(start) = circle.iter().position(|p| *p == next) {
+                        let mut names: Vec<String> = circle[start..].iter().map(|p| programs[*p].name.clone()).collect();
+                        names.push(programs[next].name.clone());
+                        return Err(names);
+                    }
+                    circle.push(next);
+                }
+            }
+        }
+    }
+
+    Ok(order)
+}
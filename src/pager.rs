@@ -0,0 +1,35 @@
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+// prints text, through the user's pager if it goes to a terminal and is longer than the terminal
+// is high (or the height isn't known), the way git does. The pager is $PAGER, or less if that isn't
+// set. less gets LESS=FRX unless LESS is already set, so it keeps colors and quits straight away if
+// the text fits on one screen after all. A PAGER of "" or "cat" turns paging off, and if the pager
+// can't be started the text is printed as it is.
+pub fn page(text: &str, height: Option<usize>) {
+    let pager = env::var("PAGER").unwrap_or_else(|_| "less".to_owned());
+    let pager = pager.trim();
+    let fits = height.is_some_and(|h| text.lines().count() < h);
+    if text.is_empty() || fits || pager.is_empty() || pager == "cat" || !io::stdout().is_terminal() {
+        print!("{}", text);
+        return;
+    }
+
+    let mut command = if cfg!(windows) { Command::new("cmd") } else { Command::new("sh") };
+    command.args(if cfg!(windows) { ["/C", pager] } else { ["-c", pager] });
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+
+    match command.stdin(Stdio::piped()).spawn() {
+        Ok(mut child) => {
+            // quitting the pager before the end closes the pipe, which isn't an error
+            if let Some(mut stdin) = child.stdin.take() {
+                let _ = stdin.write_all(text.as_bytes());
+            }
+            let _ = child.wait();
+        }
+        Err(_) => print!("{}", text),
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+// runs work on every item, on up to jobs threads at once, and hands each result to done on the
+// calling thread in the items' order, as soon as it and every result before it are in. That keeps
+// output in the same order as a sequential run while the work itself overlaps. With one job
+// nothing is spawned.
+pub fn for_each<T, R, W, D>(items: &[T], jobs: usize, work: W, mut done: D)
+where
+    T: Sync,
+    R: Send,
+    W: Fn(&T) -> R + Sync,
+    D: FnMut(R),
+{
+    if jobs <= 1 || items.len() <= 1 {
+        for item in items {
+            done(work(item));
+        }
+        return;
+    }
+
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(items.len()) {
+            let (sender, next, work) = (sender.clone(), &next, &work);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let item = match items.get(index) {
+                    Some(val) => val,
+                    None => break,
+                };
+                if sender.send((index, work(item))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        let mut waiting: BTreeMap<usize, R> = BTreeMap::new();
+        let mut handed = 0;
+        for (index, result) in receiver {
+            waiting.insert(index, result);
+            while let Some(result) = waiting.remove(&handed) {
+                done(result);
+                handed += 1;
+            }
+        }
+    });
+}
@@ -0,0 +1,69 @@
+use std::path::{Component, Path, PathBuf};
+use crate::expand;
+
+// turning paths as written in a config or on the command line into the ones rodeo works with.
+// Paths stay Strings like everywhere else in rodeo, but are taken apart into components to tidy
+// them up, so that only a leading ~ stands for the home directory and separators are cleaned up
+// without touching the names between them.
+
+// path with a leading "~" replaced by home. A ~ anywhere else, or one followed by a user name as
+// in "~bob/", is part of a name and kept.
+pub fn expand_home(path: &str, home: &str) -> String {
+    match path.strip_prefix('~') {
+        Some("") => home.to_owned(),
+        Some(rest) if rest.starts_with('/') => format!("{}{}", home.trim_end_matches('/'), rest),
+        _ => path.to_owned(),
+    }
+}
+
+// path with a leading home replaced by "~", the way it is most likely written in a config
+pub fn contract_home(path: &str, home: &str) -> String {
+    match relative_to(path, home) {
+        Some(rest) if !home.is_empty() && rest.is_empty() => "~".to_owned(),
+        Some(rest) if !home.is_empty() => format!("~/{}", rest),
+        _ => path.to_owned(),
+    }
+}
+
+// path without repeated separators, "." components or a trailing separator; "/" stays "/" and a
+// path that is only "." components becomes ".". ".." is kept, since what it refers to depends on
+// symlinks along the way.
+pub fn normalize(path: &str) -> String {
+    let normalized: PathBuf = Path::new(path).components().filter(|c| *c != Component::CurDir).collect();
+    match normalized.to_string_lossy() {
+        empty if empty.is_empty() && !path.is_empty() => ".".to_owned(),
+        val => val.into_owned(),
+    }
+}
+
+// path with ~ expanded and normalized
+pub fn standardize(path: &str, home: &str) -> String {
+    normalize(&expand_home(path, home))
+}
+
+// path with environment variables and ~ expanded, normalized
+pub fn resolve(path: &str, home: &str) -> Result<String, String> {
+    Ok(standardize(&expand::expand_vars(path)?, home))
+}
+
+// the path of name below dir. name is always taken to be below dir, even if it starts with a
+// separator; an empty name gives dir itself.
+pub fn join(dir: &str, name: &str) -> String {
+    normalize(&format!("{}/{}", dir, name))
+}
+
+// the path of name below dir, unless name is absolute, in which case it is the path itself. Used
+// for where files are deployed, which may be anywhere on the system.
+pub fn locate(dir: &str, name: &str) -> String {
+    if Path::new(name).is_absolute() {
+        normalize(name)
+    } else {
+        join(dir, name)
+    }
+}
+
+// where path is relative to dir if it is dir or below it ("" for dir itself), comparing whole
+// names, so "/home/al" isn't below "/home/a"
+pub fn relative_to(path: &str, dir: &str) -> Option<String> {
+    Path::new(path).strip_prefix(dir).ok().map(|p| p.to_string_lossy().into_owned())
+}
@@ -0,0 +1,402 @@
+// the data model behind `rodeo plan`, `--dry-run` and `rodeo check`, and their JSON output.
+//
+// These types are a public contract for tools built on top of rodeo (editor plugins, dashboards,
+// ...), so they follow stricter rules than the rest of the crate. Within one SCHEMA_VERSION:
+//
+//  - fields are never removed, renamed or given a different type
+//  - new fields may be added, so consumers must ignore fields they don't know
+//  - enums may gain new variants, so consumers must handle values they don't know
+//  - the serialized names of fields and variants (snake_case and kebab-case respectively) never
+//    change
+//
+// Anything else is a breaking change and bumps SCHEMA_VERSION.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use serde::Serialize;
+use crate::cache::StatusCache;
+use crate::include::Layer;
+use crate::journal::Operation;
+use crate::link::{self, DeployMode};
+use crate::mapping::{FileState, Side, Target};
+use crate::state;
+use crate::vcs;
+use crate::Program;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+// what an operation would do to every file it manages, worked out without touching anything. Hooks,
+// registry keys and the remote aren't covered, only managed files.
+#[derive(Clone, Debug, Serialize)]
+pub struct Plan {
+    pub schema_version: u32,
+    pub operation: Operation,
+    pub actions: Vec<FileAction>,
+}
+
+// what would happen to a single file
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ActionKind {
+    // destination doesn't exist and would be created from source
+    Create,
+    // destination exists and would be replaced by source
+    Overwrite,
+    // source and destination are already identical
+    Unchanged,
+    // nothing can be done, see reason
+    Skip,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FileAction {
+    pub program: String,
+    // the file's path relative to the program's root
+    pub path: String,
+    pub kind: ActionKind,
+    pub source: String,
+    pub destination: String,
+    // why the file is skipped; None for every other kind
+    pub reason: Option<String>,
+}
+
+// how the deployed copy of a managed file differs from the repository copy
+#[derive(Clone, Debug, Serialize)]
+pub struct Drift {
+    pub schema_version: u32,
+    pub program: String,
+    pub path: String,
+    pub repo_file: String,
+    pub deployed_file: String,
+    pub state: FileState,
+}
+
+// where a program comes from and how its files stand, as shown by `rodeo status`
+#[derive(Clone, Debug, Serialize)]
+pub struct ProgramStatus {
+    pub schema_version: u32,
+    pub program: String,
+    pub layer: Layer,
+    // the config file the program is defined in
+    pub source: String,
+    // the repository its files are kept in
+    pub repository: String,
+    // how many of its files are in each state, by FileState::as_str
+    pub files: BTreeMap<String, usize>,
+    // how the repository stands with git; absent for other backends
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git: Option<RepoStatus>,
+}
+
+// how a git repository stands, as of its last fetch: the remote itself isn't asked
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct RepoStatus {
+    // the checked out branch; None if HEAD is detached
+    pub branch: Option<String>,
+    // the branch it follows, e.g. "origin/main"; None if it follows none
+    pub upstream: Option<String>,
+    // the commits the branch has that upstream doesn't, and the other way around
+    pub ahead: usize,
+    pub behind: usize,
+    // the files, relative to the repository, with uncommitted changes
+    pub uncommitted: Vec<String>,
+    // what git was stopped in the middle of, e.g. a merge that conflicted
+    pub in_progress: Option<InProgress>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum InProgress {
+    Merge,
+    Rebase,
+    CherryPick,
+    Revert,
+    Bisect,
+}
+
+// a commit that changed managed files, as listed by `rodeo history`
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct Revision {
+    pub commit: String,
+    // when it was committed, in seconds since the unix epoch
+    pub time: u64,
+    pub author: String,
+    // the first line of its message
+    pub message: String,
+    // which of the files asked about it changed, relative to the repository
+    pub files: Vec<String>,
+}
+
+impl Plan {
+    // the text form used by `rodeo plan` and --dry-run
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for action in self.actions.iter() {
+            text.push_str(&action.to_text());
+            text.push('\n');
+        }
+        text
+    }
+
+    // whether carrying out the plan would change any file
+    pub fn has_changes(&self) -> bool {
+        self.actions.iter().any(|a| a.kind == ActionKind::Create || a.kind == ActionKind::Overwrite)
+    }
+}
+
+impl FileAction {
+    pub fn to_text(&self) -> String {
+        match (&self.kind, &self.reason) {
+            (ActionKind::Create, _) => format!("create {} => {}", self.source, self.destination),
+            (ActionKind::Overwrite, _) => format!("overwrite {} => {}", self.source, self.destination),
+            (ActionKind::Unchanged, _) => format!("unchanged {}", self.destination),
+            (ActionKind::Skip, Some(reason)) => format!("skip {} ({})", self.destination, reason),
+            (ActionKind::Skip, None) => format!("skip {}", self.destination),
+        }
+    }
+}
+
+impl Drift {
+    pub fn new(target: &Target) -> Drift {
+        Drift {
+            schema_version: SCHEMA_VERSION,
+            program: target.program.clone(),
+            path: target.path.clone(),
+            repo_file: target.repo_file.clone(),
+            deployed_file: target.deployed_file.clone(),
+            state: target.state(),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        format!("{}: {} [{}]", self.program, self.deployed_file, self.state.as_str())
+    }
+}
+
+impl ProgramStatus {
+    pub fn new(program: &Program, home_dir: &str, dotfiles_dir: &str, source: &str, cache: &mut StatusCache) -> ProgramStatus {
+        let mut files = BTreeMap::new();
+        for target in program.deploy_targets(home_dir, dotfiles_dir) {
+            *files.entry(cache.state(&target).as_str().to_owned()).or_insert(0) += 1;
+        }
+        ProgramStatus {
+            schema_version: SCHEMA_VERSION,
+            program: program.name.clone(),
+            layer: program.layer,
+            source: source.to_owned(),
+            repository: program.repository(home_dir, dotfiles_dir),
+            files,
+            git: None,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let files: Vec<String> = self.files.iter().map(|(state, n)| format!("{} {}", n, state)).collect();
+        let files = if files.is_empty() { "no files".to_owned() } else { files.join(", ") };
+        format!("{} ({}): {}", self.program, self.layer.as_str(), files)
+    }
+}
+
+impl RepoStatus {
+    // whether there is something to do before the repository and its remote agree
+    pub fn needs_attention(&self) -> bool {
+        self.branch.is_none() || self.ahead > 0 || self.behind > 0 || !self.uncommitted.is_empty() || self.in_progress.is_some()
+    }
+
+    pub fn to_text(&self, repository: &str) -> String {
+        let commits = |n: usize| if n == 1 { "1 commit".to_owned() } else { format!("{} commits", n) };
+        let mut parts = vec![match &self.branch {
+            Some(val) => format!("on {}", val),
+            None => "HEAD detached".to_owned(),
+        }];
+        parts.push(match (&self.upstream, self.ahead, self.behind) {
+            (None, _, _) => "following no remote branch".to_owned(),
+            (Some(upstream), 0, 0) => format!("up to date with {}", upstream),
+            (Some(upstream), ahead, 0) => format!("{} ahead of {}", commits(ahead), upstream),
+            (Some(upstream), 0, behind) => format!("{} behind {}", commits(behind), upstream),
+            (Some(upstream), ahead, behind) => format!("{} ahead of and {} behind {}", commits(ahead), commits(behind), upstream),
+        });
+        match self.uncommitted.len() {
+            0 => {}
+            1 => parts.push("1 uncommitted change".to_owned()),
+            n => parts.push(format!("{} uncommitted changes", n)),
+        }
+        if let Some(operation) = self.in_progress {
+            parts.push(format!("in the middle of a {}", operation.as_str()));
+        }
+        format!("{}: {}", repository, parts.join(", "))
+    }
+}
+
+impl Revision {
+    // e.g. "1a2b3c4 2024-05-01 18:30:00 rodeo sync from laptop", followed by the files it changed
+    // if with_files is set
+    pub fn to_text(&self, with_files: bool) -> String {
+        let short = self.commit.get(..7).unwrap_or(&self.commit);
+        let text = format!("{} {} {}", short, state::format_timestamp(self.time), self.message);
+        if with_files {
+            format!("{} ({})", text, vcs::short_list(&self.files, "no files"))
+        } else {
+            text
+        }
+    }
+}
+
+impl InProgress {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InProgress::Merge => "merge",
+            InProgress::Rebase => "rebase",
+            InProgress::CherryPick => "cherry-pick",
+            InProgress::Revert => "revert",
+            InProgress::Bisect => "bisect",
+        }
+    }
+}
+
+// works out what operation would do to the programs' files
+pub fn plan(operation: Operation, programs: &[Program], home_dir: &str, dotfiles_dir: &str) -> Plan {
+    let side = match operation {
+        Operation::Deploy | Operation::Unlink => Side::Repo,
+        Operation::Collect => Side::Deployed,
+        Operation::Sync { .. } => Side::Both,
+        Operation::Prune | Operation::Revert | Operation::Rollback => Side::Repo,
+    };
+
+    let mut actions = Vec::new();
+    for program in programs {
+        for target in program.targets_on(home_dir, dotfiles_dir, side) {
+            let (source, destination) = match operation {
+                // files only copied the other way aren't part of the operation at all
+                Operation::Deploy if !target.direction.deploys() => continue,
+                Operation::Collect if !target.direction.collects() => continue,
+                // templates are rendered on their way to the system, and never come back
+                Operation::Deploy | Operation::Sync { .. } if target.template.is_some() => {
+                    if target.direction.deploys() {
+                        actions.push(template_action(&target, operation));
+                    }
+                    continue;
+                }
+                Operation::Collect if target.template.is_some() => {
+                    let reason = format!("\"{}\" is rendered from a template, which it can't be collected into", target.deployed_file);
+                    actions.push(action(&target, ActionKind::Skip, &target.deployed_file, &target.repo_file, Some(reason)));
+                    continue;
+                }
+                Operation::Deploy if program.deploy_with != DeployMode::Copy => {
+                    actions.push(link_action(&target, program.deploy_with));
+                    continue;
+                }
+                Operation::Deploy => (&target.repo_file, &target.deployed_file),
+                // only links are replaced, by copies of what they point to
+                Operation::Unlink if !link::linked(&target.deployed_file, &target.repo_file) => continue,
+                Operation::Unlink => {
+                    actions.push(action(&target, ActionKind::Overwrite, &target.repo_file, &target.deployed_file, None));
+                    continue;
+                }
+                Operation::Collect => (&target.deployed_file, &target.repo_file),
+                // only touches files no program has anymore
+                Operation::Prune => continue,
+                // put back single files or generations, which aren't planned
+                Operation::Revert | Operation::Rollback => continue,
+                Operation::Sync { .. } => match newer(&target) {
+                    Ok(true) if !target.direction.deploys() => continue,
+                    Ok(false) if !target.direction.collects() => continue,
+                    Ok(true) => (&target.repo_file, &target.deployed_file),
+                    Ok(false) => (&target.deployed_file, &target.repo_file),
+                    // identical copies don't need a winner
+                    Err(_) if fs::read(&target.repo_file).ok() == fs::read(&target.deployed_file).ok() => {
+                        (&target.repo_file, &target.deployed_file)
+                    }
+                    Err(reason) => {
+                        actions.push(action(&target, ActionKind::Skip, &target.repo_file, &target.deployed_file, Some(reason)));
+                        continue;
+                    }
+                },
+            };
+            actions.push(copy_action(&target, source, destination));
+        }
+    }
+
+    Plan { schema_version: SCHEMA_VERSION, operation, actions }
+}
+
+// every managed file of the programs whose deployed copy isn't identical to the repository copy
+pub fn drift(programs: &[Program], home_dir: &str, dotfiles_dir: &str) -> Vec<Drift> {
+    programs.iter()
+        .flat_map(|i| i.deploy_targets(home_dir, dotfiles_dir))
+        .map(|t| Drift::new(&t))
+        .filter(|d| d.state != FileState::Deployed)
+        .collect()
+}
+
+fn copy_action(target: &Target, source: &str, destination: &str) -> FileAction {
+    match (fs::read(source), fs::read(destination)) {
+        (Err(e), _) => action(target, ActionKind::Skip, source, destination, Some(format!("could not read \"{}\": {}", source, e))),
+        (Ok(a), Ok(b)) if a == b => action(target, ActionKind::Unchanged, source, destination, None),
+        (Ok(_), Ok(_)) => action(target, ActionKind::Overwrite, source, destination, None),
+        (Ok(_), Err(_)) => action(target, ActionKind::Create, source, destination, None),
+    }
+}
+
+// what deploy or sync would do with a template: render it, unless it renders to what is deployed.
+// Sync leaves a deployed file changed since the template was alone.
+fn template_action(target: &Target, operation: Operation) -> FileAction {
+    let (source, destination) = (&target.repo_file, &target.deployed_file);
+    let rendered = match target.deployed_contents() {
+        Ok(val) => val,
+        Err(e) => return action(target, ActionKind::Skip, source, destination, Some(e)),
+    };
+    let modified = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match fs::read(destination) {
+        Ok(deployed) if deployed == rendered => action(target, ActionKind::Unchanged, source, destination, None),
+        Ok(_) if matches!(operation, Operation::Sync { .. }) && modified(destination) > modified(source) => {
+            let reason = format!("\"{}\" was changed since it was rendered from the template", destination);
+            action(target, ActionKind::Skip, source, destination, Some(reason))
+        }
+        Ok(_) => action(target, ActionKind::Overwrite, source, destination, None),
+        Err(_) => action(target, ActionKind::Create, source, destination, None),
+    }
+}
+
+fn link_action(target: &Target, mode: DeployMode) -> FileAction {
+    let (source, destination) = (&target.repo_file, &target.deployed_file);
+    if link::in_place(mode, destination, source) {
+        action(target, ActionKind::Unchanged, source, destination, None)
+    } else if !Path::new(source).is_file() {
+        action(target, ActionKind::Skip, source, destination, Some(format!("\"{}\" doesn't exist", source)))
+    } else if link::is_link(destination) || Path::new(destination).exists() {
+        action(target, ActionKind::Overwrite, source, destination, None)
+    } else {
+        action(target, ActionKind::Create, source, destination, None)
+    }
+}
+
+fn action(target: &Target, kind: ActionKind, source: &str, destination: &str, reason: Option<String>) -> FileAction {
+    FileAction {
+        program: target.program.clone(),
+        path: target.path.clone(),
+        kind,
+        source: source.to_owned(),
+        destination: destination.to_owned(),
+        reason,
+    }
+}
+
+// which way a sync would copy target, following the same rules as Program::sync_local: a file that
+// only exists on one side is copied to the other, otherwise the most recently modified copy wins.
+// Ok(true) means the repository copy wins.
+fn newer(target: &Target) -> Result<bool, String> {
+    let repo = fs::metadata(&target.repo_file).and_then(|m| m.modified());
+    let deployed = fs::metadata(&target.deployed_file).and_then(|m| m.modified());
+
+    match (repo, deployed) {
+        (Ok(repo), Ok(deployed)) if repo == deployed => {
+            Err("modified at the same time at both locations".to_owned())
+        }
+        (Ok(repo), Ok(deployed)) => Ok(repo > deployed),
+        (Ok(_), Err(_)) => Ok(true),
+        (Err(_), Ok(_)) => Ok(false),
+        (Err(_), Err(_)) => Err("does not exist in the repository or on this system".to_owned()),
+    }
+}
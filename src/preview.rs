@@ -0,0 +1,65 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use serde::Serialize;
+use crate::copy;
+use crate::mapping::Target;
+
+// a deployed file that a sync would overwrite with a version coming from the remote
+#[derive(Clone, Debug, Serialize)]
+pub struct IncomingChange {
+    pub program: String,
+    pub deployed_file: String,
+    // unified diff from the deployed file to the incoming version
+    pub diff: String,
+}
+
+// where the remote's revision is checked out while it is being previewed
+pub fn worktree_dir(state_dir: &str) -> String {
+    format!("{}/incoming", state_dir)
+}
+
+// compares the remote's revision, checked out in worktree_dir, with what is deployed. Only files
+// the remote actually changed are included, since the others won't be touched by pulling.
+pub fn incoming_changes(targets: &[Target], dotfiles_dir: &str, worktree_dir: &str) -> Vec<IncomingChange> {
+    let mut changes = Vec::new();
+
+    for target in targets {
+        let relative = match target.repo_relative(dotfiles_dir) {
+            Some(val) => val,
+            None => continue,
+        };
+        let incoming_file = format!("{}/{}", worktree_dir, relative);
+
+        let incoming = fs::read(&incoming_file).ok();
+        if incoming.is_none() || incoming == fs::read(&target.repo_file).ok() || incoming == fs::read(&target.deployed_file).ok() {
+            continue;
+        }
+
+        changes.push(IncomingChange {
+            program: target.program.clone(),
+            deployed_file: target.deployed_file.clone(),
+            diff: diff(&target.deployed_file, &incoming_file),
+        });
+    }
+
+    changes
+}
+
+// unified diff between two files. The deployed side may not exist yet, in which case the whole
+// incoming file shows up as added. Binary files are only named, as their diff couldn't be read.
+fn diff(deployed_file: &str, incoming_file: &str) -> String {
+    if copy::is_binary(deployed_file) || copy::is_binary(incoming_file) {
+        return format!("binary file {} differs from the incoming version\n", deployed_file);
+    }
+    let from = if Path::new(deployed_file).exists() { deployed_file } else { "/dev/null" };
+    let output = Command::new("git")
+        .args(["diff", "--no-index", "--no-color", "--", from, incoming_file])
+        .output();
+
+    // git diff --no-index exits with 1 when the files differ, so the status says nothing here
+    match output {
+        Ok(val) if !val.stdout.is_empty() => String::from_utf8_lossy(&val.stdout).into_owned(),
+        _ => format!("{} differs from the incoming version\n", deployed_file),
+    }
+}
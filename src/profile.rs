@@ -0,0 +1,21 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+
+// a named way of using the config, e.g. [profile.work], chosen with --profile so one config can
+// serve machines that need different things from it. A profile narrows the programs down like
+// --program and --tag do, and sets variables of its own over those in [env].
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+pub struct Profile {
+    // names of the programs the profile uses; all of them if empty
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub programs: Vec<String>,
+    // only the programs carrying at least one of these tags; all of them if empty
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // variables replacing or adding to those in [env] while the profile is in use
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
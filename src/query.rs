@@ -0,0 +1,193 @@
+use serde_json::{json, Value};
+use crate::plan::Drift;
+use crate::Settings;
+
+// a question about the resolved config for `rodeo query`, e.g. "programs[hosts=laptop].paths" or
+// "files[state=modified]". The grammar is
+//
+//   query  := collection ("[" filter ("," filter)* "]")? ("." field)?
+//   filter := field ("=" | "!=") value
+//
+// where collection is programs, files or groups. A filter on a list field (such as hosts or paths)
+// matches if any element equals the value. Only the programs selected for this machine and command
+// line are looked at, the same ones every other command would act on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    pub collection: Collection,
+    pub filters: Vec<Filter>,
+    // the field to print instead of whole items
+    pub field: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Collection {
+    Programs,
+    Files,
+    Groups,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    pub field: String,
+    pub value: String,
+    pub negated: bool,
+}
+
+impl Collection {
+    // the fields every item in the collection has
+    pub fn fields(&self) -> &'static [&'static str] {
+        match self {
+            Collection::Programs => &[
+                "name", "root", "paths", "mode", "dotfiles_directory", "repo_subdir", "layout", "strip_dots", "deploy_mode", "symlinks", "tags", "hosts", "os", "when", "enabled", "post_deploy_cmd",
+                "hook_when", "shell", "depends_on", "backup", "sync_direction", "flavor", "registry", "source", "layer",
+            ],
+            Collection::Files => &["program", "path", "repo_file", "deployed_file", "state", "mode", "direction", "binary"],
+            Collection::Groups => &["name", "programs", "pre_deploy_cmd", "post_deploy_cmd", "hook_when"],
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Query, String> {
+        let text = text.trim();
+        let end = text.find(['[', '.']).unwrap_or(text.len());
+        let collection = match &text[..end] {
+            "programs" => Collection::Programs,
+            "files" => Collection::Files,
+            "groups" => Collection::Groups,
+            other => return Err(format!("unknown collection \"{}\", expected programs, files or groups", other)),
+        };
+        let mut rest = &text[end..];
+
+        let mut filters = Vec::new();
+        if let Some(inner) = rest.strip_prefix('[') {
+            let close = inner.find(']').ok_or("missing \"]\" after the filters")?;
+            for filter in inner[..close].split(',').map(|f| f.trim()).filter(|f| !f.is_empty()) {
+                let (field, value, negated) = match (filter.split_once("!="), filter.split_once('=')) {
+                    (Some((field, value)), _) => (field, value, true),
+                    (None, Some((field, value))) => (field, value, false),
+                    (None, None) => return Err(format!("filter \"{}\" needs a value, e.g. \"{}=...\"", filter, filter)),
+                };
+                let field = field.trim().to_owned();
+                check_field(collection, &field)?;
+                let value = value.trim().trim_matches(|c| c == '"' || c == '\'').to_owned();
+                filters.push(Filter { field, value, negated });
+            }
+            rest = &inner[close + 1..];
+        }
+
+        let field = match rest.strip_prefix('.') {
+            Some(field) => {
+                check_field(collection, field)?;
+                Some(field.to_owned())
+            }
+            None if rest.is_empty() => None,
+            None => return Err(format!("unexpected \"{}\" at the end of the query", rest)),
+        };
+
+        Ok(Query { collection, filters, field })
+    }
+
+    // the matching items, or the requested field of each, in config order
+    pub fn run(&self, settings: &Settings) -> Vec<Value> {
+        items(self.collection, settings)
+            .into_iter()
+            .filter(|item| self.filters.iter().all(|f| f.matches(item)))
+            .map(|item| match &self.field {
+                Some(field) => item.get(field).cloned().unwrap_or(Value::Null),
+                None => item,
+            })
+            .collect()
+    }
+}
+
+impl Filter {
+    fn matches(&self, item: &Value) -> bool {
+        let found = match item.get(&self.field) {
+            Some(Value::Array(values)) => values.iter().any(|v| as_text(v) == self.value),
+            Some(value) => as_text(value) == self.value,
+            None => false,
+        };
+        found != self.negated
+    }
+}
+
+// a value the way it is written in filters and printed as text: strings without their quotes
+pub fn as_text(value: &Value) -> String {
+    match value {
+        Value::String(val) => val.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+fn check_field(collection: Collection, field: &str) -> Result<(), String> {
+    if collection.fields().contains(&field) {
+        Ok(())
+    } else {
+        Err(format!("unknown field \"{}\", expected one of {}", field, collection.fields().join(", ")))
+    }
+}
+
+// every item of the collection as JSON, with all of its fields whatever their value
+fn items(collection: Collection, settings: &Settings) -> Vec<Value> {
+    match collection {
+        Collection::Programs => settings.programs.iter()
+            .map(|p| {
+                json!({
+                    "name": p.name,
+                    "root": p.root,
+                    "paths": p.paths,
+                    "mode": p.mode,
+                    "dotfiles_directory": p.dotfiles_directory,
+                    "repo_subdir": p.repo_subdir,
+                    "layout": p.repo_layout,
+                    "strip_dots": p.undotted,
+                    "deploy_mode": p.deploy_with,
+                    "symlinks": p.symlinks,
+                    "tags": p.tags,
+                    "hosts": p.hosts,
+                    "os": p.os,
+                    "when": p.when,
+                    "enabled": p.enabled,
+                    "post_deploy_cmd": p.post_deploy_cmd,
+                    "hook_when": p.hook_when,
+                    "shell": p.shell,
+                    "depends_on": p.depends_on,
+                    "backup": p.backup,
+                    "sync_direction": p.sync_direction,
+                    "flavor": p.flavor,
+                    "registry": p.registry,
+                    "source": p.source,
+                    "layer": p.layer,
+                })
+            })
+            .collect(),
+        Collection::Files => settings.targets().iter()
+            .map(|t| {
+                let drift = Drift::new(t);
+                json!({
+                    "program": drift.program,
+                    "path": drift.path,
+                    "repo_file": drift.repo_file,
+                    "deployed_file": drift.deployed_file,
+                    "state": drift.state,
+                    "mode": t.mode,
+                    "direction": t.direction,
+                    "binary": t.is_binary(),
+                })
+            })
+            .collect(),
+        Collection::Groups => settings.groups.iter()
+            .map(|(name, g)| {
+                json!({
+                    "name": name,
+                    "programs": g.programs,
+                    "pre_deploy_cmd": g.pre_deploy_cmd,
+                    "post_deploy_cmd": g.post_deploy_cmd,
+                    "hook_when": g.hook_when,
+                })
+            })
+            .collect(),
+    }
+}
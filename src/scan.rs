@@ -0,0 +1,118 @@
+use std::path::Path;
+use serde::Serialize;
+use crate::mapping::{PathEntry, PathFilter};
+use crate::windows::Flavor;
+use crate::Program;
+
+// well-known configuration files, as (program name, root, paths). Roots use ~ the same way
+// rodeo.toml does.
+pub const KNOWN: &[(&str, &str, &[&str])] = &[
+    ("bash", "~/", &[".bashrc", ".bash_profile", ".bash_aliases", ".bash_logout"]),
+    ("zsh", "~/", &[".zshrc", ".zprofile", ".zshenv"]),
+    ("profile", "~/", &[".profile", ".inputrc"]),
+    ("git", "~/", &[".gitconfig", ".gitignore_global"]),
+    ("vim", "~/", &[".vimrc", ".gvimrc"]),
+    ("tmux", "~/", &[".tmux.conf"]),
+    ("xorg", "~/", &[".xinitrc", ".Xresources"]),
+    ("nvim", "~/.config/nvim", &["init.vim", "init.lua"]),
+    ("tmux-xdg", "~/.config/tmux", &["tmux.conf"]),
+    ("alacritty", "~/.config/alacritty", &["alacritty.toml", "alacritty.yml"]),
+    ("kitty", "~/.config/kitty", &["kitty.conf"]),
+    ("fish", "~/.config/fish", &["config.fish"]),
+    ("i3", "~/.config/i3", &["config"]),
+    ("sway", "~/.config/sway", &["config"]),
+    ("starship", "~/.config", &["starship.toml"]),
+];
+
+// a program found by scan that could be added to the config
+#[derive(Clone, Debug, Serialize)]
+pub struct Candidate {
+    pub name: String,
+    pub root: String,
+    pub paths: Vec<String>,
+}
+
+impl Candidate {
+    pub fn to_program(&self) -> Program {
+        Program {
+            name: self.name.clone(),
+            root: self.root.clone(),
+            paths: self.paths.iter().cloned().map(PathEntry::Plain).collect(),
+            mode: String::new(),
+            dotfiles_directory: String::new(),
+            repo_subdir: String::new(),
+            layout: None,
+            strip_dots: None,
+            deploy_mode: None,
+            symlinks: Default::default(),
+            tags: Vec::new(),
+            hosts: Vec::new(),
+            os: Vec::new(),
+            when: String::new(),
+            enabled: true,
+            post_deploy_cmd: String::new(),
+            hook_when: Default::default(),
+            shell: String::new(),
+            depends_on: Vec::new(),
+            backup: None,
+            sync_direction: None,
+            flavor: Flavor::Unix,
+            registry: Vec::new(),
+            filter: PathFilter::default(),
+            source: String::new(),
+            layer: Default::default(),
+            repo_layout: Default::default(),
+            undotted: false,
+            deploy_with: Default::default(),
+            template_vars: Default::default(),
+        }
+    }
+
+    // the [[program]] table for the candidate, ready to be appended to rodeo.toml
+    pub fn to_toml(&self) -> String {
+        let paths: Vec<String> = self.paths.iter()
+            .map(|p| toml::Value::String(p.clone()).to_string())
+            .collect();
+        format!(
+            "[[program]]\nname = {}\nroot = {}\npaths = [{}]\n",
+            toml::Value::String(self.name.clone()),
+            toml::Value::String(self.root.clone()),
+            paths.join(", ")
+        )
+    }
+}
+
+// looks through the home directory for the well-known files in KNOWN. Files that one of programs
+// already manages are left out, as are programs whose name is already taken in the config.
+pub fn scan(programs: &[Program], home_dir: &str, dotfiles_dir: &str) -> Vec<Candidate> {
+    let managed: Vec<String> = programs.iter()
+        .flat_map(|i| i.deploy_targets(home_dir, dotfiles_dir))
+        .map(|t| t.deployed_file)
+        .collect();
+
+    let mut candidates = Vec::new();
+    for (name, root, paths) in KNOWN {
+        if programs.iter().any(|i| &i.name == name) {
+            continue;
+        }
+
+        // work out where each file would be deployed to the same way every other command does
+        let known = Candidate {
+            name: name.to_string(),
+            root: root.to_string(),
+            paths: paths.iter().map(|p| p.to_string()).collect(),
+        };
+        let found: Vec<String> = known.to_program()
+            .deploy_targets(home_dir, dotfiles_dir)
+            .into_iter()
+            .filter(|t| Path::new(&t.deployed_file).is_file() && !managed.contains(&t.deployed_file))
+            .map(|t| t.path)
+            .collect();
+
+        if !found.is_empty() {
+            candidates.push(Candidate { paths: found, ..known });
+        }
+    }
+
+    candidates
+}
@@ -0,0 +1,176 @@
+use toml_edit::{DocumentMut, Item, TableLike};
+
+// the format of config files, which changes from time to time as keys are renamed or tables are
+// restructured. A config says which version of the format it is written in with its version key;
+// one without it is taken to be version 1, the format from before there were versions. Older
+// configs are upgraded as they are read, so they keep working, and `rodeo migrate` rewrites them
+// in the current format.
+
+pub const CURRENT: i64 = 1;
+
+// a key that moved, as dotted paths from the top of the config, e.g. from "shell" to
+// "defaults.shell". Paths that start out through the same array of tables, such as
+// "program.post_deploy_cmd" and "program.post_deploy", move the key within each of its tables.
+pub struct Move {
+    pub from: &'static str,
+    pub to: &'static str,
+}
+
+// what changed in a version of the format since the one before it
+pub struct Migration {
+    pub version: i64,
+    pub moves: &'static [Move],
+}
+
+// every change to the format since version 1, oldest first
+pub const MIGRATIONS: &[Migration] = &[];
+
+// the version of the format config is written in
+pub fn version_of(config: &toml::Value) -> Result<i64, String> {
+    match config.get("version") {
+        None => Ok(1),
+        Some(toml::Value::Integer(val)) if (1..=CURRENT).contains(val) => Ok(*val),
+        Some(toml::Value::Integer(val)) if *val > CURRENT => Err(format!(
+            "this config is written in version {} of the config format, but rodeo {} only knows versions up to {}; upgrade rodeo to use it",
+            val,
+            crate::version::VERSION,
+            CURRENT
+        )),
+        Some(_) => Err(format!("version must be a whole number from 1 to {}", CURRENT)),
+    }
+}
+
+// moves config's keys to where the current format has them, returning the version it was written in
+pub fn upgrade(config: &mut toml::Value) -> Result<i64, String> {
+    let written_in = version_of(config)?;
+    let table = config.as_table_mut().ok_or("the config file must be a table")?;
+    for change in pending(written_in) {
+        move_value(table, &split(change.from), &split(change.to));
+    }
+    table.insert("version".to_owned(), toml::Value::Integer(CURRENT));
+    Ok(written_in)
+}
+
+// the TOML text of a config file written in version written_in of the format, rewritten in the
+// current one, keeping its comments and formatting. Only the file holding the version key is given
+// one, as included files go by the version of the config that includes them.
+pub fn upgrade_toml(contents: &str, written_in: i64, stamp: bool) -> Result<String, String> {
+    let mut document: DocumentMut = contents.parse().map_err(|e| format!("could not parse the config: {}", e))?;
+    for change in pending(written_in) {
+        move_item(document.as_table_mut(), &split(change.from), &split(change.to));
+    }
+    if stamp {
+        document.insert("version", toml_edit::value(CURRENT));
+    }
+    Ok(document.to_string())
+}
+
+fn pending(written_in: i64) -> impl Iterator<Item = &'static Move> {
+    MIGRATIONS.iter().filter(move |m| m.version > written_in).flat_map(|m| m.moves.iter())
+}
+
+fn split(path: &str) -> Vec<&str> {
+    path.split('.').collect()
+}
+
+fn move_value(table: &mut toml::value::Table, from: &[&str], to: &[&str]) {
+    match (from, to) {
+        ([first, from_rest @ ..], [other, to_rest @ ..]) if first == other && !from_rest.is_empty() && !to_rest.is_empty() => {
+            match table.get_mut(*first) {
+                Some(toml::Value::Table(inner)) => move_value(inner, from_rest, to_rest),
+                Some(toml::Value::Array(items)) => {
+                    for inner in items.iter_mut().filter_map(|i| i.as_table_mut()) {
+                        move_value(inner, from_rest, to_rest);
+                    }
+                }
+                _ => (),
+            }
+        }
+        _ => {
+            if let Some(value) = take_value(table, from) {
+                put_value(table, to, value);
+            }
+        }
+    }
+}
+
+fn take_value(table: &mut toml::value::Table, path: &[&str]) -> Option<toml::Value> {
+    match path {
+        [key] => table.remove(*key),
+        [key, rest @ ..] => take_value(table.get_mut(*key)?.as_table_mut()?, rest),
+        [] => None,
+    }
+}
+
+// a key that is already where the value would go is left as it is
+fn put_value(table: &mut toml::value::Table, path: &[&str], value: toml::Value) {
+    match path {
+        [key] => {
+            table.entry(*key).or_insert(value);
+        }
+        [key, rest @ ..] => {
+            if let Some(inner) = table.entry(*key).or_insert_with(|| toml::Value::Table(Default::default())).as_table_mut() {
+                put_value(inner, rest, value);
+            }
+        }
+        [] => (),
+    }
+}
+
+// move_value for a document being edited
+fn move_item(table: &mut dyn TableLike, from: &[&str], to: &[&str]) {
+    match (from, to) {
+        ([first, from_rest @ ..], [other, to_rest @ ..]) if first == other && !from_rest.is_empty() && !to_rest.is_empty() => {
+            match table.get_mut(first) {
+                Some(Item::ArrayOfTables(items)) => {
+                    for inner in items.iter_mut() {
+                        move_item(inner, from_rest, to_rest);
+                    }
+                }
+                Some(Item::Value(toml_edit::Value::Array(items))) => {
+                    for inner in items.iter_mut().filter_map(|i| i.as_inline_table_mut()) {
+                        move_item(inner, from_rest, to_rest);
+                    }
+                }
+                Some(item) => {
+                    if let Some(inner) = item.as_table_like_mut() {
+                        move_item(inner, from_rest, to_rest);
+                    }
+                }
+                None => (),
+            }
+        }
+        _ => {
+            if let Some(item) = take_item(table, from) {
+                put_item(table, to, item);
+            }
+        }
+    }
+}
+
+fn take_item(table: &mut dyn TableLike, path: &[&str]) -> Option<Item> {
+    match path {
+        [key] => table.remove(key),
+        [key, rest @ ..] => take_item(table.get_mut(key)?.as_table_like_mut()?, rest),
+        [] => None,
+    }
+}
+
+fn put_item(table: &mut dyn TableLike, path: &[&str], item: Item) {
+    match path {
+        [key] => {
+            if !table.contains_key(key) {
+                table.insert(key, item);
+            }
+        }
+        [key, rest @ ..] => {
+            if !table.contains_key(key) {
+                table.insert(key, Item::Table(toml_edit::Table::new()));
+            }
+            if let Some(inner) = table.get_mut(key).and_then(|i| i.as_table_like_mut()) {
+                put_item(inner, rest, item);
+            }
+        }
+        [] => (),
+    }
+}
@@ -0,0 +1,54 @@
+use std::fs;
+use serde::Serialize;
+use crate::Program;
+
+// a line in one of the repository's managed files that contains the searched-for text
+#[derive(Clone, Debug, Serialize)]
+pub struct Match {
+    pub program: String,
+    pub path: String,
+    // 1-based
+    pub line: usize,
+    pub text: String,
+}
+
+impl Match {
+    pub fn to_text(&self) -> String {
+        format!("{}: {}:{}: {}", self.program, self.path, self.line, self.text)
+    }
+}
+
+// searches the repository copies of the programs' files for lines containing pattern. Files that
+// can't be read as text (missing, binary, ...) are skipped.
+pub fn grep(programs: &[Program], home_dir: &str, dotfiles_dir: &str, pattern: &str, ignore_case: bool) -> Vec<Match> {
+    let pattern = if ignore_case { pattern.to_lowercase() } else { pattern.to_owned() };
+    let mut matches = Vec::new();
+
+    for program in programs {
+        for target in program.deploy_targets(home_dir, dotfiles_dir) {
+            let repo_file = target.repo_file;
+            let contents = match fs::read_to_string(&repo_file) {
+                Ok(val) => val,
+                Err(_) => continue,
+            };
+
+            for (i, line) in contents.lines().enumerate() {
+                let found = if ignore_case {
+                    line.to_lowercase().contains(&pattern)
+                } else {
+                    line.contains(&pattern)
+                };
+                if found {
+                    matches.push(Match {
+                        program: program.name.clone(),
+                        path: repo_file.clone(),
+                        line: i + 1,
+                        text: line.to_owned(),
+                    });
+                }
+            }
+        }
+    }
+
+    matches
+}
@@ -0,0 +1,38 @@
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// where rodeo keeps the things it needs to remember between runs (backups and the like). This is
+// $XDG_DATA_HOME/rodeo if set, ~/.local/share/rodeo otherwise.
+pub fn state_dir(home: &str) -> String {
+    match env::var("XDG_DATA_HOME") {
+        Ok(val) if !val.is_empty() => format!("{}/rodeo", val.trim_end_matches('/')),
+        _ => format!("{}/.local/share/rodeo", home.trim_end_matches('/')),
+    }
+}
+
+// seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+// formats seconds since the unix epoch as "YYYY-MM-DD HH:MM:SS" (UTC)
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+
+    // civil-from-days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year, month, day, time / 3600, (time % 3600) / 60, time % 60
+    )
+}
@@ -0,0 +1,137 @@
+use crate::event::{Event, EventKind, EventResult, Observer};
+
+// process exit codes used by the rodeo binary. Anything that went wrong with a file (copying,
+// syncing, running a hook) is a sync error, anything that went wrong talking to the remote is a
+// vcs error. If both happen, the vcs error wins since it usually means the remote is now out of
+// date.
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_SYNC_ERROR: i32 = 1;
+pub const EXIT_CONFIG_ERROR: i32 = 2;
+pub const EXIT_VCS_ERROR: i32 = 3;
+
+// file events about rodeo's own state rather than a managed file, which aren't counted as updates
+const BOOKKEEPING: [&str; 5] = ["backup", "generation", "journal", "manifest", "trash"];
+
+// tally of what happened during a Settings operation, returned so that callers can tell whether
+// everything went through without having to parse output.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    // managed files written, linked or removed; what the tally calls "updated"
+    pub succeeded: usize,
+    // everything else that went through: backups, hooks and vcs steps such as a commit or push
+    pub steps: usize,
+    pub unchanged: usize,
+    pub skipped: usize,
+    pub sync_errors: usize,
+    pub vcs_errors: usize,
+}
+
+impl Summary {
+    // counts a single event
+    pub fn add(&mut self, event: &Event) {
+        match (event.result, event.kind) {
+            (EventResult::Ok, EventKind::File) if !BOOKKEEPING.contains(&&event.action[..]) => self.succeeded += 1,
+            (EventResult::Ok, _) => self.steps += 1,
+            (EventResult::Unchanged, _) => self.unchanged += 1,
+            (EventResult::Skipped, _) => self.skipped += 1,
+            (EventResult::Error, EventKind::Vcs) => self.vcs_errors += 1,
+            (EventResult::Error, _) => self.sync_errors += 1,
+        }
+    }
+
+    // counts the event, then hands it to the observer
+    pub fn record(&mut self, event: Event, observer: &mut dyn Observer) {
+        self.add(&event);
+        observer.event(&event);
+    }
+
+    // folds another summary into this one
+    pub fn merge(&mut self, other: Summary) {
+        self.succeeded += other.succeeded;
+        self.steps += other.steps;
+        self.unchanged += other.unchanged;
+        self.skipped += other.skipped;
+        self.sync_errors += other.sync_errors;
+        self.vcs_errors += other.vcs_errors;
+    }
+
+    // one line tallying what happened, e.g. "3 updated, 42 unchanged, 1 error"; empty if nothing did
+    pub fn to_text(&self) -> String {
+        let errors = self.sync_errors + self.vcs_errors;
+        let counts = [
+            (self.succeeded, "updated", "updated"),
+            (self.unchanged, "unchanged", "unchanged"),
+            (self.skipped, "skipped", "skipped"),
+            (errors, "error", "errors"),
+        ];
+        counts.iter()
+            .filter(|(n, _, _)| *n > 0)
+            .map(|(n, one, many)| format!("{} {}", n, if *n == 1 { one } else { many }))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.sync_errors > 0 || self.vcs_errors > 0
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        if self.vcs_errors > 0 {
+            EXIT_VCS_ERROR
+        } else if self.sync_errors > 0 {
+            EXIT_SYNC_ERROR
+        } else {
+            EXIT_OK
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tally(events: Vec<Event>) -> Summary {
+        let mut summary = Summary::default();
+        for event in events.iter() {
+            summary.add(event);
+        }
+        summary
+    }
+
+    #[test]
+    fn counts_only_file_writes_as_updated() {
+        let summary = tally(vec![
+            Event::new(EventKind::File, "render", "repo/.a", "home/.a"),
+            Event::new(EventKind::File, "backup", "home/.a", "backups/1/.a"),
+            Event::new(EventKind::File, "backup", "home/.b", "backups/1/.b"),
+            Event::new(EventKind::File, "backup", "", "backups/1"),
+        ]);
+        assert_eq!(summary.succeeded, 1);
+        assert_eq!(summary.steps, 3);
+        assert_eq!(summary.to_text(), "1 updated");
+    }
+
+    #[test]
+    fn counts_vcs_steps_and_hooks_apart() {
+        let summary = tally(vec![
+            Event::new(EventKind::File, "copy", "home/.a", "repo/.a"),
+            Event::new(EventKind::Vcs, "commit", "repo", ""),
+            Event::new(EventKind::Vcs, "push", "repo", ""),
+            Event::new(EventKind::Hook, "post-deploy", "x", ""),
+            Event::new(EventKind::File, "copy", "repo/.b", "home/.b").unchanged(),
+        ]);
+        assert_eq!(summary.succeeded, 1);
+        assert_eq!(summary.steps, 3);
+        assert_eq!(summary.to_text(), "1 updated, 1 unchanged");
+    }
+
+    #[test]
+    fn counts_failed_steps_as_errors() {
+        let summary = tally(vec![
+            Event::new(EventKind::Vcs, "push", "repo", "").failed("rejected"),
+            Event::new(EventKind::File, "backup", "home/.a", "").failed("no space"),
+        ]);
+        assert_eq!((summary.succeeded, summary.steps, summary.vcs_errors, summary.sync_errors), (0, 0, 1, 1));
+        assert_eq!(summary.exit_code(), EXIT_VCS_ERROR);
+    }
+}
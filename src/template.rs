@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::env;
+use crate::config;
+
+// files whose entry in paths has `template = true` are rendered by deploy instead of being copied
+// as they are. In them
+//
+//   {{ name }}                 is replaced with the variable's value
+//   {{ if name == "value" }}   keeps what follows up to the matching {{ end }} only if the
+//                              variable has that value; != keeps it if it doesn't. A variable
+//                              that isn't set counts as "". {{ else }} starts what is kept
+//                              otherwise, and ifs can be nested
+//   {{ "text" }}               writes text as it is, e.g. {{ "{{" }}
+//
+// The variables are host, os, user and home, and those in [variables], which go over them. Using
+// a variable that isn't set outside of an if is an error, unless it is in a part that isn't kept.
+pub fn render(template: &str, variables: &BTreeMap<String, String>) -> Result<String, String> {
+    let mut rendered = String::new();
+    // for each if that is open: whether its condition held, and whether its else was reached
+    let mut blocks: Vec<(bool, bool)> = Vec::new();
+    let keeping = |blocks: &[(bool, bool)]| blocks.iter().all(|(held, in_else)| held != in_else);
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        if keeping(&blocks) {
+            rendered.push_str(&rest[..start]);
+        }
+        let line = template[..template.len() - rest.len() + start].matches('\n').count() + 1;
+        let after = &rest[start + 2..];
+        let end = match after.find("}}") {
+            Some(val) => val,
+            None => return Err(format!("line {}: \"{{{{\" without a \"}}}}\" closing it", line)),
+        };
+        let tag = after[..end].trim();
+        rest = &after[end + 2..];
+
+        if tag == "end" {
+            if blocks.pop().is_none() {
+                return Err(format!("line {}: {{{{ end }}}} without an {{{{ if }}}} before it", line));
+            }
+        } else if tag == "else" {
+            match blocks.last_mut() {
+                Some(block) if !block.1 => block.1 = true,
+                _ => return Err(format!("line {}: {{{{ else }}}} without an {{{{ if }}}} before it", line)),
+            }
+        } else if let Some(condition) = tag.strip_prefix("if ") {
+            let held = holds(condition, variables).map_err(|e| format!("line {}: {}", line, e))?;
+            blocks.push((held, false));
+        } else if !keeping(&blocks) {
+            continue;
+        } else if let Some(text) = quoted(tag) {
+            rendered.push_str(text);
+        } else {
+            match variables.get(tag) {
+                Some(val) => rendered.push_str(val),
+                None => return Err(format!("line {}: variable \"{}\" is not set; define it in [variables]", line, tag)),
+            }
+        }
+    }
+
+    if !blocks.is_empty() {
+        return Err("an {{ if }} has no {{ end }}".to_owned());
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+// the variables templates are rendered with on this machine, as host is known to rodeo (which
+// --host changes), before those in [variables]
+pub fn variables(host: &str, home: &str, config: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    let user = env::var("USER").or_else(|_| env::var("USERNAME")).unwrap_or_default();
+    let host = if host.is_empty() { config::detect_hostname() } else { host.to_owned() };
+    let mut variables: BTreeMap<String, String> = vec![
+        ("host".to_owned(), host),
+        ("os".to_owned(), env::consts::OS.to_owned()),
+        ("user".to_owned(), user),
+        ("home".to_owned(), home.to_owned()),
+    ].into_iter().collect();
+    variables.extend(config.iter().map(|(name, value)| (name.clone(), value.clone())));
+    variables
+}
+
+// whether `name == "value"` or `name != "value"` holds
+fn holds(condition: &str, variables: &BTreeMap<String, String>) -> Result<bool, String> {
+    let (name, equal, value) = match (condition.split_once("=="), condition.split_once("!=")) {
+        (Some((name, value)), _) => (name, true, value),
+        (None, Some((name, value))) => (name, false, value),
+        (None, None) => return Err(format!("\"{}\" is not a condition; use name == \"value\" or name != \"value\"", condition.trim())),
+    };
+    let value = value.trim();
+    let value = quoted(value).unwrap_or(value);
+    let actual = variables.get(name.trim()).map(|v| &v[..]).unwrap_or("");
+    Ok((actual == value) == equal)
+}
+
+// text between double quotes, if that is what tag is
+fn quoted(tag: &str) -> Option<&str> {
+    tag.strip_prefix('"')?.strip_suffix('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn replaces_variables_and_quoted_text() {
+        let variables = vars(&[("host", "laptop")]);
+        assert_eq!(render("host={{ host }}\n", &variables).as_deref(), Ok("host=laptop\n"));
+        assert_eq!(render("{{ \"{{\" }} host }}", &variables).as_deref(), Ok("{{ host }}"));
+    }
+
+    #[test]
+    fn keeps_the_branch_whose_condition_holds() {
+        let template = "{{ if os == \"linux\" }}tux{{ else }}other{{ end }}";
+        assert_eq!(render(template, &vars(&[("os", "linux")])).as_deref(), Ok("tux"));
+        assert_eq!(render(template, &vars(&[("os", "macos")])).as_deref(), Ok("other"));
+        assert_eq!(render("{{ if os != \"linux\" }}not tux{{ end }}", &vars(&[("os", "linux")])).as_deref(), Ok(""));
+    }
+
+    #[test]
+    fn nests_ifs() {
+        let template = "{{ if a == \"1\" }}a{{ if b == \"1\" }}b{{ else }}!b{{ end }}{{ else }}!a{{ if b == \"1\" }}b{{ end }}{{ end }}";
+        assert_eq!(render(template, &vars(&[("a", "1"), ("b", "1")])).as_deref(), Ok("ab"));
+        assert_eq!(render(template, &vars(&[("a", "1"), ("b", "0")])).as_deref(), Ok("a!b"));
+        assert_eq!(render(template, &vars(&[("a", "0"), ("b", "1")])).as_deref(), Ok("!ab"));
+        assert_eq!(render(template, &vars(&[("a", "0"), ("b", "0")])).as_deref(), Ok("!a"));
+    }
+
+    #[test]
+    fn an_unset_variable_in_a_condition_counts_as_empty() {
+        assert_eq!(render("{{ if missing == \"\" }}empty{{ end }}", &vars(&[])).as_deref(), Ok("empty"));
+    }
+
+    #[test]
+    fn reports_unknown_variables_with_their_line() {
+        let error = render("first\n{{ missing }}\n", &vars(&[])).unwrap_err();
+        assert!(error.starts_with("line 2:"), "{}", error);
+        assert!(error.contains("\"missing\""), "{}", error);
+    }
+
+    #[test]
+    fn ignores_unknown_variables_in_parts_that_are_not_kept() {
+        let template = "{{ if os == \"windows\" }}{{ missing }}{{ end }}ok";
+        assert_eq!(render(template, &vars(&[("os", "linux")])).as_deref(), Ok("ok"));
+    }
+
+    #[test]
+    fn rejects_unbalanced_blocks() {
+        let variables = vars(&[("os", "linux")]);
+        assert!(render("{{ if os == \"linux\" }}open", &variables).is_err());
+        assert!(render("{{ end }}", &variables).is_err());
+        assert!(render("{{ else }}", &variables).is_err());
+        assert!(render("{{ if os == \"linux\" }}{{ else }}{{ else }}{{ end }}", &variables).is_err());
+        assert!(render("{{ os", &variables).is_err());
+        assert!(render("{{ if os }}{{ end }}", &variables).is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use std::env;
+use std::io::{self, IsTerminal};
+
+// what the terminal rodeo's text output goes to can display. Colors are only used when stdout is a
+// terminal that isn't "dumb" and NO_COLOR isn't set; unicode (e.g. the box drawing in `rodeo tree`)
+// only when the locale is UTF-8. Pipes, CI logs and --plain get neither, so they never end up
+// with escape codes or mangled characters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Terminal {
+    pub color: bool,
+    pub unicode: bool,
+    // number of columns, if known; None when writing to a pipe or file
+    pub width: Option<usize>,
+    // number of lines, likewise
+    pub height: Option<usize>,
+}
+
+// how a piece of text is highlighted when colors are available
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Style {
+    Ok,
+    Warning,
+    Error,
+    Dim,
+}
+
+impl Default for Terminal {
+    fn default() -> Self {
+        Terminal::plain()
+    }
+}
+
+impl Terminal {
+    // works out what stdout can display from the environment
+    pub fn detect() -> Terminal {
+        let interactive = io::stdout().is_terminal();
+        let term = env::var("TERM").unwrap_or_default();
+        // Windows consoles don't set TERM at all
+        let dumb = term == "dumb" || (term.is_empty() && !cfg!(windows));
+        let ci = env::var_os("CI").is_some();
+        let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+
+        Terminal {
+            color: interactive && !dumb && !ci && !no_color,
+            unicode: !dumb && (cfg!(windows) || utf8_locale()),
+            width: if interactive { env::var("COLUMNS").ok().and_then(|c| c.trim().parse().ok()) } else { None },
+            height: if interactive { env::var("LINES").ok().and_then(|c| c.trim().parse().ok()) } else { None },
+        }
+    }
+
+    // plain ASCII without colors, as asked for with --plain
+    pub fn plain() -> Terminal {
+        Terminal { color: false, unicode: false, width: None, height: None }
+    }
+
+    // text in the given style, or unchanged if colors aren't available
+    pub fn paint(&self, style: Style, text: &str) -> String {
+        if !self.color {
+            return text.to_owned();
+        }
+        let code = match style {
+            Style::Ok => "32",
+            Style::Warning => "33",
+            Style::Error => "31",
+            Style::Dim => "2",
+        };
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    }
+
+    // the prefixes used to draw a tree: (branch, last branch, continued, blank)
+    pub fn tree_glyphs(&self) -> (&'static str, &'static str, &'static str, &'static str) {
+        if self.unicode {
+            ("├── ", "└── ", "│   ", "    ")
+        } else {
+            ("|-- ", "`-- ", "|   ", "    ")
+        }
+    }
+}
+
+// whether the locale's character set is UTF-8, going by the variables that select it in order of
+// precedence
+fn utf8_locale() -> bool {
+    ["LC_ALL", "LC_CTYPE", "LANG"].iter()
+        .filter_map(|v| env::var(v).ok())
+        .find(|v| !v.is_empty())
+        .is_some_and(|v| {
+            let v = v.to_lowercase();
+            v.contains("utf-8") || v.contains("utf8")
+        })
+}
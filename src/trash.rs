@@ -0,0 +1,248 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::copy::Copier;
+use crate::event::{Event, EventKind};
+use crate::state;
+
+// where the files deploy, collect, sync, unlink, prune, rollback and revert overwrite or remove
+// go, chosen in rodeo.toml with e.g. `trash = "freedesktop"`. Rodeo (the default) keeps them in its
+// own state directory, freedesktop in the desktop's trash (~/.local/share/Trash), where file
+// managers can restore them too, and off throws them away.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrashKind {
+    #[default]
+    Rodeo,
+    Freedesktop,
+    Off,
+}
+
+impl TrashKind {
+    // what is there before a restore has to go somewhere, even with the trash turned off
+    fn or_rodeo(self) -> TrashKind {
+        if self == TrashKind::Off { TrashKind::Rodeo } else { self }
+    }
+}
+
+// how long trashed files are kept unless trash_days says otherwise
+pub const DEFAULT_DAYS: u64 = 30;
+
+// a file in the trash. Whichever trash holds its contents, rodeo keeps one of these for it as
+// <state_dir>/trash/info/<id>.json, so that only files it put there are listed and expired.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Item {
+    pub id: String,
+    // where the file was
+    pub original: String,
+    // seconds since the unix epoch
+    pub trashed: u64,
+    // what replaced or removed it: "deploy", "collect", ...
+    pub operation: String,
+    // where its contents are now
+    pub file: String,
+    pub size: u64,
+}
+
+impl Item {
+    pub fn to_text(&self) -> String {
+        format!("{}\t{}\t{}\t{}", self.id, state::format_timestamp(self.trashed), self.operation, self.original)
+    }
+}
+
+pub fn trash_dir(state_dir: &str) -> String {
+    format!("{}/trash", state_dir)
+}
+
+fn info_path(state_dir: &str, id: &str) -> String {
+    format!("{}/info/{}.json", trash_dir(state_dir), id)
+}
+
+// $XDG_DATA_HOME/Trash, or ~/.local/share/Trash
+fn freedesktop_dir(home_dir: &str) -> String {
+    match env::var("XDG_DATA_HOME") {
+        Ok(val) if !val.is_empty() => format!("{}/Trash", val.trim_end_matches('/')),
+        _ => format!("{}/.local/share/Trash", home_dir.trim_end_matches('/')),
+    }
+}
+
+// moves contents, a copy of what was at original before operation replaced or removed it, into
+// the trash of the given kind
+pub fn put(state_dir: &str, home_dir: &str, kind: TrashKind, contents: &str, original: &str, operation: &str) -> io::Result<Item> {
+    let trashed = state::now();
+    let size = fs::symlink_metadata(contents)?.len();
+    let stamp = state::format_timestamp(trashed).replace(' ', "T").replace(':', "-");
+    // several files are usually trashed in the same second
+    let mut id = stamp.clone();
+    let mut n = 1;
+    while Path::new(&info_path(state_dir, &id)).exists() {
+        n += 1;
+        id = format!("{}-{}", stamp, n);
+    }
+
+    let file = match kind {
+        TrashKind::Freedesktop => put_freedesktop(home_dir, contents, original, trashed)?,
+        _ => {
+            let file = format!("{}/files/{}", trash_dir(state_dir), id);
+            move_file(contents, &file)?;
+            file
+        }
+    };
+
+    let item = Item { id, original: original.to_owned(), trashed, operation: operation.to_owned(), file, size };
+    let info = serde_json::to_string_pretty(&item).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::create_dir_all(format!("{}/info", trash_dir(state_dir)))?;
+    fs::write(info_path(state_dir, &item.id), info)?;
+    Ok(item)
+}
+
+// moves contents into the desktop's trash as the trash specification has it: files/<name> with a
+// .trashinfo under info/ saying where it came from, created first so that the name is taken
+fn put_freedesktop(home_dir: &str, contents: &str, original: &str, trashed: u64) -> io::Result<String> {
+    let dir = freedesktop_dir(home_dir);
+    fs::create_dir_all(format!("{}/files", dir))?;
+    fs::create_dir_all(format!("{}/info", dir))?;
+    let base = Path::new(original).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| "file".to_owned());
+
+    let mut name = base.clone();
+    let mut n = 1;
+    let mut info = loop {
+        match OpenOptions::new().write(true).create_new(true).open(format!("{}/info/{}.trashinfo", dir, name)) {
+            Ok(val) => break val,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                n += 1;
+                name = format!("{}.{}", base, n);
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    // the specification asks for local time; this is UTC, which file managers show a few hours off
+    // at worst
+    let date = state::format_timestamp(trashed).replace(' ', "T");
+    io::Write::write_all(&mut info, format!("[Trash Info]\nPath={}\nDeletionDate={}\n", escape(original), date).as_bytes())?;
+
+    let file = format!("{}/files/{}", dir, name);
+    if let Err(e) = move_file(contents, &file) {
+        let _ = fs::remove_file(format!("{}/info/{}.trashinfo", dir, name));
+        return Err(e);
+    }
+    Ok(file)
+}
+
+// path with everything but unreserved characters and slashes percent-encoded, as Path= takes it
+fn escape(path: &str) -> String {
+    path.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// renames from to to, or copies it there and removes it if they're on different filesystems
+fn move_file(from: &str, to: &str) -> io::Result<()> {
+    if let Some(parent) = Path::new(to).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    Copier::default().copy(from, to)?;
+    fs::remove_file(from)
+}
+
+// every item in the trash, newest first. Items whose contents are gone, e.g. because the desktop's
+// trash was emptied, are forgotten.
+pub fn list(state_dir: &str) -> Vec<Item> {
+    let mut items: Vec<Item> = fs::read_dir(format!("{}/info", trash_dir(state_dir)))
+        .map(|entries| entries.flatten().map(|e| e.path()).collect())
+        .unwrap_or_else(|_| Vec::new())
+        .into_iter()
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .filter_map(|p| serde_json::from_str(&fs::read_to_string(p).ok()?).ok())
+        .collect();
+    items.retain(|item: &Item| {
+        let there = fs::symlink_metadata(&item.file).is_ok();
+        if !there {
+            let _ = fs::remove_file(info_path(state_dir, &item.id));
+        }
+        there
+    });
+    items.sort_by(|a, b| (b.trashed, &b.id).cmp(&(a.trashed, &a.id)));
+    items
+}
+
+// moves item's contents back to where they were, creating missing directories. Whatever is there
+// now is put in the trash first, so restoring can be undone too.
+pub fn restore(state_dir: &str, home_dir: &str, kind: TrashKind, item: &Item) -> Event {
+    let event = Event::new(EventKind::File, "restore", &item.file[..], &item.original[..]);
+    if fs::symlink_metadata(&item.original).is_ok() {
+        if let Err(e) = put(state_dir, home_dir, kind.or_rodeo(), &item.original, &item.original, "trash restore") {
+            return event.failed(format!("could not move \"{}\" out of the way: {}", item.original, e));
+        }
+    }
+    if let Err(e) = move_file(&item.file, &item.original) {
+        return event.failed(format!("could not restore \"{}\": {}", item.original, e));
+    }
+    forget(state_dir, item);
+    event
+}
+
+// removes item and its contents from the trash for good
+pub fn remove(state_dir: &str, item: &Item) -> Event {
+    let event = Event::new(EventKind::File, "remove", &item.file[..], "");
+    match fs::remove_file(&item.file) {
+        Ok(_) => {
+            forget(state_dir, item);
+            event
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            forget(state_dir, item);
+            event
+        }
+        Err(e) => event.failed(format!("could not remove \"{}\": {}", item.file, e)),
+    }
+}
+
+// drops rodeo's record of item, and its .trashinfo if it is in the desktop's trash
+fn forget(state_dir: &str, item: &Item) {
+    let path = Path::new(&item.file);
+    if !item.file.starts_with(&trash_dir(state_dir)) {
+        if let (Some(name), Some(trash)) = (path.file_name(), path.parent().and_then(Path::parent)) {
+            let _ = fs::remove_file(trash.join("info").join(format!("{}.trashinfo", name.to_string_lossy())));
+        }
+    }
+    let _ = fs::remove_file(info_path(state_dir, &item.id));
+}
+
+// removes the items trashed more than days ago (none if days is 0), then the oldest ones until
+// what is left takes up at most max_size bytes
+pub fn expire(state_dir: &str, days: u64, max_size: Option<u64>) -> Vec<Event> {
+    let now = state::now();
+    let mut events = Vec::new();
+    let mut kept = Vec::new();
+    for item in list(state_dir) {
+        if days > 0 && now.saturating_sub(item.trashed) > days * 86400 {
+            events.push(remove(state_dir, &item));
+        } else {
+            kept.push(item);
+        }
+    }
+
+    if let Some(max_size) = max_size {
+        let mut total: u64 = kept.iter().map(|i| i.size).sum();
+        // newest first, so the oldest are at the end
+        while total > max_size {
+            match kept.pop() {
+                Some(item) => {
+                    total -= item.size;
+                    events.push(remove(state_dir, &item));
+                }
+                None => break,
+            }
+        }
+    }
+    events
+}
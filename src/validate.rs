@@ -0,0 +1,515 @@
+use std::collections::BTreeMap;
+use std::fs;
+use crate::cli::{self, KeySpec};
+use crate::condition::Condition;
+use crate::copy;
+use crate::glob;
+use crate::link::DeployMode;
+use crate::mapping::{Direction, PathEntry, Side, Target};
+use crate::mode;
+use crate::order;
+use crate::Settings;
+
+// something wrong with the config, and where it is
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    pub file: String,
+    // 1-based, if the offending line could be found
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Problem {
+    pub fn to_text(&self) -> String {
+        match self.line {
+            Some(line) => format!("{}:{}: {}", self.file, line, self.message),
+            None => format!("{}: {}", self.file, self.message),
+        }
+    }
+}
+
+// every problem on its own line, in the order they appear in the files
+pub fn to_text(problems: &[Problem]) -> String {
+    let mut problems = problems.to_vec();
+    problems.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    let lines: Vec<String> = problems.iter().map(|p| p.to_text()).collect();
+    match lines.len() {
+        1 => lines[0].clone(),
+        n => format!("{} problems:\n{}", n, lines.join("\n")),
+    }
+}
+
+// checks the merged config for unknown keys, values of the wrong type and missing keys before it is
+// deserialized, using the keys documented in cli::CONFIG_KEYS. files holds the contents of every
+// config file read and sources the file each program came from, as returned by merge_includes.
+pub fn check_keys(config: &toml::Value, config_path: &str, sources: &[String], files: &BTreeMap<String, String>) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let table = match config.as_table() {
+        Some(val) => val,
+        None => return problems,
+    };
+    let location = Location { file: config_path, contents: contents(files, config_path), start: 0 };
+    check_table(table, "", &["program", "group", "profile", "defaults"], &location, "", &mut problems);
+
+    match table.get("defaults").map(|d| d.as_table()) {
+        None => (),
+        Some(None) => problems.push(location.problem("defaults", "defaults must be a table".to_owned())),
+        Some(Some(defaults)) => {
+            let start = location.contents.lines().position(|l| l.trim() == "[defaults]" || defines(l, "defaults")).unwrap_or(0);
+            let location = Location { start, ..location };
+            check_table(defaults, "defaults", &[], &location, "defaults", &mut problems);
+        }
+    }
+
+    match table.get("program").map(|p| p.as_array()) {
+        None => (),
+        Some(None) => problems.push(location.problem("program", "program must be a list of [[program]] tables".to_owned())),
+        Some(Some(programs)) => {
+            for (i, program) in programs.iter().enumerate() {
+                let name = program.get("name").and_then(|n| n.as_str()).unwrap_or("");
+                let location = program_location(files, config_path, sources, i, name);
+                let what = if name.is_empty() { format!("program #{}", i + 1) } else { format!("program \"{}\"", name) };
+                match program.as_table() {
+                    Some(program) => check_table(program, "program", &[], &location, &what, &mut problems),
+                    None => problems.push(location.problem("", format!("{} must be a table", what))),
+                }
+            }
+        }
+    }
+
+    match table.get("group").map(|g| g.as_table()) {
+        None => (),
+        Some(None) => problems.push(location.problem("group", "group must be a table of [group.<name>] tables".to_owned())),
+        Some(Some(groups)) => {
+            for (name, group) in groups {
+                let location = named_location(files, config_path, "group", name);
+                let what = format!("group \"{}\"", name);
+                match group.as_table() {
+                    Some(group) => check_table(group, "group.<name>", &[], &location, &what, &mut problems),
+                    None => problems.push(location.problem("", format!("{} must be a table", what))),
+                }
+            }
+        }
+    }
+
+    match table.get("profile").map(|p| p.as_table()) {
+        None => (),
+        Some(None) => problems.push(location.problem("profile", "profile must be a table of [profile.<name>] tables".to_owned())),
+        Some(Some(profiles)) => {
+            for (name, profile) in profiles {
+                let location = named_location(files, config_path, "profile", name);
+                let what = format!("profile \"{}\"", name);
+                match profile.as_table() {
+                    Some(profile) => check_table(profile, "profile.<name>", &[], &location, &what, &mut problems),
+                    None => problems.push(location.problem("", format!("{} must be a table", what))),
+                }
+            }
+        }
+    }
+
+    problems
+}
+
+// checks that make sense once the config has been read: unique program names, programs with files
+// to manage, valid when conditions, and groups and profiles whose programs and tags exist
+pub fn check_settings(settings: &Settings, config_path: &str, files: &BTreeMap<String, String>) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let sources: Vec<String> = settings.programs.iter()
+        .map(|p| if p.source.is_empty() { config_path.to_owned() } else { p.source.clone() })
+        .collect();
+
+    if !settings.limit_rate.is_empty() {
+        if let Err(e) = copy::parse_rate(&settings.limit_rate) {
+            let location = Location { file: config_path, contents: contents(files, config_path), start: 0 };
+            problems.push(location.problem("limit_rate", format!("limit_rate: {}", e)));
+        }
+    }
+
+    if !settings.trash_size.is_empty() && copy::parse_rate(&settings.trash_size).is_err() {
+        let location = Location { file: config_path, contents: contents(files, config_path), start: 0 };
+        problems.push(location.problem("trash_size", format!("trash_size: \"{}\" is not a size; use a number of bytes like \"500M\" or \"2G\"", settings.trash_size)));
+    }
+
+    if settings.jobs == Some(0) {
+        let location = Location { file: config_path, contents: contents(files, config_path), start: 0 };
+        problems.push(location.problem("jobs", "jobs: has to be at least 1".to_owned()));
+    }
+
+    if settings.clone_depth == Some(0) {
+        let location = Location { file: config_path, contents: contents(files, config_path), start: 0 };
+        problems.push(location.problem("clone_depth", "clone_depth: has to be at least 1; leave it out to clone all of the history".to_owned()));
+    }
+
+    if !settings.umask.is_empty() {
+        if let Err(e) = mode::parse(&settings.umask) {
+            let location = Location { file: config_path, contents: contents(files, config_path), start: 0 };
+            problems.push(location.problem("umask", format!("umask: {}", e)));
+        }
+    }
+
+    // ${name} could refer to almost any name, but $name only to these
+    let start = contents(files, config_path).lines().position(|l| l.trim() == "[variables]").unwrap_or(0);
+    let location = Location { file: config_path, contents: contents(files, config_path), start };
+    for name in settings.variables.keys().filter(|n| n.is_empty() || !n.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')) {
+        problems.push(location.problem(name, format!("variable \"{}\" must be named with letters, digits and underscores only", name)));
+    }
+
+    for (i, program) in settings.programs.iter().enumerate() {
+        let location = program_location(files, config_path, &sources, i, &program.name);
+        let what = format!("program \"{}\"", program.name);
+
+        if program.name.trim().is_empty() {
+            problems.push(location.problem("name", format!("program #{} has an empty name", i + 1)));
+        } else if let Some(first) = settings.programs[..i].iter().position(|p| p.name == program.name) {
+            let first = program_location(files, config_path, &sources, first, &program.name);
+            problems.push(location.problem("name", format!("{} is already defined at {}", what, first.describe("name"))));
+        }
+        if program.paths.is_empty() {
+            problems.push(location.problem("paths", format!("{} has no paths to manage", what)));
+        }
+        for entry in program.paths.iter().filter(|p| matches!(p, PathEntry::Table { .. })) {
+            if entry.src().is_empty() {
+                problems.push(location.problem("paths", format!("{} has a path with an empty src", what)));
+            } else if entry.is_renamed() && [entry.src(), entry.dest()].iter().any(|p| glob::Pattern::new(p).is_glob()) {
+                problems.push(location.problem("paths", format!("{} has a renamed path \"{}\", which can't be a glob pattern", what, entry)));
+            }
+            if !entry.mode().is_empty() {
+                if let Err(e) = mode::parse(entry.mode()) {
+                    problems.push(location.problem("paths", format!("{}: path \"{}\": {}", what, entry, e)));
+                }
+            }
+            if let PathEntry::Table { direction, .. } = entry {
+                if Direction::from_name(direction).is_none() {
+                    problems.push(location.problem("paths", format!(
+                        "{}: path \"{}\" has direction \"{}\"; use \"deploy_only\", \"collect_only\" or \"bidirectional\"",
+                        what, entry, direction
+                    )));
+                }
+            }
+        }
+        if !program.mode.is_empty() {
+            if let Err(e) = mode::parse(&program.mode) {
+                problems.push(location.problem("mode", format!("{}: {}", what, e)));
+            }
+        }
+        if !program.when.is_empty() {
+            if let Err(e) = Condition::parse(&program.when) {
+                problems.push(location.problem("when", format!("{} has an invalid when condition \"{}\": {}", what, program.when, e)));
+            }
+        }
+        for dependency in program.depends_on.iter() {
+            if dependency == &program.name {
+                problems.push(location.problem("depends_on", format!("{} depends on itself", what)));
+            } else if !settings.programs.iter().any(|i| &i.name == dependency) {
+                let message = match suggest(dependency, settings.programs.iter().map(|p| &p.name[..])) {
+                    Some(val) => format!("{} depends on \"{}\", but there is no program with that name; did you mean \"{}\"?", what, dependency, val),
+                    None => format!("{} depends on \"{}\", but there is no program with that name", what, dependency),
+                };
+                problems.push(location.problem("depends_on", message));
+            }
+        }
+    }
+
+    if let Err(circle) = order::dependency_order(&settings.programs) {
+        let first = settings.programs.iter().position(|p| p.name == circle[0]).unwrap_or(0);
+        let location = program_location(files, config_path, &sources, first, &circle[0]);
+        problems.push(location.problem("depends_on", format!("programs depend on each other in a circle: {}", circle.join(" -> "))));
+    }
+
+    for (name, group) in settings.groups.iter() {
+        let location = named_location(files, config_path, "group", name);
+        for member in group.programs.iter() {
+            if !settings.programs.iter().any(|i| &i.name == member) {
+                let message = match suggest(member, settings.programs.iter().map(|p| &p.name[..])) {
+                    Some(val) => format!("group \"{}\" lists \"{}\", but there is no program with that name; did you mean \"{}\"?", name, member, val),
+                    None => format!("group \"{}\" lists \"{}\", but there is no program with that name", name, member),
+                };
+                problems.push(location.problem("programs", message));
+            }
+        }
+    }
+
+    for (name, profile) in settings.profiles.iter() {
+        let location = named_location(files, config_path, "profile", name);
+        for member in profile.programs.iter() {
+            if !settings.programs.iter().any(|i| &i.name == member) {
+                let message = match suggest(member, settings.programs.iter().map(|p| &p.name[..])) {
+                    Some(val) => format!("profile \"{}\" lists \"{}\", but there is no program with that name; did you mean \"{}\"?", name, member, val),
+                    None => format!("profile \"{}\" lists \"{}\", but there is no program with that name", name, member),
+                };
+                problems.push(location.problem("programs", message));
+            }
+        }
+        for tag in profile.tags.iter() {
+            if !settings.programs.iter().any(|i| i.tags.contains(tag)) {
+                problems.push(location.problem("tags", format!("profile \"{}\" lists tag \"{}\", but no program carries it", name, tag)));
+            }
+        }
+    }
+
+    problems
+}
+
+// entries of the selected programs' paths that match no file, neither in the repository nor on this
+// system, and files rodeo has no permission to deploy, such as ones below /etc for a user other than
+// root, unless they can be copied through the escalation helper, and templates that can't be
+// rendered, such as ones using a variable that isn't set. These would otherwise only show up as
+// errors halfway through a deploy, after other files were written.
+pub fn check_paths(settings: &Settings) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut files: BTreeMap<String, String> = BTreeMap::new();
+
+    for program in settings.programs.iter() {
+        let dangling = program.dangling_paths(&settings.home, &settings.dotfiles_directory);
+        let escalated = settings.escalation().is_some() && program.deploy_with == DeployMode::Copy;
+        let targets: Vec<Target> = program.targets_on(&settings.home, &settings.dotfiles_directory, Side::Repo).into_iter()
+            .filter(|target| target.direction.deploys())
+            .collect();
+        let unwritable: Vec<&String> = targets.iter()
+            .filter(|_| !escalated)
+            .map(|target| &target.deployed_file)
+            .filter(|file| !mode::writable(file))
+            .collect();
+        let unrenderable: Vec<String> = targets.iter()
+            .filter(|target| target.template.is_some())
+            .filter_map(|target| target.deployed_contents().err())
+            .collect();
+        if dangling.is_empty() && unwritable.is_empty() && unrenderable.is_empty() {
+            continue;
+        }
+
+        let file = if program.source.is_empty() { &settings.config_path } else { &program.source };
+        let contents = files.entry(file.clone()).or_insert_with(|| fs::read_to_string(file).unwrap_or_default());
+        // only the selected programs are left, so they're found by name rather than position
+        let start = contents.lines()
+            .position(|l| defines(l, "name") && l.contains(&format!("\"{}\"", program.name)))
+            .unwrap_or(0);
+        let location = Location { file, contents, start };
+
+        for path in dangling {
+            problems.push(location.problem(
+                "paths",
+                format!(
+                    "program \"{}\": \"{}\" matches no file in {} or {}",
+                    program.name,
+                    path,
+                    program.repo_dir(&settings.home, &settings.dotfiles_directory),
+                    program.deployed_dir(&settings.home)
+                ),
+            ));
+        }
+        for file in unwritable {
+            problems.push(location.problem(
+                "paths",
+                format!("program \"{}\": no permission to write \"{}\"; deploy as a user who may, e.g. with sudo", program.name, file),
+            ));
+        }
+        for reason in unrenderable {
+            problems.push(location.problem("paths", format!("program \"{}\": {}", program.name, reason)));
+        }
+    }
+
+    problems
+}
+
+// where a table starts in one of the config files
+struct Location<'a> {
+    file: &'a str,
+    contents: &'a str,
+    // 0-based line the table starts on
+    start: usize,
+}
+
+impl<'a> Location<'a> {
+    // a problem with key in the table, or with the table as a whole if the key can't be found
+    fn problem(&self, key: &str, message: String) -> Problem {
+        Problem { file: self.file.to_owned(), line: Some(self.line(key)), message }
+    }
+
+    fn describe(&self, key: &str) -> String {
+        format!("{}:{}", self.file, self.line(key))
+    }
+
+    fn line(&self, key: &str) -> usize {
+        let found = if key.is_empty() {
+            None
+        } else {
+            // stop at the next TOML table so a key missing here isn't found in the table after it
+            self.contents.lines()
+                .enumerate()
+                .skip(self.start)
+                .take_while(|(i, l)| *i == self.start || !l.trim_start().starts_with('['))
+                .find(|(_, l)| defines(l, key))
+                .map(|(i, _)| i)
+        };
+        found.unwrap_or(self.start) + 1
+    }
+}
+
+fn check_table(
+    table: &toml::value::Table,
+    spec_table: &str,
+    tables: &[&str],
+    location: &Location,
+    what: &str,
+    problems: &mut Vec<Problem>,
+) {
+    let known: Vec<&KeySpec> = cli::CONFIG_KEYS.iter().filter(|k| k.table == spec_table).collect();
+    let in_what = |message: String| if what.is_empty() { message } else { format!("{}: {}", what, message) };
+    // keys offered as corrections, which aren't reported as missing on top of that
+    let mut suggested = Vec::new();
+
+    for (key, value) in table.iter() {
+        if tables.contains(&&key[..]) {
+            continue;
+        }
+        match known.iter().find(|k| k.key == key) {
+            Some(spec) if !has_kind(value, spec.kind) => problems.push(location.problem(
+                key,
+                in_what(format!("{} must be {}, not {}", key, article(spec.kind), article(value.type_str()))),
+            )),
+            Some(_) => (),
+            None => {
+                let message = match suggest(key, known.iter().map(|k| k.key).chain(tables.iter().copied())) {
+                    Some(val) => {
+                        suggested.push(val);
+                        format!("unknown key \"{}\"; did you mean \"{}\"?", key, val)
+                    }
+                    None => format!("unknown key \"{}\"", key),
+                };
+                problems.push(location.problem(key, in_what(message)));
+            }
+        }
+    }
+
+    for spec in known.iter().filter(|k| k.required && !table.contains_key(k.key) && !suggested.contains(&k.key)) {
+        let message = if what.is_empty() { format!("missing {}", spec.key) } else { format!("{} is missing {}", what, spec.key) };
+        problems.push(location.problem("", message));
+    }
+}
+
+// whether value is of the kind named in a KeySpec
+fn has_kind(value: &toml::Value, kind: &str) -> bool {
+    match kind {
+        "string" => value.is_str(),
+        "boolean" => value.is_bool(),
+        "integer" => value.is_integer(),
+        "array of strings" => value.as_array().is_some_and(|a| a.iter().all(|v| v.is_str())),
+        "array of strings and { src, dest, mode, direction, template } tables" => value.as_array().is_some_and(|a| {
+            a.iter().all(|v| match v.as_table() {
+                Some(t) => {
+                    t.get("src").is_some_and(|s| s.is_str())
+                        && t.iter().all(|(key, value)| match &key[..] {
+                            "src" | "dest" | "mode" | "direction" => value.is_str(),
+                            "template" => value.is_bool(),
+                            _ => false,
+                        })
+                }
+                None => v.is_str(),
+            })
+        }),
+        "table of strings" => value.as_table().is_some_and(|t| t.values().all(|v| v.is_str())),
+        _ => true,
+    }
+}
+
+// "a string", "an integer", ...
+fn article(kind: &str) -> String {
+    match kind.chars().next() {
+        Some('a') | Some('e') | Some('i') | Some('o') | Some('u') => format!("an {}", kind),
+        _ => format!("a {}", kind),
+    }
+}
+
+// the candidate closest to word, if any is close enough to be a likely typo or a shortened form
+fn suggest<'a>(word: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (word.len() / 3).max(1);
+    candidates
+        .map(|c| {
+            let shortened = word.len() >= 3 && (c.starts_with(word) || word.starts_with(c));
+            (if shortened { 1 } else { distance(word, c) }, c)
+        })
+        .filter(|(d, _)| *d <= limit)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+// edit distance between a and b, counting swapped neighbouring characters as a single edit
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+fn contents<'a>(files: &'a BTreeMap<String, String>, file: &str) -> &'a str {
+    files.get(file).map(|c| &c[..]).unwrap_or("")
+}
+
+// where the i-th program of the merged list is defined
+fn program_location<'a>(
+    files: &'a BTreeMap<String, String>,
+    config_path: &'a str,
+    sources: &'a [String],
+    i: usize,
+    name: &str,
+) -> Location<'a> {
+    let file = sources.get(i).map(|s| &s[..]).unwrap_or(config_path);
+    let contents = contents(files, file);
+    // which of the file's programs it is
+    let nth = sources[..i.min(sources.len())].iter().filter(|s| *s == file).count();
+
+    // TOML has a header per program; other formats are searched for the program's name instead,
+    // as are programs of a base config that were left out because the user's config overrides them
+    let names = |l: &str| defines(l, "name") && !name.is_empty() && l.contains(name);
+    let start = contents.lines()
+        .enumerate()
+        .filter(|(_, l)| l.trim() == "[[program]]")
+        .nth(nth)
+        .filter(|(i, _)| name.is_empty() || contents.lines().skip(i + 1).take_while(|l| !l.trim_start().starts_with('[')).any(names))
+        .or_else(|| contents.lines().enumerate().find(|(_, l)| names(l)))
+        .map_or(0, |(i, _)| i);
+    Location { file, contents, start }
+}
+
+// where [<table>.<name>], e.g. a group, is defined, looking through every file read
+fn named_location<'a>(files: &'a BTreeMap<String, String>, config_path: &'a str, table: &str, name: &str) -> Location<'a> {
+    let header = format!("[{}.{}]", table, name);
+    let found = files.iter()
+        .find_map(|(f, c)| c.lines().position(|l| l.trim() == header).map(|i| (f, c, i)))
+        .or_else(|| files.iter().find_map(|(f, c)| c.lines().position(|l| defines(l, name)).map(|i| (f, c, i))));
+
+    match found {
+        Some((file, contents, start)) => Location { file, contents, start },
+        None => Location { file: config_path, contents: contents(files, config_path), start: 0 },
+    }
+}
+
+// whether line sets key, in any of the config formats ("key = ...", "key: ...", "\"key\": ...",
+// "- key: ...")
+fn defines(line: &str, key: &str) -> bool {
+    let line = line.trim().trim_start_matches("- ").trim_start_matches(['"', '\'']);
+    match line.strip_prefix(key) {
+        Some(rest) => {
+            let rest = rest.trim_start_matches(['"', '\'']).trim_start();
+            rest.starts_with('=') || rest.starts_with(':')
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,695 @@
+use std::process::Command;
+use serde::{Deserialize, Serialize};
+use crate::audit::{self, AuditLog};
+use crate::event::{Event, EventKind, EventResult};
+use crate::plan::{InProgress, RepoStatus, Revision};
+use crate::state;
+
+// which backend keeps the dotfiles directory in sync with the outside world, chosen in rodeo.toml
+// with e.g. `vcs = "git"`. Git is the default for compatibility with older configs.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VcsKind {
+    #[default]
+    Git,
+    Rsync,
+    None,
+}
+
+// how git pulls when the remote's branch and the local one both have commits the other doesn't,
+// chosen in [defaults] with e.g. `pull_strategy = "rebase"`
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PullStrategy {
+    // a merge commit joining the two
+    Merge,
+    // the local commits replayed on top of the remote's
+    Rebase,
+    // neither; the pull fails unless it can fast-forward
+    FfOnly,
+}
+
+// the operations rodeo needs from whatever is synchronizing the dotfiles directory. Both methods
+// report what happened as an Event rather than printing, so the caller decides how to show it.
+pub trait Vcs {
+    // brings changes from the remote into the local dotfiles directory
+    fn pull(&self, dotfiles_dir: &str) -> Event;
+    // records the changes to files (relative to dotfiles_dir), and only those, and sends them to
+    // the remote. This doesn't pull first; callers that want that call pull themselves.
+    fn push(&self, dotfiles_dir: &str, files: &[String]) -> Event;
+    // records the changes to files in dotfiles_dir without sending them anywhere, for push_to
+    fn commit(&self, dotfiles_dir: &str, _files: &[String]) -> Event {
+        Event::new(EventKind::Vcs, "commit", dotfiles_dir, "")
+            .failed("pushing to several remotes needs vcs = \"git\"")
+    }
+    // sends the checked out branch to the branch of the same name on remote. Nothing is
+    // committed; see commit.
+    fn push_to(&self, dotfiles_dir: &str, remote: &str) -> Event {
+        Event::new(EventKind::Vcs, "push", dotfiles_dir, remote)
+            .failed("pushing to several remotes needs vcs = \"git\"")
+    }
+    // puts the uncommitted changes in dotfiles_dir aside, untracked files included, so that pull
+    // runs on a clean working tree
+    fn stash(&self, dotfiles_dir: &str) -> Event {
+        Event::new(EventKind::Vcs, "stash", dotfiles_dir, "")
+            .failed("stashing uncommitted changes needs vcs = \"git\"")
+    }
+    // brings back what stash put aside. If that conflicts with what was pulled in the meantime,
+    // the working tree is left as the pull left it and the changes stay stashed.
+    fn unstash(&self, dotfiles_dir: &str) -> Event {
+        Event::new(EventKind::Vcs, "unstash", dotfiles_dir, "")
+            .failed("stashing uncommitted changes needs vcs = \"git\"")
+    }
+    // checks out what pull would bring in at worktree_dir, without changing dotfiles_dir, so it can
+    // be reviewed first. Returns None if the backend can't do that.
+    fn fetch_incoming(&self, _dotfiles_dir: &str, _worktree_dir: &str) -> Option<Event> {
+        None
+    }
+    // has Git LFS store files (relative to dotfiles_dir) from now on, so that the next push sends
+    // them through it
+    fn track_binary(&self, dotfiles_dir: &str, files: &[String]) -> Event {
+        Event::new(EventKind::Vcs, "lfs-track", dotfiles_dir, "")
+            .with_output(files.join("\n"))
+            .failed("storing binary files through Git LFS needs vcs = \"git\"")
+    }
+    // how dotfiles_dir stands against its remote, as of the last fetch. None for backends that
+    // keep no history, or if dotfiles_dir isn't a repository.
+    fn repo_status(&self, _dotfiles_dir: &str) -> Option<RepoStatus> {
+        None
+    }
+    // the commits on the checked out branch that changed any of files (relative to dotfiles_dir),
+    // newest first
+    fn history(&self, _dotfiles_dir: &str, _files: &[String]) -> Result<Vec<Revision>, String> {
+        Err("browsing history needs vcs = \"git\"".to_owned())
+    }
+    // the contents file (relative to dotfiles_dir) had as of commit, which may be anything git
+    // takes for one, e.g. a hash or HEAD~2
+    fn show_file(&self, _dotfiles_dir: &str, _commit: &str, _file: &str) -> Result<Vec<u8>, String> {
+        Err("browsing history needs vcs = \"git\"".to_owned())
+    }
+    // the files in dotfiles_dir, relative to it, with changes that aren't committed. Empty for
+    // backends that don't commit.
+    fn changed_files(&self, _dotfiles_dir: &str) -> Vec<String> {
+        Vec::new()
+    }
+    // checks out branch in dotfiles_dir if another one (or none) is, so that pull and push go to
+    // it. None if it already is checked out.
+    fn switch_branch(&self, dotfiles_dir: &str, branch: &str) -> Option<Event> {
+        Some(Event::new(EventKind::Vcs, "checkout", dotfiles_dir, branch)
+            .failed("syncing a branch of its own needs vcs = \"git\""))
+    }
+    // cleans up after fetch_incoming
+    fn remove_incoming(&self, _dotfiles_dir: &str, _worktree_dir: &str) {}
+    // checks out the commits dotfiles_dir records for its submodules (and theirs), initializing
+    // any that are new. None if it has no submodules, or the backend doesn't know about them.
+    fn update_submodules(&self, _dotfiles_dir: &str) -> Option<Event> {
+        None
+    }
+    // clones remote into dotfiles_dir, checking out branch (or the remote's default branch if it's
+    // empty) along with any submodules. depth and filter (if not empty) limit how much of its
+    // history and file contents are fetched.
+    fn clone_repo(&self, remote: &str, _branch: &str, _depth: Option<u32>, _filter: &str, dotfiles_dir: &str) -> Event {
+        Event::new(EventKind::Vcs, "clone", remote, dotfiles_dir)
+            .failed("cloning remote needs vcs = \"git\"")
+    }
+    // applies a patch (such as one written by export-drift) to dotfiles_dir, committing it if
+    // commit is set. The event's output shows what changed.
+    fn apply_patch(&self, dotfiles_dir: &str, patch_file: &str, _commit: bool) -> Event {
+        Event::new(EventKind::Vcs, "apply", patch_file, dotfiles_dir)
+            .failed("applying patches needs vcs = \"git\"")
+    }
+}
+
+// builds the backend selected by the config. rsync_target is only used by the rsync backend, and
+// commit_message, signing (see Defaults::signing) and pull_strategy by git. rate_limit, in bytes a second, slows
+// down what either sends or receives.
+// Every command a backend runs is recorded in audit.
+pub fn from_kind(kind: VcsKind, rsync_target: &str, commit_message: &str, signing: Option<&str>, pull_strategy: Option<PullStrategy>, rate_limit: Option<u64>, audit: AuditLog) -> Box<dyn Vcs> {
+    match kind {
+        VcsKind::Git => git(Git { audit, commit_message: commit_message.to_owned(), signing: signing.map(str::to_owned), pull_strategy, rate_limit }),
+        VcsKind::Rsync => Box::new(Rsync { target: rsync_target.to_owned(), rate_limit, audit }),
+        VcsKind::None => Box::new(NoVcs),
+    }
+}
+
+// pulls and pushes go through libgit2, unless there is a rate limit, which only trickle can keep
+// git to
+#[cfg(all(feature = "libgit2", not(feature = "system-git")))]
+fn git(git: Git) -> Box<dyn Vcs> {
+    if git.rate_limit.is_some() {
+        Box::new(git)
+    } else {
+        Box::new(crate::libgit::LibGit { git })
+    }
+}
+
+#[cfg(not(all(feature = "libgit2", not(feature = "system-git"))))]
+fn git(git: Git) -> Box<dyn Vcs> {
+    Box::new(git)
+}
+
+// rate in the KiB a second trickle and rsync's --bwlimit take, never rounded down to 0, which
+// both take to mean unlimited
+fn kib(rate: u64) -> u64 {
+    (rate / 1024).max(1)
+}
+
+// runs the given string through bash, turning the result into an Event for the given action
+fn run_shell(audit: &AuditLog, action: &str, dotfiles_dir: &str, destination: &str, shell_command: String) -> Event {
+    let event = Event::new(EventKind::Vcs, action, dotfiles_dir, destination);
+    match audit.run("vcs", &shell_command) {
+        Ok(val) if val.status.success() => event.with_output(String::from_utf8_lossy(val.stdout.as_slice())),
+        Ok(val) => event
+            .with_output(String::from_utf8_lossy(val.stdout.as_slice()))
+            .failed(format!("{} failed ({})", action, audit::describe_failure(&val))),
+        Err(e) => event.failed(format!("could not run {}: {}", action, e)),
+    }
+}
+
+// why a pull that can't fast-forward failed
+pub const DIVERGED: &str = "the remote's branch and this one both have commits the other doesn't, so it can't be fast-forwarded; set pull_strategy in [defaults] to \"merge\" or \"rebase\", or settle it with git";
+
+// why uncommitted changes put aside before a pull couldn't be brought back, conflicting in files
+pub fn unstash_conflict(files: &[String]) -> String {
+    format!(
+        "the uncommitted changes put aside before pulling conflict with the pulled ones in {}; they are kept in git's stash, bring them back with git stash pop",
+        short_list(files, "files git couldn't merge")
+    )
+}
+
+pub struct Git {
+    pub audit: AuditLog,
+    // what push commits local changes with
+    pub commit_message: String,
+    // None if commits aren't signed, otherwise the key to sign them with, or empty for the one
+    // git's config names
+    pub signing: Option<String>,
+    // how pull brings in diverged commits; None leaves it to git's config
+    pub pull_strategy: Option<PullStrategy>,
+    // bytes a second pulls, pushes and clones are limited to
+    pub rate_limit: Option<u64>,
+}
+
+impl Git {
+    // git itself, or with a rate limit, git run through trickle, as git has no way of limiting
+    // its transfers
+    fn git(&self) -> String {
+        match self.rate_limit {
+            Some(rate) => format!("trickle -s -d {} -u {} git", kib(rate), kib(rate)),
+            None => "git".to_owned(),
+        }
+    }
+
+    // stages files, removals included, and commits them with commit_message if any of them
+    // changed. Naming them to git commit keeps whatever else is staged out of the commit. Having
+    // nothing to commit is fine, as earlier commits may still need pushing.
+    fn commit_command(&self, files: &[String]) -> String {
+        if files.is_empty() {
+            return "true".to_owned();
+        }
+        let files: Vec<String> = files.iter().map(|f| quote(f)).collect();
+        let files = files.join(" ");
+        format!(
+            "git add --all -- {} && (git diff --cached --quiet -- {} || git commit{} -m {} -- {})",
+            files,
+            files,
+            self.sign(),
+            quote(&self.commit_message),
+            files
+        )
+    }
+
+    // what makes commit and pull sign the commits they make, if they should
+    fn sign(&self) -> String {
+        match self.signing.as_deref() {
+            None => String::new(),
+            Some("") => " -S".to_owned(),
+            Some(key) => format!(" '-S{}'", key.replace('\'', "'\\''")),
+        }
+    }
+}
+
+impl Vcs for Git {
+    fn pull(&self, dotfiles_dir: &str) -> Event {
+        // a rebase that stops at a conflict is undone, so the repository isn't left half-way
+        // through one
+        let strategy = match self.pull_strategy {
+            None => "",
+            Some(PullStrategy::Merge) => " --no-rebase",
+            Some(PullStrategy::Rebase) => " --rebase",
+            Some(PullStrategy::FfOnly) => " --ff-only",
+        };
+        let mut command = format!("cd {} && {} pull{}{}", quote(dotfiles_dir), self.git(), strategy, self.sign());
+        if self.pull_strategy == Some(PullStrategy::Rebase) {
+            command = format!("{} || {{ git rebase --abort 2>/dev/null; false; }}", command);
+        }
+        let event = run_shell(&self.audit, "git-pull", dotfiles_dir, "", command);
+
+        // git's reasons for these are several lines of hints further down its output
+        let error = event.error.clone().unwrap_or_default();
+        if error.contains("Not possible to fast-forward") || error.contains("divergent branches") {
+            event.failed(DIVERGED)
+        } else if self.pull_strategy == Some(PullStrategy::Rebase) && event.output.as_deref().unwrap_or_default().contains("CONFLICT") {
+            event.failed("rebasing the local commits onto the remote's would conflict, so the rebase was undone; settle it with git pull --rebase")
+        } else {
+            event
+        }
+    }
+
+    fn push(&self, dotfiles_dir: &str, files: &[String]) -> Event {
+        // pulling, committing, and pushing are all done via bash commands - while this is
+        // admittedly not ideal, it has the advantage of being simple to write and simple to use,
+        // automatically respecting user's git configs and, more importantly, making it very simple
+        // to use features such as authentication via ssh. Additionally, it results in very
+        // graceful handling of failures to push/pull/commit/etc, respecting user's git configs and
+        // git's own internal logic and expectations.
+
+        // initialize the git command outside of the command build for legibility
+        let git_command = format!("cd {} && {} && {} push", quote(dotfiles_dir), self.commit_command(files), self.git());
+       // since i can't figure out how to put a comment between the lines of a multiline string,
+       // the explanation of the bash is as follows:
+       //   "cd {}" (where {} is replaced by dotfiles_dir) changes the working directory to the
+       //   local dotfiles repo
+       //
+       //   the commit command (see commit_command) stages the files rodeo manages and commits
+       //   them, and nothing else, if any of them changed
+       //
+       //   finally, "git push" is probably also pretty self explanatory, as it simply pushes the
+       //   changes to the remote repository (through trickle if there is a rate limit).
+
+        run_shell(&self.audit, "git-push", dotfiles_dir, "", git_command)
+    }
+
+    fn commit(&self, dotfiles_dir: &str, files: &[String]) -> Event {
+        run_shell(&self.audit, "git-commit", dotfiles_dir, "", format!("cd {} && {}", quote(dotfiles_dir), self.commit_command(files)))
+    }
+
+    fn stash(&self, dotfiles_dir: &str) -> Event {
+        run_shell(
+            &self.audit,
+            "git-stash",
+            dotfiles_dir,
+            "",
+            format!("cd {} && git stash push --include-untracked --message 'rodeo: before pulling' 2>&1", quote(dotfiles_dir)),
+        )
+    }
+
+    fn unstash(&self, dotfiles_dir: &str) -> Event {
+        // a pop that conflicts keeps the stash, and its conflict markers are cleared away again
+        // so that they aren't synced onto the system
+        let event = run_shell(
+            &self.audit,
+            "git-unstash",
+            dotfiles_dir,
+            "",
+            format!("cd {} && (git stash pop 2>&1 || {{ git reset --quiet --hard; false; }})", quote(dotfiles_dir)),
+        );
+        if event.result != EventResult::Error {
+            return event;
+        }
+        let conflicts: Vec<String> = event.output.as_deref().unwrap_or_default().lines()
+            .filter_map(|l| l.split_once("Merge conflict in ").map(|(_, file)| file.trim().to_owned()))
+            .collect();
+        event.failed(unstash_conflict(&conflicts))
+    }
+
+    fn push_to(&self, dotfiles_dir: &str, remote: &str) -> Event {
+        run_shell(
+            &self.audit,
+            "git-push",
+            dotfiles_dir,
+            remote,
+            format!("cd {} && {} push {} HEAD 2>&1", quote(dotfiles_dir), self.git(), quote(remote)),
+        )
+    }
+
+    fn fetch_incoming(&self, dotfiles_dir: &str, worktree_dir: &str) -> Option<Event> {
+        // a worktree left behind by an earlier run would make "worktree add" fail
+        self.remove_incoming(dotfiles_dir, worktree_dir);
+        Some(run_shell(
+            &self.audit,
+            "git-fetch",
+            dotfiles_dir,
+            worktree_dir,
+            format!(
+                "cd {} && {} fetch && git worktree add --force --detach {} \"@{{upstream}}\"",
+                quote(dotfiles_dir), self.git(), quote(worktree_dir)
+            ),
+        ))
+    }
+
+    fn clone_repo(&self, remote: &str, branch: &str, depth: Option<u32>, filter: &str, dotfiles_dir: &str) -> Event {
+        run_shell(
+            &self.audit,
+            "git-clone",
+            remote,
+            dotfiles_dir,
+            format!("{} clone{} {} {} 2>&1", self.git(), clone_options(branch, depth, filter), quote(remote), quote(dotfiles_dir)),
+        )
+    }
+
+    fn update_submodules(&self, dotfiles_dir: &str) -> Option<Event> {
+        if !std::path::Path::new(dotfiles_dir).join(".gitmodules").is_file() {
+            return None;
+        }
+        // sync first so that a submodule whose url changed in .gitmodules is fetched from the new one
+        Some(run_shell(
+            &self.audit,
+            "git-submodule",
+            dotfiles_dir,
+            "",
+            format!(
+                "cd {} && git submodule sync --recursive 2>&1 && {} submodule update --init --recursive 2>&1",
+                quote(dotfiles_dir), self.git()
+            ),
+        ))
+    }
+
+    fn apply_patch(&self, dotfiles_dir: &str, patch_file: &str, commit: bool) -> Event {
+        // git am keeps the patch's author and message; a failed am is rolled back so that it
+        // doesn't leave the repository mid-way through applying
+        let command = if commit {
+            format!(
+                "cd {} && (git am {} || (git am --abort; false)) && git show --stat --format=%s HEAD",
+                quote(dotfiles_dir), quote(patch_file)
+            )
+        } else {
+            format!("cd {} && git apply {} && git apply --stat {}", quote(dotfiles_dir), quote(patch_file), quote(patch_file))
+        };
+        run_shell(&self.audit, "git-apply", patch_file, dotfiles_dir, command)
+    }
+
+    fn history(&self, dotfiles_dir: &str, files: &[String]) -> Result<Vec<Revision>, String> {
+        // each commit starts with a record separator, its fields split by unit separators, and is
+        // followed by the names of the files it changed, one to a line
+        let output = Command::new("git")
+            .args(["--literal-pathspecs", "-c", "core.quotePath=false", "-C", dotfiles_dir, "log", "--name-only", "--format=%x1e%H%x1f%ct%x1f%an%x1f%s", "--"])
+            .args(files)
+            .output()
+            .map_err(|e| format!("could not run git: {}", e))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+        }
+        let output = String::from_utf8_lossy(&output.stdout);
+        Ok(output.split('\x1e').filter_map(|record| {
+            let mut lines = record.lines();
+            let fields: Vec<&str> = lines.next()?.split('\x1f').collect();
+            match fields[..] {
+                [commit, time, author, message] => Some(Revision {
+                    commit: commit.to_owned(),
+                    time: time.parse().unwrap_or_default(),
+                    author: author.to_owned(),
+                    message: message.to_owned(),
+                    files: lines.filter(|l| !l.is_empty()).map(str::to_owned).collect(),
+                }),
+                _ => None,
+            }
+        }).collect())
+    }
+
+    fn show_file(&self, dotfiles_dir: &str, commit: &str, file: &str) -> Result<Vec<u8>, String> {
+        let output = Command::new("git")
+            .args(["-C", dotfiles_dir, "show", &format!("{}:{}", commit, file)])
+            .output()
+            .map_err(|e| format!("could not run git: {}", e))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+        }
+        Ok(output.stdout)
+    }
+
+    fn changed_files(&self, dotfiles_dir: &str) -> Vec<String> {
+        let output = Command::new("git")
+            .args(["-C", dotfiles_dir, "status", "--porcelain", "-z", "--untracked-files=all", "--ignore-submodules"])
+            .output();
+        let output = match output {
+            Ok(val) if val.status.success() => val.stdout,
+            _ => return Vec::new(),
+        };
+        // entries are "XY path", and a rename is followed by the name it had as an entry of its own
+        let mut files = Vec::new();
+        let mut entries = output.split(|b| *b == 0).filter(|e| e.len() > 3);
+        while let Some(entry) = entries.next() {
+            files.push(String::from_utf8_lossy(&entry[3..]).into_owned());
+            if entry[0] == b'R' || entry[0] == b'C' {
+                entries.next();
+            }
+        }
+        files
+    }
+
+    fn switch_branch(&self, dotfiles_dir: &str, branch: &str) -> Option<Event> {
+        let current = Command::new("git")
+            .args(["-C", dotfiles_dir, "symbolic-ref", "--quiet", "--short", "HEAD"])
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned());
+        if current.as_deref() == Some(branch) {
+            return None;
+        }
+
+        // git checkout creates the branch from the remote's if there is no local one yet, which is
+        // why the remote's branches are fetched first, and refuses to switch if that would
+        // overwrite local changes
+        let event = run_shell(
+            &self.audit,
+            "git-checkout",
+            dotfiles_dir,
+            branch,
+            format!("cd {} && ({} fetch --quiet 2>&1; git checkout {} 2>&1)", quote(dotfiles_dir), self.git(), quote(branch)),
+        );
+        if event.result != EventResult::Error {
+            return Some(event);
+        }
+        let current = match current {
+            Some(val) => format!("branch \"{}\" is checked out", val),
+            None => "no branch is checked out".to_owned(),
+        };
+        Some(event.failed(format!("{} rather than \"{}\", which could not be checked out; not syncing", current, branch)))
+    }
+
+    fn repo_status(&self, dotfiles_dir: &str) -> Option<RepoStatus> {
+        let git = |args: &[&str]| Command::new("git")
+            .arg("-C")
+            .arg(dotfiles_dir)
+            .args(args)
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).into_owned());
+
+        // the branch and how it compares with its upstream are in the headers of the v2 format
+        let mut status = RepoStatus::default();
+        for line in git(&["status", "--porcelain=v2", "--branch", "--untracked-files=no"])?.lines() {
+            if let Some(head) = line.strip_prefix("# branch.head ") {
+                status.branch = Some(head.to_owned()).filter(|h| h != "(detached)");
+            } else if let Some(upstream) = line.strip_prefix("# branch.upstream ") {
+                status.upstream = Some(upstream.to_owned());
+            } else if let Some(counts) = line.strip_prefix("# branch.ab ") {
+                for count in counts.split_whitespace() {
+                    if let Some(ahead) = count.strip_prefix('+') {
+                        status.ahead = ahead.parse().unwrap_or_default();
+                    } else if let Some(behind) = count.strip_prefix('-') {
+                        status.behind = behind.parse().unwrap_or_default();
+                    }
+                }
+            }
+        }
+
+        // git notes what it was stopped in the middle of with files in the git directory
+        let git_dir = git(&["rev-parse", "--absolute-git-dir"])?;
+        let exists = |name: &str| std::path::Path::new(git_dir.trim()).join(name).exists();
+        status.in_progress = if exists("rebase-merge") || exists("rebase-apply") {
+            Some(InProgress::Rebase)
+        } else if exists("MERGE_HEAD") {
+            Some(InProgress::Merge)
+        } else if exists("CHERRY_PICK_HEAD") {
+            Some(InProgress::CherryPick)
+        } else if exists("REVERT_HEAD") {
+            Some(InProgress::Revert)
+        } else if exists("BISECT_LOG") {
+            Some(InProgress::Bisect)
+        } else {
+            None
+        };
+        status.uncommitted = self.changed_files(dotfiles_dir);
+        Some(status)
+    }
+
+    fn track_binary(&self, dotfiles_dir: &str, files: &[String]) -> Event {
+        // --filename takes the names as they are rather than as patterns. Files committed before
+        // are renormalized, so that the next commit turns them into LFS pointers; older commits
+        // keep them as they were (git lfs migrate rewrites those).
+        let quoted: Vec<String> = files.iter().map(|f| quote(f)).collect();
+        let quoted = quoted.join(" ");
+        run_shell(
+            &self.audit,
+            "lfs-track",
+            dotfiles_dir,
+            "",
+            format!(
+                "cd {} && git lfs track --filename -- {} && git add .gitattributes && git add --renormalize -- {} && git add -- {}",
+                quote(dotfiles_dir), quoted, quoted, quoted
+            ),
+        )
+    }
+
+    fn remove_incoming(&self, dotfiles_dir: &str, worktree_dir: &str) {
+        if std::path::Path::new(worktree_dir).exists() {
+            run_shell(
+                &self.audit,
+                "git-worktree-remove",
+                dotfiles_dir,
+                worktree_dir,
+                format!("cd {} && git worktree remove --force {}", quote(dotfiles_dir), quote(worktree_dir)),
+            );
+        }
+    }
+}
+
+// whether a .gitattributes file with the given contents has Git LFS store the file at relative.
+// Spaces are written as [[:space:]] there, as git lfs track does.
+pub fn lfs_tracked(attributes: &str, relative: &str) -> bool {
+    let escaped = relative.replace(' ', "[[:space:]]");
+    attributes.lines().any(|line| {
+        let mut words = line.split_whitespace();
+        words.next().map(|p| p.trim_start_matches('/')) == Some(&escaped[..]) && words.any(|w| w == "filter=lfs")
+    })
+}
+
+// fills in commit_message's placeholders: {programs} and {files} name the programs and the files
+// (relative to the repository) being committed, {count} says how many files there are, {hostname}
+// is the name of this machine and {date} the time in UTC. Long lists are cut short. Anything
+// else in braces is left as it is.
+pub fn commit_message(template: &str, host: &str, programs: &[String], files: &[String]) -> String {
+    template
+        .replace("{programs}", &short_list(programs, "no programs"))
+        .replace("{files}", &short_list(files, "no files"))
+        .replace("{count}", &files.len().to_string())
+        .replace("{hostname}", if host.is_empty() { "localhost" } else { host })
+        .replace("{date}", &state::format_timestamp(state::now()))
+}
+
+// text single-quoted for bash
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+// what git clone is given ahead of the remote to check out branch (the remote's default branch if
+// empty) with submodules, limited to depth commits and by filter if those are set
+pub fn clone_options(branch: &str, depth: Option<u32>, filter: &str) -> String {
+    let mut options = " --recurse-submodules".to_owned();
+    if !branch.is_empty() {
+        options.push_str(&format!(" --branch {}", quote(branch)));
+    }
+    // a shallow clone would otherwise only know the one branch, and couldn't switch to others
+    if let Some(depth) = depth {
+        options.push_str(&format!(" --depth {} --no-single-branch --shallow-submodules", depth));
+    }
+    if !filter.is_empty() {
+        options.push_str(&format!(" --filter {}", quote(filter)));
+    }
+    options
+}
+
+// items separated by commas, or the first four and how many more there are if there are over
+// five; none if there are no items
+pub fn short_list(items: &[String], none: &str) -> String {
+    match items.len() {
+        0 => none.to_owned(),
+        n if n <= 5 => items.join(", "),
+        n => format!("{} and {} more", items[..4].join(", "), n - 4),
+    }
+}
+
+// mirrors the dotfiles directory to and from a plain directory, which may be local or on another
+// machine (anything rsync accepts as a destination, e.g. "user@host:dotfiles/"). There is no
+// history or merging here: --update means whichever side has the newer copy of a file wins.
+pub struct Rsync {
+    pub target: String,
+    // bytes a second rsync is limited to
+    pub rate_limit: Option<u64>,
+    pub audit: AuditLog,
+}
+
+impl Rsync {
+    fn rsync(&self) -> String {
+        match self.rate_limit {
+            Some(rate) => format!("rsync -a --update --bwlimit={}", kib(rate)),
+            None => "rsync -a --update".to_owned(),
+        }
+    }
+
+    // the rsync command copying the contents of the directory from into the directory to; the
+    // trailing slashes make rsync copy the directories' contents rather than the directories
+    fn command(&self, from: &str, to: &str) -> String {
+        let slashed = |dir: &str| quote(&format!("{}/", dir.trim_end_matches('/')));
+        format!("{} {} {}", self.rsync(), slashed(from), slashed(to))
+    }
+}
+
+impl Vcs for Rsync {
+    fn pull(&self, dotfiles_dir: &str) -> Event {
+        if self.target.is_empty() {
+            return Event::new(EventKind::Vcs, "rsync-pull", dotfiles_dir, "")
+                .failed("vcs is \"rsync\" but no rsync_target is set in the config");
+        }
+
+        run_shell(&self.audit, "rsync-pull", dotfiles_dir, &self.target[..], self.command(&self.target, dotfiles_dir))
+    }
+
+    fn push(&self, dotfiles_dir: &str, _files: &[String]) -> Event {
+        if self.target.is_empty() {
+            return Event::new(EventKind::Vcs, "rsync-push", dotfiles_dir, "")
+                .failed("vcs is \"rsync\" but no rsync_target is set in the config");
+        }
+
+        run_shell(&self.audit, "rsync-push", dotfiles_dir, &self.target[..], self.command(dotfiles_dir, &self.target))
+    }
+}
+
+// for dotfiles directories that are synchronized by something outside of rodeo (syncthing, a
+// network share, ...) or not at all. Remote operations do nothing.
+pub struct NoVcs;
+
+impl Vcs for NoVcs {
+    fn pull(&self, dotfiles_dir: &str) -> Event {
+        Event::new(EventKind::Vcs, "pull", dotfiles_dir, "").skipped("vcs is \"none\", not pulling")
+    }
+
+    fn push(&self, dotfiles_dir: &str, _files: &[String]) -> Event {
+        Event::new(EventKind::Vcs, "push", dotfiles_dir, "").skipped("vcs is \"none\", not pushing")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    // the words bash splits a command line's arguments into, after quoting is undone
+    fn words(arguments: &str) -> Vec<String> {
+        let output = Command::new("bash").arg("-c").arg(format!("printf '%s\\n' {}", arguments)).output().unwrap();
+        String::from_utf8(output.stdout).unwrap().lines().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn quote_keeps_text_as_it_is() {
+        for text in ["plain", "with space", "$HOME", "`id`", "it's", "say \"hi\"", "a\\b", "$(touch x); rm -rf y"] {
+            assert_eq!(words(&quote(text)), vec![text.to_owned()]);
+        }
+    }
+
+    #[test]
+    fn rsync_quotes_both_directories() {
+        let rsync = Rsync { target: "host:my $dotfiles/".to_owned(), rate_limit: None, audit: AuditLog::new("/nonexistent") };
+        let pull = rsync.command(&rsync.target, "/home/me/dot files`id`");
+        let arguments = pull.strip_prefix("rsync -a --update ").unwrap();
+        assert_eq!(words(arguments), vec!["host:my $dotfiles/", "/home/me/dot files`id`/"]);
+
+        let push = rsync.command("/home/me/$dots", &rsync.target);
+        let arguments = push.strip_prefix("rsync -a --update ").unwrap();
+        assert_eq!(words(arguments), vec!["/home/me/$dots/", "host:my $dotfiles/"]);
+    }
+}
@@ -0,0 +1,74 @@
+// which versions of rodeo a config works with, from its requires_rodeo key, e.g. ">=1.2" or
+// ">=1.2, <2". A requirement is a comma separated list of comparisons, all of which must hold; a
+// version without an operator means at least that version. Versions are compared number by number,
+// with missing ones taken as 0, so "1.2" is the same as "1.2.0".
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    AtLeast,
+    Above,
+    AtMost,
+    Below,
+    Exactly,
+}
+
+type Version = [u64; 3];
+
+fn parse_version(text: &str) -> Option<Version> {
+    let mut version = [0; 3];
+    let parts: Vec<&str> = text.trim().split('.').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    for (i, part) in parts.iter().enumerate() {
+        version[i] = part.parse().ok()?;
+    }
+    Some(version)
+}
+
+fn parse(requirement: &str) -> Result<Vec<(Op, Version)>, String> {
+    let invalid = || format!("\"{}\" is not a version requirement; use something like \">=1.2\" or \">=1.2, <2\"", requirement);
+    let mut comparisons = Vec::new();
+    for part in requirement.split(',') {
+        let part = part.trim();
+        let (op, rest) = [(">=", Op::AtLeast), ("<=", Op::AtMost), (">", Op::Above), ("<", Op::Below), ("=", Op::Exactly)].iter()
+            .find_map(|(prefix, op)| part.strip_prefix(prefix).map(|rest| (*op, rest)))
+            .unwrap_or((Op::AtLeast, part));
+        comparisons.push((op, parse_version(rest).ok_or_else(invalid)?));
+    }
+    Ok(comparisons)
+}
+
+// an error explaining that the config needs another rodeo, if this one doesn't meet requirement
+pub fn check(requirement: &str) -> Result<(), String> {
+    let current = parse_version(VERSION).unwrap_or_default();
+    let holds = parse(requirement)?.iter().all(|(op, version)| match op {
+        Op::AtLeast => current >= *version,
+        Op::Above => current > *version,
+        Op::AtMost => current <= *version,
+        Op::Below => current < *version,
+        Op::Exactly => current == *version,
+    });
+    if holds {
+        Ok(())
+    } else {
+        Err(format!(
+            "this config needs rodeo {}, but this is rodeo {}; install a version that matches to use it",
+            requirement.trim(),
+            VERSION
+        ))
+    }
+}
+
+// checks the requires_rodeo key of a config file, if it has one. This happens before anything else
+// looks at the config, which would otherwise stumble over keys added in the newer rodeo it was
+// written for.
+pub fn check_config(config: &toml::Value) -> Result<(), String> {
+    match config.get("requires_rodeo") {
+        None => Ok(()),
+        Some(toml::Value::String(requirement)) => check(requirement),
+        Some(_) => Err("requires_rodeo must be a string".to_owned()),
+    }
+}
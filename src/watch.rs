@@ -0,0 +1,227 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+// how long after a running rodeo watch last touched its pid file it is taken to have stopped
+const STALE: Duration = Duration::from_secs(15);
+
+// where rodeo watch says it is running: its process id, in a file it touches every so often
+pub fn pid_path(state_dir: &str) -> String {
+    format!("{}/watch.pid", state_dir)
+}
+
+// the process id of the rodeo watch keeping the status cache in state_dir up to date, if one is
+// running. One that stopped without cleaning up after itself is noticed by its process being gone
+// where /proc tells, and otherwise by its pid file going stale.
+pub fn running(state_dir: &str) -> Option<u32> {
+    let path = pid_path(state_dir);
+    let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+    if SystemTime::now().duration_since(modified).unwrap_or_default() > STALE {
+        return None;
+    }
+    let pid: u32 = fs::read_to_string(&path).ok()?.trim().parse().ok()?;
+    if Path::new("/proc/self").exists() && !Path::new(&format!("/proc/{}", pid)).exists() {
+        return None;
+    }
+    Some(pid)
+}
+
+#[cfg(feature = "watch")]
+pub use watcher::run;
+
+#[cfg(not(feature = "watch"))]
+pub fn run(settings: &crate::Settings, observer: &mut dyn crate::event::Observer) -> crate::summary::Summary {
+    use crate::event::{Event, EventKind};
+
+    let mut summary = crate::summary::Summary::default();
+    let event = Event::new(EventKind::File, "watch", &settings.state_dir[..], "")
+        .failed("this rodeo was built without the watch feature");
+    summary.record(event, observer);
+    summary
+}
+
+#[cfg(feature = "watch")]
+mod watcher {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc::{self, RecvTimeoutError};
+    use std::time::{Duration, Instant, SystemTime};
+    use notify::{RecursiveMode, Watcher};
+    use crate::Settings;
+    use crate::cache::StatusCache;
+    use crate::event::{Event, EventKind, Observer};
+    use crate::mapping::{FileState, Target};
+    use crate::summary::Summary;
+
+    // how often the pid file is touched to say the watcher is still running; well within STALE
+    const HEARTBEAT: Duration = Duration::from_secs(5);
+    // how long after a change more changes are waited for, so that a burst of them (a checkout, an
+    // editor saving through a temporary file) is handled at once
+    const SETTLE: Duration = Duration::from_millis(200);
+    // how often files changed too recently to be cached are looked at again when nothing happens
+    const RECHECK: Duration = Duration::from_secs(1);
+
+    // says rodeo watch is running until dropped, which it is whichever way run returns
+    struct PidFile {
+        path: String,
+    }
+
+    impl PidFile {
+        fn create(state_dir: &str) -> std::io::Result<PidFile> {
+            fs::create_dir_all(state_dir)?;
+            let pid_file = PidFile { path: super::pid_path(state_dir) };
+            pid_file.touch()?;
+            Ok(pid_file)
+        }
+
+        fn touch(&self) -> std::io::Result<()> {
+            fs::write(&self.path, format!("{}\n", std::process::id()))
+        }
+    }
+
+    impl Drop for PidFile {
+        fn drop(&mut self) {
+            fs::remove_file(&self.path).unwrap_or_default();
+        }
+    }
+
+    // keeps the status cache up to date as the selected programs' files change, until stopped. The
+    // directories holding both copies of every managed file are watched, and so are those of the
+    // config files: when one of them changes the config is read again, so that the files watched
+    // follow it.
+    pub fn run(settings: &Settings, observer: &mut dyn Observer) -> Summary {
+        let mut summary = Summary::default();
+        let failed = |reason: String| Event::new(EventKind::File, "watch", &settings.state_dir[..], "").failed(reason);
+        if let Some(pid) = super::running(&settings.state_dir) {
+            summary.record(failed(format!("rodeo watch is already running as process {}", pid)), observer);
+            return summary;
+        }
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = match notify::recommended_watcher(sender) {
+            Ok(val) => val,
+            Err(e) => {
+                summary.record(failed(format!("could not watch files: {}", e)), observer);
+                return summary;
+            }
+        };
+        let pid_file = match PidFile::create(&settings.state_dir) {
+            Ok(val) => val,
+            Err(e) => {
+                summary.record(failed(format!("could not write {}: {}", super::pid_path(&settings.state_dir), e)), observer);
+                return summary;
+            }
+        };
+        let mut beat = SystemTime::now();
+
+        let mut settings = settings.clone();
+        let mut cache = StatusCache::load(&settings.state_dir).checking();
+        let mut watched = BTreeSet::new();
+        // the state each file was last seen in, to tell when one changes
+        let mut known: BTreeMap<String, FileState> = BTreeMap::new();
+        let mut changed: BTreeSet<PathBuf> = BTreeSet::new();
+        let mut everything = true;
+        loop {
+            let config_changed = settings.loaded.keys().any(|f| changed.contains(Path::new(f)));
+            if config_changed {
+                match reload(&settings) {
+                    Ok(val) => {
+                        settings = val;
+                        everything = true;
+                    }
+                    Err(e) => summary.record(failed(format!("could not read the config again, still watching what it was: {}", e)), observer),
+                }
+            }
+
+            // watches go up before the files are looked at, so that nothing changing in between
+            // goes unnoticed
+            let targets = settings.targets();
+            let wanted = directories(&settings, &targets);
+            for dir in watched.difference(&wanted) {
+                watcher.unwatch(Path::new(dir)).unwrap_or_default();
+            }
+            for dir in wanted.difference(&watched) {
+                if let Err(e) = watcher.watch(Path::new(dir), RecursiveMode::NonRecursive) {
+                    summary.record(failed(format!("could not watch {}: {}", dir, e)), observer);
+                }
+            }
+            watched = wanted;
+
+            let unsettled = cache.take_unsettled();
+            for target in targets.iter() {
+                if !everything && !unsettled.contains(&target.deployed_file) && !affected(target, &changed) {
+                    continue;
+                }
+                cache.forget(&target.deployed_file);
+                let state = cache.state(target);
+                if let Some(before) = known.insert(target.deployed_file.clone(), state) {
+                    if before != state {
+                        observer.event(&Event::new(EventKind::File, state.as_str(), &target.deployed_file[..], ""));
+                    }
+                }
+            }
+            let files: BTreeSet<String> = targets.iter().map(|t| t.deployed_file.clone()).collect();
+            known.retain(|file, _| files.contains(file));
+            cache.keep_only(&files);
+            cache.save();
+
+            if everything && !config_changed {
+                let message = format!("watching {} files in {} directories", files.len(), watched.len());
+                observer.event(&Event::new(EventKind::File, "watch", &settings.state_dir[..], "").with_output(message));
+            }
+            everything = false;
+            changed.clear();
+
+            if SystemTime::now().duration_since(beat).unwrap_or_default() >= HEARTBEAT {
+                pid_file.touch().unwrap_or_default();
+                beat = SystemTime::now();
+            }
+            // wait for a change, then for the burst it starts to end
+            match receiver.recv_timeout(RECHECK) {
+                Ok(event) => changed.extend(event.map(|e| e.paths).unwrap_or_default()),
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            let until = Instant::now() + SETTLE;
+            while let Ok(event) = receiver.recv_timeout(until.saturating_duration_since(Instant::now())) {
+                changed.extend(event.map(|e| e.paths).unwrap_or_default());
+            }
+        }
+        summary
+    }
+
+    // the config read again, with the programs meant for this machine selected as before
+    fn reload(settings: &Settings) -> Result<Settings, String> {
+        let contents = fs::read_to_string(&settings.config_path).map_err(|e| e.to_string())?;
+        let mut fresh = Settings::new_from_str(contents, settings.home.clone(), settings.config_path.clone(), settings.format)
+            .map_err(|e| e.to_string())?;
+        fresh.state_dir = settings.state_dir.clone();
+        fresh.select_profile(&settings.profile)?;
+        fresh.select_programs(&[])?;
+        fresh.select_host(&settings.host);
+        fresh.select_os(env::consts::OS);
+        fresh.select_conditions();
+        Ok(fresh)
+    }
+
+    // the directories to watch: the ones holding both copies of every target and the config files.
+    // One that doesn't exist yet can only be seen being created from the nearest one above it
+    // that does.
+    fn directories(settings: &Settings, targets: &[Target]) -> BTreeSet<String> {
+        let files = targets.iter()
+            .flat_map(|t| vec![&t.repo_file, &t.deployed_file])
+            .chain(settings.loaded.keys());
+        files
+            .filter_map(|f| Path::new(f).parent())
+            .filter_map(|dir| dir.ancestors().find(|d| d.is_dir()))
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    // whether one of the paths that changed is either copy of target or a directory above one
+    fn affected(target: &Target, changed: &BTreeSet<PathBuf>) -> bool {
+        let (repo, deployed) = (Path::new(&target.repo_file), Path::new(&target.deployed_file));
+        changed.iter().any(|p| repo.starts_with(p) || deployed.starts_with(p))
+    }
+}
@@ -0,0 +1,86 @@
+use std::env;
+use std::process::Command;
+use serde::{Deserialize, Serialize};
+use crate::audit::{self, AuditLog};
+use crate::event::{Event, EventKind};
+
+// what sort of system a program's files are deployed to. Windows programs may use the variables in
+// VARIABLES in their root and may manage registry keys as .reg files.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Flavor {
+    #[default]
+    Unix,
+    Windows,
+}
+
+// the built-in variables of windows programs' roots, as (name, environment variable, where the
+// directory is relative to the home directory). The last is used when the environment variable is
+// unset, and is always where the directory's files are stored in the repository, so the layout
+// doesn't depend on the user name or drive letter of the machine that collected them.
+pub const VARIABLES: &[(&str, &str, &str)] = &[
+    ("%APPDATA%", "APPDATA", "AppData/Roaming"),
+    ("%LOCALAPPDATA%", "LOCALAPPDATA", "AppData/Local"),
+    ("%USERPROFILE%", "USERPROFILE", ""),
+];
+
+// replaces the built-in variables in root with the directories they stand for on this machine.
+// Backslashes are turned into forward slashes, which Windows accepts just as well.
+pub fn expand_root(root: &str, home_dir: &str) -> String {
+    let mut root = root.to_owned();
+    for (name, var, fallback) in VARIABLES {
+        if !root.contains(name) {
+            continue;
+        }
+        let dir = match env::var(var) {
+            Ok(val) if !val.is_empty() => val,
+            _ => format!("{}/{}", home_dir.trim_end_matches('/'), fallback),
+        };
+        root = root.replace(name, &dir);
+    }
+    root.replace('\\', "/")
+}
+
+// root with the built-in variables replaced by their home-relative stand-ins, e.g.
+// "%APPDATA%/Code/User" becomes "~/AppData/Roaming/Code/User"
+pub fn portable_root(root: &str) -> String {
+    let mut root = root.to_owned();
+    for (name, _, fallback) in VARIABLES {
+        root = root.replace(name, &format!("~/{}", fallback));
+    }
+    root.replace('\\', "/").replace("//", "/")
+}
+
+// where the .reg file for a registry key is kept, relative to the program's directory in the
+// repository, e.g. HKCU\Software\SimonTatham\PuTTY is kept at
+// "registry/HKCU_Software_SimonTatham_PuTTY.reg"
+pub fn registry_file(key: &str) -> String {
+    let name: String = key.chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+    format!("registry/{}.reg", name)
+}
+
+// writes key (and everything below it) to file with `reg export`, recording it in audit
+pub fn export(audit: &AuditLog, key: &str, file: &str) -> Event {
+    let event = Event::new(EventKind::File, "reg-export", key, file);
+    let mut command = Command::new("reg");
+    command.args(["export", key, file, "/y"]);
+    run(audit, event, &format!("reg export \"{}\" \"{}\" /y", key, file), &mut command)
+}
+
+// merges file into the registry with `reg import`, recording it in audit
+pub fn import(audit: &AuditLog, key: &str, file: &str) -> Event {
+    let event = Event::new(EventKind::File, "reg-import", file, key);
+    let mut command = Command::new("reg");
+    command.args(["import", file]);
+    run(audit, event, &format!("reg import \"{}\"", file), &mut command)
+}
+
+fn run(audit: &AuditLog, event: Event, description: &str, command: &mut Command) -> Event {
+    match audit.run_command("registry", description, command) {
+        Ok(val) if val.status.success() => event,
+        Ok(val) => event.failed(format!("{} failed ({})", description, audit::describe_failure(&val))),
+        Err(e) => event.failed(format!("could not run {}: {}", description, e)),
+    }
+}